@@ -0,0 +1,217 @@
+//!
+//! 读取和恢复文件的访问控制列表
+//! Linux 上是 POSIX ACL（system.posix_acl_access 扩展属性），
+//! macOS 上是扩展安全信息（ACL_TYPE_EXTENDED）
+//!
+
+use std::io;
+use std::path::Path;
+
+pub use imp::Acl;
+
+///
+/// 读取文件的 ACL，文件没有 ACL 时返回 None
+/// 文件系统不支持 ACL 时返回 is_unsupported 的错误，由调用方决定跳过还是报错
+///
+pub fn read(path: &Path) -> io::Result<Option<Acl>> {
+    imp::read(path)
+}
+
+///
+/// 把 ACL 写到文件上
+///
+pub fn write(path: &Path, acl: &Acl) -> io::Result<()> {
+    imp::write(path, acl)
+}
+
+///
+/// 错误是否是文件系统不支持 ACL
+///
+pub fn is_unsupported(err: &io::Error) -> bool {
+    imp::is_unsupported(err)
+}
+
+#[cfg(unix)]
+fn c_path(path: &Path) -> io::Result<std::ffi::CString> {
+    use std::os::unix::ffi::OsStrExt;
+    std::ffi::CString::new(path.as_os_str().as_bytes())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use super::c_path;
+    use std::io;
+    use std::path::Path;
+
+    const ACCESS_ACL: &[u8] = b"system.posix_acl_access\0";
+
+    ///
+    /// system.posix_acl_access 的原始内容
+    ///
+    pub struct Acl(Vec<u8>);
+
+    pub fn read(path: &Path) -> io::Result<Option<Acl>> {
+        let path = c_path(path)?;
+        loop {
+            let size = unsafe {
+                libc::getxattr(
+                    path.as_ptr(),
+                    ACCESS_ACL.as_ptr().cast(),
+                    std::ptr::null_mut(),
+                    0,
+                )
+            };
+            if size < 0 {
+                let err = io::Error::last_os_error();
+                return match err.raw_os_error() {
+                    Some(libc::ENODATA) => Ok(None),
+                    _ => Err(err),
+                };
+            }
+
+            let mut value = vec![0u8; size as usize];
+            let read = unsafe {
+                libc::getxattr(
+                    path.as_ptr(),
+                    ACCESS_ACL.as_ptr().cast(),
+                    value.as_mut_ptr().cast(),
+                    value.len(),
+                )
+            };
+            if read < 0 {
+                let err = io::Error::last_os_error();
+                //
+                // 两次调用之间 ACL 变大了，重新读取
+                //
+                if err.raw_os_error() == Some(libc::ERANGE) {
+                    continue;
+                }
+                return Err(err);
+            }
+            value.truncate(read as usize);
+            return Ok(Some(Acl(value)));
+        }
+    }
+
+    pub fn write(path: &Path, acl: &Acl) -> io::Result<()> {
+        let path = c_path(path)?;
+        let result = unsafe {
+            libc::setxattr(
+                path.as_ptr(),
+                ACCESS_ACL.as_ptr().cast(),
+                acl.0.as_ptr().cast(),
+                acl.0.len(),
+                0,
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub fn is_unsupported(err: &io::Error) -> bool {
+        err.raw_os_error() == Some(libc::ENOTSUP)
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod imp {
+    use super::c_path;
+    use std::io;
+    use std::os::raw::{c_char, c_int, c_void};
+    use std::path::Path;
+
+    const ACL_TYPE_EXTENDED: c_int = 0x0000_0100;
+
+    extern "C" {
+        fn acl_get_file(path: *const c_char, acl_type: c_int) -> *mut c_void;
+        fn acl_set_file(path: *const c_char, acl_type: c_int, acl: *mut c_void) -> c_int;
+        fn acl_free(obj: *mut c_void) -> c_int;
+    }
+
+    ///
+    /// acl_get_file 返回的 acl_t
+    ///
+    pub struct Acl(*mut c_void);
+
+    //
+    // acl_t 只在读取和写入时使用，不会被多个线程同时访问
+    //
+    unsafe impl Send for Acl {}
+    unsafe impl Sync for Acl {}
+
+    impl Drop for Acl {
+        fn drop(&mut self) {
+            unsafe {
+                acl_free(self.0);
+            }
+        }
+    }
+
+    pub fn read(path: &Path) -> io::Result<Option<Acl>> {
+        let path = c_path(path)?;
+        let acl = unsafe { acl_get_file(path.as_ptr(), ACL_TYPE_EXTENDED) };
+        if acl.is_null() {
+            let err = io::Error::last_os_error();
+            return match err.raw_os_error() {
+                Some(libc::ENOENT) => Ok(None),
+                _ => Err(err),
+            };
+        }
+        Ok(Some(Acl(acl)))
+    }
+
+    pub fn write(path: &Path, acl: &Acl) -> io::Result<()> {
+        let path = c_path(path)?;
+        if unsafe { acl_set_file(path.as_ptr(), ACL_TYPE_EXTENDED, acl.0) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub fn is_unsupported(err: &io::Error) -> bool {
+        err.raw_os_error() == Some(libc::ENOTSUP)
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+mod imp {
+    use std::io;
+    use std::path::Path;
+
+    ///
+    /// 其他平台不处理 ACL
+    ///
+    pub struct Acl;
+
+    pub fn read(_path: &Path) -> io::Result<Option<Acl>> {
+        Ok(None)
+    }
+
+    pub fn write(_path: &Path, _acl: &Acl) -> io::Result<()> {
+        Ok(())
+    }
+
+    pub fn is_unsupported(_err: &io::Error) -> bool {
+        false
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_without_acl() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        assert!(read(file.path()).unwrap().is_none());
+    }
+
+    #[test]
+    fn unsupported_filesystem_is_an_error() {
+        let err = read(Path::new("/proc/self/status")).err().unwrap();
+        assert!(is_unsupported(&err));
+    }
+}
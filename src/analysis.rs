@@ -0,0 +1,162 @@
+//!
+//! 分析正则的语法树，判断一次匹配最多跨越多少行，据此选择逐行替换还是整个文件替换，
+//! --smart-case 是否应该忽略大小写，以及每次匹配都必须包含的字面量
+//!
+
+use regex_syntax::ast::{self, Ast, ClassSetItem};
+use regex_syntax::hir::literal::{ExtractKind, Extractor};
+use regex_syntax::hir::{Class, Hir, HirKind, Look};
+
+///
+/// 一次匹配最多跨越的行数
+/// 返回 None 时只能整个文件替换：匹配的换行数没有上限，或者正则能匹配 \r，
+/// 而逐行读取时行尾的 \r 会和 \n 一起被去掉，或者跨行的正则使用了整段文本的锚点
+///
+pub fn max_line_number(pattern: &str) -> Option<usize> {
+    let hir = regex_syntax::parse(pattern).ok()?;
+    if matches_char(&hir, '\r') {
+        return None;
+    }
+    let count = max_newlines(&hir)?;
+    //
+    // 跨行匹配时逐行替换的缓冲区从任意一行开始，不开启多行模式的 ^、$ 会在缓冲区边界上错误地匹配
+    //
+    let looks = hir.properties().look_set();
+    if count > 0 && (looks.contains(Look::Start) || looks.contains(Look::End)) {
+        return None;
+    }
+    Some(count.saturating_add(1))
+}
+
+///
+/// 每次匹配都必须包含的字面量：匹配一定以其中之一开头（或者结尾）
+/// 文本中一个都找不到时不可能有匹配，可以跳过正则直接用字面量查找
+/// 字面量太多、没有上限或者可能是空字符串时返回 None
+///
+pub fn required_literals(pattern: &str) -> Option<Vec<Vec<u8>>> {
+    let hir = regex_syntax::parse(pattern).ok()?;
+    //
+    // 前缀和后缀中选最短的字面量更长的一组，误报更少
+    //
+    [ExtractKind::Prefix, ExtractKind::Suffix]
+        .into_iter()
+        .filter_map(|kind| {
+            let seq = Extractor::new().kind(kind).extract(&hir);
+            let literals = seq.literals()?;
+            if literals.iter().any(|literal| literal.is_empty()) {
+                return None;
+            }
+            Some(
+                literals
+                    .iter()
+                    .map(|literal| literal.as_bytes().to_vec())
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .max_by_key(|literals| literals.iter().map(Vec::len).min().unwrap_or(0))
+}
+
+///
+/// 一次匹配中最多包含的 \n 的个数，没有上限时为 None
+///
+fn max_newlines(hir: &Hir) -> Option<usize> {
+    match hir.kind() {
+        HirKind::Empty | HirKind::Look(_) => Some(0),
+        HirKind::Literal(literal) => Some(literal.0.iter().filter(|&&b| b == b'\n').count()),
+        HirKind::Class(_) => Some(usize::from(matches_char(hir, '\n'))),
+        HirKind::Repetition(repetition) => match max_newlines(&repetition.sub)? {
+            0 => Some(0),
+            count => {
+                let max = repetition.max?;
+                count.checked_mul(usize::try_from(max).ok()?)
+            }
+        },
+        HirKind::Capture(capture) => max_newlines(&capture.sub),
+        HirKind::Concat(subs) => subs
+            .iter()
+            .try_fold(0usize, |total, sub| total.checked_add(max_newlines(sub)?)),
+        HirKind::Alternation(subs) => subs
+            .iter()
+            .try_fold(0usize, |max, sub| Some(max.max(max_newlines(sub)?))),
+    }
+}
+
+///
+/// 正则的某个部分能否匹配字符 ch（只用于 ASCII 字符）
+///
+fn matches_char(hir: &Hir, ch: char) -> bool {
+    match hir.kind() {
+        HirKind::Empty | HirKind::Look(_) => false,
+        HirKind::Literal(literal) => literal.0.contains(&(ch as u8)),
+        HirKind::Class(Class::Unicode(class)) => class
+            .ranges()
+            .iter()
+            .any(|range| range.start() <= ch && ch <= range.end()),
+        HirKind::Class(Class::Bytes(class)) => class
+            .ranges()
+            .iter()
+            .any(|range| range.start() <= ch as u8 && ch as u8 <= range.end()),
+        HirKind::Repetition(repetition) => {
+            repetition.max != Some(0) && matches_char(&repetition.sub, ch)
+        }
+        HirKind::Capture(capture) => matches_char(&capture.sub, ch),
+        HirKind::Concat(subs) | HirKind::Alternation(subs) => {
+            subs.iter().any(|sub| matches_char(sub, ch))
+        }
+    }
+}
+
+///
+/// --smart-case：正则里有字面字符并且都不是大写时忽略大小写，和 ripgrep 的行为一致
+/// 字符类中写出的字符也算字面字符，\w、\S 这类转义不算
+///
+pub fn smart_case_insensitive(pattern: &str) -> bool {
+    let Ok(ast) = ast::parse::Parser::new().parse(pattern) else {
+        return false;
+    };
+    match ast::visit(&ast, LiteralCase::default()) {
+        Ok(case) => case.any_literal && !case.any_uppercase,
+        Err(()) => false,
+    }
+}
+
+#[derive(Default)]
+struct LiteralCase {
+    any_literal: bool,
+    any_uppercase: bool,
+}
+
+impl LiteralCase {
+    fn literal(&mut self, ch: char) {
+        self.any_literal = true;
+        self.any_uppercase |= ch.is_uppercase();
+    }
+}
+
+impl ast::Visitor for LiteralCase {
+    type Output = Self;
+    type Err = ();
+
+    fn finish(self) -> Result<Self, ()> {
+        Ok(self)
+    }
+
+    fn visit_pre(&mut self, ast: &Ast) -> Result<(), ()> {
+        if let Ast::Literal(literal) = ast {
+            self.literal(literal.c);
+        }
+        Ok(())
+    }
+
+    fn visit_class_set_item_pre(&mut self, item: &ClassSetItem) -> Result<(), ()> {
+        match item {
+            ClassSetItem::Literal(literal) => self.literal(literal.c),
+            ClassSetItem::Range(range) => {
+                self.literal(range.start.c);
+                self.literal(range.end.c);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,111 @@
+//!
+//! --bytes：用 regex::bytes 按字节匹配和替换，内容不是有效 UTF-8 的文件（混合编码、夹杂二进制的日志）也能处理
+//! 文件按原样读入，不识别编码和 BOM，匹配之外的字节原样写回，不做任何有损的转换
+//! 正则本身仍然按文本编译，不能匹配无效的 UTF-8；. 和 \S 之类的字符类不会匹配无效的字节
+//!
+
+use regex::bytes::Regex;
+use std::borrow::Cow;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::select::Selector;
+use crate::template::Template;
+use crate::{install, regex_error, RegexLimits, Rule};
+
+///
+/// 每条规则按字节匹配的正则，和 ReplacePlan::rules 一一对应
+///
+#[derive(Clone, Default)]
+pub struct ByteRules {
+    res: Vec<Regex>,
+}
+
+impl ByteRules {
+    ///
+    /// 用规则的正则编译按字节匹配的版本，--null-data 的选项和 Rule::null_data 相同
+    ///
+    pub fn new(rules: &[Rule], null_data: bool, limits: RegexLimits) -> Result<Self, String> {
+        let res = rules
+            .iter()
+            .map(|rule| {
+                let mut builder = limits.bytes_builder(rule.re.as_str());
+                if null_data {
+                    builder.multi_line(true).line_terminator(b'\0');
+                }
+                builder.build().map_err(regex_error)
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { res })
+    }
+
+    ///
+    /// 文件中是否有任何规则的匹配
+    ///
+    pub fn has_match(&self, file: &Path) -> io::Result<bool> {
+        let contents = fs::read(file)?;
+        Ok(self.res.iter().any(|re| re.is_match(&contents)))
+    }
+}
+
+///
+/// 替换一段字节中被选中的匹配，行号按 \n 计算
+///
+fn replace_bytes<'a>(
+    text: &'a [u8],
+    re: &Regex,
+    replacement: &Template,
+    selector: &mut Selector,
+) -> Cow<'a, [u8]> {
+    let mut replaced = Vec::new();
+    let mut last_end = 0;
+    let mut line = 1;
+    let mut counted = 0;
+    let mut changed = false;
+    selector.set_text("");
+
+    for caps in re.captures_iter(text) {
+        let m = caps.get(0).unwrap();
+        line += memchr::memchr_iter(b'\n', &text[counted..m.start()]).count();
+        counted = m.start();
+        if !selector.allows(m.range(), line) {
+            continue;
+        }
+        replaced.extend_from_slice(&text[last_end..m.start()]);
+        replacement.append_bytes(&caps, &mut replaced);
+        last_end = m.end();
+        changed = true;
+    }
+
+    if !changed {
+        return Cow::Borrowed(text);
+    }
+    replaced.extend_from_slice(&text[last_end..]);
+    Cow::Owned(replaced)
+}
+
+///
+/// 依次应用所有规则，把替换结果写到临时文件
+///
+pub fn replace_in_file(
+    target_file: &Path,
+    rules: &[Rule],
+    byte_rules: &ByteRules,
+    selector: &mut Selector,
+) -> Result<PathBuf, Box<dyn Error>> {
+    let mut contents = fs::read(target_file)?;
+    for (rule, re) in rules.iter().zip(&byte_rules.res) {
+        if let Cow::Owned(replaced) = replace_bytes(&contents, re, &rule.replacement, selector) {
+            contents = replaced;
+        }
+    }
+
+    let temp_file = install::create_temp_file(target_file)?;
+    let temp_file_path = temp_file.path().to_path_buf();
+    fs::write(&temp_file_path, contents)?;
+    let _ = temp_file.persist(&temp_file_path)?;
+
+    Ok(temp_file_path)
+}
@@ -0,0 +1,196 @@
+//!
+//! --cache：记录检查过、没有任何匹配的文件，下次用同样的规则运行时跳过它们
+//! 反复对一个大部分没有改动的目录运行同一次迁移时，只需要重新检查改动过的文件
+//! 文件的大小或者修改时间变化之后缓存失效
+//!
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::queue::{escape, unescape};
+use crate::{install, paths, ReplacePlan};
+
+const HEADER: &str = "# regex-replace cache v1";
+
+///
+/// 修改时间距离检查时不到这么久的文件不记录
+/// 同一个时间戳之内再次修改、大小又不变的文件分辨不出来
+///
+const SETTLE_TIME: Duration = Duration::from_secs(2);
+
+///
+/// 缓存的一条记录：在这个大小和修改时间上，文件没有规则的匹配
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    ///
+    /// 检查时的规则和匹配选项的哈希，规则变化之后记录失效
+    ///
+    plan: u64,
+    size: u64,
+    modified: u128,
+}
+
+pub struct Cache {
+    path: PathBuf,
+    ///
+    /// 规则和影响匹配的选项的描述，和每个文件的编码一起计算哈希
+    ///
+    plan_key: String,
+    entries: Mutex<HashMap<PathBuf, Entry>>,
+}
+
+impl Cache {
+    ///
+    /// 读取缓存文件，文件不存在时从空的缓存开始
+    /// 无法识别的缓存文件同样当作空的，保存时覆盖
+    ///
+    pub fn load(path: &Path, plan: &ReplacePlan) -> Self {
+        let mut entries = HashMap::new();
+        if let Ok(contents) = fs::read_to_string(path) {
+            let mut lines = contents.lines();
+            if lines.next() == Some(HEADER) {
+                entries.extend(lines.filter_map(parse_entry));
+            }
+        }
+        Self {
+            path: path.to_path_buf(),
+            plan_key: plan_key(plan),
+            entries: Mutex::new(entries),
+        }
+    }
+
+    ///
+    /// 文件在上次检查之后没有变化，并且当时没有规则的匹配
+    ///
+    pub fn is_clean(&self, file: &Path, plan: &ReplacePlan) -> bool {
+        let Some(entry) = self.entry(file, plan) else {
+            return false;
+        };
+        self.entries.lock().unwrap().get(file) == Some(&entry)
+    }
+
+    ///
+    /// 记录检查过、没有匹配的文件
+    ///
+    pub fn mark_clean(&self, file: &Path, plan: &ReplacePlan) {
+        if let Some(entry) = self.entry(file, plan) {
+            self.entries
+                .lock()
+                .unwrap()
+                .insert(file.to_path_buf(), entry);
+        }
+    }
+
+    ///
+    /// 有匹配的文件删除旧的记录
+    ///
+    pub fn forget(&self, file: &Path) {
+        self.entries.lock().unwrap().remove(file);
+    }
+
+    ///
+    /// 文件当前状态对应的记录，读不到元信息或者刚刚修改过时为 None
+    ///
+    fn entry(&self, file: &Path, plan: &ReplacePlan) -> Option<Entry> {
+        let metadata = fs::metadata(file).ok()?;
+        let modified = metadata.modified().ok()?;
+        if SystemTime::now()
+            .duration_since(modified)
+            .map_or(true, |age| age < SETTLE_TIME)
+        {
+            return None;
+        }
+        let encoding = plan.encoding_for(file);
+        Some(Entry {
+            plan: fnv1a(format!("{}\t{:?}", self.plan_key, encoding).as_bytes()),
+            size: metadata.len(),
+            modified: modified.duration_since(UNIX_EPOCH).ok()?.as_nanos(),
+        })
+    }
+
+    ///
+    /// 写回缓存文件，已经不存在的文件的记录被丢弃
+    ///
+    pub fn save(&self) -> Result<(), String> {
+        let write_error =
+            |err: std::io::Error| format!("写入缓存文件 {:?} 失败: {}", self.path, err);
+        let entries = self.entries.lock().unwrap();
+        let mut sorted: Vec<_> = entries.iter().filter(|(file, _)| file.exists()).collect();
+        sorted.sort_by(|a, b| a.0.cmp(b.0));
+
+        let temp_file = install::create_temp_file(&self.path).map_err(write_error)?;
+        let mut output = BufWriter::new(temp_file.as_file());
+        writeln!(output, "{}", HEADER).map_err(write_error)?;
+        for (file, entry) in sorted {
+            writeln!(
+                output,
+                "{:016x}\t{}\t{}\t{}",
+                entry.plan,
+                entry.size,
+                entry.modified,
+                escape(&paths::to_host(file).to_string_lossy())
+            )
+            .map_err(write_error)?;
+        }
+        output.flush().map_err(write_error)?;
+        drop(output);
+        temp_file
+            .persist(&self.path)
+            .map_err(|err| write_error(err.error))?;
+        Ok(())
+    }
+}
+
+///
+/// 缓存文件中的一行：规则的哈希、大小、修改时间(纳秒)、路径，用制表符分隔
+///
+fn parse_entry(line: &str) -> Option<(PathBuf, Entry)> {
+    let [plan, size, modified, path] = line.split('\t').collect::<Vec<_>>()[..] else {
+        return None;
+    };
+    let entry = Entry {
+        plan: u64::from_str_radix(plan, 16).ok()?,
+        size: size.parse().ok()?,
+        modified: modified.parse().ok()?,
+    };
+    Some((paths::to_local(Path::new(&unescape(path))), entry))
+}
+
+///
+/// 影响一个文件有没有匹配的所有选项：正则、按记录或者按行处理的方式、规范化以及是否按字节匹配
+/// 替换字符串和选择条件不影响有没有匹配
+///
+fn plan_key(plan: &ReplacePlan) -> String {
+    let mut key = String::new();
+    for rule in &plan.rules {
+        key.push_str(&escape(rule.re.as_str()));
+        key.push('\t');
+    }
+    key.push_str(&format!(
+        "{:?}\t{:?}\t{}\t{:?}",
+        plan.line_edit,
+        plan.normalize,
+        plan.null_data,
+        plan.record_separator
+            .as_ref()
+            .map(|separator| separator.as_str())
+    ));
+    if plan.bytes.is_some() {
+        key.push_str("\tbytes");
+    }
+    key
+}
+
+///
+/// 64 位 FNV-1a 哈希，不同版本的编译器上结果相同，可以写进缓存文件
+///
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
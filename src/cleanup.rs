@@ -0,0 +1,114 @@
+//!
+//! 清理崩溃或被强制结束的运行留下的临时文件
+//! 临时文件名里带有创建它的进程号，进程已经不存在的临时文件就是遗留的
+//! --atomic-run 遗留的备份可能是原文件唯一的副本，只在指定了 --backups 时才删除
+//!
+
+use ignore::WalkBuilder;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::install::{BACKUP_PREFIX, TEMP_PREFIX};
+use crate::paths;
+
+///
+/// 在 dirs 下查找遗留的临时文件并删除，dry_run 时只列出不删除，backups 时同时删除遗留的备份
+///
+pub fn cleanup(dirs: &[PathBuf], dry_run: bool, backups: bool) {
+    let mut found = 0;
+    //
+    // --tmpdir 可能就在要查找的目录里面，同一个文件只处理一次
+    //
+    let mut seen = HashSet::new();
+    for dir in dirs {
+        //
+        // 临时文件是隐藏文件，也可能被 .gitignore 忽略，这里不能使用默认的过滤规则
+        //
+        let walker = WalkBuilder::new(dir).standard_filters(false).build();
+        for entry in walker.flatten() {
+            let path = entry.path();
+            if !entry
+                .file_type()
+                .is_some_and(|file_type| file_type.is_file())
+                || !is_orphaned(path, backups)
+            {
+                continue;
+            }
+            if !seen.insert(fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())) {
+                continue;
+            }
+            found += 1;
+            let host_path = paths::to_host(path);
+            if dry_run {
+                println!("将删除 {:?}", host_path);
+                continue;
+            }
+            match fs::remove_file(path) {
+                Ok(()) => println!("已删除 {:?}", host_path),
+                Err(err) => eprintln!("删除文件错误 {:?}: {}", host_path, err),
+            }
+        }
+    }
+
+    if found == 0 {
+        println!("没有找到遗留的临时文件");
+    } else if dry_run {
+        println!("共找到 {} 个遗留的临时文件", found);
+    } else {
+        println!("共清理 {} 个遗留的临时文件", found);
+    }
+}
+
+///
+/// 文件名符合临时文件（backups 时还有备份）的命名规则，并且创建它的进程已经不存在
+///
+fn is_orphaned(path: &Path, backups: bool) -> bool {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return false;
+    };
+    //
+    // 备份的前缀以临时文件的前缀开头，要先判断
+    //
+    let rest = match name.strip_prefix(BACKUP_PREFIX) {
+        Some(rest) if backups => Some(rest),
+        Some(_) => None,
+        None => name.strip_prefix(TEMP_PREFIX),
+    };
+    let Some((pid, _)) = rest.and_then(|rest| rest.split_once('-')) else {
+        return false;
+    };
+    match pid.parse() {
+        Ok(pid) => !imp::is_running(pid),
+        Err(_) => false,
+    }
+}
+
+#[cfg(unix)]
+mod imp {
+    use std::io;
+
+    ///
+    /// 向进程发送 0 号信号只检查进程是否存在
+    /// 没有权限发送信号（EPERM）说明进程存在，只是属于其他用户
+    ///
+    pub fn is_running(pid: u32) -> bool {
+        let Ok(pid) = libc::pid_t::try_from(pid) else {
+            return false;
+        };
+        if unsafe { libc::kill(pid, 0) } == 0 {
+            return true;
+        }
+        io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    }
+}
+
+//
+// 无法判断进程是否存在的平台上不删除任何文件
+//
+#[cfg(not(unix))]
+mod imp {
+    pub fn is_running(_pid: u32) -> bool {
+        true
+    }
+}
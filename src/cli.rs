@@ -0,0 +1,1588 @@
+//!
+//! 命令行参数的解析和各个子命令的流程，src/main.rs 只调用这里的 run
+//!
+
+use atty::Stream;
+use clap::{CommandFactory, Parser, Subcommand};
+use regex::Regex;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::mpsc;
+use std::thread;
+
+use crate::bytes::ByteRules;
+use crate::cache::Cache;
+use crate::completions::{self, Shell};
+use crate::config::Config;
+use crate::csv::{self, Column, CsvOptions};
+use crate::encoding::BomMode;
+use crate::ident::{self, IdentRename};
+use crate::install::{self, Preserve, WriteOptions};
+use crate::jsonpath::JsonPath;
+use crate::keypath::KeyPath;
+use crate::lines::LineEdit;
+use crate::normalize::Normalization;
+use crate::prefilter::Prefilter;
+use crate::progress::Progress;
+use crate::rename::Rename;
+use crate::select::{self, FrontMatter, NumberRange, Selection};
+use crate::template::{Template, TokenScope, Tokens};
+use crate::validate::ValidateMode;
+use crate::vcs::{self, FileSet};
+use crate::{
+    analysis, cleanup, estimate, interrupt, matrix, metrics, paths, priority, queue, records,
+    rename, rules, ruletest, search, staged, stop, tui, InvalidUtf8, RegexLimits, ReplacePlan,
+    Replacer, Reporting, Rule, Strategy, WalkOptions,
+};
+
+#[derive(Parser)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+    ///
+    /// 指定的目录，默认为当前目录
+    ///
+    #[clap(
+        short = 'd',
+        long = "directory",
+        conflicts_with = "files",
+        global = true
+    )]
+    directory: Option<PathBuf>,
+    ///
+    /// 指定的文件，可以指定多个
+    ///
+    #[clap(short = 'f', long = "files", conflicts_with = "directory", value_delimiter = ' ', num_args = 1.., global = true)]
+    files: Option<Vec<PathBuf>>,
+    ///
+    /// 从标准输入读取的文件列表以 NUL 分隔，而不是按行分隔，配合 find -print0、rg -0 -l 使用
+    /// 文件名中可以包含换行和空格
+    ///
+    #[clap(short = '0', long = "null", global = true)]
+    null: bool,
+    ///
+    /// 从文件读取要处理的文件列表，每行一个路径，- 表示标准输入
+    /// 不管标准输入是不是终端都按列表读取，-0 同样生效
+    ///
+    #[clap(long = "files-from", conflicts_with_all = ["files", "directory", "git"], global = true)]
+    files_from: Option<PathBuf>,
+    ///
+    /// 没有指定 -d 和 -f、标准输入又不是终端时，从标准输入读取文件列表
+    /// 指定了 -d 或 -f 时按指定的处理，在 CI 之类没有终端的环境中同样生效
+    ///
+    #[clap(skip)]
+    paths_from_stdin: bool,
+    ///
+    /// 从 git 获取文件列表，代替遍历目录：tracked 所有跟踪的文件，modified 相对 HEAD 有改动的文件，
+    /// staged 已暂存的文件；只保留 -d 指定的目录（默认为当前目录）下的文件
+    ///
+    #[clap(long, value_enum, conflicts_with = "files", global = true)]
+    git: Option<FileSet>,
+    ///
+    /// 替换暂存区中的文件内容并写回暂存区，不修改工作区，可以作为 pre-commit 钩子使用
+    /// 只处理 -d 指定的目录（默认为当前目录）下已暂存的文件，输出写回了暂存区的文件
+    ///
+    #[clap(long, conflicts_with_all = ["files", "files_from", "git", "rename", "rename_only", "atomic_run"])]
+    staged: bool,
+    ///
+    /// 遍历目录时额外使用的忽略文件，格式和 .gitignore 相同，可以指定多个
+    /// 遍历时总是会读取 .gitignore、.ignore 和 .rgignore
+    ///
+    #[clap(long = "ignore-file", global = true)]
+    ignore_files: Vec<PathBuf>,
+    ///
+    /// 查询正则
+    ///
+    #[clap(short = 'p', long = "pattern", global = true)]
+    pattern: Option<String>,
+    ///
+    /// 替换字符串
+    ///
+    #[clap(short = 'r', long = "replacement", requires = "pattern", global = true)]
+    replacement: Option<String>,
+    ///
+    /// 用外部命令生成替换结果，代替 -r：每个匹配执行一次命令，命令的标准输出（去掉末尾的一个换行）作为替换结果
+    /// 匹配的文本从标准输入传入，也放在环境变量 MATCH 中；捕获组作为位置参数 $1、$2……，
+    /// 命名捕获组还放在环境变量 GROUP_<组名> 中；命令失败时保留原文
+    ///
+    #[clap(
+        long = "exec-replace",
+        requires = "pattern",
+        conflicts_with_all = ["replacement", "rules_file", "ident"],
+        global = true
+    )]
+    exec_replace: Option<String>,
+    ///
+    /// 用表达式计算替换结果，代替 -r，例如 'm.group(1).upper() + "_" + m.file_stem'
+    /// 可以使用匹配的文本、捕获组、行号和文件路径，语法见 script.rs
+    ///
+    #[clap(
+        long = "script",
+        requires = "pattern",
+        conflicts_with_all = ["replacement", "exec_replace", "rules_file", "ident"],
+        global = true
+    )]
+    script: Option<String>,
+    ///
+    /// 规则文件，每行一条 sed 风格的规则：s/正则/替换/
+    /// 支持 # 注释和空行
+    /// 扩展名为 .toml 时按规则清单读取，每条规则是一个 [[rule]] 表
+    ///
+    #[clap(long = "rules-file", conflicts_with_all = ["pattern", "replacement"], global = true)]
+    rules_file: Option<PathBuf>,
+    ///
+    /// 重命名标识符：old_name=new_name，可以指定多次
+    /// 同时替换 snake_case、camelCase、PascalCase、SCREAMING_SNAKE、kebab-case 各种写法，
+    /// 每种写法替换成新名字的同一种写法
+    ///
+    #[clap(
+        long,
+        value_parser = ident::parse_rename,
+        conflicts_with_all = ["pattern", "replacement", "rules_file"],
+        global = true
+    )]
+    ident: Vec<IdentRename>,
+    ///
+    /// 替换字符串中 {{now}}、{{uuid}} 的取值范围：整次运行相同 (run) 或每次匹配重新生成 (match)
+    ///
+    #[clap(long = "token-scope", value_enum, default_value = "run", global = true)]
+    token_scope: TokenScope,
+    ///
+    /// 允许替换字符串用 ${env:VAR} 引用环境变量，例如 -r 'version = "${env:VERSION}"'
+    ///
+    #[clap(long, global = true)]
+    env: bool,
+    ///
+    /// 匹配之前把文件内容和正则规范化成 NFC 或 NFD，修改过的文件按这种形式写回
+    /// 用来匹配组合字符和分解字符写法不同的文本
+    ///
+    #[clap(long, value_enum, global = true)]
+    normalize: Option<Normalization>,
+    ///
+    /// 只读扫描，报告受影响的文件数、匹配数以及预计耗时，不修改文件
+    ///
+    #[clap(long = "estimate")]
+    estimate: bool,
+    ///
+    /// 只读扫描，输出每条规则在每个顶层目录中的匹配数，不修改文件
+    ///
+    #[clap(long = "matrix", conflicts_with = "estimate")]
+    matrix: bool,
+    ///
+    /// 用于 CI 的检查：不修改文件，按 path:line:column: 匹配的文本 的格式输出每一处匹配
+    /// 有任何匹配时以状态 1 退出，可以直接使用修复时用的 -p 或者规则清单，不需要 -r
+    ///
+    #[clap(long = "check", conflicts_with_all = ["estimate", "matrix", "staged"])]
+    check: bool,
+    ///
+    /// search 和 --check 输出匹配的格式，sarif 输出 SARIF 2.1.0，可以上传到 GitHub 代码扫描
+    /// vimgrep 和 grep 分别和 rg --vimgrep、grep -nH 的输出相同，可以载入 Vim 的 quickfix 列表
+    ///
+    #[clap(long, value_enum, default_value = "text", global = true)]
+    format: search::Format,
+    ///
+    /// 替换完成后按 git diff --stat 的格式输出每个文件增加和删除的行数
+    ///
+    #[clap(long = "stat", global = true)]
+    stat: bool,
+    ///
+    /// 替换完成后列出每个被修改的文件、改动的行号以及改动前后的摘录
+    ///
+    #[clap(long, visible_alias = "verbose", global = true)]
+    report: bool,
+    ///
+    /// 把 JSON 格式的进度记录（每行一条）写到这个已经打开的文件描述符上
+    ///
+    #[clap(long = "progress-fd", global = true)]
+    progress_fd: Option<i32>,
+    ///
+    /// 控制文件，运行中创建这个文件会在处理完当前的文件后停止，并输出已完成部分的统计
+    /// unix 上也可以发送 SIGUSR1 信号
+    ///
+    #[clap(long = "stop-file", global = true)]
+    stop_file: Option<PathBuf>,
+    ///
+    /// 先扫描一遍，按匹配数从多到少的顺序处理文件，影响最大的文件最先完成
+    ///
+    #[clap(long = "prioritize-matches", global = true)]
+    prioritize_matches: bool,
+    ///
+    /// 用内存映射读取文件来检查匹配，没有匹配的文件省去一次完整的读取
+    ///
+    #[clap(long, global = true)]
+    mmap: bool,
+    ///
+    /// 并行处理文件的线程数，默认等于 CPU 核数，1 表示按顺序逐个处理
+    /// 在网络文件系统上并行读写往往更慢，可以调小
+    ///
+    #[clap(short = 'j', long, global = true)]
+    threads: Option<NonZeroUsize>,
+    ///
+    /// 运行结束后输出资源使用情况：峰值内存、读写字节数、每秒处理的文件数和线程利用率
+    ///
+    #[clap(long = "resource-usage", global = true)]
+    resource_usage: bool,
+    ///
+    /// 记录每个文件检查匹配、替换和写回的耗时，运行结束后列出最慢的 N 个文件（默认 10 个）和各阶段的合计
+    /// 用来调整线程数，找出在某些文件上特别慢的正则
+    ///
+    #[clap(long, value_name = "N", num_args = 0..=1, default_missing_value = "10", global = true)]
+    timings: Option<usize>,
+    ///
+    /// --timings 的结果以一行 JSON 输出到标准输出
+    ///
+    #[clap(long = "timings-json", requires = "timings", global = true)]
+    timings_json: bool,
+    ///
+    /// 把所有写回的改动写成统一 diff 格式的补丁文件，可以附到代码评审里，或者之后用 git apply 应用
+    /// 路径相对于当前目录，不是 UTF-8 的文件不会写进补丁
+    ///
+    #[clap(long = "output-patch", value_name = "FILE", global = true)]
+    output_patch: Option<PathBuf>,
+    ///
+    /// 多行模式：^、$ 匹配每一行的开头和结尾（行尾的 \r\n 也能识别），而不只是整个文件的开头和结尾
+    ///
+    #[clap(short = 'U', long, global = true)]
+    multiline: bool,
+    ///
+    /// 多行模式下让 . 也能匹配换行
+    ///
+    #[clap(long, requires = "multiline", global = true)]
+    dotall: bool,
+    ///
+    /// 只匹配完整的单词，相当于把正则写成 \b(?:正则)\b
+    ///
+    #[clap(short = 'w', long = "word-regexp", global = true)]
+    word_regexp: bool,
+    ///
+    /// 正则中没有大写字母时忽略大小写，有大写字母时区分大小写
+    ///
+    #[clap(short = 'S', long = "smart-case", global = true)]
+    smart_case: bool,
+    ///
+    /// 按匹配文本的大小写形式调整替换结果：Foo -> Bar，FOO -> BAR，foo -> bar
+    ///
+    #[clap(long = "preserve-case", global = true)]
+    preserve_case: bool,
+    ///
+    /// 按 CSV 格式解析文件，只替换 --column 指定的列
+    ///
+    #[clap(
+        long,
+        requires = "column",
+        conflicts_with = "within_start",
+        global = true
+    )]
+    csv: bool,
+    ///
+    /// --csv 要替换的列：从 1 开始的列号，或者表头中的列名（此时第一行作为表头，不会被替换）
+    ///
+    #[clap(long, value_parser = csv::parse_column, requires = "csv", global = true)]
+    column: Option<Column>,
+    ///
+    /// --csv 的字段分隔符，默认为逗号，TSV 文件使用 \t
+    ///
+    #[clap(long, value_parser = csv::parse_delimiter, default_value = ",", requires = "csv", global = true)]
+    delimiter: u8,
+    ///
+    /// 按 JSON 格式解析文件，只替换 JSONPath 选中的字符串值，例如 '$.dependencies.*.version'
+    ///
+    #[clap(
+        long,
+        conflicts_with_all = ["csv", "within_start"],
+        global = true
+    )]
+    json_path: Option<JsonPath>,
+    ///
+    /// 只替换 YAML 或 TOML 文件中这个键路径下的字符串值，例如 metadata.name、jobs.*.steps.0.uses
+    ///
+    #[clap(
+        long,
+        visible_aliases = ["yaml-path", "toml-path"],
+        conflicts_with_all = ["csv", "json_path", "within_start"],
+        global = true
+    )]
+    key_path: Option<KeyPath>,
+    ///
+    /// 不做替换，删除所有包含匹配的行，和 sed 的 /正则/d 一样逐行匹配
+    ///
+    #[clap(
+        long,
+        conflicts_with_all = ["replacement", "exec_replace", "script", "csv", "json_path", "key_path"],
+        global = true
+    )]
+    delete_lines: bool,
+    ///
+    /// 和 --delete-lines 一起使用，反过来只保留包含匹配的行，没有匹配的文件会被清空
+    ///
+    #[clap(long, requires = "delete_lines", global = true)]
+    invert: bool,
+    ///
+    /// 不做替换，在每个包含匹配的行之前插入一行，可以像 -r 一样引用捕获组，例如 '${1}// TODO'
+    ///
+    #[clap(
+        long,
+        conflicts_with_all = ["replacement", "exec_replace", "script", "delete_lines", "csv", "json_path", "key_path"],
+        global = true
+    )]
+    insert_before: Option<String>,
+    ///
+    /// 和 --insert-before 相同，但是插入在包含匹配的行之后
+    ///
+    #[clap(
+        long,
+        conflicts_with_all = ["replacement", "exec_replace", "script", "delete_lines", "insert_before", "csv", "json_path", "key_path"],
+        global = true
+    )]
+    insert_after: Option<String>,
+    ///
+    /// 同时把规则应用到遍历到的文件名和目录名上，先替换内容再重命名
+    /// 任何一个重命名有冲突时都不会重命名
+    ///
+    #[clap(long, conflicts_with_all = ["delete_lines", "insert_before", "insert_after"])]
+    rename: bool,
+    ///
+    /// 只重命名文件和目录，不替换文件内容
+    ///
+    #[clap(long, conflicts_with_all = ["rename", "delete_lines", "insert_before", "insert_after"])]
+    rename_only: bool,
+    ///
+    /// 替换引擎：auto 自动选择，line 只逐行替换（需要整个文件时报错），whole 总是整个文件替换
+    ///
+    #[clap(long, value_enum, default_value = "auto", global = true)]
+    strategy: Strategy,
+    ///
+    /// 显式设置替换后文件的权限（八进制，例如 644），默认沿用原文件的权限
+    ///
+    #[clap(long = "chmod", value_parser = install::parse_mode, global = true)]
+    chmod: Option<u32>,
+    ///
+    /// 写回时保留的原文件属性，逗号分隔：owner（属主）、times（时间）、mode（权限和 ACL）、
+    /// xattrs（扩展属性），不需要保留扩展属性时可以只指定 mode
+    /// 默认保留属主，没有权限修改属主时跳过，例如普通用户替换属组不同的文件
+    ///
+    #[clap(
+        long = "preserve",
+        value_enum,
+        value_delimiter = ',',
+        default_value = "owner,mode,xattrs",
+        global = true
+    )]
+    preserve: Vec<Preserve>,
+    ///
+    /// 只替换起始行落在指定范围内的匹配，例如 120..180，两端都包含，可以指定多次
+    ///
+    #[clap(long = "lines", value_parser = select::parse_line_range, global = true)]
+    lines: Vec<NumberRange>,
+    ///
+    /// 以 NUL 而不是换行作为记录的分隔符，和 sed -z 一样：^ 和 $ 匹配每条记录的开头和结尾，
+    /// . 可以匹配换行，匹配可以跨越任意多行；--delete-lines 等按行编辑的选项按记录处理
+    ///
+    #[clap(
+        long = "null-data",
+        short = 'z',
+        conflicts_with = "lines",
+        global = true
+    )]
+    null_data: bool,
+    ///
+    /// 按这个分隔符（正则）把文件切成记录，每条记录单独替换，匹配不会跨越分隔符，
+    /// ^ 和 $ 匹配记录的开头和结尾，例如 SQL 导出文件用 ';\n'，段落模式用 '\n\n'
+    ///
+    #[clap(
+        long,
+        value_parser = records::parse_separator,
+        conflicts_with_all = ["null_data", "csv", "json_path", "key_path", "delete_lines", "insert_before", "insert_after"],
+        global = true
+    )]
+    record_separator: Option<Regex>,
+    ///
+    /// 按字节匹配和替换，内容不是有效 UTF-8 的文件（混合编码、夹杂二进制的日志）也能处理，
+    /// 匹配之外的字节原样保留；不识别编码和 BOM，正则不能匹配无效的 UTF-8
+    ///
+    #[clap(
+        long,
+        conflicts_with_all = ["exec_replace", "script", "csv", "json_path", "key_path", "delete_lines", "insert_before", "insert_after", "record_separator", "normalize", "within_start", "front_matter", "body_only", "strip_bom", "add_bom"],
+        global = true
+    )]
+    bytes: bool,
+    ///
+    /// 按 UTF-8 处理的文件内容不是有效的 UTF-8 时：skip 跳过并在最后列出，
+    /// lossy 把无效的字节替换成 U+FFFD 后照常处理（写回的文件同样如此），error 报错
+    ///
+    #[clap(
+        long,
+        value_enum,
+        default_value = "error",
+        conflicts_with = "bytes",
+        global = true
+    )]
+    invalid_utf8: InvalidUtf8,
+    ///
+    /// 写回的文件去掉开头的 BOM，默认保留原文件的 BOM
+    ///
+    #[clap(long, global = true)]
+    strip_bom: bool,
+    ///
+    /// 写回的 UTF-8、UTF-16 文件总是带 BOM
+    ///
+    #[clap(long, conflicts_with = "strip_bom", global = true)]
+    add_bom: bool,
+    ///
+    /// 文件系统不支持 ACL 时跳过保留 ACL，而不是报错
+    ///
+    #[clap(long = "skip-unsupported-acl", global = true)]
+    skip_unsupported_acl: bool,
+    ///
+    /// 区域起始标记的正则，只替换起始标记和结束标记之间的内容
+    ///
+    #[clap(long = "within-start", requires = "within_end", global = true)]
+    within_start: Option<String>,
+    ///
+    /// 区域结束标记的正则
+    ///
+    #[clap(long = "within-end", requires = "within_start", global = true)]
+    within_end: Option<String>,
+    ///
+    /// 只替换 Markdown 文件开头的 front matter（--- 和 --- 之间的 YAML），没有 front matter 的文件不替换
+    ///
+    #[clap(
+        long,
+        conflicts_with_all = ["within_start", "body_only"],
+        global = true
+    )]
+    front_matter: bool,
+    ///
+    /// 只替换 front matter 之后的正文，没有 front matter 的文件整个替换
+    ///
+    #[clap(long, conflicts_with = "within_start", global = true)]
+    body_only: bool,
+    ///
+    /// 只替换每个文件中的第 N 处匹配，也可以是范围，例如 2..4，可以指定多次
+    ///
+    #[clap(long = "occurrence", value_parser = select::parse_occurrence, global = true)]
+    occurrence: Vec<NumberRange>,
+    ///
+    /// 保留每个文件中的前 N 处匹配，从之后的匹配开始替换
+    ///
+    #[clap(long = "skip", default_value_t = 0, global = true)]
+    skip: usize,
+    ///
+    /// 每个文件最多替换 N 处
+    ///
+    #[clap(long = "max-count", global = true)]
+    max_count: Option<usize>,
+    ///
+    /// 整次运行最多替换 N 处
+    ///
+    #[clap(long = "global-max", global = true)]
+    global_max: Option<usize>,
+    ///
+    /// 路径前缀映射，例如 /host=/mnt，可以指定多次
+    /// 输入的路径列表按宿主机路径给出，输出和报告中的路径也换算回宿主机路径
+    ///
+    #[clap(long = "path-prefix-map", value_parser = paths::parse_prefix_map, global = true)]
+    path_prefix_map: Vec<paths::PrefixMap>,
+    ///
+    /// 项目级配置文件，默认读取运行目录下的 .regex-replace.toml
+    ///
+    #[clap(long = "config", global = true)]
+    config: Option<PathBuf>,
+    ///
+    /// 写回之前检查替换后的 JSON、YAML、TOML 文件和文件编码是否仍然有效
+    /// warn 只报告改坏的文件，skip 报告并且不写回这些文件
+    ///
+    #[clap(long = "validate", value_enum, global = true)]
+    validate: Option<ValidateMode>,
+    ///
+    /// 临时文件所在的目录，默认放在目标文件所在的目录
+    ///
+    #[clap(long = "tmpdir", global = true)]
+    tmpdir: Option<PathBuf>,
+    ///
+    /// 跳过超过这个大小的文件，例如 10M，单位可以是 K、M、G，按 1024 计算
+    /// 避免把巨大的构建产物整个读进内存，跳过的文件在最后列出
+    ///
+    #[clap(long = "max-filesize", value_parser = metrics::parse_size, global = true)]
+    max_filesize: Option<u64>,
+    ///
+    /// 跳过有超过这个长度（字节）的行的文件，例如压缩过的 JS 和 JSON
+    /// 这类文件逐行替换时要把很长的一行整个读进内存，而且几乎不需要编辑，跳过的文件在最后列出
+    ///
+    #[clap(long = "max-line-length", global = true)]
+    max_line_length: Option<usize>,
+    ///
+    /// 同样替换生成的文件，默认跳过开头 5 行中有 @generated 或者 DO NOT EDIT 标记的文件，
+    /// 避免修改的内容被代码生成器覆盖，跳过的文件在最后列出
+    ///
+    #[clap(long = "include-generated", global = true)]
+    include_generated: bool,
+    ///
+    /// 编译后的正则大小上限，例如 100M，单位同 --max-filesize
+    /// 规则文件生成的巨大正则（上千个分支）超过默认的上限而无法编译时调大
+    ///
+    #[clap(long = "regex-size-limit", value_parser = metrics::parse_size, global = true)]
+    regex_size_limit: Option<u64>,
+    ///
+    /// 匹配时惰性 DFA 缓存的大小上限，例如 100M，巨大的正则匹配变慢时调大
+    ///
+    #[clap(long = "dfa-size-limit", value_parser = metrics::parse_size, global = true)]
+    dfa_size_limit: Option<u64>,
+    ///
+    /// 截断原文件并写入新内容，保持 inode 不变，硬链接和监视文件的程序（tail -f、编辑器）不受影响
+    /// 属主、权限、扩展属性自然保留；代价是写入时被中断会留下只写了一部分的文件
+    ///
+    #[clap(long = "in-place-truncate", global = true)]
+    in_place_truncate: bool,
+    ///
+    /// 写回时把临时文件刷到磁盘再重命名，之后再刷一次所在的目录，断电也不会留下被截断的文件
+    /// 在生产环境的部署脚本中使用，代价是每个文件都要等待磁盘写入
+    ///
+    #[clap(long, global = true)]
+    fsync: bool,
+    ///
+    /// 不检查临时文件所在的文件系统有没有足够的空间
+    /// 默认写每个有匹配的文件的临时文件之前按文件的大小估计需要的空间，不够时不再开始新的文件，
+    /// --atomic-run 时不修改任何文件
+    ///
+    #[clap(long = "no-space-check", global = true)]
+    no_space_check: bool,
+    ///
+    /// 确认要处理 -d 指定的目录：根目录、主目录或者文件数超过 --max-files 的目录默认拒绝运行
+    /// 没有 --yes 时遍历目录之前先数一遍文件，最多数到 --max-files 就停下
+    ///
+    #[clap(long, global = true)]
+    yes: bool,
+    ///
+    /// 没有 --yes 时，-d 指定的目录下最多允许的文件数
+    ///
+    #[clap(long = "max-files", global = true, default_value_t = 10000)]
+    max_files: usize,
+    ///
+    /// 要修改的文件超过 N 个时放弃运行，不修改任何文件，防止写错的正则匹配到意料之外的大量文件
+    /// 所有文件都替换完、确认没有超过上限之后才开始写回
+    ///
+    #[clap(
+        long = "abort-if-changed-files-exceed",
+        global = true,
+        value_name = "N"
+    )]
+    abort_changed_files: Option<usize>,
+    ///
+    /// 要替换的匹配超过 M 处时放弃运行，不修改任何文件
+    ///
+    #[clap(long = "abort-if-matches-exceed", global = true, value_name = "M")]
+    abort_matches: Option<usize>,
+    ///
+    /// 全部成功或者什么都不改：所有文件先替换到临时文件，全部成功之后才写回
+    /// 任何文件处理失败、没有通过 --validate 检查或者写回、重命名失败时，恢复已经写回的文件和重命名
+    /// 写回之前会备份每个目标文件，需要额外的磁盘空间
+    ///
+    #[clap(long = "atomic-run", global = true)]
+    atomic_run: bool,
+    ///
+    /// 写回时不检查文件在读取之后有没有被修改，直接覆盖
+    /// 默认读取时记录文件的大小和修改时间，写回之前发生变化的文件跳过写回
+    ///
+    #[clap(long, global = true)]
+    force: bool,
+    ///
+    /// 遇到第一个处理失败的文件就停止运行：不再开始新的文件，也不再写回后面的文件，已经写回的文件保持修改，要么全部修改要么都不修改时使用 --atomic-run
+    /// 默认继续处理其他文件，最后列出处理失败的文件并以非零状态退出
+    ///
+    #[clap(long = "fail-fast", global = true)]
+    fail_fast: bool,
+    ///
+    /// 缓存文件，记录检查过、没有任何匹配的文件，下次用同样的规则运行时跳过大小和修改时间都没变的文件
+    /// 反复对同一个目录运行同一次迁移时只需要检查改动过的文件
+    ///
+    #[clap(long, global = true, value_name = "FILE")]
+    cache: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    ///
+    /// 只扫描不修改，把待处理的文件和匹配概要写入队列文件
+    ///
+    Scan {
+        ///
+        /// 队列文件的输出路径
+        ///
+        #[clap(long = "queue")]
+        queue: PathBuf,
+    },
+    ///
+    /// 按队列文件执行替换，规则也从队列文件中读取
+    /// 扫描之后被修改过的文件会被跳过
+    ///
+    Apply {
+        ///
+        /// scan 生成的队列文件
+        ///
+        #[clap(long = "queue")]
+        queue: PathBuf,
+    },
+    ///
+    /// 只查找不替换，按 path:line:column: 匹配的文本 的格式输出每一处匹配，不需要 -r
+    /// 没有任何匹配时以非零状态退出
+    ///
+    Search,
+    ///
+    /// 全屏审阅每一处改动：左边是文件列表，右边是改动和高亮的匹配，逐段接受或拒绝之后再写回
+    /// 文件列表可以从标准输入读取，按键从终端读取，不是 UTF-8 的文件会被跳过
+    ///
+    Tui,
+    ///
+    /// 运行规则清单中每条规则的 tests，有失败的测试时以非零状态退出
+    /// -U、--dotall、--strategy 以及匹配的选择条件同样生效
+    ///
+    TestRules {
+        ///
+        /// .toml 格式的规则清单
+        ///
+        manifest: PathBuf,
+    },
+    ///
+    /// 清理崩溃或被强制结束的运行遗留的临时文件
+    /// 在 -d 指定的目录（默认为当前目录）和 --tmpdir 下查找
+    ///
+    Cleanup {
+        ///
+        /// 只列出要删除的文件，不实际删除
+        ///
+        #[clap(long = "dry-run")]
+        dry_run: bool,
+        ///
+        /// 同时删除 --atomic-run 遗留的备份（.regex-replace-backup-*），
+        /// 恢复失败时备份是原文件唯一的副本，确认不再需要之后才使用
+        ///
+        #[clap(long)]
+        backups: bool,
+    },
+    ///
+    /// 输出 shell 的补全脚本，例如 regex-replace completions bash > /etc/bash_completion.d/regex-replace
+    ///
+    Completions {
+        #[clap(value_enum)]
+        shell: Shell,
+    },
+}
+
+///
+/// 从版本库获取 dir 下属于集合的文件，路径和遍历目录时一样以 dir 开头
+///
+fn vcs_files(dir: &Path, set: FileSet) -> Vec<PathBuf> {
+    let (_, files) = vcs_entries(dir, set);
+    files
+        .into_iter()
+        //
+        // 子模块在 git 中是一个条目，但不是文件
+        //
+        .filter_map(|(_, file)| file.is_file().then_some(file))
+        .collect()
+}
+
+///
+/// 从 dir 所在的版本库获取文件列表，只保留 dir 下的文件
+/// 返回版本库和每个文件在版本库中的路径、在 dir 下的路径
+///
+fn vcs_entries(dir: &Path, set: FileSet) -> (Box<dyn vcs::Vcs>, Vec<(PathBuf, PathBuf)>) {
+    let Some(vcs) = vcs::detect(dir) else {
+        eprintln!("错误: 目录 {:?} 不在版本库中", paths::to_host(dir));
+        process::exit(1);
+    };
+    let files = match vcs.files(set) {
+        Ok(files) => files,
+        Err(err) => {
+            eprintln!("错误: 从 {} 获取文件列表失败: {}", vcs.name(), err);
+            process::exit(1);
+        }
+    };
+    //
+    // dir 在版本库中的位置，detect 成功时 dir 一定能规范化
+    //
+    let canonical = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    let prefix = canonical.strip_prefix(vcs.root()).unwrap_or(Path::new(""));
+    let files = files
+        .into_iter()
+        .filter_map(|file| {
+            let relative = file
+                .strip_prefix(vcs.root())
+                .ok()?
+                .strip_prefix(prefix)
+                .ok()?;
+            let path = dir.join(relative);
+            Some((file, path))
+        })
+        .collect();
+    (vcs, files)
+}
+
+impl Args {
+    fn parse_args() -> Self {
+        let mut args = Self::parse();
+        if args.directory.is_none() && args.files.is_none() {
+            args.paths_from_stdin = !atty::is(Stream::Stdin);
+            args.directory = Some(env::current_dir().unwrap());
+        }
+        if args.files.is_none() {
+            args.files = None
+        }
+        paths::set_prefix_maps(args.path_prefix_map.clone());
+        if let Some(tmpdir) = &args.tmpdir {
+            if !tmpdir.is_dir() {
+                eprintln!("错误: 临时文件目录 {:?} 不存在或不是一个目录", tmpdir);
+                process::exit(1);
+            }
+            install::set_temp_dir(tmpdir.clone());
+        }
+        if let Some(threads) = args.threads {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads.get())
+                .build_global()
+                .unwrap();
+        }
+        args.directory = args.directory.map(|dir| paths::to_local(&dir));
+        args.files = args
+            .files
+            .map(|files| files.iter().map(|file| paths::to_local(file)).collect());
+        //
+        // 除了 apply、test-rules 从文件读取规则和 cleanup、completions 不需要规则，其他情况都需要指定规则
+        // 这些参数是全局的，没法交给 clap 检查
+        //
+        if !matches!(
+            args.command,
+            Some(
+                Command::Apply { .. }
+                    | Command::TestRules { .. }
+                    | Command::Cleanup { .. }
+                    | Command::Completions { .. }
+            )
+        ) && args.pattern.is_none()
+            && args.rules_file.is_none()
+            && args.ident.is_empty()
+        {
+            Self::command()
+                .error(
+                    clap::error::ErrorKind::MissingRequiredArgument,
+                    "需要指定 --pattern 和 --replacement，或者 --rules-file、--ident",
+                )
+                .exit();
+        }
+        //
+        // 只有 search 和 --check 可以不指定替换字符串
+        //
+        if args.pattern.is_some()
+            && args.replacement.is_none()
+            && args.exec_replace.is_none()
+            && args.script.is_none()
+            && !args.delete_lines
+            && args.insert_before.is_none()
+            && args.insert_after.is_none()
+            && !matches!(args.command, Some(Command::Search))
+            && !args.check
+        {
+            Self::command()
+                .error(
+                    clap::error::ErrorKind::MissingRequiredArgument,
+                    "--pattern 需要同时指定 --replacement、--exec-replace、--script，或者按行编辑的 --delete-lines、--insert-before、--insert-after",
+                )
+                .exit();
+        }
+        if args.format != search::Format::Text
+            && !args.check
+            && !matches!(args.command, Some(Command::Search))
+        {
+            Self::command()
+                .error(
+                    clap::error::ErrorKind::ArgumentConflict,
+                    "--format 只能和 search 子命令或者 --check 一起使用",
+                )
+                .exit();
+        }
+        //
+        // 队列文件只记录替换字符串，记录不了外部命令、脚本和按行编辑
+        //
+        if (args.exec_replace.is_some() || args.script.is_some() || line_edit(&args).is_some())
+            && matches!(args.command, Some(Command::Scan { .. }))
+        {
+            Self::command()
+                .error(
+                    clap::error::ErrorKind::ArgumentConflict,
+                    "scan 不支持 --exec-replace、--script 和按行编辑",
+                )
+                .exit();
+        }
+        args.validate_paths();
+        args
+    }
+
+    fn validate_paths(&self) {
+        if let Some(dir) = &self.directory {
+            if !dir.exists() {
+                eprintln!("错误: 目录 {:?} 不存在", paths::to_host(dir));
+                process::exit(1);
+            }
+            if !dir.is_dir() {
+                eprintln!("错误: {:?} 不是一个目录", paths::to_host(dir));
+                process::exit(1);
+            }
+        }
+
+        if let Some(files) = &self.files {
+            for file in files {
+                if !file.exists() {
+                    eprintln!("错误: 文件 {:?} 不存在", paths::to_host(file));
+                    process::exit(1);
+                }
+                if !file.is_file() {
+                    eprintln!("错误: {:?} 不是一个文件", paths::to_host(file));
+                    process::exit(1);
+                }
+            }
+        }
+    }
+}
+
+///
+/// 按 -U、--dotall、-w、--smart-case 改写每条规则的正则，按 --preserve-case 设置替换模板
+/// 正则的选项直接写在正则里，语法树分析、流式替换和写入队列文件的正则都能看到它们
+///
+fn apply_rule_options(rules: Vec<Rule>, args: &Args, tokens: &Tokens) -> Result<Vec<Rule>, String> {
+    let rules = if args.multiline
+        || args.dotall
+        || args.word_regexp
+        || args.smart_case
+        || args.normalize.is_some()
+    {
+        rules
+            .iter()
+            .map(|rule| {
+                let pattern = pattern_with_options(rule.re.as_str(), args);
+                Rule::with_limits(&pattern, &rule.raw_replacement, tokens, regex_limits(args))
+            })
+            .collect::<Result<Vec<_>, _>>()?
+    } else {
+        rules
+    };
+    let mut rules = null_data_rules(rules, args)?;
+    if let Some(command) = &args.exec_replace {
+        for rule in &mut rules {
+            rule.replacement = Template::exec(command, &rule.re);
+        }
+    }
+    if let Some(source) = &args.script {
+        for rule in &mut rules {
+            rule.replacement = Template::script(source, &rule.re)?;
+        }
+    }
+    set_insert_text(&mut rules, args, tokens)?;
+    set_preserve_case(&mut rules, args);
+    Ok(rules)
+}
+
+///
+/// --bytes 时编译每条规则按字节匹配的版本
+///
+fn byte_rules(rules: &[Rule], args: &Args) -> Result<Option<ByteRules>, String> {
+    if !args.bytes {
+        return Ok(None);
+    }
+    ByteRules::new(rules, args.null_data, regex_limits(args)).map(Some)
+}
+
+///
+/// --regex-size-limit 和 --dfa-size-limit 指定的正则大小限制
+///
+fn regex_limits(args: &Args) -> RegexLimits {
+    let limit =
+        |limit: Option<u64>| limit.map(|limit| usize::try_from(limit).unwrap_or(usize::MAX));
+    RegexLimits {
+        size_limit: limit(args.regex_size_limit),
+        dfa_size_limit: limit(args.dfa_size_limit),
+    }
+}
+
+fn null_data_rules(rules: Vec<Rule>, args: &Args) -> Result<Vec<Rule>, String> {
+    if !args.null_data {
+        return Ok(rules);
+    }
+    rules
+        .into_iter()
+        .map(|rule| rule.null_data(regex_limits(args)))
+        .collect()
+}
+
+///
+/// --insert-before、--insert-after 插入的行作为每条规则的替换模板
+///
+fn set_insert_text(rules: &mut [Rule], args: &Args, tokens: &Tokens) -> Result<(), String> {
+    if let Some(text) = args.insert_before.as_ref().or(args.insert_after.as_ref()) {
+        for rule in rules {
+            rule.replacement = Template::parse(text, &rule.re, tokens)?;
+        }
+    }
+    Ok(())
+}
+
+fn set_preserve_case(rules: &mut [Rule], args: &Args) {
+    for rule in rules {
+        rule.replacement.set_preserve_case(args.preserve_case);
+    }
+}
+
+fn pattern_with_options(pattern: &str, args: &Args) -> String {
+    let pattern = match args.normalize {
+        Some(form) => form.apply(pattern),
+        None => pattern.to_string(),
+    };
+    let pattern = pattern.as_str();
+    let case_insensitive = args.smart_case && analysis::smart_case_insensitive(pattern);
+    //
+    // 先分组再加 \b，否则 a|b 会变成 \ba|b\b
+    //
+    let pattern = if args.word_regexp {
+        format!(r"\b(?:{})\b", pattern)
+    } else {
+        pattern.to_string()
+    };
+    let mut flags = String::new();
+    if args.multiline {
+        flags.push_str("mR");
+    }
+    if args.dotall {
+        flags.push('s');
+    }
+    if case_insensitive {
+        flags.push('i');
+    }
+    if flags.is_empty() {
+        pattern
+    } else {
+        format!("(?{}){}", flags, pattern)
+    }
+}
+
+fn bom_mode(args: &Args) -> BomMode {
+    if args.strip_bom {
+        BomMode::Strip
+    } else if args.add_bom {
+        BomMode::Add
+    } else {
+        BomMode::Preserve
+    }
+}
+
+fn line_edit(args: &Args) -> Option<LineEdit> {
+    if args.insert_before.is_some() {
+        return Some(LineEdit::InsertBefore);
+    }
+    if args.insert_after.is_some() {
+        return Some(LineEdit::InsertAfter);
+    }
+    match (args.delete_lines, args.invert) {
+        (false, _) => None,
+        (true, false) => Some(LineEdit::Delete),
+        (true, true) => Some(LineEdit::Keep),
+    }
+}
+
+fn csv_options(args: &Args) -> Option<CsvOptions> {
+    let column = args.column.clone()?;
+    Some(CsvOptions {
+        column,
+        delimiter: args.delimiter,
+    })
+}
+
+///
+/// 按命令行参数组装替换计划，apply 和 test-rules 的规则来源不同，其他选项都一样
+///
+fn plan(
+    args: &Args,
+    rules: Vec<Rule>,
+    bytes: Option<ByteRules>,
+    selection: Selection,
+    config: Config,
+) -> ReplacePlan {
+    ReplacePlan {
+        rules,
+        selection,
+        config,
+        mmap: args.mmap,
+        strategy: args.strategy,
+        csv: csv_options(args),
+        json_path: args.json_path.clone(),
+        key_path: args.key_path.clone(),
+        max_filesize: args.max_filesize,
+        max_line_length: args.max_line_length,
+        include_generated: args.include_generated,
+        line_edit: line_edit(args),
+        normalize: args.normalize,
+        null_data: args.null_data,
+        record_separator: args.record_separator.clone(),
+        bom: bom_mode(args),
+        prefilter: Prefilter::default(),
+        bytes,
+        invalid_utf8: args.invalid_utf8,
+        regex_limits: regex_limits(args),
+    }
+}
+
+///
+/// 指定了 --prioritize-matches 时按匹配数排序文件
+///
+fn prioritize(args: &Args, files: Vec<PathBuf>, plan: &ReplacePlan) -> Vec<PathBuf> {
+    if args.prioritize_matches {
+        priority::prioritize(files, plan)
+    } else {
+        files
+    }
+}
+
+///
+/// 按命令行参数准备运行中和运行后的输出
+///
+fn reporting(args: &Args, files_total: usize) -> Reporting {
+    let progress = args
+        .progress_fd
+        .map(|fd| match Progress::open(fd, files_total) {
+            Ok(progress) => progress,
+            Err(err) => {
+                eprintln!("错误: 无法使用文件描述符 {} 输出进度: {}", fd, err);
+                process::exit(1);
+            }
+        });
+    let mut reporting = Reporting::new(progress);
+    reporting.errors = true;
+    reporting.stat = args.stat;
+    reporting.report = args.report;
+    reporting.resource_usage = args.resource_usage;
+    reporting.timings = args.timings;
+    reporting.timings_json = args.timings_json;
+    reporting.patch = args.output_patch.clone();
+    reporting
+}
+
+///
+/// 替换文件并写回，然后执行重命名，写回或者重命名失败时退出
+///
+fn replace_files(
+    args: &Args,
+    files: &[PathBuf],
+    plan: &ReplacePlan,
+    options: &WriteOptions,
+    renames: &[Rename],
+) {
+    let cache = args.cache.as_ref().map(|path| Cache::load(path, plan));
+    let replacer = Replacer::new(plan, options)
+        .reporting(reporting(args, files.len()))
+        .renames(renames)
+        .cache(cache.as_ref());
+    let summary = replacer.replace_files(files);
+    summary.print();
+    if let Err(err) = summary.result() {
+        eprintln!("{}", err);
+        process::exit(exit_code());
+    }
+}
+
+///
+/// 运行失败时的退出状态，因为中断信号停下时和 shell 的约定一样使用 130
+///
+fn exit_code() -> i32 {
+    if interrupt::requested() {
+        130
+    } else {
+        1
+    }
+}
+
+///
+/// 读取文件路径列表，每行一个路径，null 时以 NUL 分隔，空行会被忽略
+/// source 是出错时报告的来源
+///
+fn read_path_list(reader: impl BufRead, null: bool, source: &str) -> Vec<PathBuf> {
+    let delimiter = if null { b'\0' } else { b'\n' };
+    let mut files = Vec::new();
+    for path in reader.split(delimiter) {
+        let mut path = match path {
+            Ok(path) => path,
+            Err(err) => {
+                eprintln!("错误: 读取{}失败: {}", source, err);
+                process::exit(1);
+            }
+        };
+        if !null && path.last() == Some(&b'\r') {
+            path.pop();
+        }
+        if !path.is_empty() {
+            files.push(paths::to_local(&paths::from_bytes(&path)));
+        }
+    }
+    files
+}
+
+///
+/// 多线程遍历目录，找到的文件直接交给替换，不等遍历结束
+/// 遍历出错时已经找到的文件照常替换，最后以非零状态退出
+///
+fn replace_walked(args: &Args, root: &Path, plan: &ReplacePlan, options: &WriteOptions) {
+    let walk = walk_options(args);
+    let cache = args.cache.as_ref().map(|path| Cache::load(path, plan));
+    let (sender, receiver) = mpsc::channel();
+    let (walked, summary) = thread::scope(|scope| {
+        let walker = scope.spawn(move || {
+            walk.walk_parallel(root, |file| {
+                let _ = sender.send(file);
+            })
+        });
+        let replacer = Replacer::new(plan, options)
+            .reporting(reporting(args, 0))
+            .cache(cache.as_ref());
+        let summary = replacer.replace_stream(receiver.into_iter());
+        (walker.join().unwrap(), summary)
+    });
+    summary.print();
+    if let Err(err) = summary.result() {
+        eprintln!("{}", err);
+        process::exit(exit_code());
+    }
+    if let Err(err) = walked {
+        eprintln!("错误: {}", err);
+        process::exit(1);
+    }
+}
+
+///
+/// 修改 -d 指定的目录之前确认它不是根目录或者主目录，在遍历之前检查
+///
+fn guard_root(root: &Path) -> Result<(), String> {
+    let resolved = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    let home = env::var_os(if cfg!(windows) { "USERPROFILE" } else { "HOME" })
+        .and_then(|home| Path::new(&home).canonicalize().ok());
+    let kind = if resolved.parent().is_none() {
+        "根目录"
+    } else if home.as_deref() == Some(resolved.as_path()) {
+        "主目录"
+    } else {
+        return Ok(());
+    };
+    Err(format!(
+        "{:?} 是{}，确认要修改其中的文件时加上 --yes",
+        paths::to_host(&resolved),
+        kind
+    ))
+}
+
+///
+/// 遍历之后确认文件数没有超过 --max-files，通过检查时输出解析后的目录和文件数
+///
+fn guard_count(root: &Path, count: usize, max_files: usize) -> Result<(), String> {
+    let resolved = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    let host_root = paths::to_host(&resolved);
+    if count > max_files {
+        return Err(format!(
+            "{:?} 下的文件超过了 --max-files {}，确认要修改这些文件时加上 --yes",
+            host_root, max_files
+        ));
+    }
+    eprintln!("处理 {:?} 下的 {} 个文件", host_root, count);
+    Ok(())
+}
+
+fn walk_options(args: &Args) -> WalkOptions {
+    WalkOptions {
+        ignore_files: args.ignore_files.clone(),
+    }
+}
+
+///
+/// 按命令行参数运行，出错时直接退出进程
+///
+pub fn run() {
+    //
+    // 读取 umask 会临时把它设成 0，要在启动任何线程之前完成
+    //
+    install::init_umask();
+    let args = Args::parse_args();
+    if let Err(err) = stop::install(args.stop_file.clone()) {
+        eprintln!("错误: {}", err);
+        process::exit(1);
+    }
+    interrupt::install();
+
+    if let Some(Command::Completions { shell }) = &args.command {
+        let mut command = Args::command();
+        command.build();
+        print!("{}", completions::generate(*shell, &command));
+        return;
+    }
+
+    if let Some(Command::Cleanup { dry_run, backups }) = &args.command {
+        let dirs: Vec<PathBuf> = args
+            .directory
+            .iter()
+            .chain(args.tmpdir.iter())
+            .cloned()
+            .collect();
+        cleanup::cleanup(&dirs, *dry_run, *backups);
+        return;
+    }
+
+    let tokens = Tokens::new(args.token_scope).env(args.env);
+    let write_options = WriteOptions {
+        chmod: args.chmod,
+        preserve: args.preserve.clone(),
+        skip_unsupported_acl: args.skip_unsupported_acl,
+        validate: args.validate,
+        in_place: args.in_place_truncate,
+        atomic: args.atomic_run,
+        force: args.force,
+        fail_fast: args.fail_fast,
+        fsync: args.fsync,
+        check_space: !args.no_space_check,
+        abort_changed_files: args.abort_changed_files,
+        abort_matches: args.abort_matches,
+    };
+    let within = match (&args.within_start, &args.within_end) {
+        (Some(start), Some(end)) => {
+            let limits = regex_limits(&args);
+            match (limits.builder(start).build(), limits.builder(end).build()) {
+                (Ok(start), Ok(end)) => Some((start, end)),
+                (Err(err), _) | (_, Err(err)) => {
+                    eprintln!("错误: 无效的区域标记正则: {}", err);
+                    process::exit(1);
+                }
+            }
+        }
+        _ => None,
+    };
+    let front_matter = if args.front_matter {
+        Some(FrontMatter::Only)
+    } else if args.body_only {
+        Some(FrontMatter::BodyOnly)
+    } else {
+        None
+    };
+    let selection = Selection {
+        lines: args.lines.clone(),
+        within,
+        front_matter,
+        occurrences: args.occurrence.clone(),
+        skip: args.skip,
+        max_count: args.max_count,
+        global_max: args.global_max,
+        ..Default::default()
+    };
+    let config = match Config::discover(args.config.as_deref()) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("错误: {}", err);
+            process::exit(1);
+        }
+    };
+
+    if let Some(Command::TestRules { manifest }) = &args.command {
+        let rules =
+            rules::load_rule_tests(manifest, &tokens, regex_limits(&args)).and_then(|rules| {
+                rules
+                    .into_iter()
+                    .map(|(rule, tests)| {
+                        let rule = apply_rule_options(vec![rule], &args, &tokens)?.remove(0);
+                        Ok((rule, tests))
+                    })
+                    .collect::<Result<Vec<_>, String>>()
+            });
+        let rules = match rules {
+            Ok(rules) => rules,
+            Err(err) => {
+                eprintln!("错误: {}", err);
+                process::exit(1);
+            }
+        };
+        let bytes = args.bytes.then(ByteRules::default);
+        let plan = plan(&args, Vec::new(), bytes, selection, config);
+        if !ruletest::run(rules, &plan) {
+            process::exit(1);
+        }
+        return;
+    }
+
+    //
+    // apply 的文件列表和规则都来自队列文件
+    //
+    if let Some(Command::Apply { queue }) = &args.command {
+        let loaded =
+            queue::load_queue(queue, &tokens, regex_limits(&args)).and_then(|(rules, files)| {
+                let mut rules = null_data_rules(rules, &args)?;
+                set_insert_text(&mut rules, &args, &tokens)?;
+                Ok((rules, files))
+            });
+        match loaded {
+            Ok((mut rules, files)) => {
+                //
+                // 正则的选项在扫描时已经写进了队列文件里的正则
+                //
+                set_preserve_case(&mut rules, &args);
+                let bytes = match byte_rules(&rules, &args) {
+                    Ok(bytes) => bytes,
+                    Err(err) => {
+                        eprintln!("错误: {}", err);
+                        process::exit(1);
+                    }
+                };
+                let plan = plan(&args, rules, bytes, selection, config);
+                let files = paths::dedup(files);
+                let files = prioritize(&args, files, &plan);
+                replace_files(&args, &files, &plan, &write_options, &[]);
+            }
+            Err(err) => {
+                eprintln!("错误: {}", err);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let mut files = Vec::new();
+    //
+    // 遍历的目录，--rename 会重命名它下面的目录
+    //
+    let mut walked_root = None;
+    //
+    // 普通的替换不需要完整的文件列表，遍历目录时边找边替换
+    // 重命名、按匹配数排序、输出进度和各个子命令都要先拿到所有文件
+    // --atomic-run 要在写回之前确认遍历没有出错，同样先拿到所有文件
+    //
+    let streaming = args.command.is_none()
+        && !args.atomic_run
+        && !args.estimate
+        && !args.matrix
+        && !args.check
+        && !args.rename
+        && !args.rename_only
+        && !args.prioritize_matches
+        && args.progress_fd.is_none();
+    let mut streamed_root = None;
+    //
+    // 会修改文件、没有 --yes 时检查 -d 指定的目录
+    //
+    let guarded =
+        !args.yes && args.command.is_none() && !args.estimate && !args.matrix && !args.check;
+    let check_root = |directory: &Path| {
+        if !guarded {
+            return;
+        }
+        if let Err(err) = guard_root(directory) {
+            eprintln!("错误: {}", err);
+            process::exit(1);
+        }
+    };
+
+    if args.staged {
+        //
+        // --staged 的文件列表来自暂存区，替换计划准备好之后再获取
+        //
+    } else if let (Some(set), Some(directory)) = (args.git, &args.directory) {
+        check_root(directory);
+        files.extend(vcs_files(directory, set));
+        walked_root = Some(directory.as_path());
+    } else if let Some(list) = &args.files_from {
+        if list == Path::new("-") {
+            files.extend(read_path_list(io::stdin().lock(), args.null, "标准输入"));
+        } else {
+            match File::open(list) {
+                Ok(file) => files.extend(read_path_list(
+                    BufReader::new(file),
+                    args.null,
+                    &format!("{:?}", list),
+                )),
+                Err(err) => {
+                    eprintln!("错误: 无法打开文件列表 {:?}: {}", list, err);
+                    process::exit(1);
+                }
+            }
+        }
+    } else if args.paths_from_stdin {
+        //
+        // 管道输入，接受的是一个文件路径列表，-0 时以 NUL 分隔
+        //
+        files.extend(read_path_list(io::stdin().lock(), args.null, "标准输入"));
+    } else {
+        if args.directory.is_some() {
+            if let Some(directory) = &args.directory {
+                check_root(directory);
+                if streaming {
+                    streamed_root = Some(directory.as_path());
+                } else {
+                    match walk_options(&args).walk(directory) {
+                        Ok(walked) => files.extend(walked),
+                        Err(err) => {
+                            eprintln!("错误: {}", err);
+                            process::exit(1);
+                        }
+                    }
+                    walked_root = Some(directory.as_path());
+                }
+            }
+        }
+
+        if let Some(file_paths) = &args.files {
+            files.extend(file_paths.iter().cloned());
+        }
+    }
+
+    let files = paths::dedup(files);
+
+    let rules = match &args.rules_file {
+        Some(rules_file) => {
+            match rules::load_rules_file(rules_file, &tokens, regex_limits(&args)) {
+                Ok(rules) => rules,
+                Err(err) => {
+                    eprintln!("错误: {}", err);
+                    process::exit(1);
+                }
+            }
+        }
+        None if !args.ident.is_empty() => {
+            match ident::rules(&args.ident, &tokens, regex_limits(&args)) {
+                Ok(rules) => rules,
+                Err(err) => {
+                    eprintln!("错误: {}", err);
+                    process::exit(1);
+                }
+            }
+        }
+        None => {
+            let pattern = args.pattern.as_deref().unwrap_or_default();
+            let replacement = args.replacement.as_deref().unwrap_or_default();
+            match Rule::with_limits(pattern, replacement, &tokens, regex_limits(&args)) {
+                Ok(rule) => vec![rule],
+                Err(err) => {
+                    eprintln!("错误: {}", err);
+                    process::exit(1);
+                }
+            }
+        }
+    };
+
+    let rules = match apply_rule_options(rules, &args, &tokens) {
+        Ok(rules) => rules,
+        Err(err) => {
+            eprintln!("错误: {}", err);
+            process::exit(1);
+        }
+    };
+
+    let bytes = match byte_rules(&rules, &args) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            eprintln!("错误: {}", err);
+            process::exit(1);
+        }
+    };
+
+    let plan = plan(&args, rules, bytes, selection, config);
+    if args.staged {
+        let directory = args.directory.clone().unwrap_or_default();
+        let (vcs, files) = vcs_entries(&directory, FileSet::Staged);
+        let files: Vec<_> = files
+            .into_iter()
+            .filter(|(_, path)| !path.is_dir())
+            .collect();
+        if !staged::replace_staged(vcs.as_ref(), &files, &plan) {
+            process::exit(1);
+        }
+        return;
+    }
+
+    if args.estimate {
+        estimate::estimate(&files, &plan);
+        return;
+    }
+
+    if args.check {
+        process::exit(search::check(&files, &plan, args.format));
+    }
+
+    if let Some(Command::Search) = &args.command {
+        if !search::search(&files, &plan, args.format) {
+            process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(Command::Tui) = &args.command {
+        if !tui::review(&files, &plan, &write_options) {
+            process::exit(1);
+        }
+        return;
+    }
+
+    if args.matrix {
+        let root = args.directory.clone().unwrap_or_default();
+        matrix::matrix(&files, &plan, &root);
+        return;
+    }
+
+    if let Some(Command::Scan { queue }) = &args.command {
+        if let Err(err) = queue::write_queue(queue, &files, &plan) {
+            eprintln!("错误: {}", err);
+            process::exit(1);
+        }
+        return;
+    }
+
+    if let (true, Some(root)) = (guarded, walked_root) {
+        if let Err(err) = guard_count(root, files.len(), args.max_files) {
+            eprintln!("错误: {}", err);
+            process::exit(1);
+        }
+    }
+    //
+    // 边遍历边替换时先数一遍文件，超过 --max-files 就停下，不需要拿到完整的文件列表
+    //
+    if let (true, Some(root)) = (guarded, streamed_root) {
+        let count = walk_options(&args).count(root, args.max_files);
+        if let Err(err) = guard_count(root, count, args.max_files) {
+            eprintln!("错误: {}", err);
+            process::exit(1);
+        }
+    }
+
+    //
+    // 在修改任何文件之前检查重命名的冲突
+    //
+    let renames = if args.rename || args.rename_only {
+        match rename::plan(&files, walked_root, &plan.rules, plan.normalize) {
+            Ok(renames) => renames,
+            Err(errors) => {
+                for err in errors {
+                    eprintln!("错误: {}", err);
+                }
+                process::exit(1);
+            }
+        }
+    } else {
+        Vec::new()
+    };
+
+    if let Some(root) = streamed_root {
+        replace_walked(&args, root, &plan, &write_options);
+    } else if !args.rename_only {
+        let files = prioritize(&args, files, &plan);
+        replace_files(&args, &files, &plan, &write_options, &renames);
+    } else {
+        let _writing = interrupt::writing();
+        if let Err(err) = rename::apply(&renames, args.atomic_run) {
+            eprintln!("错误: {}", err);
+            process::exit(exit_code());
+        }
+    }
+}
@@ -0,0 +1,358 @@
+//!
+//! completions 子命令：按命令行的定义生成 bash、zsh、fish、PowerShell 的补全脚本
+//! 可选值固定的参数（例如 --strategy）补全这些值，其他带值的参数补全文件路径
+//!
+
+use clap::{Arg, Command, ValueEnum};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+}
+
+///
+/// 补全脚本需要的一个选项
+///
+struct Opt {
+    short: Option<char>,
+    long: Option<String>,
+    help: String,
+    takes_value: bool,
+    values: Vec<String>,
+}
+
+impl Opt {
+    fn new(arg: &Arg) -> Option<Self> {
+        if arg.is_positional() || arg.is_hide_set() {
+            return None;
+        }
+        Some(Self {
+            short: arg.get_short(),
+            long: arg.get_long().map(str::to_string),
+            help: arg
+                .get_help()
+                .map(|help| help.to_string())
+                .unwrap_or_default()
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+            takes_value: arg.get_action().takes_values(),
+            values: arg
+                .get_possible_values()
+                .iter()
+                .filter(|value| !value.is_hide_set())
+                .map(|value| value.get_name().to_string())
+                .collect(),
+        })
+    }
+
+    fn flags(&self) -> Vec<String> {
+        self.short
+            .map(|short| format!("-{}", short))
+            .into_iter()
+            .chain(self.long.iter().map(|long| format!("--{}", long)))
+            .collect()
+    }
+}
+
+fn options(command: &Command) -> Vec<Opt> {
+    command.get_arguments().filter_map(Opt::new).collect()
+}
+
+///
+/// 子命令和它自己的选项，全局选项不重复列出
+///
+struct Sub {
+    name: String,
+    about: String,
+    options: Vec<Opt>,
+    ///
+    /// 位置参数的可选值，例如 completions 的 shell
+    ///
+    values: Vec<String>,
+}
+
+fn subcommands(command: &Command) -> Vec<Sub> {
+    command
+        .get_subcommands()
+        .filter(|sub| !sub.is_hide_set())
+        .map(|sub| Sub {
+            name: sub.get_name().to_string(),
+            about: sub
+                .get_about()
+                .map(|about| about.to_string())
+                .unwrap_or_default(),
+            options: sub
+                .get_arguments()
+                .filter(|arg| !arg.is_global_set())
+                .filter_map(Opt::new)
+                .collect(),
+            values: sub
+                .get_positionals()
+                .flat_map(|arg| arg.get_possible_values())
+                .filter(|value| !value.is_hide_set())
+                .map(|value| value.get_name().to_string())
+                .collect(),
+        })
+        .collect()
+}
+
+///
+/// 生成补全脚本
+///
+pub fn generate(shell: Shell, command: &Command) -> String {
+    let name = command.get_name();
+    let options = options(command);
+    let subcommands = subcommands(command);
+    match shell {
+        Shell::Bash => bash(name, &options, &subcommands),
+        Shell::Zsh => zsh(name, &options, &subcommands),
+        Shell::Fish => fish(name, &options, &subcommands),
+        Shell::Powershell => powershell(name, &options, &subcommands),
+    }
+}
+
+///
+/// 按前一个词补全选项的值：有可选值时补全可选值，否则补全文件路径
+///
+fn bash_values(options: &[Opt], indent: &str) -> String {
+    let mut script = String::new();
+    for opt in options.iter().filter(|opt| opt.takes_value) {
+        let completion = if opt.values.is_empty() {
+            "compgen -f -- \"$cur\"".to_string()
+        } else {
+            format!("compgen -W \"{}\" -- \"$cur\"", opt.values.join(" "))
+        };
+        script.push_str(&format!(
+            "{indent}{})\n{indent}    COMPREPLY=($({}))\n{indent}    return\n{indent}    ;;\n",
+            opt.flags().join("|"),
+            completion
+        ));
+    }
+    script
+}
+
+fn bash(name: &str, options: &[Opt], subcommands: &[Sub]) -> String {
+    let function = format!("_{}", name.replace('-', "_"));
+    let names: Vec<&str> = subcommands.iter().map(|sub| sub.name.as_str()).collect();
+    let flags: Vec<String> = options.iter().flat_map(Opt::flags).collect();
+
+    let mut script = format!(
+        "{function}() {{\n    \
+         local cur prev subcommand word\n    \
+         cur=\"${{COMP_WORDS[COMP_CWORD]}}\"\n    \
+         prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"\n    \
+         subcommand=\"\"\n    \
+         for word in \"${{COMP_WORDS[@]:1:COMP_CWORD-1}}\"; do\n        \
+         case \"$word\" in\n            \
+         {}) subcommand=\"$word\"; break ;;\n        \
+         esac\n    \
+         done\n\n    \
+         case \"$prev\" in\n",
+        names.join("|")
+    );
+    script.push_str(&bash_values(options, "        "));
+    for sub in subcommands {
+        script.push_str(&bash_values(&sub.options, "        "));
+    }
+    script.push_str("    esac\n\n");
+    script.push_str(&format!("    local words=\"{}\"\n", flags.join(" ")));
+    script.push_str("    case \"$subcommand\" in\n");
+    script.push_str(&format!(
+        "        \"\") words=\"$words {}\" ;;\n",
+        names.join(" ")
+    ));
+    for sub in subcommands {
+        let words: Vec<String> = sub
+            .options
+            .iter()
+            .flat_map(Opt::flags)
+            .chain(sub.values.iter().cloned())
+            .collect();
+        if !words.is_empty() {
+            script.push_str(&format!(
+                "        {}) words=\"$words {}\" ;;\n",
+                sub.name,
+                words.join(" ")
+            ));
+        }
+    }
+    script.push_str("    esac\n");
+    script.push_str("    COMPREPLY=($(compgen -W \"$words\" -- \"$cur\"))\n}\n\n");
+    script.push_str(&format!(
+        "complete -F {} -o bashdefault -o default {}\n",
+        function, name
+    ));
+    script
+}
+
+///
+/// zsh 的 _arguments 描述中 [ ] : 和单引号需要转义
+///
+fn zsh_escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('\'', "'\\''")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+        .replace(':', "\\:")
+}
+
+fn zsh_specs(options: &[Opt], indent: &str) -> String {
+    let mut specs = String::new();
+    for opt in options {
+        let value = if !opt.takes_value {
+            String::new()
+        } else if opt.values.is_empty() {
+            ":value:_files".to_string()
+        } else {
+            format!(":value:({})", opt.values.join(" "))
+        };
+        for flag in opt.flags() {
+            specs.push_str(&format!(
+                "{}'{}[{}]{}' \\\n",
+                indent,
+                flag,
+                zsh_escape(&opt.help),
+                value
+            ));
+        }
+    }
+    specs
+}
+
+fn zsh(name: &str, options: &[Opt], subcommands: &[Sub]) -> String {
+    let function = format!("_{}", name.replace('-', "_"));
+    let mut script = format!(
+        "#compdef {name}\n\n{function}() {{\n    local context state line\n    local -a commands\n    _arguments -C \\\n"
+    );
+    script.push_str(&zsh_specs(options, "        "));
+    script.push_str("        '1: :->command' \\\n        '*:: :->args'\n\n");
+    script.push_str("    case $state in\n    command)\n        commands=(\n");
+    for sub in subcommands {
+        script.push_str(&format!(
+            "            '{}:{}'\n",
+            sub.name,
+            sub.about.replace('\'', "'\\''").replace(':', "\\:")
+        ));
+    }
+    script.push_str("        )\n        _describe 'command' commands\n        ;;\n");
+    script.push_str("    args)\n        case $words[1] in\n");
+    for sub in subcommands {
+        script.push_str(&format!(
+            "        {})\n            _arguments \\\n",
+            sub.name
+        ));
+        script.push_str(&zsh_specs(&sub.options, "                "));
+        if sub.values.is_empty() {
+            script.push_str("                '*:file:_files'\n            ;;\n");
+        } else {
+            script.push_str(&format!(
+                "                '1:value:({})'\n            ;;\n",
+                sub.values.join(" ")
+            ));
+        }
+    }
+    script.push_str("        esac\n        ;;\n    esac\n}\n\n");
+    script.push_str(&format!("{function} \"$@\"\n"));
+    script
+}
+
+fn fish_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+fn fish_options(name: &str, options: &[Opt], condition: &str) -> String {
+    let mut script = String::new();
+    for opt in options {
+        let mut line = format!("complete -c {}", name);
+        if !condition.is_empty() {
+            line.push_str(&format!(" -n '{}'", condition));
+        }
+        if let Some(short) = opt.short {
+            line.push_str(&format!(" -s {}", short));
+        }
+        if let Some(long) = &opt.long {
+            line.push_str(&format!(" -l {}", long));
+        }
+        if opt.takes_value {
+            if opt.values.is_empty() {
+                line.push_str(" -r -F");
+            } else {
+                line.push_str(&format!(" -x -a '{}'", opt.values.join(" ")));
+            }
+        }
+        if !opt.help.is_empty() {
+            line.push_str(&format!(" -d '{}'", fish_escape(&opt.help)));
+        }
+        script.push_str(&line);
+        script.push('\n');
+    }
+    script
+}
+
+fn fish(name: &str, options: &[Opt], subcommands: &[Sub]) -> String {
+    let mut script = fish_options(name, options, "");
+    for sub in subcommands {
+        script.push_str(&format!(
+            "complete -c {} -n '__fish_use_subcommand' -f -a {} -d '{}'\n",
+            name,
+            sub.name,
+            fish_escape(&sub.about)
+        ));
+    }
+    for sub in subcommands {
+        let condition = format!("__fish_seen_subcommand_from {}", sub.name);
+        script.push_str(&fish_options(name, &sub.options, &condition));
+        if !sub.values.is_empty() {
+            script.push_str(&format!(
+                "complete -c {} -n '{}' -f -a '{}'\n",
+                name,
+                condition,
+                sub.values.join(" ")
+            ));
+        }
+    }
+    script
+}
+
+fn powershell_escape(text: &str) -> String {
+    text.replace('\'', "''")
+}
+
+fn powershell(name: &str, options: &[Opt], subcommands: &[Sub]) -> String {
+    let mut script = format!(
+        "using namespace System.Management.Automation\n\n\
+         Register-ArgumentCompleter -Native -CommandName '{name}' -ScriptBlock {{\n    \
+         param($wordToComplete, $commandAst, $cursorPosition)\n    \
+         $completions = @(\n"
+    );
+    let mut add = |text: &str, kind: &str, help: &str| {
+        let help = if help.is_empty() { text } else { help };
+        script.push_str(&format!(
+            "        [CompletionResult]::new('{}', '{}', [CompletionResultType]::{}, '{}')\n",
+            powershell_escape(text),
+            powershell_escape(text),
+            kind,
+            powershell_escape(help)
+        ));
+    };
+    for sub in subcommands {
+        add(&sub.name, "ParameterValue", &sub.about);
+    }
+    let sub_options = subcommands.iter().flat_map(|sub| &sub.options);
+    for opt in options.iter().chain(sub_options) {
+        for flag in opt.flags() {
+            add(&flag, "ParameterName", &opt.help);
+        }
+    }
+    script.push_str(
+        "    )\n    \
+         $completions.Where{ $_.CompletionText -like \"$wordToComplete*\" } |\n        \
+         Sort-Object -Property ListItemText -Unique\n}\n",
+    );
+    script
+}
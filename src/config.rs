@@ -0,0 +1,127 @@
+use globset::{Glob, GlobMatcher};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::encoding::Encoding;
+use crate::toml::{self, Value};
+
+///
+/// 项目级配置文件的默认文件名，放在运行目录下
+///
+pub const CONFIG_FILE: &str = ".regex-replace.toml";
+
+///
+/// 项目级配置
+///
+/// ```toml
+/// [encodings]
+/// "legacy/**/*.txt" = "latin1"
+/// "*.utf16.txt" = "utf-16le"
+/// ```
+///
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    ///
+    /// 配置文件所在目录，glob 按相对于这个目录的路径匹配
+    ///
+    root: PathBuf,
+    ///
+    /// glob 到编码的映射，先写的优先
+    ///
+    encodings: Vec<(GlobMatcher, Encoding)>,
+}
+
+impl Config {
+    ///
+    /// 指定了配置文件时读取它，否则读取运行目录下的 .regex-replace.toml（如果存在）
+    ///
+    pub fn discover(path: Option<&Path>) -> Result<Self, String> {
+        match path {
+            Some(path) => Self::load(path),
+            None => {
+                let path = Path::new(CONFIG_FILE);
+                if path.is_file() {
+                    Self::load(path)
+                } else {
+                    Ok(Self::default())
+                }
+            }
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| format!("读取配置文件 {:?} 失败: {}", path, err))?;
+        let document =
+            toml::parse(&contents).map_err(|err| format!("配置文件 {:?} {}", path, err))?;
+        let error = |line: usize, message: String| {
+            format!("配置文件 {:?} 第 {} 行: {}", path, line, message)
+        };
+
+        let root = absolute(path)
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        let mut config = Self {
+            root,
+            ..Self::default()
+        };
+
+        for (key, spanned) in &document.entries {
+            match (key.as_str(), &spanned.value) {
+                ("encodings", Value::Table(table)) => {
+                    for (glob, encoding) in &table.entries {
+                        let Value::String(name) = &encoding.value else {
+                            return Err(error(
+                                encoding.line,
+                                format!("编码应该是字符串，而不是{}", encoding.value.type_name()),
+                            ));
+                        };
+                        let matcher = Glob::new(glob)
+                            .map_err(|err| error(encoding.line, format!("无效的 glob: {}", err)))?
+                            .compile_matcher();
+                        let encoding =
+                            Encoding::parse(name).map_err(|err| error(encoding.line, err))?;
+                        config.encodings.push((matcher, encoding));
+                    }
+                }
+                ("encodings", value) => {
+                    return Err(error(
+                        spanned.line,
+                        format!("encodings 应该是表，而不是{}", value.type_name()),
+                    ))
+                }
+                (key, _) => return Err(error(spanned.line, format!("未知的配置项 {}", key))),
+            }
+        }
+
+        Ok(config)
+    }
+
+    ///
+    /// 文件使用的编码，没有匹配的 glob 时是 UTF-8
+    ///
+    pub fn encoding_for(&self, file: &Path) -> Encoding {
+        if self.encodings.is_empty() {
+            return Encoding::Utf8;
+        }
+        let file = absolute(file);
+        let relative = file.strip_prefix(&self.root).unwrap_or(&file);
+        self.encodings
+            .iter()
+            .find(|(matcher, _)| matcher.is_match(relative))
+            .map(|(_, encoding)| *encoding)
+            .unwrap_or(Encoding::Utf8)
+    }
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        env::current_dir()
+            .map(|dir| dir.join(path))
+            .unwrap_or_else(|_| path.to_path_buf())
+    }
+}
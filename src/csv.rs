@@ -0,0 +1,246 @@
+//!
+//! --csv：只在分隔符分隔的文件的某一列中替换
+//! 按 RFC 4180 解析引号，没有改动的字段原样保留，改动过的字段在需要时重新加引号
+//!
+
+use std::borrow::Cow;
+use std::error::Error;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::select::Selector;
+use crate::{count_newlines, install, replace_text, Rule};
+
+///
+/// 要替换的列
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Column {
+    ///
+    /// 从 1 开始的列号，所有行都会处理
+    ///
+    Index(usize),
+    ///
+    /// 表头中的列名，第一行作为表头，不会被替换
+    ///
+    Name(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct CsvOptions {
+    pub column: Column,
+    pub delimiter: u8,
+}
+
+///
+/// 解析 --column：纯数字按列号处理，其他按表头中的列名处理
+///
+pub fn parse_column(value: &str) -> Result<Column, String> {
+    match value.parse::<usize>() {
+        Ok(0) => Err("列号从 1 开始".to_string()),
+        Ok(index) => Ok(Column::Index(index)),
+        Err(_) if value.is_empty() => Err("列名不能为空".to_string()),
+        Err(_) => Ok(Column::Name(value.to_string())),
+    }
+}
+
+///
+/// 解析 --delimiter：单个 ASCII 字符，\t 或 tab 表示制表符
+///
+pub fn parse_delimiter(value: &str) -> Result<u8, String> {
+    match value {
+        "\\t" | "tab" => Ok(b'\t'),
+        _ => match value.as_bytes() {
+            [byte] if byte.is_ascii() && !matches!(byte, b'"' | b'\r' | b'\n') => Ok(*byte),
+            _ => Err("分隔符必须是单个 ASCII 字符，引号和换行除外".to_string()),
+        },
+    }
+}
+
+///
+/// 一个字段在文本中的位置
+///
+struct Field {
+    raw: Range<usize>,
+    quoted: bool,
+}
+
+impl Field {
+    ///
+    /// 字段的值：去掉外层引号，"" 还原成 "
+    ///
+    fn value<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let raw = &text[self.raw.clone()];
+        if !self.quoted {
+            return Cow::Borrowed(raw);
+        }
+        let inner = &raw[1..raw.len() - 1];
+        if inner.contains("\"\"") {
+            Cow::Owned(inner.replace("\"\"", "\""))
+        } else {
+            Cow::Borrowed(inner)
+        }
+    }
+}
+
+///
+/// 解析一条记录，返回字段和下一条记录的起始位置
+/// 行尾的 \r\n 或 \n 不属于任何字段
+///
+fn parse_record(text: &str, start: usize, delimiter: u8) -> Result<(Vec<Field>, usize), String> {
+    let bytes = text.as_bytes();
+    let mut fields = Vec::new();
+    let mut position = start;
+    loop {
+        let field_start = position;
+        let quoted = bytes.get(position) == Some(&b'"');
+        if quoted {
+            position += 1;
+            loop {
+                match bytes.get(position) {
+                    Some(b'"') if bytes.get(position + 1) == Some(&b'"') => position += 2,
+                    Some(b'"') => {
+                        position += 1;
+                        break;
+                    }
+                    Some(_) => position += 1,
+                    None => {
+                        return Err(format!(
+                            "第 {} 行的引号没有闭合",
+                            count_newlines(&text[..field_start]) + 1
+                        ))
+                    }
+                }
+            }
+        }
+        //
+        // 引号之后到分隔符之前如果还有内容，格式不规范，整体当作不带引号的字段
+        //
+        let mut quoted = quoted;
+        while let Some(&byte) = bytes.get(position) {
+            if byte == delimiter || byte == b'\n' {
+                break;
+            }
+            if byte == b'\r' && bytes.get(position + 1) == Some(&b'\n') {
+                break;
+            }
+            quoted = false;
+            position += 1;
+        }
+        fields.push(Field {
+            raw: field_start..position,
+            quoted,
+        });
+
+        match bytes.get(position) {
+            Some(&byte) if byte == delimiter => position += 1,
+            Some(b'\r') => return Ok((fields, position + 2)),
+            Some(b'\n') => return Ok((fields, position + 1)),
+            _ => return Ok((fields, position)),
+        }
+    }
+}
+
+///
+/// 按 CSV 的规则写出字段的值
+/// 原来带引号的字段保持带引号，包含分隔符、引号或换行的值必须加引号
+///
+fn quote(value: &str, quoted: bool, delimiter: u8) -> Cow<'_, str> {
+    let needs_quote = value
+        .bytes()
+        .any(|byte| byte == delimiter || matches!(byte, b'"' | b'\r' | b'\n'));
+    if quoted || needs_quote {
+        Cow::Owned(format!("\"{}\"", value.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+///
+/// 只替换指定列，返回替换后的文本
+///
+pub fn replace_columns(
+    text: &str,
+    rules: &[Rule],
+    options: &CsvOptions,
+    selector: &mut Selector,
+) -> Result<String, String> {
+    let mut output = String::with_capacity(text.len());
+    let mut written = 0;
+    let mut position = 0;
+    let mut line = 1;
+    let mut counted = 0;
+    let mut column = match &options.column {
+        Column::Index(index) => Some(index - 1),
+        Column::Name(_) => None,
+    };
+
+    while position < text.len() {
+        let (fields, next) = parse_record(text, position, options.delimiter)?;
+        line += count_newlines(&text[counted..position]);
+        counted = position;
+
+        let Some(index) = column else {
+            //
+            // 第一行是表头，从中找到列名对应的列
+            //
+            let Column::Name(name) = &options.column else {
+                unreachable!()
+            };
+            let index = fields
+                .iter()
+                .position(|field| field.value(text) == name.as_str())
+                .ok_or_else(|| format!("表头中没有名为 {:?} 的列", name))?;
+            column = Some(index);
+            position = next;
+            continue;
+        };
+
+        if let Some(field) = fields.get(index) {
+            let original = field.value(text);
+            let mut value = original.clone();
+            for rule in rules {
+                let replaced =
+                    match replace_text(&value, line, &rule.re, &rule.replacement, selector) {
+                        Cow::Owned(replaced) => replaced,
+                        Cow::Borrowed(_) => continue,
+                    };
+                value = Cow::Owned(replaced);
+            }
+            if value != original {
+                output.push_str(&text[written..field.raw.start]);
+                output.push_str(&quote(&value, field.quoted, options.delimiter));
+                written = field.raw.end;
+            }
+        }
+        position = next;
+    }
+
+    output.push_str(&text[written..]);
+    Ok(output)
+}
+
+///
+/// 替换 CSV 文件到临时文件
+///
+pub fn replace_in_file(
+    target_file: &Path,
+    rules: &[Rule],
+    options: &CsvOptions,
+    selector: &mut Selector,
+) -> Result<PathBuf, Box<dyn Error>> {
+    let text = fs::read_to_string(target_file)?;
+    let replaced = replace_columns(&text, rules, options, selector)?;
+
+    let temp_file = install::create_temp_file(target_file)?;
+    let temp_file_path = temp_file.path().to_path_buf();
+    let mut file = OpenOptions::new()
+        .append(true)
+        .open(temp_file_path.clone())?;
+    file.write_all(replaced.as_bytes())?;
+    let _ = temp_file.persist(&temp_file_path)?;
+
+    Ok(temp_file_path)
+}
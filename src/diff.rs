@@ -0,0 +1,114 @@
+//!
+//! --output-patch：把所有文件的改动写成统一 diff 格式的补丁，可以附在代码评审里，
+//! 也可以在另一份检出上用 git apply 应用
+//!
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::paths;
+use crate::report::{hunks, Hunk};
+
+///
+/// 每处改动前后保留的上下文行数，和 diff -u、git diff 的默认值相同
+///
+const CONTEXT: usize = 3;
+
+///
+/// 一个文件的补丁
+///
+pub struct FileDiff {
+    pub path: PathBuf,
+    text: String,
+}
+
+impl FileDiff {
+    ///
+    /// 按原始字节比较，保留行尾的 \r\n 和 BOM，git apply 才能找到对应的行
+    /// 不是 UTF-8 的文件返回 None
+    ///
+    pub fn new(path: PathBuf, original: &[u8], replaced: &[u8]) -> Option<Self> {
+        let original = std::str::from_utf8(original).ok()?;
+        let replaced = std::str::from_utf8(replaced).ok()?;
+        let a: Vec<&str> = original.split_inclusive('\n').collect();
+        let b: Vec<&str> = replaced.split_inclusive('\n').collect();
+        let name = paths::portable(&path);
+        let mut text = format!("diff --git a/{0} b/{0}\n--- a/{0}\n+++ b/{0}\n", name);
+        for group in group_hunks(&hunks(&a, &b)) {
+            write_hunk(&mut text, &a, &b, group);
+        }
+        Some(Self { path, text })
+    }
+}
+
+///
+/// 上下文会重叠的改动合进同一段，相距超过两倍上下文的改动分开
+///
+fn group_hunks(hunks: &[Hunk]) -> Vec<&[Hunk]> {
+    let mut groups = Vec::new();
+    let mut start = 0;
+    for index in 1..=hunks.len() {
+        let split = index == hunks.len()
+            || hunks[index].original.start - hunks[index - 1].original.end > 2 * CONTEXT;
+        if split {
+            groups.push(&hunks[start..index]);
+            start = index;
+        }
+    }
+    groups
+}
+
+///
+/// 输出一段补丁：@@ 行、上下文和改动的行
+///
+fn write_hunk(text: &mut String, a: &[&str], b: &[&str], group: &[Hunk]) {
+    let (first, last) = (&group[0], &group[group.len() - 1]);
+    let old_start = first.original.start.saturating_sub(CONTEXT);
+    let old_end = (last.original.end + CONTEXT).min(a.len());
+    let new_start = first.replaced.start - (first.original.start - old_start);
+    let new_end = last.replaced.end + (old_end - last.original.end);
+    text.push_str(&format!(
+        "@@ -{} +{} @@\n",
+        range(old_start, old_end),
+        range(new_start, new_end)
+    ));
+
+    let mut position = old_start;
+    for hunk in group {
+        push_lines(text, ' ', &a[position..hunk.original.start]);
+        push_lines(text, '-', &a[hunk.original.clone()]);
+        push_lines(text, '+', &b[hunk.replaced.clone()]);
+        position = hunk.original.end;
+    }
+    push_lines(text, ' ', &a[position..old_end]);
+}
+
+///
+/// @@ 行中的范围，行号从 1 开始；没有行时起点是前一行
+///
+fn range(start: usize, end: usize) -> String {
+    match end - start {
+        0 => format!("{},0", start),
+        1 => (start + 1).to_string(),
+        count => format!("{},{}", start + 1, count),
+    }
+}
+
+fn push_lines(text: &mut String, prefix: char, lines: &[&str]) {
+    for line in lines {
+        text.push(prefix);
+        text.push_str(line);
+        if !line.ends_with('\n') {
+            text.push_str("\n\\ No newline at end of file\n");
+        }
+    }
+}
+
+///
+/// 按路径排序后把所有文件的补丁写进一个文件
+///
+pub fn write(path: &Path, diffs: &mut [FileDiff]) -> Result<(), String> {
+    diffs.sort_by(|a, b| a.path.cmp(&b.path));
+    let patch: String = diffs.iter().map(|diff| diff.text.as_str()).collect();
+    fs::write(path, patch).map_err(|err| format!("写入补丁文件 {:?} 失败: {}", path, err))
+}
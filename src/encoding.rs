@@ -0,0 +1,166 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::multibyte;
+
+///
+/// 支持的文件编码
+/// 多字节编码的码表见 encoding_tables.rs，编码和解码见 multibyte.rs
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+    ///
+    /// GBK，gb2312 和 cp936 也按 GBK 处理
+    ///
+    Gbk,
+    Gb18030,
+    Big5,
+    ShiftJis,
+    EucJp,
+    ///
+    /// EUC-KR，按兼容它的 CP949（统一韩文码）处理，cp949 和 uhc 也按它处理
+    ///
+    EucKr,
+}
+
+impl Encoding {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name.to_ascii_lowercase().replace('_', "-").as_str() {
+            "utf-8" | "utf8" => Ok(Self::Utf8),
+            "utf-16le" | "utf16le" => Ok(Self::Utf16Le),
+            "utf-16be" | "utf16be" => Ok(Self::Utf16Be),
+            "latin1" | "latin-1" | "iso-8859-1" => Ok(Self::Latin1),
+            "gbk" | "gb2312" | "cp936" => Ok(Self::Gbk),
+            "gb18030" => Ok(Self::Gb18030),
+            "big5" => Ok(Self::Big5),
+            "shift-jis" | "sjis" => Ok(Self::ShiftJis),
+            "euc-jp" => Ok(Self::EucJp),
+            "euc-kr" | "cp949" | "uhc" => Ok(Self::EucKr),
+            _ => Err(format!("未知的编码: {}", name)),
+        }
+    }
+
+    pub fn decode(&self, bytes: &[u8]) -> Result<String, String> {
+        match self {
+            Self::Utf8 => String::from_utf8(bytes.to_vec()).map_err(|err| err.to_string()),
+            Self::Utf16Le | Self::Utf16Be => {
+                if !bytes.len().is_multiple_of(2) {
+                    return Err("UTF-16 内容的字节数不是偶数".to_string());
+                }
+                let units = bytes.chunks(2).map(|pair| match self {
+                    Self::Utf16Le => u16::from_le_bytes([pair[0], pair[1]]),
+                    _ => u16::from_be_bytes([pair[0], pair[1]]),
+                });
+                char::decode_utf16(units)
+                    .collect::<Result<String, _>>()
+                    .map_err(|err| err.to_string())
+            }
+            Self::Latin1 => Ok(bytes.iter().map(|&byte| byte as char).collect()),
+            Self::Gbk => multibyte::GBK.decode(bytes),
+            Self::Gb18030 => multibyte::GB18030.decode(bytes),
+            Self::Big5 => multibyte::BIG5.decode(bytes),
+            Self::ShiftJis => multibyte::SHIFT_JIS.decode(bytes),
+            Self::EucJp => multibyte::EUC_JP.decode(bytes),
+            Self::EucKr => multibyte::EUC_KR.decode(bytes),
+        }
+    }
+
+    pub fn encode(&self, text: &str) -> Result<Vec<u8>, String> {
+        match self {
+            Self::Utf8 => Ok(text.as_bytes().to_vec()),
+            Self::Utf16Le => Ok(text.encode_utf16().flat_map(u16::to_le_bytes).collect()),
+            Self::Utf16Be => Ok(text.encode_utf16().flat_map(u16::to_be_bytes).collect()),
+            Self::Latin1 => text
+                .chars()
+                .map(|ch| {
+                    u8::try_from(u32::from(ch))
+                        .map_err(|_| format!("字符 {:?} 无法用 latin1 编码", ch))
+                })
+                .collect(),
+            Self::Gbk => multibyte::GBK.encode(text),
+            Self::Gb18030 => multibyte::GB18030.encode(text),
+            Self::Big5 => multibyte::BIG5.encode(text),
+            Self::ShiftJis => multibyte::SHIFT_JIS.encode(text),
+            Self::EucJp => multibyte::EUC_JP.encode(text),
+            Self::EucKr => multibyte::EUC_KR.encode(text),
+        }
+    }
+
+    ///
+    /// 这种编码的字节顺序标记（BOM），latin1 和多字节编码没有 BOM
+    ///
+    pub fn bom(&self) -> &'static [u8] {
+        match self {
+            Self::Utf8 => &[0xEF, 0xBB, 0xBF],
+            Self::Utf16Le => &[0xFF, 0xFE],
+            Self::Utf16Be => &[0xFE, 0xFF],
+            Self::Latin1
+            | Self::Gbk
+            | Self::Gb18030
+            | Self::Big5
+            | Self::ShiftJis
+            | Self::EucJp
+            | Self::EucKr => &[],
+        }
+    }
+}
+
+///
+/// BOM 的处理方式
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BomMode {
+    ///
+    /// 原文件有 BOM 时写回的文件同样有 BOM
+    ///
+    #[default]
+    Preserve,
+    ///
+    /// 写回的文件不带 BOM
+    ///
+    Strip,
+    ///
+    /// 写回的文件总是带 BOM
+    ///
+    Add,
+}
+
+///
+/// 按文件开头的 BOM 判断编码，没有 BOM 时返回 None
+///
+pub fn sniff(file: &Path) -> io::Result<Option<Encoding>> {
+    let mut head = [0; 3];
+    let mut len = 0;
+    let mut reader = File::open(file)?;
+    while len < head.len() {
+        match reader.read(&mut head[len..])? {
+            0 => break,
+            read => len += read,
+        }
+    }
+    Ok([Encoding::Utf8, Encoding::Utf16Le, Encoding::Utf16Be]
+        .into_iter()
+        .find(|encoding| head[..len].starts_with(encoding.bom())))
+}
+
+///
+/// 文件实际使用的编码：按 UTF-8 处理的文件开头有 UTF-16 的 BOM 时按 BOM 判断
+///
+pub fn detect(file: &Path, encoding: Encoding) -> Encoding {
+    match (encoding, sniff(file)) {
+        (Encoding::Utf8, Ok(Some(sniffed))) => sniffed,
+        _ => encoding,
+    }
+}
+
+///
+/// 去掉解码后文本开头的 BOM
+///
+pub fn strip_bom(text: &str) -> &str {
+    text.strip_prefix('\u{FEFF}').unwrap_or(text)
+}
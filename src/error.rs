@@ -0,0 +1,138 @@
+//!
+//! 单个文件处理失败的原因，最后的汇总和 --progress-fd 的 JSON 记录按原因分类
+//!
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use crate::metrics;
+
+#[derive(Debug)]
+pub enum FileError {
+    ///
+    /// 没有读取或者写入的权限
+    ///
+    PermissionDenied(String),
+    ///
+    /// 文件不存在，例如列表中的文件在处理之前被删除
+    ///
+    NotFound(String),
+    ///
+    /// 内容不是有效的 UTF-8，或者不是 --encoding 指定的编码
+    ///
+    NotUtf8(String),
+    ///
+    /// 超过 --max-filesize，参数是限制的字节数
+    ///
+    TooLarge(u64),
+    ///
+    /// 有超过 --max-line-length 的行，参数是限制的字节数
+    ///
+    LineTooLong(usize),
+    ///
+    /// 开头有生成标记的文件，参数是找到的标记
+    ///
+    Generated(&'static str),
+    ///
+    /// 临时文件所在的文件系统空间不足
+    ///
+    NoSpace(String),
+    ///
+    /// 读取或者替换时的其他错误
+    ///
+    Replace(String),
+    ///
+    /// 替换之后没有通过 --validate 检查
+    ///
+    Invalid(String),
+    ///
+    /// 读取之后被其他程序修改
+    ///
+    Modified,
+    ///
+    /// 写回失败
+    ///
+    Write(String),
+}
+
+impl FileError {
+    ///
+    /// 读取和替换阶段的错误，按 io::Error 的种类分类
+    ///
+    pub fn from_replace(err: &(dyn Error + 'static)) -> Self {
+        let message = err.to_string();
+        match err.downcast_ref::<io::Error>().map(io::Error::kind) {
+            Some(io::ErrorKind::PermissionDenied) => Self::PermissionDenied(message),
+            Some(io::ErrorKind::NotFound) => Self::NotFound(message),
+            Some(io::ErrorKind::InvalidData) => Self::NotUtf8(message),
+            _ => Self::Replace(message),
+        }
+    }
+
+    ///
+    /// JSON 记录中使用的分类名
+    ///
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::PermissionDenied(_) => "permission_denied",
+            Self::NotFound(_) => "not_found",
+            Self::NotUtf8(_) => "not_utf8",
+            Self::TooLarge(_) => "too_large",
+            Self::LineTooLong(_) => "line_too_long",
+            Self::Generated(_) => "generated",
+            Self::NoSpace(_) => "no_space",
+            Self::Replace(_) => "replace_failed",
+            Self::Invalid(_) => "invalid",
+            Self::Modified => "modified",
+            Self::Write(_) => "write_failed",
+        }
+    }
+
+    ///
+    /// 汇总中使用的简短说明
+    ///
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::PermissionDenied(_) => "没有权限",
+            Self::NotFound(_) => "文件不存在",
+            Self::NotUtf8(_) => "编码无效",
+            Self::TooLarge(_) => "文件过大",
+            Self::LineTooLong(_) => "行过长",
+            Self::Generated(_) => "生成的文件",
+            Self::NoSpace(_) => "空间不足",
+            Self::Replace(_) => "替换失败",
+            Self::Invalid(_) => "没有通过检查",
+            Self::Modified => "读取之后被修改",
+            Self::Write(_) => "写回失败",
+        }
+    }
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::PermissionDenied(message)
+            | Self::NotFound(message)
+            | Self::NotUtf8(message)
+            | Self::Replace(message) => write!(f, "处理文件错误: {}", message),
+            Self::TooLarge(max_filesize) => {
+                write!(f, "文件超过 {}，跳过", metrics::format_bytes(*max_filesize))
+            }
+            Self::LineTooLong(max_line_length) => {
+                write!(f, "文件中有超过 {} 字节的行，跳过", max_line_length)
+            }
+            Self::Generated(marker) => {
+                write!(f, "文件开头有 {} 标记，是生成的文件，跳过", marker)
+            }
+            Self::Invalid(problem) => write!(f, "替换改坏了文件: {}，跳过写回", problem),
+            Self::Modified => write!(
+                f,
+                "文件在读取之后被修改，跳过写回，可以使用 --force 强制写回"
+            ),
+            Self::NoSpace(message) | Self::Write(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for FileError {}
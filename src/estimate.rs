@@ -0,0 +1,96 @@
+use rayon::prelude::*;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::paths;
+use crate::{replace_in_file, selected_match_lines, ReplacePlan};
+
+///
+/// 用来测量替换吞吐量的样本文件数
+///
+const SAMPLE_FILES: usize = 16;
+
+///
+/// 单个文件的扫描结果
+///
+struct Scanned {
+    file: PathBuf,
+    size: u64,
+    matches: usize,
+}
+
+///
+/// 只读扫描，统计会受影响的文件和匹配数，并根据实测吞吐量预估真正执行所需的时间
+/// 不会修改任何目标文件
+///
+pub fn estimate(files: &[PathBuf], plan: &ReplacePlan) {
+    let scan_started = Instant::now();
+    let scanned: Vec<Scanned> = files
+        .par_iter()
+        .filter_map(|file| {
+            let contents = match plan.read_text(file) {
+                Ok(contents) => contents,
+                Err(err) => {
+                    eprintln!("读取文件错误 {:?}: {}", paths::to_host(file), err);
+                    return None;
+                }
+            };
+            let matches = plan
+                .rules
+                .iter()
+                .map(|rule| selected_match_lines(&contents, &rule.re, &plan.selection).len())
+                .sum();
+            Some(Scanned {
+                file: file.clone(),
+                size: contents.len() as u64,
+                matches,
+            })
+        })
+        .collect();
+    let scan_elapsed = scan_started.elapsed();
+
+    let affected: Vec<&Scanned> = scanned.iter().filter(|item| item.matches > 0).collect();
+    let affected_bytes: u64 = affected.iter().map(|item| item.size).sum();
+    let total_matches: usize = affected.iter().map(|item| item.matches).sum();
+
+    //
+    // 取一部分受影响的文件，完整跑一遍替换到临时文件，用来测量写入阶段的吞吐量
+    // 临时文件随后删除，目标文件不受影响
+    //
+    let sample = &affected[..affected.len().min(SAMPLE_FILES)];
+    let sample_bytes: u64 = sample.iter().map(|item| item.size).sum();
+    let sample_plan = ReplacePlan {
+        selection: plan.selection.fresh(),
+        ..plan.clone()
+    };
+    let sample_started = Instant::now();
+    for item in sample {
+        match replace_in_file(&item.file, &sample_plan) {
+            Ok(replaced) => {
+                let _ = fs::remove_file(replaced.temp_file);
+            }
+            Err(err) => eprintln!("处理文件错误 {:?}: {}", paths::to_host(&item.file), err),
+        }
+    }
+    let sample_elapsed = sample_started.elapsed();
+
+    let projected = if sample_bytes == 0 {
+        Duration::ZERO
+    } else {
+        let throughput = sample_bytes as f64 / sample_elapsed.as_secs_f64().max(f64::EPSILON);
+        //
+        // 写入阶段是并行的，按线程数折算
+        //
+        Duration::from_secs_f64(
+            affected_bytes as f64 / throughput / rayon::current_num_threads() as f64,
+        )
+    };
+
+    println!("扫描文件数: {}", scanned.len());
+    println!("受影响文件数: {}", affected.len());
+    println!("匹配数: {}", total_matches);
+    println!("受影响文件总大小: {} 字节", affected_bytes);
+    println!("扫描耗时: {:.2?}", scan_elapsed);
+    println!("预计替换耗时: {:.2?}", projected);
+}
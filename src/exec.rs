@@ -0,0 +1,92 @@
+//!
+//! --exec-replace：把每个匹配交给外部命令，命令的标准输出作为替换结果
+//!
+
+use regex::{Captures, Regex};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+///
+/// 生成替换结果的外部命令
+/// 命令由 shell 执行，匹配的文本从标准输入传入，也放在环境变量 MATCH 中
+/// 捕获组依次作为位置参数 $1、$2……，命名捕获组还会放在环境变量 GROUP_<组名> 中
+/// 没有参与匹配的捕获组是空字符串
+///
+#[derive(Debug, Clone)]
+pub struct ExecCommand {
+    command: String,
+    names: Vec<Option<String>>,
+}
+
+impl ExecCommand {
+    pub fn new(command: &str, re: &Regex) -> Self {
+        Self {
+            command: command.to_string(),
+            names: re
+                .capture_names()
+                .map(|name| name.map(str::to_string))
+                .collect(),
+        }
+    }
+
+    fn shell(&self) -> Command {
+        if cfg!(windows) {
+            let mut shell = Command::new("cmd");
+            shell.arg("/C").arg(&self.command);
+            shell
+        } else {
+            let mut shell = Command::new("sh");
+            shell.arg("-c").arg(&self.command).arg("regex-replace");
+            shell
+        }
+    }
+
+    ///
+    /// 对一个匹配执行命令，去掉输出末尾的一个换行
+    ///
+    pub fn run(&self, caps: &Captures<'_>) -> Result<String, String> {
+        let mut shell = self.shell();
+        shell.env("MATCH", &caps[0]);
+        for (index, name) in self.names.iter().enumerate().skip(1) {
+            let value = caps.get(index).map_or("", |m| m.as_str());
+            if !cfg!(windows) {
+                shell.arg(value);
+            }
+            if let Some(name) = name {
+                shell.env(format!("GROUP_{}", name), value);
+            }
+        }
+
+        let mut child = shell
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|err| format!("无法执行命令 {:?}: {}", self.command, err))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            //
+            // 命令可以不读标准输入，提前关闭管道导致的写入错误不算失败
+            //
+            let _ = stdin.write_all(caps[0].as_bytes());
+        }
+        let output = child
+            .wait_with_output()
+            .map_err(|err| format!("无法执行命令 {:?}: {}", self.command, err))?;
+        if !output.status.success() {
+            return Err(format!(
+                "命令 {:?} 执行失败: {}",
+                self.command, output.status
+            ));
+        }
+
+        let mut stdout = String::from_utf8(output.stdout)
+            .map_err(|_| format!("命令 {:?} 的输出不是有效的 UTF-8", self.command))?;
+        if stdout.ends_with('\n') {
+            stdout.pop();
+            if stdout.ends_with('\r') {
+                stdout.pop();
+            }
+        }
+        Ok(stdout)
+    }
+}
@@ -0,0 +1,40 @@
+//!
+//! 识别代码生成器产生的文件：开头几行带有约定的标记时默认不做替换，避免和生成器互相覆盖
+//! 使用 --include-generated 时照常替换
+//!
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+///
+/// 只检查文件开头的这么多行
+///
+pub const HEADER_LINES: usize = 5;
+
+///
+/// 开头几行最多读取的字节数，压缩过的文件第一行可能非常长
+///
+const HEADER_BYTES: u64 = 4096;
+
+///
+/// 生成文件的标记，按字节查找，区分大小写
+///
+const MARKERS: [&str; 2] = ["@generated", "DO NOT EDIT"];
+
+///
+/// 文件开头 HEADER_LINES 行中出现的生成标记，不是生成的文件时返回 None
+///
+pub fn marker(file: &Path) -> io::Result<Option<&'static str>> {
+    let reader = BufReader::new(File::open(file)?.take(HEADER_BYTES));
+    for line in reader.split(b'\n').take(HEADER_LINES) {
+        let line = line?;
+        let found = MARKERS
+            .into_iter()
+            .find(|marker| memchr::memmem::find(&line, marker.as_bytes()).is_some());
+        if found.is_some() {
+            return Ok(found);
+        }
+    }
+    Ok(None)
+}
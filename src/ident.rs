@@ -0,0 +1,94 @@
+//!
+//! --ident old_name=new_name：按各种命名风格同时重命名一个标识符
+//!
+
+use heck::{ToKebabCase, ToLowerCamelCase, ToShoutySnakeCase, ToSnakeCase, ToUpperCamelCase};
+
+use crate::template::Tokens;
+use crate::{RegexLimits, Rule};
+
+///
+/// 一次重命名，old 和 new 可以是任意一种命名风格
+///
+#[derive(Debug, Clone)]
+pub struct IdentRename {
+    old: String,
+    new: String,
+}
+
+///
+/// 解析 old_name=new_name
+///
+pub fn parse_rename(spec: &str) -> Result<IdentRename, String> {
+    let Some((old, new)) = spec.split_once('=') else {
+        return Err("格式应该是 old_name=new_name".to_string());
+    };
+    for name in [old, new] {
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|ch| ch.is_alphanumeric() || ch == '_' || ch == '-')
+        {
+            return Err(format!("{:?} 不是有效的标识符", name));
+        }
+    }
+    Ok(IdentRename {
+        old: old.to_string(),
+        new: new.to_string(),
+    })
+}
+
+///
+/// 标识符在各种命名风格下的写法：snake_case、camelCase、PascalCase、SCREAMING_SNAKE、kebab-case
+///
+fn variants(name: &str) -> [String; 5] {
+    [
+        name.to_snake_case(),
+        name.to_lower_camel_case(),
+        name.to_upper_camel_case(),
+        name.to_shouty_snake_case(),
+        name.to_kebab_case(),
+    ]
+}
+
+///
+/// 为每个重命名生成规则，每种不同的旧写法一条，替换成同一风格的新写法
+/// 多种风格写法相同时（例如单个单词的 snake_case 和 camelCase）只保留第一条
+///
+pub fn rules(
+    renames: &[IdentRename],
+    tokens: &Tokens,
+    limits: RegexLimits,
+) -> Result<Vec<Rule>, String> {
+    let mut pairs: Vec<(String, String)> = Vec::new();
+    for rename in renames {
+        for (old, new) in variants(&rename.old).into_iter().zip(variants(&rename.new)) {
+            if !pairs.iter().any(|(existing, _)| *existing == old) {
+                pairs.push((old, new));
+            }
+        }
+    }
+
+    let rules = pairs
+        .iter()
+        .map(|(old, new)| {
+            Rule::with_limits(&format!(r"\b{}\b", regex::escape(old)), new, tokens, limits)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    //
+    // 规则依次应用，前面的规则替换出的新名字不能再被后面的规则匹配，否则会被改两次
+    //
+    for (index, (_, new)) in pairs.iter().enumerate() {
+        for (rule, (old, _)) in rules.iter().zip(&pairs).skip(index + 1) {
+            if rule.re.is_match(new) {
+                return Err(format!(
+                    "新名字 {} 包含旧名字 {}，依次替换时会被重复替换，请分成多次运行",
+                    new, old
+                ));
+            }
+        }
+    }
+
+    Ok(rules)
+}
@@ -0,0 +1,868 @@
+use clap::ValueEnum;
+use std::collections::HashSet;
+use std::fs::{self, File, Metadata, OpenOptions, Permissions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::SystemTime;
+use tempfile::{Builder, NamedTempFile};
+
+use crate::validate::ValidateMode;
+use crate::{acl, paths, xattr};
+
+///
+/// 写回时可以保留的原文件属性
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Preserve {
+    ///
+    /// 属主和属组，需要 root 或者 CAP_CHOWN，没有权限时跳过
+    ///
+    Owner,
+    ///
+    /// 访问时间和修改时间
+    ///
+    Times,
+    ///
+    /// 权限和 ACL
+    ///
+    Mode,
+    ///
+    /// 扩展属性，例如 SELinux 标签、macOS 的隔离标记
+    ///
+    Xattrs,
+}
+
+///
+/// 写回目标文件时的选项
+///
+#[derive(Debug, Clone, Default)]
+pub struct WriteOptions {
+    ///
+    /// 显式指定的文件权限，不指定时沿用原文件的权限
+    ///
+    pub chmod: Option<u32>,
+    ///
+    /// 需要保留的原文件属性
+    ///
+    pub preserve: Vec<Preserve>,
+    ///
+    /// 文件系统不支持 ACL 时跳过，而不是报错
+    ///
+    pub skip_unsupported_acl: bool,
+    ///
+    /// 写回之前检查替换有没有把 JSON、YAML、TOML 等文件改坏
+    ///
+    pub validate: Option<ValidateMode>,
+    ///
+    /// 截断原文件并写入新内容，而不是用新文件替换它
+    ///
+    pub in_place: bool,
+    ///
+    /// 所有文件都替换成功之后才写回，写回失败时恢复已经写回的文件
+    ///
+    pub atomic: bool,
+    ///
+    /// 写回之前不检查目标文件在读取之后有没有被修改
+    ///
+    pub force: bool,
+    ///
+    /// 遇到第一个处理失败的文件就停止，默认记下失败的文件继续处理其他文件
+    ///
+    pub fail_fast: bool,
+    ///
+    /// 重命名之前把临时文件刷到磁盘，重命名之后再刷一次所在的目录
+    ///
+    pub fsync: bool,
+    ///
+    /// 写临时文件之前检查它所在的文件系统有没有足够的空间
+    ///
+    pub check_space: bool,
+    ///
+    /// 要修改的文件超过这么多个时放弃运行，不写回任何文件
+    ///
+    pub abort_changed_files: Option<usize>,
+    ///
+    /// 要替换的匹配超过这么多处时放弃运行，不写回任何文件
+    ///
+    pub abort_matches: Option<usize>,
+}
+
+///
+/// 读取时记录的文件大小和修改时间，写回之前用来确认文件没有被其他程序修改
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fingerprint {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl Fingerprint {
+    pub fn of(file: &Path) -> io::Result<Self> {
+        let metadata = fs::metadata(file)?;
+        Ok(Self {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+}
+
+static TEMP_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+///
+/// 创建过临时文件的目录，中断时在这些目录里删除这次运行的临时文件
+///
+static TEMP_DIRS: Mutex<Option<HashSet<PathBuf>>> = Mutex::new(None);
+
+///
+/// 临时文件名的前缀，后面跟着创建它的进程号和一个 "-"
+/// cleanup 根据进程号判断临时文件是否是已经退出的运行留下的
+///
+pub const TEMP_PREFIX: &str = ".regex-replace-";
+
+///
+/// --atomic-run 备份文件名的前缀，后面同样跟着进程号和一个 "-"
+/// 备份是原文件唯一的副本，中断时和 cleanup 默认都不会删除它们
+///
+pub const BACKUP_PREFIX: &str = ".regex-replace-backup-";
+
+///
+/// 进程的 umask，启动时由 init_umask 读取
+///
+static UMASK: OnceLock<u32> = OnceLock::new();
+
+///
+/// 读取进程的 umask，只在启动时、创建其他线程之前调用一次
+/// umask 只能在设置的同时读出，读出和还原之间其他线程新建的文件会得到 0666 的权限
+///
+#[cfg(unix)]
+pub fn init_umask() {
+    let mask = unsafe { libc::umask(0) };
+    unsafe { libc::umask(mask) };
+    let _ = UMASK.set(mask as u32);
+}
+
+#[cfg(not(unix))]
+pub fn init_umask() {}
+
+///
+/// 设置 --tmpdir 指定的临时文件目录，只在启动时调用一次
+///
+pub fn set_temp_dir(dir: PathBuf) {
+    let _ = TEMP_DIR.set(dir);
+}
+
+///
+/// 为目标文件创建临时文件
+/// 默认和目标文件放在同一个目录下，写回时可以直接原子地重命名
+/// 指定了 --tmpdir 时放在那个目录，不在同一个文件系统时写回会退回到复制
+/// 创建时就使用原文件的权限（再由系统按 umask 过滤），而不是 NamedTempFile 默认的 0600，
+/// 这样临时文件所在目录的 setgid、默认 ACL 也能按正常的新建文件语义生效
+/// 为了后续能继续写入，临时文件总是保留属主的读写权限
+///
+pub fn create_temp_file(target: &Path) -> io::Result<NamedTempFile> {
+    create_named(target, TEMP_PREFIX)
+}
+
+///
+/// 在目标文件的临时文件目录中创建以 prefix 和进程号开头的文件，权限同 create_temp_file
+///
+fn create_named(target: &Path, prefix: &str) -> io::Result<NamedTempFile> {
+    let target = resolve(target);
+    let mut builder = Builder::new();
+    let prefix = format!("{}{}-", prefix, std::process::id());
+    builder.prefix(&prefix);
+    if let Ok(metadata) = fs::metadata(&target) {
+        builder.permissions(writable(metadata.permissions()));
+    }
+    let dir = temp_dir(&target);
+    //
+    // 记录目录和创建文件在同一个锁里完成，中断时删除临时文件之后不会再有新的临时文件
+    //
+    let mut dirs = TEMP_DIRS.lock().unwrap_or_else(|err| err.into_inner());
+    dirs.get_or_insert_with(HashSet::new).insert(dir.clone());
+    builder.tempfile_in(dir)
+}
+
+///
+/// 目标文件的临时文件所在的目录：指定了 --tmpdir 时是那个目录，否则是目标文件所在的目录
+///
+pub fn temp_dir(target: &Path) -> PathBuf {
+    let target = resolve(target);
+    match (TEMP_DIR.get(), target.parent()) {
+        (Some(dir), _) => dir.clone(),
+        (None, Some(dir)) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+///
+/// 删除这次运行创建的所有临时文件，返回删除的文件数，只在中断退出之前调用
+///
+pub fn remove_temp_files() -> usize {
+    let prefix = format!("{}{}-", TEMP_PREFIX, std::process::id());
+    let dirs = TEMP_DIRS.lock().unwrap_or_else(|err| err.into_inner());
+    let mut removed = 0;
+    for dir in dirs.iter().flatten() {
+        let Ok(entries) = fs::read_dir(dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            if entry.file_name().to_string_lossy().starts_with(&prefix)
+                && fs::remove_file(entry.path()).is_ok()
+            {
+                removed += 1;
+            }
+        }
+    }
+    //
+    // 锁一直不释放，调用之后进程马上退出
+    //
+    std::mem::forget(dirs);
+    removed
+}
+
+///
+/// 用临时文件替换目标文件
+/// 优先原子地重命名，目标文件不会出现只写了一半的状态
+/// 临时文件和目标文件不在同一个文件系统，或者目标文件有多个硬链接时，退回到复制内容
+/// 按 --preserve 保留原文件的属主、时间、权限（包括 ACL）和扩展属性
+///
+pub fn install(file: &Path, temp_file: &Path, options: &WriteOptions) -> Result<(), String> {
+    let host_file = paths::to_host(file);
+    //
+    // 符号链接替换的是它指向的文件，而不是链接本身
+    //
+    let file = &resolve(file);
+    let metadata =
+        fs::metadata(file).map_err(|err| format!("获取元信息错误 {:?}: {}", host_file, err))?;
+    if options.in_place {
+        return install_in_place(file, temp_file, &metadata, options, &host_file);
+    }
+    let preserve = |attribute| options.preserve.contains(&attribute);
+    let keep_mode = options.chmod.is_some() || preserve(Preserve::Mode);
+    let acl = if keep_mode {
+        match acl::read(file) {
+            Ok(acl) => acl,
+            Err(err) if options.skip_unsupported_acl && acl::is_unsupported(&err) => {
+                eprintln!("文件系统不支持 ACL，跳过 {:?}", host_file);
+                None
+            }
+            Err(err) if acl::is_unsupported(&err) => {
+                return Err(format!(
+                    "文件系统不支持 ACL {:?}，可以用 --skip-unsupported-acl 跳过",
+                    host_file
+                ));
+            }
+            Err(err) => return Err(format!("读取 ACL 错误 {:?}: {}", host_file, err)),
+        }
+    } else {
+        None
+    };
+    let xattrs = if preserve(Preserve::Xattrs) {
+        xattr::read_all(file).map_err(|err| format!("读取扩展属性错误 {:?}: {}", host_file, err))?
+    } else {
+        Vec::new()
+    };
+    let permissions = match options.chmod {
+        Some(mode) => from_mode(mode, metadata.permissions()),
+        None if preserve(Preserve::Mode) => metadata.permissions(),
+        None => default_permissions(metadata.permissions()),
+    };
+
+    //
+    // 修改属主会清除 setuid 和 setgid 位，所以要在设置权限之前
+    //
+    if preserve(Preserve::Owner) {
+        match copy_owner(temp_file, &metadata) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::PermissionDenied => {
+                eprintln!("没有权限保留属主，跳过 {:?}", host_file);
+            }
+            Err(err) => return Err(format!("设置属主错误 {:?}: {}", host_file, err)),
+        }
+    }
+    //
+    // user.* 属性需要文件可写，趁临时文件还保留着属主的写权限时设置
+    //
+    for attribute in &xattrs {
+        match xattr::write(temp_file, attribute) {
+            Ok(()) => {}
+            Err(err) if xattr::is_skippable(&err) => {
+                eprintln!(
+                    "无法保留扩展属性 {}，跳过 {:?}: {}",
+                    attribute.name(),
+                    host_file,
+                    err
+                );
+            }
+            Err(err) => {
+                return Err(format!(
+                    "写入扩展属性 {} 错误 {:?}: {}",
+                    attribute.name(),
+                    host_file,
+                    err
+                ))
+            }
+        }
+    }
+    //
+    // 重命名不会改动时间，趁临时文件还可以写入时设置
+    //
+    if preserve(Preserve::Times) {
+        copy_times(temp_file, &metadata)
+            .map_err(|err| format!("设置文件时间错误 {:?}: {}", host_file, err))?;
+    }
+    fs::set_permissions(temp_file, permissions)
+        .map_err(|err| format!("设置文件权限错误 {:?}: {}", temp_file, err))?;
+    //
+    // 内容落盘之前就重命名的话，断电之后目标文件可能是空的或者只有一部分
+    //
+    if options.fsync {
+        sync_file(temp_file).map_err(|err| format!("同步临时文件错误 {:?}: {}", host_file, err))?;
+    }
+
+    let links = link_count(&metadata);
+    let copied = if links > 1 {
+        eprintln!(
+            "文件 {:?} 有 {} 个硬链接，改为复制内容以保持硬链接",
+            host_file, links
+        );
+        copy_over(temp_file, file, &host_file)?;
+        true
+    } else {
+        match fs::rename(temp_file, file) {
+            Ok(()) => false,
+            Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+                copy_over(temp_file, file, &host_file)?;
+                true
+            }
+            Err(err) => return Err(format!("重命名文件错误 {:?}: {}", host_file, err)),
+        }
+    };
+    //
+    // 复制内容会更新目标文件的修改时间，需要再设置一次
+    //
+    if copied && preserve(Preserve::Times) {
+        copy_times(file, &metadata)
+            .map_err(|err| format!("设置文件时间错误 {:?}: {}", host_file, err))?;
+    }
+    if options.fsync {
+        let synced = if copied {
+            sync_file(file)
+        } else {
+            sync_dir(file)
+        };
+        synced.map_err(|err| format!("同步文件错误 {:?}: {}", host_file, err))?;
+    }
+
+    //
+    // 设置权限会改动 ACL 的 mask，写回之后再恢复一次
+    //
+    if let Some(acl) = &acl {
+        match acl::write(file, acl) {
+            Ok(()) => {}
+            Err(err) if options.skip_unsupported_acl && acl::is_unsupported(&err) => {
+                eprintln!("文件系统不支持 ACL，跳过 {:?}", host_file);
+            }
+            Err(err) => return Err(format!("恢复 ACL 错误 {:?}: {}", host_file, err)),
+        }
+        //
+        // 恢复 ACL 会同时恢复原来的权限位，--chmod 指定的权限要在之后重新设置
+        // 带有 ACL 的文件设置权限时，组的权限位改动的是 ACL 的 mask，具名的条目保留
+        //
+        if let Some(mode) = options.chmod {
+            fs::set_permissions(file, from_mode(mode, metadata.permissions()))
+                .map_err(|err| format!("设置文件权限错误 {:?}: {}", host_file, err))?;
+        }
+    }
+
+    Ok(())
+}
+
+///
+/// --atomic-run 的写回：每个文件写回之前先备份，任何一步失败时把已经写回的文件全部恢复
+///
+#[derive(Debug, Default)]
+pub struct Transaction {
+    backups: Vec<Backup>,
+}
+
+///
+/// 写回之前的原文件，file 是解析过符号链接的目标文件
+///
+#[derive(Debug)]
+struct Backup {
+    file: PathBuf,
+    backup: PathBuf,
+    metadata: Metadata,
+    in_place: bool,
+}
+
+impl Transaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///
+    /// 备份目标文件后再写回，写回中途失败的文件也会在回滚时恢复
+    /// 写回失败但内容没有变化的文件不需要恢复，直接删除备份
+    ///
+    pub fn install(
+        &mut self,
+        file: &Path,
+        temp_file: &Path,
+        options: &WriteOptions,
+    ) -> Result<(), String> {
+        let host_file = paths::to_host(file);
+        let resolved = resolve(file);
+        let metadata = fs::metadata(&resolved)
+            .map_err(|err| format!("获取元信息错误 {:?}: {}", host_file, err))?;
+        let backup =
+            backup(&resolved).map_err(|err| format!("备份文件错误 {:?}: {}", host_file, err))?;
+        self.backups.push(Backup {
+            in_place: options.in_place || link_count(&metadata) > 1,
+            file: resolved,
+            backup,
+            metadata,
+        });
+        let installed = install(file, temp_file, options);
+        if installed.is_err() {
+            let backup = self.backups.last().unwrap();
+            if fs::read(&backup.file).ok() == fs::read(&backup.backup).ok() {
+                let _ = fs::remove_file(&backup.backup);
+                self.backups.pop();
+            }
+        }
+        installed
+    }
+
+    ///
+    /// 所有文件都写回成功，删除备份
+    ///
+    pub fn commit(self) {
+        for backup in self.backups {
+            let _ = fs::remove_file(backup.backup);
+        }
+    }
+
+    ///
+    /// 按相反的顺序恢复所有备份，返回恢复的文件数
+    /// 恢复失败的文件保留备份，错误信息中给出备份的位置
+    ///
+    pub fn rollback(self) -> Result<usize, String> {
+        let mut errors = Vec::new();
+        let mut restored = 0;
+        for backup in self.backups.into_iter().rev() {
+            match restore(&backup) {
+                Ok(()) => restored += 1,
+                Err(err) => errors.push(format!(
+                    "恢复文件 {:?} 失败: {}，原内容保存在 {:?}",
+                    paths::to_host(&backup.file),
+                    err,
+                    paths::to_host(&backup.backup)
+                )),
+            }
+        }
+        if errors.is_empty() {
+            Ok(restored)
+        } else {
+            Err(errors.join("\n"))
+        }
+    }
+}
+
+///
+/// 把文件复制到它旁边的备份文件，复制时带上权限
+/// 备份使用单独的前缀，不会被当成可以随意删除的临时文件
+///
+fn backup(file: &Path) -> io::Result<PathBuf> {
+    let backup = create_named(file, BACKUP_PREFIX)?;
+    let backup_path = backup.path().to_path_buf();
+    fs::copy(file, &backup_path)?;
+    let _ = backup.persist(&backup_path)?;
+    Ok(backup_path)
+}
+
+///
+/// 用备份恢复文件
+/// 原地写回的文件和有多个硬链接的文件需要保持 inode，写回备份的内容，其他文件把备份重命名回去
+///
+fn restore(backup: &Backup) -> io::Result<()> {
+    let renamed = !backup.in_place
+        && match fs::rename(&backup.backup, &backup.file) {
+            Ok(()) => true,
+            Err(err) if err.kind() == io::ErrorKind::CrossesDevices => false,
+            Err(err) => return Err(err),
+        };
+    if !renamed {
+        fs::set_permissions(&backup.file, writable(backup.metadata.permissions()))?;
+        rewrite(&backup.backup, &backup.file)?;
+        fs::set_permissions(&backup.file, backup.metadata.permissions())?;
+        fs::remove_file(&backup.backup)?;
+    }
+    copy_times(&backup.file, &backup.metadata)
+}
+
+///
+/// --in-place-truncate：截断原文件并写入新内容，文件的 inode 保持不变
+/// 硬链接、属主、扩展属性和 ACL 都不受影响，监视 inode 的程序（tail -f、编辑器）也能继续工作
+/// 代价是写入过程中被中断时，目标文件可能只写了一部分
+///
+fn install_in_place(
+    file: &Path,
+    temp_file: &Path,
+    metadata: &Metadata,
+    options: &WriteOptions,
+    host_file: &Path,
+) -> Result<(), String> {
+    let links = link_count(metadata);
+    if links > 1 {
+        eprintln!(
+            "文件 {:?} 有 {} 个硬链接，原地写入会同时改变所有链接的内容",
+            host_file, links
+        );
+    }
+
+    rewrite(temp_file, file).map_err(|err| format!("写入文件错误 {:?}: {}", host_file, err))?;
+    fs::remove_file(temp_file).map_err(|err| format!("删除临时文件错误: {}", err))?;
+
+    if let Some(mode) = options.chmod {
+        fs::set_permissions(file, from_mode(mode, metadata.permissions()))
+            .map_err(|err| format!("设置文件权限错误 {:?}: {}", host_file, err))?;
+    }
+    if options.preserve.contains(&Preserve::Times) {
+        copy_times(file, metadata)
+            .map_err(|err| format!("设置文件时间错误 {:?}: {}", host_file, err))?;
+    }
+
+    Ok(())
+}
+
+///
+/// 截断目标文件，写入临时文件的内容
+///
+fn rewrite(temp_file: &Path, file: &Path) -> io::Result<()> {
+    let mut source = File::open(temp_file)?;
+    let mut target = OpenOptions::new().write(true).truncate(true).open(file)?;
+    io::copy(&mut source, &mut target)?;
+    target.sync_all()
+}
+
+///
+/// 把临时文件的内容复制到目标文件，然后删除临时文件
+///
+fn copy_over(temp_file: &Path, file: &Path, host_file: &Path) -> Result<(), String> {
+    fs::copy(temp_file, file).map_err(|err| format!("复制文件错误 {:?}: {}", host_file, err))?;
+    fs::remove_file(temp_file).map_err(|err| format!("删除临时文件错误: {}", err))
+}
+
+///
+/// 把文件的内容和元信息刷到磁盘
+///
+fn sync_file(file: &Path) -> io::Result<()> {
+    File::open(file)?.sync_all()
+}
+
+///
+/// 把文件所在的目录刷到磁盘，重命名产生的目录项在断电之后才不会丢失
+///
+#[cfg(unix)]
+fn sync_dir(file: &Path) -> io::Result<()> {
+    let dir = match file.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir)?.sync_all()
+}
+
+//
+// Windows 上不能直接打开目录同步，重命名由 NTFS 的日志保证
+//
+#[cfg(not(unix))]
+fn sync_dir(_file: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+///
+/// 把文件的访问时间和修改时间设置成原文件的
+///
+fn copy_times(file: &Path, original: &Metadata) -> io::Result<()> {
+    let mut times = fs::FileTimes::new();
+    if let Ok(accessed) = original.accessed() {
+        times = times.set_accessed(accessed);
+    }
+    if let Ok(modified) = original.modified() {
+        times = times.set_modified(modified);
+    }
+    OpenOptions::new().write(true).open(file)?.set_times(times)
+}
+
+#[cfg(unix)]
+fn copy_owner(file: &Path, original: &Metadata) -> io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+    let current = fs::metadata(file)?;
+    if current.uid() == original.uid() && current.gid() == original.gid() {
+        return Ok(());
+    }
+    std::os::unix::fs::chown(file, Some(original.uid()), Some(original.gid()))
+}
+
+#[cfg(not(unix))]
+fn copy_owner(_file: &Path, _original: &Metadata) -> io::Result<()> {
+    Ok(())
+}
+
+///
+/// 符号链接解析成它最终指向的文件，其他路径原样返回
+///
+fn resolve(file: &Path) -> PathBuf {
+    match fs::symlink_metadata(file) {
+        Ok(metadata) if metadata.file_type().is_symlink() => {
+            fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf())
+        }
+        _ => file.to_path_buf(),
+    }
+}
+
+///
+/// 文件的硬链接数
+/// 重命名会让其他硬链接继续指向旧的内容，有多个硬链接的文件只能复制
+///
+#[cfg(unix)]
+fn link_count(metadata: &Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.nlink()
+}
+
+#[cfg(not(unix))]
+fn link_count(_metadata: &Metadata) -> u64 {
+    1
+}
+
+///
+/// 解析 --chmod 的八进制权限，例如 644、0755
+///
+pub fn parse_mode(mode: &str) -> Result<u32, String> {
+    match u32::from_str_radix(mode, 8) {
+        Ok(mode) if mode <= 0o7777 => Ok(mode),
+        _ => Err(format!("无效的八进制权限: {}", mode)),
+    }
+}
+
+#[cfg(unix)]
+fn writable(permissions: Permissions) -> Permissions {
+    use std::os::unix::fs::PermissionsExt;
+    Permissions::from_mode(permissions.mode() | 0o600)
+}
+
+#[cfg(not(unix))]
+fn writable(mut permissions: Permissions) -> Permissions {
+    #[allow(clippy::permissions_set_readonly_false)]
+    permissions.set_readonly(false);
+    permissions
+}
+
+///
+/// 不保留权限时，使用新建文件的默认权限 0666 & ~umask
+///
+#[cfg(unix)]
+fn default_permissions(_original: Permissions) -> Permissions {
+    use std::os::unix::fs::PermissionsExt;
+    //
+    // 没有调用 init_umask 时按常见的 022 处理
+    //
+    let umask = UMASK.get().copied().unwrap_or(0o022);
+    Permissions::from_mode(0o666 & !umask)
+}
+
+#[cfg(not(unix))]
+fn default_permissions(original: Permissions) -> Permissions {
+    original
+}
+
+#[cfg(unix)]
+fn from_mode(mode: u32, _original: Permissions) -> Permissions {
+    use std::os::unix::fs::PermissionsExt;
+    Permissions::from_mode(mode)
+}
+
+///
+/// 非 unix 平台只能表达只读与否，按属主写权限换算
+///
+#[cfg(not(unix))]
+fn from_mode(mode: u32, mut original: Permissions) -> Permissions {
+    original.set_readonly(mode & 0o200 == 0);
+    original
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    ///
+    /// 给文件设置一个带有具名用户条目的访问 ACL，文件系统不支持时返回 false
+    ///
+    fn set_access_acl(path: &Path) -> bool {
+        const UNDEFINED: u32 = u32::MAX;
+        let mut value = 2u32.to_le_bytes().to_vec();
+        for (tag, perm, id) in [
+            (0x01u16, 6u16, UNDEFINED),
+            (0x02, 6, 1000),
+            (0x04, 4, UNDEFINED),
+            (0x10, 6, UNDEFINED),
+            (0x20, 4, UNDEFINED),
+        ] {
+            value.extend(tag.to_le_bytes());
+            value.extend(perm.to_le_bytes());
+            value.extend(id.to_le_bytes());
+        }
+        let path = std::ffi::CString::new(path.to_str().unwrap()).unwrap();
+        let name = b"system.posix_acl_access\0";
+        unsafe {
+            libc::setxattr(
+                path.as_ptr(),
+                name.as_ptr().cast(),
+                value.as_ptr().cast(),
+                value.len(),
+                0,
+            ) == 0
+        }
+    }
+
+    fn replace(file: &Path, contents: &str, options: &WriteOptions) {
+        install(file, &temp_file(file, contents), options).unwrap();
+    }
+
+    fn temp_file(file: &Path, contents: &str) -> PathBuf {
+        let temp_file = create_temp_file(file).unwrap();
+        let temp_file_path = temp_file.path().to_path_buf();
+        fs::write(&temp_file_path, contents).unwrap();
+        temp_file.persist(&temp_file_path).unwrap();
+        temp_file_path
+    }
+
+    fn file_names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn mode(path: &Path) -> u32 {
+        fs::metadata(path).unwrap().permissions().mode() & 0o7777
+    }
+
+    #[test]
+    fn chmod_is_not_overwritten_by_restored_acl() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("config");
+        fs::write(&file, "old").unwrap();
+        fs::set_permissions(&file, Permissions::from_mode(0o644)).unwrap();
+        if !set_access_acl(&file) {
+            return;
+        }
+        let options = WriteOptions {
+            chmod: Some(0o600),
+            preserve: vec![Preserve::Mode],
+            ..WriteOptions::default()
+        };
+        replace(&file, "new", &options);
+        assert_eq!(fs::read_to_string(&file).unwrap(), "new");
+        assert_eq!(mode(&file), 0o600);
+        assert!(acl::read(&file).unwrap().is_some());
+    }
+
+    #[test]
+    fn chmod_without_acl() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("script");
+        fs::write(&file, "old").unwrap();
+        fs::set_permissions(&file, Permissions::from_mode(0o644)).unwrap();
+        let options = WriteOptions {
+            chmod: Some(0o755),
+            ..WriteOptions::default()
+        };
+        replace(&file, "new", &options);
+        assert_eq!(mode(&file), 0o755);
+    }
+
+    #[test]
+    fn rollback_restores_installed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("first");
+        let second = dir.path().join("second");
+        fs::write(&first, "first").unwrap();
+        fs::write(&second, "second").unwrap();
+        fs::set_permissions(&second, Permissions::from_mode(0o640)).unwrap();
+        let options = WriteOptions::default();
+        let mut transaction = Transaction::new();
+        for file in [&first, &second] {
+            let temp_file = temp_file(file, "replaced");
+            transaction.install(file, &temp_file, &options).unwrap();
+        }
+        assert_eq!(fs::read_to_string(&second).unwrap(), "replaced");
+        assert_eq!(transaction.rollback(), Ok(2));
+        assert_eq!(fs::read_to_string(&first).unwrap(), "first");
+        assert_eq!(fs::read_to_string(&second).unwrap(), "second");
+        assert_eq!(mode(&second), 0o640);
+        assert_eq!(file_names(dir.path()), ["first", "second"]);
+    }
+
+    #[test]
+    fn rollback_keeps_hard_links() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("file");
+        let link = dir.path().join("link");
+        fs::write(&file, "old").unwrap();
+        fs::hard_link(&file, &link).unwrap();
+        let mut transaction = Transaction::new();
+        let temp_file = temp_file(&file, "new");
+        transaction
+            .install(&file, &temp_file, &WriteOptions::default())
+            .unwrap();
+        assert_eq!(fs::read_to_string(&link).unwrap(), "new");
+        assert_eq!(transaction.rollback(), Ok(1));
+        assert_eq!(fs::read_to_string(&link).unwrap(), "old");
+        assert_eq!(
+            fs::metadata(&file).unwrap().ino(),
+            fs::metadata(&link).unwrap().ino()
+        );
+    }
+
+    #[test]
+    fn commit_removes_backups() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("file");
+        fs::write(&file, "old").unwrap();
+        let mut transaction = Transaction::new();
+        let temp_file = temp_file(&file, "new");
+        transaction
+            .install(&file, &temp_file, &WriteOptions::default())
+            .unwrap();
+        transaction.commit();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "new");
+        assert_eq!(file_names(dir.path()), ["file"]);
+    }
+
+    #[test]
+    fn owner_is_preserved() {
+        if unsafe { libc::geteuid() } != 0 {
+            return;
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("owned");
+        fs::write(&file, "old").unwrap();
+        std::os::unix::fs::chown(&file, Some(1234), Some(1234)).unwrap();
+        let options = WriteOptions {
+            preserve: vec![Preserve::Owner, Preserve::Mode, Preserve::Xattrs],
+            ..WriteOptions::default()
+        };
+        replace(&file, "new", &options);
+        let metadata = fs::metadata(&file).unwrap();
+        assert_eq!((metadata.uid(), metadata.gid()), (1234, 1234));
+    }
+}
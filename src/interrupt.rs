@@ -0,0 +1,133 @@
+//!
+//! 中断一次运行：收到 Ctrl-C（SIGINT）或 SIGTERM 时删除这次运行创建的临时文件再退出
+//! 写回阶段收到信号时不会立即退出，写回完当前文件后停下，--atomic-run 时恢复已经写回的文件
+//! 和 stop 不同，中断不会等待正在处理的文件，也不会输出统计
+//!
+
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, TryLockError};
+
+use crate::install;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+///
+/// 写回文件时持有的锁，收到信号时拿不到这个锁就说明正在写回
+///
+static WRITING: Mutex<()> = Mutex::new(());
+
+///
+/// 收到信号退出之前执行的恢复操作，例如 tui 恢复终端的设置和屏幕
+///
+static RESTORE: Mutex<Option<Box<dyn Fn() + Send>>> = Mutex::new(None);
+
+///
+/// 注册 SIGINT 和 SIGTERM 的处理
+///
+pub fn install() {
+    imp::install_signal_handler();
+}
+
+///
+/// 是否已经收到中断信号
+///
+pub fn requested() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+///
+/// 进入写回阶段，返回的锁释放之前收到信号不会退出，由写回的循环检查 requested 后停下
+///
+pub fn writing() -> MutexGuard<'static, ()> {
+    WRITING.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+///
+/// 设置或清除收到信号退出之前的恢复操作，退出不会执行 Drop，持有终端等资源的一方要在这里恢复
+///
+pub fn on_exit(restore: Option<Box<dyn Fn() + Send>>) {
+    *RESTORE.lock().unwrap_or_else(|err| err.into_inner()) = restore;
+}
+
+///
+/// 收到信号之后在单独的线程里执行，不受信号处理函数的限制
+///
+fn interrupt() {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+    let _writing = match WRITING.try_lock() {
+        Ok(guard) => guard,
+        Err(TryLockError::Poisoned(err)) => err.into_inner(),
+        Err(TryLockError::WouldBlock) => {
+            eprintln!("收到中断信号，写回完当前文件后停止");
+            return;
+        }
+    };
+    if let Some(restore) = RESTORE
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .as_ref()
+    {
+        restore();
+    }
+    let removed = install::remove_temp_files();
+    eprintln!("收到中断信号，已删除 {} 个临时文件", removed);
+    process::exit(130);
+}
+
+#[cfg(unix)]
+mod imp {
+    use std::sync::atomic::{AtomicI32, Ordering};
+    use std::{io, thread};
+
+    static SIGNAL_FD: AtomicI32 = AtomicI32::new(-1);
+
+    //
+    // 信号处理函数里只向管道写一个字节，是异步信号安全的
+    // 删除临时文件、恢复文件都交给读管道的线程
+    //
+    extern "C" fn handle_signal(_signal: libc::c_int) {
+        let byte = 0u8;
+        unsafe {
+            libc::write(
+                SIGNAL_FD.load(Ordering::SeqCst),
+                &byte as *const u8 as *const libc::c_void,
+                1,
+            );
+        }
+    }
+
+    pub fn install_signal_handler() {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            return;
+        }
+        let [read_fd, write_fd] = fds;
+        SIGNAL_FD.store(write_fd, Ordering::SeqCst);
+        thread::spawn(move || loop {
+            let mut byte = 0u8;
+            let read = unsafe { libc::read(read_fd, &mut byte as *mut u8 as *mut libc::c_void, 1) };
+            if read == 1 {
+                super::interrupt();
+            } else if read == 0 || io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+                break;
+            }
+        });
+        unsafe {
+            for signal in [libc::SIGINT, libc::SIGTERM] {
+                libc::signal(
+                    signal,
+                    handle_signal as extern "C" fn(libc::c_int) as libc::sighandler_t,
+                );
+            }
+        }
+    }
+}
+
+//
+// 其他平台保持默认的处理，Ctrl-C 直接结束进程，遗留的临时文件可以用 cleanup 删除
+//
+#[cfg(not(unix))]
+mod imp {
+    pub fn install_signal_handler() {}
+}
@@ -0,0 +1,304 @@
+//!
+//! JSON 解析：记录每个值在原文中的位置，--validate 用它检查语法，--json-path 用它定位要替换的字符串
+//!
+
+use std::ops::Range;
+
+///
+/// 解析出的一个 JSON 值
+///
+pub struct Node {
+    ///
+    /// 值在原文中的字节范围，字符串包含两边的引号
+    ///
+    pub span: Range<usize>,
+    pub kind: Kind,
+}
+
+pub enum Kind {
+    ///
+    /// 对象的成员按原文中的顺序保存
+    ///
+    Object(Vec<(String, Node)>),
+    Array(Vec<Node>),
+    ///
+    /// 已经处理过转义的字符串
+    ///
+    String(String),
+    ///
+    /// 数字、true、false、null
+    ///
+    Scalar,
+}
+
+///
+/// 解析完整的 JSON 文本，出错时返回带行号的错误信息
+///
+pub fn parse(text: &str) -> Result<Node, String> {
+    let mut parser = Parser {
+        bytes: text.as_bytes(),
+        position: 0,
+    };
+    parser
+        .value(0)
+        .and_then(|node| {
+            parser.skip_whitespace();
+            match parser.peek() {
+                None => Ok(node),
+                Some(_) => Err("JSON 值之后还有多余的内容".to_string()),
+            }
+        })
+        .map_err(|err| format!("第 {} 行: {}", parser.line(), err))
+}
+
+///
+/// 把字符串编码成 JSON 字符串字面量，非 ASCII 字符原样保留
+///
+pub fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for ch in value.chars() {
+        match ch {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            '\u{08}' => quoted.push_str("\\b"),
+            '\u{0c}' => quoted.push_str("\\f"),
+            ch if (ch as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => quoted.push(ch),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+///
+/// 嵌套层数的上限，防止恶意构造的输入导致栈溢出
+///
+const MAX_DEPTH: usize = 512;
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn line(&self) -> usize {
+        self.bytes[..self.position.min(self.bytes.len())]
+            .iter()
+            .filter(|&&byte| byte == b'\n')
+            .count()
+            + 1
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.position).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), String> {
+        self.skip_whitespace();
+        if self.peek() == Some(byte) {
+            self.position += 1;
+            Ok(())
+        } else {
+            Err(format!("缺少 {:?}", byte as char))
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Node, String> {
+        if depth > MAX_DEPTH {
+            return Err("嵌套层数过多".to_string());
+        }
+        self.skip_whitespace();
+        let start = self.position;
+        let kind = match self.peek() {
+            Some(b'{') => self.object(depth)?,
+            Some(b'[') => self.array(depth)?,
+            Some(b'"') => Kind::String(self.string()?),
+            Some(b'-' | b'0'..=b'9') => {
+                self.number()?;
+                Kind::Scalar
+            }
+            Some(b't') => self.literal("true")?,
+            Some(b'f') => self.literal("false")?,
+            Some(b'n') => self.literal("null")?,
+            Some(byte) => return Err(format!("无法识别的字符 {:?}", byte as char)),
+            None => return Err("内容不完整".to_string()),
+        };
+        Ok(Node {
+            span: start..self.position,
+            kind,
+        })
+    }
+
+    fn object(&mut self, depth: usize) -> Result<Kind, String> {
+        self.position += 1;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.position += 1;
+            return Ok(Kind::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(b'"') {
+                return Err("对象的键必须是字符串".to_string());
+            }
+            let key = self.string()?;
+            self.expect(b':')?;
+            members.push((key, self.value(depth + 1)?));
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b'}') => {
+                    self.position += 1;
+                    return Ok(Kind::Object(members));
+                }
+                _ => return Err("对象缺少 ',' 或 '}'".to_string()),
+            }
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Result<Kind, String> {
+        self.position += 1;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.position += 1;
+            return Ok(Kind::Array(items));
+        }
+        loop {
+            items.push(self.value(depth + 1)?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b']') => {
+                    self.position += 1;
+                    return Ok(Kind::Array(items));
+                }
+                _ => return Err("数组缺少 ',' 或 ']'".to_string()),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.position += 1;
+        let mut value = Vec::new();
+        loop {
+            match self.peek() {
+                Some(b'"') => {
+                    self.position += 1;
+                    return String::from_utf8(value)
+                        .map_err(|_| "字符串不是有效的 UTF-8".to_string());
+                }
+                Some(b'\\') => {
+                    self.position += 1;
+                    let escaped = match self.peek() {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{08}',
+                        Some(b'f') => '\u{0c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => self.unicode_escape()?,
+                        _ => return Err("无效的转义序列".to_string()),
+                    };
+                    self.position += 1;
+                    let mut buffer = [0; 4];
+                    value.extend_from_slice(escaped.encode_utf8(&mut buffer).as_bytes());
+                }
+                Some(0x00..=0x1f) => return Err("字符串中有未转义的控制字符".to_string()),
+                Some(byte) => {
+                    value.push(byte);
+                    self.position += 1;
+                }
+                None => return Err("字符串没有结束".to_string()),
+            }
+        }
+    }
+
+    ///
+    /// 解析 \uXXXX，位置停在最后一个十六进制数字上
+    /// 代理对合并成一个字符，单独出现的代理项换成 U+FFFD
+    ///
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.hex4(self.position + 1)?;
+        self.position += 4;
+        if !(0xD800..0xDC00).contains(&high) {
+            return Ok(char::from_u32(high).unwrap_or(char::REPLACEMENT_CHARACTER));
+        }
+        if self.bytes.get(self.position + 1..self.position + 3) == Some(b"\\u") {
+            if let Ok(low) = self.hex4(self.position + 3) {
+                if (0xDC00..0xE000).contains(&low) {
+                    self.position += 6;
+                    let code = 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00);
+                    return Ok(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                }
+            }
+        }
+        Ok(char::REPLACEMENT_CHARACTER)
+    }
+
+    fn hex4(&self, start: usize) -> Result<u32, String> {
+        self.bytes
+            .get(start..start + 4)
+            .filter(|digits| digits.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| "无效的 \\u 转义".to_string())
+    }
+
+    fn number(&mut self) -> Result<(), String> {
+        if self.peek() == Some(b'-') {
+            self.position += 1;
+        }
+        match self.peek() {
+            Some(b'0') => self.position += 1,
+            Some(b'1'..=b'9') => self.digits(),
+            _ => return Err("无效的数字".to_string()),
+        }
+        if self.peek() == Some(b'.') {
+            self.position += 1;
+            if !matches!(self.peek(), Some(b'0'..=b'9')) {
+                return Err("无效的数字".to_string());
+            }
+            self.digits();
+        }
+        if matches!(self.peek(), Some(b'e' | b'E')) {
+            self.position += 1;
+            if matches!(self.peek(), Some(b'+' | b'-')) {
+                self.position += 1;
+            }
+            if !matches!(self.peek(), Some(b'0'..=b'9')) {
+                return Err("无效的数字".to_string());
+            }
+            self.digits();
+        }
+        Ok(())
+    }
+
+    fn digits(&mut self) {
+        while matches!(self.peek(), Some(b'0'..=b'9')) {
+            self.position += 1;
+        }
+    }
+
+    fn literal(&mut self, literal: &str) -> Result<Kind, String> {
+        if self.bytes[self.position..].starts_with(literal.as_bytes()) {
+            self.position += literal.len();
+            Ok(Kind::Scalar)
+        } else {
+            Err("无法识别的字面量".to_string())
+        }
+    }
+}
@@ -0,0 +1,275 @@
+//!
+//! --json-path：只在 JSONPath 选中的字符串值中替换
+//! 改动过的字符串重新转义后写回原来的位置，其余内容（缩进、键的顺序、数字写法等）原样保留
+//!
+
+use std::borrow::Cow;
+use std::error::Error;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::json::{self, Kind, Node};
+use crate::select::Selector;
+use crate::{count_newlines, install, replace_text, Rule};
+
+///
+/// 路径中的一段
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    ///
+    /// .key 或 ['key']
+    ///
+    Key(String),
+    ///
+    /// [n]，负数从数组末尾开始数
+    ///
+    Index(isize),
+    ///
+    /// .* 或 [*]：对象的所有值或数组的所有元素
+    ///
+    Wildcard,
+    ///
+    /// ..：当前值以及它的所有后代，后面必须跟着一段
+    ///
+    Descendants,
+}
+
+///
+/// 支持的 JSONPath 子集：$、.key、['key']、[n]、.*、[*]、..key、..*
+///
+#[derive(Debug, Clone)]
+pub struct JsonPath {
+    segments: Vec<Segment>,
+}
+
+impl FromStr for JsonPath {
+    type Err = String;
+
+    fn from_str(path: &str) -> Result<Self, Self::Err> {
+        let Some(mut rest) = path.strip_prefix('$') else {
+            return Err("JSONPath 必须以 $ 开头".to_string());
+        };
+        let mut segments = Vec::new();
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix("..") {
+                segments.push(Segment::Descendants);
+                //
+                // ..key 和 ..* 省略了中间的点，..[...] 直接接下标
+                //
+                rest = if after.starts_with('[') {
+                    after
+                } else {
+                    let (segment, after) = dot_segment(after)?;
+                    segments.push(segment);
+                    after
+                };
+            } else if let Some(after) = rest.strip_prefix('.') {
+                let (segment, after) = dot_segment(after)?;
+                segments.push(segment);
+                rest = after;
+            } else if let Some(after) = rest.strip_prefix('[') {
+                let (segment, after) = bracket_segment(after)?;
+                segments.push(segment);
+                rest = after;
+            } else {
+                return Err(format!("JSONPath 中无法识别的部分: {:?}", rest));
+            }
+        }
+        if segments.last() == Some(&Segment::Descendants) {
+            return Err("JSONPath 不能以 .. 结尾".to_string());
+        }
+        Ok(JsonPath { segments })
+    }
+}
+
+///
+/// 解析 . 之后的 key 或 *，key 到下一个 . 或 [ 为止
+///
+fn dot_segment(rest: &str) -> Result<(Segment, &str), String> {
+    let end = rest.find(['.', '[']).unwrap_or(rest.len());
+    match &rest[..end] {
+        "" => Err("JSONPath 中 . 之后缺少键名".to_string()),
+        "*" => Ok((Segment::Wildcard, &rest[end..])),
+        key => Ok((Segment::Key(key.to_string()), &rest[end..])),
+    }
+}
+
+///
+/// 解析 [ 之后的 *、数字或带引号的键名，直到对应的 ]
+///
+fn bracket_segment(rest: &str) -> Result<(Segment, &str), String> {
+    if let Some(quote) = rest.chars().next().filter(|ch| matches!(ch, '\'' | '"')) {
+        let mut key = String::new();
+        let mut chars = rest[1..].char_indices();
+        while let Some((offset, ch)) = chars.next() {
+            match ch {
+                '\\' => match chars.next() {
+                    Some((_, escaped)) => key.push(escaped),
+                    None => break,
+                },
+                ch if ch == quote => {
+                    let after = &rest[1 + offset + 1..];
+                    return match after.strip_prefix(']') {
+                        Some(after) => Ok((Segment::Key(key), after)),
+                        None => Err("JSONPath 中的键名之后缺少 ]".to_string()),
+                    };
+                }
+                ch => key.push(ch),
+            }
+        }
+        return Err("JSONPath 中的键名缺少结束引号".to_string());
+    }
+
+    let Some(end) = rest.find(']') else {
+        return Err("JSONPath 中缺少 ]".to_string());
+    };
+    let segment = match rest[..end].trim() {
+        "*" => Segment::Wildcard,
+        index => index
+            .parse()
+            .map(Segment::Index)
+            .map_err(|_| format!("JSONPath 中无效的下标: {:?}", index))?,
+    };
+    Ok((segment, &rest[end + 1..]))
+}
+
+impl JsonPath {
+    ///
+    /// 选出路径匹配的所有值，按在文本中的位置排序并去重
+    ///
+    fn select<'a>(&self, root: &'a Node) -> Vec<&'a Node> {
+        let mut current = vec![root];
+        let mut descendants = false;
+        for segment in &self.segments {
+            if *segment == Segment::Descendants {
+                descendants = true;
+                continue;
+            }
+            if descendants {
+                let mut all = Vec::new();
+                for node in current {
+                    collect_descendants(node, &mut all);
+                }
+                current = all;
+                descendants = false;
+            }
+            current = current
+                .into_iter()
+                .flat_map(|node| children(node, segment))
+                .collect();
+        }
+        current.sort_by_key(|node| node.span.start);
+        current.dedup_by_key(|node| node.span.start);
+        current
+    }
+}
+
+fn collect_descendants<'a>(node: &'a Node, all: &mut Vec<&'a Node>) {
+    all.push(node);
+    match &node.kind {
+        Kind::Object(members) => members
+            .iter()
+            .for_each(|(_, child)| collect_descendants(child, all)),
+        Kind::Array(items) => items
+            .iter()
+            .for_each(|child| collect_descendants(child, all)),
+        Kind::String(_) | Kind::Scalar => {}
+    }
+}
+
+fn children<'a>(node: &'a Node, segment: &Segment) -> Vec<&'a Node> {
+    match (&node.kind, segment) {
+        //
+        // 重复的键都会被选中，JSON 没有禁止重复的键
+        //
+        (Kind::Object(members), Segment::Key(key)) => members
+            .iter()
+            .filter(|(name, _)| name == key)
+            .map(|(_, child)| child)
+            .collect(),
+        (Kind::Object(members), Segment::Wildcard) => {
+            members.iter().map(|(_, child)| child).collect()
+        }
+        (Kind::Array(items), Segment::Wildcard) => items.iter().collect(),
+        (Kind::Array(items), Segment::Index(index)) => {
+            let index = if *index < 0 {
+                items.len().checked_sub(index.unsigned_abs())
+            } else {
+                Some(*index as usize)
+            };
+            index
+                .and_then(|index| items.get(index))
+                .into_iter()
+                .collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+///
+/// 只替换路径选中的字符串值，返回替换后的文本
+/// 选中的对象、数组、数字等不是字符串的值会被忽略
+///
+pub fn replace_values(
+    text: &str,
+    rules: &[Rule],
+    path: &JsonPath,
+    selector: &mut Selector,
+) -> Result<String, String> {
+    let root = json::parse(text)?;
+    let mut output = String::with_capacity(text.len());
+    let mut written = 0;
+    let mut line = 1;
+
+    for node in path.select(&root) {
+        let Kind::String(original) = &node.kind else {
+            continue;
+        };
+        line += count_newlines(&text[written..node.span.start]);
+
+        let mut value = Cow::Borrowed(original.as_str());
+        for rule in rules {
+            let replaced = match replace_text(&value, line, &rule.re, &rule.replacement, selector) {
+                Cow::Owned(replaced) => replaced,
+                Cow::Borrowed(_) => continue,
+            };
+            value = Cow::Owned(replaced);
+        }
+        if value != original.as_str() {
+            output.push_str(&text[written..node.span.start]);
+            output.push_str(&json::quote(&value));
+        } else {
+            output.push_str(&text[written..node.span.end]);
+        }
+        written = node.span.end;
+    }
+
+    output.push_str(&text[written..]);
+    Ok(output)
+}
+
+///
+/// 替换 JSON 文件到临时文件
+///
+pub fn replace_in_file(
+    target_file: &Path,
+    rules: &[Rule],
+    path: &JsonPath,
+    selector: &mut Selector,
+) -> Result<PathBuf, Box<dyn Error>> {
+    let text = fs::read_to_string(target_file)?;
+    let replaced = replace_values(&text, rules, path, selector)?;
+
+    let temp_file = install::create_temp_file(target_file)?;
+    let temp_file_path = temp_file.path().to_path_buf();
+    let mut file = OpenOptions::new()
+        .append(true)
+        .open(temp_file_path.clone())?;
+    file.write_all(replaced.as_bytes())?;
+    let _ = temp_file.persist(&temp_file_path)?;
+
+    Ok(temp_file_path)
+}
@@ -0,0 +1,292 @@
+//!
+//! --key-path：只在 YAML 或 TOML 文件中指定键路径下的字符串值中替换
+//! 改动过的值按原来的引号风格写回原来的位置，注释和其他内容原样保留
+//!
+
+use std::borrow::Cow;
+use std::error::Error;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::json;
+use crate::select::Selector;
+use crate::toml::{self, Spanned, Value};
+use crate::validate::Syntax;
+use crate::yaml;
+use crate::{install, replace_text, Rule};
+
+///
+/// 用 . 分隔的键路径，* 匹配任意一个键或下标，序列和数组的元素用从 0 开始的下标表示
+/// 例如 metadata.name、jobs.*.steps.0.uses
+///
+#[derive(Debug, Clone)]
+pub struct KeyPath {
+    segments: Vec<String>,
+}
+
+impl FromStr for KeyPath {
+    type Err = String;
+
+    fn from_str(path: &str) -> Result<Self, Self::Err> {
+        let segments: Vec<String> = path.split('.').map(str::to_string).collect();
+        if segments.iter().any(String::is_empty) {
+            return Err("键路径中不能有空的部分".to_string());
+        }
+        Ok(KeyPath { segments })
+    }
+}
+
+impl KeyPath {
+    fn matches(&self, path: &[String]) -> bool {
+        self.segments.len() == path.len()
+            && self
+                .segments
+                .iter()
+                .zip(path)
+                .all(|(segment, key)| segment == "*" || segment == key)
+    }
+}
+
+///
+/// 值在原文中的写法，改动后按同样的写法写回
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    ///
+    /// YAML 不带引号的值
+    ///
+    Plain,
+    ///
+    /// YAML '...'，'' 表示一个单引号
+    ///
+    SingleQuoted,
+    ///
+    /// YAML 或 TOML 的 "..."，转义规则和 JSON 兼容
+    ///
+    DoubleQuoted,
+    ///
+    /// YAML 的 | 或 > 块标量，内容的每一行都有 indent 个空格的缩进
+    ///
+    Block {
+        indent: usize,
+    },
+    ///
+    /// TOML '...'，不能转义
+    ///
+    Literal,
+    ///
+    /// TOML 多行字符串，newline 表示开头引号后面有换行
+    ///
+    MultilineBasic {
+        newline: bool,
+    },
+    MultilineLiteral {
+        newline: bool,
+    },
+}
+
+impl Style {
+    ///
+    /// 把新的值写成原来的风格，原来的风格表示不了时改用双引号
+    ///
+    fn encode(self, value: &str) -> String {
+        let controls = value
+            .chars()
+            .any(|ch| ch.is_control() && ch != '\t' && ch != '\n');
+        match self {
+            Style::Plain if is_plain_safe(value) => value.to_string(),
+            Style::SingleQuoted if !controls && !value.contains('\n') => {
+                format!("'{}'", value.replace('\'', "''"))
+            }
+            Style::Block { indent } => value
+                .split('\n')
+                .enumerate()
+                .map(|(index, line)| match index {
+                    0 => line.to_string(),
+                    _ if line.is_empty() => String::new(),
+                    _ => format!("{}{}", " ".repeat(indent), line),
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Style::Literal if !controls && !value.contains(['\'', '\n']) => format!("'{}'", value),
+            Style::MultilineLiteral { newline } if !controls && !value.contains("'''") => {
+                format!("'''{}{}'''", if newline { "\n" } else { "" }, value)
+            }
+            Style::MultilineBasic { newline } | Style::MultilineLiteral { newline } => {
+                //
+                // 多行字符串中的换行原样保留，其他字符按普通字符串转义
+                //
+                let escaped = value
+                    .split('\n')
+                    .map(|line| {
+                        let quoted = json::quote(line);
+                        quoted[1..quoted.len() - 1].to_string()
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                format!("\"\"\"{}{}\"\"\"", if newline { "\n" } else { "" }, escaped)
+            }
+            _ => json::quote(value),
+        }
+    }
+}
+
+///
+/// 值能否不加引号写成 YAML 普通标量
+///
+fn is_plain_safe(value: &str) -> bool {
+    !value.is_empty()
+        && value.trim() == value
+        && !value.chars().any(char::is_control)
+        && !value.contains(": ")
+        && !value.contains(" #")
+        && !value.ends_with(':')
+        && !value.starts_with([
+            ',', '[', ']', '{', '}', '#', '&', '*', '!', '|', '>', '\'', '"', '%', '@', '`',
+        ])
+        && !["- ", "? ", ": "]
+            .iter()
+            .any(|prefix| value.starts_with(prefix))
+        && !matches!(value, "-" | "?" | ":")
+}
+
+///
+/// 一个标量值：从根开始的键路径、在原文中的位置和还原后的内容
+///
+pub struct Scalar {
+    pub path: Vec<String>,
+    pub span: Range<usize>,
+    pub line: usize,
+    pub value: String,
+    pub style: Style,
+}
+
+///
+/// TOML 中所有的字符串值
+///
+fn toml_scalars(text: &str) -> Result<Vec<Scalar>, String> {
+    let document = toml::parse(text).map_err(|err| err.to_string())?;
+    let mut scalars = Vec::new();
+    let mut path = Vec::new();
+    for (key, value) in &document.entries {
+        path.push(key.clone());
+        collect_toml(text, value, &mut path, &mut scalars);
+        path.pop();
+    }
+    scalars.sort_by_key(|scalar| scalar.span.start);
+    Ok(scalars)
+}
+
+fn collect_toml(text: &str, spanned: &Spanned, path: &mut Vec<String>, scalars: &mut Vec<Scalar>) {
+    match &spanned.value {
+        Value::String(value) => {
+            let raw = &text[spanned.span.clone()];
+            let newline = |quotes: &str| {
+                raw[quotes.len()..].starts_with('\n') || raw[quotes.len()..].starts_with("\r\n")
+            };
+            let style = if raw.starts_with("\"\"\"") {
+                Style::MultilineBasic {
+                    newline: newline("\"\"\""),
+                }
+            } else if raw.starts_with("'''") {
+                Style::MultilineLiteral {
+                    newline: newline("'''"),
+                }
+            } else if raw.starts_with('\'') {
+                Style::Literal
+            } else {
+                Style::DoubleQuoted
+            };
+            scalars.push(Scalar {
+                path: path.clone(),
+                span: spanned.span.clone(),
+                line: spanned.line,
+                value: value.clone(),
+                style,
+            });
+        }
+        Value::Table(table) => {
+            for (key, value) in &table.entries {
+                path.push(key.clone());
+                collect_toml(text, value, path, scalars);
+                path.pop();
+            }
+        }
+        Value::Array(items) => {
+            for (index, item) in items.iter().enumerate() {
+                path.push(index.to_string());
+                collect_toml(text, item, path, scalars);
+                path.pop();
+            }
+        }
+        Value::Integer(_) | Value::Boolean(_) => {}
+    }
+}
+
+///
+/// 只替换键路径下的字符串值，返回替换后的文本
+///
+pub fn replace_values(
+    text: &str,
+    syntax: Syntax,
+    rules: &[Rule],
+    path: &KeyPath,
+    selector: &mut Selector,
+) -> Result<String, String> {
+    let scalars = match syntax {
+        Syntax::Yaml => yaml::scalars(text),
+        Syntax::Toml => toml_scalars(text)?,
+        Syntax::Json => return Err("JSON 文件请使用 --json-path".to_string()),
+    };
+    let mut output = String::with_capacity(text.len());
+    let mut written = 0;
+
+    for scalar in scalars.iter().filter(|scalar| path.matches(&scalar.path)) {
+        let mut value = Cow::Borrowed(scalar.value.as_str());
+        for rule in rules {
+            let replaced =
+                match replace_text(&value, scalar.line, &rule.re, &rule.replacement, selector) {
+                    Cow::Owned(replaced) => replaced,
+                    Cow::Borrowed(_) => continue,
+                };
+            value = Cow::Owned(replaced);
+        }
+        if value != scalar.value.as_str() {
+            output.push_str(&text[written..scalar.span.start]);
+            output.push_str(&scalar.style.encode(&value));
+            written = scalar.span.end;
+        }
+    }
+
+    output.push_str(&text[written..]);
+    Ok(output)
+}
+
+///
+/// 替换 YAML 或 TOML 文件到临时文件，格式按扩展名识别
+///
+pub fn replace_in_file(
+    target_file: &Path,
+    rules: &[Rule],
+    path: &KeyPath,
+    selector: &mut Selector,
+) -> Result<PathBuf, Box<dyn Error>> {
+    let Some(syntax) = Syntax::detect(target_file) else {
+        return Err("--key-path 只支持扩展名为 .yaml、.yml 或 .toml 的文件".into());
+    };
+    let text = fs::read_to_string(target_file)?;
+    let replaced = replace_values(&text, syntax, rules, path, selector)?;
+
+    let temp_file = install::create_temp_file(target_file)?;
+    let temp_file_path = temp_file.path().to_path_buf();
+    let mut file = OpenOptions::new()
+        .append(true)
+        .open(temp_file_path.clone())?;
+    file.write_all(replaced.as_bytes())?;
+    let _ = temp_file.persist(&temp_file_path)?;
+
+    Ok(temp_file_path)
+}
@@ -0,0 +1,1987 @@
+//!
+//! 正则替换引擎：命令行工具 regex-replace 的全部功能，也可以嵌入到其他程序中使用
+//! 构造 ReplacePlan 描述要做的替换，用 WalkOptions 遍历目录得到文件列表，再交给 Replacer 替换并写回
+//!
+
+use clap::ValueEnum;
+use ignore::{WalkBuilder, WalkState};
+use rayon::prelude::*;
+use regex::{Regex, RegexBuilder, RegexSetBuilder};
+use std::borrow::Cow;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::iter;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::Instant;
+
+mod acl;
+mod analysis;
+mod bytes;
+mod cache;
+mod cleanup;
+pub mod cli;
+mod completions;
+mod config;
+mod csv;
+mod diff;
+mod encoding;
+mod encoding_tables;
+mod error;
+mod estimate;
+mod exec;
+mod generated;
+mod ident;
+mod install;
+mod interrupt;
+mod json;
+mod jsonpath;
+mod keypath;
+mod lines;
+mod matrix;
+mod metrics;
+mod mmap;
+mod multibyte;
+mod normalize;
+mod patch;
+mod paths;
+mod prefilter;
+mod priority;
+mod progress;
+mod queue;
+mod records;
+mod rename;
+mod report;
+mod rules;
+mod ruletest;
+mod sarif;
+mod script;
+mod search;
+mod select;
+mod space;
+mod staged;
+mod stat;
+mod stop;
+mod stream;
+mod summary;
+mod template;
+mod toml;
+mod tui;
+mod unicode_tables;
+mod validate;
+mod vcs;
+mod xattr;
+mod yaml;
+
+//
+// 嵌入到其他程序时用到的类型，其他模块只供命令行使用
+//
+pub use cache::Cache;
+pub use config::Config;
+pub use encoding::{BomMode, Encoding};
+pub use error::FileError;
+pub use install::{Preserve, WriteOptions};
+pub use metrics::{Timings, Usage};
+pub use progress::Progress;
+pub use rename::Rename;
+pub use report::FileReport;
+pub use select::Selection;
+pub use stat::FileStat;
+pub use summary::{Summary, SummaryOptions};
+pub use template::{TokenScope, Tokens};
+pub use validate::ValidateMode;
+
+use bytes::ByteRules;
+use csv::CsvOptions;
+use install::{Fingerprint, Transaction};
+use jsonpath::JsonPath;
+use keypath::KeyPath;
+use lines::LineEdit;
+use metrics::Metrics;
+use normalize::Normalization;
+use prefilter::Prefilter;
+use select::Selector;
+use template::Template;
+
+///
+/// 选择替换引擎的方式
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Strategy {
+    ///
+    /// 根据正则和选择条件自动选择，逐行替换失败时改用整个文件替换
+    ///
+    Auto,
+    ///
+    /// 只使用逐行替换，需要整个文件替换时报错，保证内存占用有上限
+    ///
+    Line,
+    ///
+    /// 总是整个文件替换
+    ///
+    Whole,
+}
+
+///
+/// 按 UTF-8 处理的文件内容不是有效的 UTF-8 时怎样处理
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum InvalidUtf8 {
+    ///
+    /// 跳过这个文件，最后列出跳过的文件，不算处理失败
+    ///
+    Skip,
+    ///
+    /// 把无效的字节替换成 U+FFFD 之后照常处理，写回的文件中这些字节同样变成 U+FFFD
+    ///
+    Lossy,
+    ///
+    /// 报错，这个文件处理失败
+    ///
+    Error,
+}
+
+///
+/// 一条替换规则
+///
+#[derive(Clone)]
+pub struct Rule {
+    pub re: Regex,
+    pub replacement: Template,
+    ///
+    /// 原始的替换字符串，写入队列文件时使用
+    ///
+    pub raw_replacement: String,
+    ///
+    /// 最大行数
+    /// 正则跨行匹配，不允许超过 n + 1 行
+    /// 否则逐行替换会失效
+    /// 由正则的语法树分析得出，None 表示只能整个文件替换
+    ///
+    pub max_line_number: Option<usize>,
+    ///
+    /// 流式替换需要的重叠窗口，正则的匹配长度没有上限时为 None
+    ///
+    pub stream_window: Option<usize>,
+}
+
+///
+/// 编译正则时的大小限制，None 时使用 regex 的默认值
+/// 规则文件生成的巨大正则（上千个分支）超过默认限制时需要调大
+///
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RegexLimits {
+    ///
+    /// 编译后的正则程序的大小上限（字节）
+    ///
+    pub size_limit: Option<usize>,
+    ///
+    /// 惰性 DFA 缓存的大小上限（字节）
+    ///
+    pub dfa_size_limit: Option<usize>,
+}
+
+impl RegexLimits {
+    ///
+    /// 使用这些大小限制的 RegexBuilder
+    ///
+    pub fn builder(&self, pattern: &str) -> RegexBuilder {
+        let mut builder = RegexBuilder::new(pattern);
+        if let Some(size_limit) = self.size_limit {
+            builder.size_limit(size_limit);
+        }
+        if let Some(dfa_size_limit) = self.dfa_size_limit {
+            builder.dfa_size_limit(dfa_size_limit);
+        }
+        builder
+    }
+
+    ///
+    /// 使用同样大小限制的 RegexSetBuilder
+    ///
+    pub fn set_builder<'p>(&self, patterns: impl IntoIterator<Item = &'p str>) -> RegexSetBuilder {
+        let mut builder = RegexSetBuilder::new(patterns);
+        if let Some(size_limit) = self.size_limit {
+            builder.size_limit(size_limit);
+        }
+        if let Some(dfa_size_limit) = self.dfa_size_limit {
+            builder.dfa_size_limit(dfa_size_limit);
+        }
+        builder
+    }
+
+    ///
+    /// --bytes 按字节匹配时使用同样大小限制的 RegexBuilder
+    ///
+    pub fn bytes_builder(&self, pattern: &str) -> regex::bytes::RegexBuilder {
+        let mut builder = regex::bytes::RegexBuilder::new(pattern);
+        if let Some(size_limit) = self.size_limit {
+            builder.size_limit(size_limit);
+        }
+        if let Some(dfa_size_limit) = self.dfa_size_limit {
+            builder.dfa_size_limit(dfa_size_limit);
+        }
+        builder
+    }
+}
+
+///
+/// 正则编译错误，超过大小限制时提示可以调大限制
+///
+fn regex_error(err: regex::Error) -> String {
+    match err {
+        regex::Error::CompiledTooBig(_) => format!(
+            "无效正则表达式: {}，可以用 --regex-size-limit 调大限制",
+            err.to_string().trim_end_matches('.')
+        ),
+        err => format!("无效正则表达式: {}", err),
+    }
+}
+
+impl Rule {
+    ///
+    /// 使用 regex 默认大小限制的规则
+    ///
+    pub fn new(pattern: &str, replacement: &str, tokens: &Tokens) -> Result<Self, String> {
+        Self::with_limits(pattern, replacement, tokens, RegexLimits::default())
+    }
+
+    ///
+    /// 按指定的大小限制编译正则，替换计划的 regex_limits 应该和这里相同
+    ///
+    pub fn with_limits(
+        pattern: &str,
+        replacement: &str,
+        tokens: &Tokens,
+        limits: RegexLimits,
+    ) -> Result<Self, String> {
+        let re = limits.builder(pattern).build().map_err(regex_error)?;
+        check_string(pattern)?;
+        let raw_replacement = replacement.to_string();
+        let replacement = Template::parse(replacement, &re, tokens)?;
+
+        Ok(Self {
+            re,
+            replacement,
+            raw_replacement,
+            max_line_number: analysis::max_line_number(pattern),
+            stream_window: stream::window(pattern),
+        })
+    }
+
+    ///
+    /// --null-data：以 NUL 作为记录的分隔符，^ 和 $ 匹配每条记录的开头和结尾，. 可以匹配换行
+    /// 匹配可以跨越任意多行，只能整个文件替换
+    ///
+    pub fn null_data(mut self, limits: RegexLimits) -> Result<Self, String> {
+        self.re = limits
+            .builder(self.re.as_str())
+            .multi_line(true)
+            .line_terminator(b'\0')
+            .build()
+            .map_err(regex_error)?;
+        self.max_line_number = None;
+        self.stream_window = None;
+        Ok(self)
+    }
+}
+
+///
+/// 一次运行的替换计划：规则、匹配的选择条件以及项目配置
+///
+#[derive(Clone)]
+pub struct ReplacePlan {
+    pub rules: Vec<Rule>,
+    pub selection: Selection,
+    pub config: Config,
+    ///
+    /// 检查匹配时使用内存映射
+    ///
+    pub mmap: bool,
+    pub strategy: Strategy,
+    ///
+    /// 按 CSV 格式只替换某一列
+    ///
+    pub csv: Option<CsvOptions>,
+    ///
+    /// 按 JSON 格式只替换选中的字符串值
+    ///
+    pub json_path: Option<JsonPath>,
+    ///
+    /// 按 YAML 或 TOML 格式只替换键路径下的字符串值
+    ///
+    pub key_path: Option<KeyPath>,
+    ///
+    /// 超过这个大小（字节）的文件不做替换
+    ///
+    pub max_filesize: Option<u64>,
+    ///
+    /// 有超过这个长度（字节）的行的文件不做替换，例如压缩过的 JS 和 JSON
+    ///
+    pub max_line_length: Option<usize>,
+    ///
+    /// 同样替换开头有生成标记的文件，默认跳过它们
+    ///
+    pub include_generated: bool,
+    ///
+    /// 不做替换，而是按行编辑：删除、只保留包含匹配的行，或者在它们前后插入一行
+    ///
+    pub line_edit: Option<LineEdit>,
+    ///
+    /// 匹配之前把文件内容规范化成这种形式，写回的内容同样是这种形式
+    ///
+    pub normalize: Option<Normalization>,
+    ///
+    /// 以 NUL 而不是换行分隔记录，按行编辑时按记录处理
+    ///
+    pub null_data: bool,
+    ///
+    /// 按这个分隔符把文件切成记录，每条记录单独替换
+    ///
+    pub record_separator: Option<Regex>,
+    ///
+    /// 写回时怎样处理 BOM
+    ///
+    pub bom: BomMode,
+    ///
+    /// 多条规则时一次检查所有规则的 RegexSet
+    ///
+    pub prefilter: Prefilter,
+    ///
+    /// --bytes：按字节匹配和替换，不按编码解码
+    ///
+    pub bytes: Option<ByteRules>,
+    ///
+    /// 按 UTF-8 处理的文件不是有效的 UTF-8 时怎样处理
+    ///
+    pub invalid_utf8: InvalidUtf8,
+    ///
+    /// 编译正则时的大小限制，规则和多条规则合并成的 RegexSet 都按它编译
+    ///
+    pub regex_limits: RegexLimits,
+}
+
+impl ReplacePlan {
+    ///
+    /// 只包含规则的替换计划，其余选项都取默认值：不限制匹配，自动选择替换引擎
+    ///
+    pub fn new(rules: Vec<Rule>) -> Self {
+        Self {
+            rules,
+            selection: Selection::default(),
+            config: Config::default(),
+            mmap: false,
+            strategy: Strategy::Auto,
+            csv: None,
+            json_path: None,
+            key_path: None,
+            max_filesize: None,
+            max_line_length: None,
+            include_generated: false,
+            line_edit: None,
+            normalize: None,
+            null_data: false,
+            record_separator: None,
+            bom: BomMode::Preserve,
+            prefilter: Prefilter::default(),
+            bytes: None,
+            invalid_utf8: InvalidUtf8::Error,
+            regex_limits: RegexLimits::default(),
+        }
+    }
+
+    ///
+    /// 文件的编码：按配置确定，按 UTF-8 处理的文件开头有 UTF-16 的 BOM 时按 BOM 判断
+    ///
+    pub fn encoding_for(&self, file: &Path) -> Encoding {
+        encoding::detect(file, self.config.encoding_for(file))
+    }
+
+    ///
+    /// 按文件的编码读取文件内容，去掉开头的 BOM，指定了规范化形式时同时规范化
+    ///
+    pub fn read_text(&self, file: &Path) -> io::Result<String> {
+        self.read_text_as(file, self.encoding_for(file))
+    }
+
+    ///
+    /// 按指定的编码读取文件内容，--staged 在临时副本上读取时使用原路径的编码
+    ///
+    pub fn read_text_as(&self, file: &Path, encoding: Encoding) -> io::Result<String> {
+        let mut text = match encoding {
+            Encoding::Utf8 if self.invalid_utf8 == InvalidUtf8::Lossy => {
+                String::from_utf8_lossy(&fs::read(file)?).into_owned()
+            }
+            Encoding::Utf8 => fs::read_to_string(file)?,
+            encoding => encoding
+                .decode(&fs::read(file)?)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+        };
+        if text.starts_with('\u{FEFF}') {
+            text.drain(..'\u{FEFF}'.len_utf8());
+        }
+        Ok(match self.normalize {
+            Some(form) => form.apply(&text),
+            None => text,
+        })
+    }
+
+    ///
+    /// text 中是否有任何规则的匹配，多条规则时用 RegexSet 一次检查
+    ///
+    fn any_match(&self, text: &str) -> bool {
+        self.prefilter
+            .is_match(&self.rules, self.null_data, self.regex_limits, text)
+    }
+
+    ///
+    /// 文件中是否有任何规则的匹配
+    /// 没有一条规则能匹配原文件时，依次应用规则也不会产生任何改动
+    /// 全部是单行规则时逐行流式检查，不需要把整个文件读入内存
+    ///
+    pub fn has_match(&self, file: &Path) -> io::Result<bool> {
+        self.has_match_as(file, self.encoding_for(file))
+    }
+
+    ///
+    /// 按指定的编码检查文件中是否有匹配，--staged 在临时副本上检查时使用原路径的编码
+    ///
+    pub fn has_match_as(&self, file: &Path, encoding: Encoding) -> io::Result<bool> {
+        //
+        // 只保留匹配的行时，没有匹配的文件也要处理
+        //
+        if self.line_edit == Some(LineEdit::Keep) {
+            return Ok(true);
+        }
+        if let Some(bytes) = &self.bytes {
+            return bytes.has_match(file);
+        }
+        //
+        // 按记录替换时 ^ 和 $ 匹配记录的边界，需要按记录检查
+        //
+        if let Some(separator) = &self.record_separator {
+            let text = self.read_text_as(file, encoding)?;
+            if !self.prefilter.may_match(&self.rules, &text) {
+                return Ok(false);
+            }
+            return Ok(separator.split(&text).any(|record| self.any_match(record)));
+        }
+        let single_line = self
+            .rules
+            .iter()
+            .all(|rule| rule.max_line_number == Some(1));
+        //
+        // 需要规范化时只能读出整个文件
+        //
+        let plain = self.normalize.is_none() && encoding == Encoding::Utf8;
+        if self.mmap && plain {
+            return self.has_match_mapped(file, single_line);
+        }
+        //
+        // 逐行读取遇到无效的 UTF-8 会出错，有损解码时读出整个文件
+        //
+        if single_line && plain && self.invalid_utf8 != InvalidUtf8::Lossy {
+            //
+            // 先按块查找字面量，大部分没有匹配的文件不需要逐行执行正则
+            //
+            if !self.prefilter.may_match_file(&self.rules, file)? {
+                return Ok(false);
+            }
+            let mut reader = BufReader::new(File::open(file)?);
+            let mut line = String::new();
+            let mut first = true;
+            while reader.read_line(&mut line)? > 0 {
+                //
+                // 和逐行替换使用的 lines() 一样去掉行尾的 \n 或 \r\n，第一行还要去掉 BOM
+                //
+                let text = if std::mem::take(&mut first) {
+                    encoding::strip_bom(&line)
+                } else {
+                    &line
+                };
+                let text = text.strip_suffix('\n').unwrap_or(text);
+                let text = text.strip_suffix('\r').unwrap_or(text);
+                if self.any_match(text) {
+                    return Ok(true);
+                }
+                line.clear();
+            }
+            return Ok(false);
+        }
+
+        let text = self.read_text_as(file, encoding)?;
+        Ok(self.prefilter.may_match(&self.rules, &text) && self.any_match(&text))
+    }
+
+    ///
+    /// 检查匹配之前就能确定不做替换的文件：超过 --max-filesize，读不到大小的文件交给后面正常报错
+    /// 替换、--staged 和 tui 都按 skip_reason 和 skip_matched 跳过文件
+    ///
+    pub fn skip_reason(&self, file: &Path) -> Option<FileError> {
+        let max_filesize = self.max_filesize?;
+        fs::metadata(file)
+            .is_ok_and(|metadata| metadata.len() > max_filesize)
+            .then_some(FileError::TooLarge(max_filesize))
+    }
+
+    ///
+    /// 有匹配的文件中不做替换的：开头有生成标记（没有指定 --include-generated），
+    /// 或者有超过 --max-line-length 的行
+    /// 都要再读一遍文件，所以只检查有匹配的文件，读取失败的文件交给后面正常报错
+    ///
+    pub fn skip_matched(&self, file: &Path) -> Option<FileError> {
+        if !self.include_generated {
+            if let Ok(Some(marker)) = generated::marker(file) {
+                return Some(FileError::Generated(marker));
+            }
+        }
+        let max_line_length = self.max_line_length?;
+        has_long_line(file, max_line_length)
+            .is_ok_and(|long| long)
+            .then_some(FileError::LineTooLong(max_line_length))
+    }
+
+    ///
+    /// 在文件的内存映射上检查匹配，没有匹配的文件不会被复制到缓冲区
+    ///
+    fn has_match_mapped(&self, file: &Path, single_line: bool) -> io::Result<bool> {
+        let mapped = mmap::Mmap::open(file)?;
+        //
+        // 先在原始的字节上查找字面量，和流式检查一样，找不到字面量的文件不检查编码
+        //
+        if !self.prefilter.may_match_bytes(&self.rules, &mapped) {
+            return Ok(false);
+        }
+        let text = match std::str::from_utf8(&mapped) {
+            Ok(text) => Cow::Borrowed(text),
+            Err(_) if self.invalid_utf8 == InvalidUtf8::Lossy => String::from_utf8_lossy(&mapped),
+            Err(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "stream did not contain valid UTF-8",
+                ))
+            }
+        };
+        let text = encoding::strip_bom(&text);
+        if single_line {
+            return Ok(text.lines().any(|line| self.any_match(line)));
+        }
+        Ok(self.any_match(text))
+    }
+}
+
+///
+/// 行读取器
+///
+struct LineReader {
+    lines: Box<dyn Iterator<Item = io::Result<String>>>,
+}
+
+impl LineReader {
+    fn new(reader: Box<dyn BufRead>) -> Self {
+        Self {
+            lines: Box::new(reader.lines()),
+        }
+    }
+
+    fn read_lines(&mut self, num_lines: usize) -> io::Result<Vec<String>> {
+        let mut lines = Vec::new();
+        for line in self.lines.by_ref().take(num_lines) {
+            lines.push(line?);
+        }
+        Ok(lines)
+    }
+}
+
+pub fn count_newlines(text: &str) -> usize {
+    text.bytes().filter(|&b| b == b'\n').count()
+}
+
+///
+/// 替换一段文本中被选中的匹配
+/// first_line 是这段文本第一行在文件中的行号，用来判断匹配所在的行
+///
+pub fn replace_text<'a>(
+    text: &'a str,
+    first_line: usize,
+    re: &Regex,
+    replacement: &Template,
+    selector: &mut Selector,
+) -> Cow<'a, str> {
+    let mut replaced = String::new();
+    let mut last_end = 0;
+    let mut line = first_line;
+    let mut counted = 0;
+    let mut changed = false;
+    selector.set_text(text);
+
+    for caps in re.captures_iter(text) {
+        let m = caps.get(0).unwrap();
+        line += count_newlines(&text[counted..m.start()]);
+        counted = m.start();
+        if !selector.allows(m.range(), line) {
+            continue;
+        }
+        replaced.push_str(&text[last_end..m.start()]);
+        replacement.append(&caps, line, &mut replaced);
+        last_end = m.end();
+        changed = true;
+    }
+
+    if !changed {
+        return Cow::Borrowed(text);
+    }
+    replaced.push_str(&text[last_end..]);
+    Cow::Owned(replaced)
+}
+
+///
+/// 返回文本中每个被选中的匹配的起始行号
+///
+fn selected_match_lines(text: &str, re: &Regex, selection: &Selection) -> Vec<usize> {
+    let mut selector = selection.selector();
+    selector.set_text(text);
+    let mut lines = Vec::new();
+    let mut line = 1;
+    let mut counted = 0;
+    for m in re.find_iter(text) {
+        line += count_newlines(&text[counted..m.start()]);
+        counted = m.start();
+        if selector.allows(m.range(), line) {
+            lines.push(line);
+        }
+    }
+    lines
+}
+
+///
+/// 用逐行的方法替换文件
+///
+fn replace_in_file_line_by_line(
+    target_file: &PathBuf,
+    re: &Regex,
+    replacement: &Template,
+    max_line_number: &usize,
+    selector: &mut Selector,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    //
+    // 创建临时文件
+    //
+    let temp_file = install::create_temp_file(target_file)?;
+    let temp_file_path = temp_file.path().to_path_buf();
+    let file = OpenOptions::new()
+        .append(true)
+        .open(temp_file_path.clone())?;
+    let mut file = BufWriter::new(file);
+
+    let f = File::open(target_file)?;
+    let reader = BufReader::new(f);
+    let mut line_reader = LineReader::new(Box::new(reader));
+
+    //
+    // 先读取若干行
+    // 单行正则匹配的话，逐行处理
+    // 多行的话，预加载两倍的行数防止跨行匹配失败
+    //
+    let mut buffer_lines = line_reader.read_lines(if *max_line_number == 1 {
+        1
+    } else {
+        *max_line_number * 2
+    })?;
+    //
+    // 缓冲区第一行的行号
+    //
+    let mut line_number = 1;
+
+    loop {
+        if buffer_lines.is_empty() {
+            break;
+        }
+        let buffer_text = &buffer_lines.join("\n");
+        let buffer_text_replaced =
+            replace_text(buffer_text, line_number, re, replacement, selector);
+
+        //
+        // 在多行匹配情况下，如果再次用正则匹配可以匹配到结果，说明不可以使用逐行匹配
+        // 例如：" \n " -> " \n  "
+        // 这时候，应该抛出错误，
+        // 然后换用整个文件替换的方式
+        //
+        if *max_line_number > 1 && re.is_match(&buffer_text_replaced) {
+            return Err(
+                "Cross-line match found, please use the whole file replacement method".into(),
+            );
+        }
+
+        //
+        // buffer_text_replaced 转换为字符串 Vec
+        //
+        let buffer_lines_replaced = buffer_text_replaced
+            .split('\n')
+            .map(String::from)
+            .collect::<Vec<_>>();
+        //
+        // 把这个 Vec 分成两部分，分别是后 n 行，和前面 len() - n 行
+        // 计算分割线索引
+        //
+        let split_at = if buffer_lines_replaced.len() > *max_line_number {
+            buffer_lines_replaced.len() - max_line_number
+        } else {
+            0
+        };
+        //
+        // 切开两部分
+        //
+        let (processed_part, unprocessed_part) = if *max_line_number == 1 {
+            //
+            // 单行的情况下，不需要分割，防止重复处理匹配，例如可能会出现以下情况
+            // 替换单个空格 " " 为两个空格 "  "
+            // 如果把已经处理过空格的 unprocessed_part 移入下一次循环，会导致重复处理
+            //
+            (buffer_lines_replaced.as_slice(), &[][..])
+        } else {
+            buffer_lines_replaced.split_at(split_at)
+        };
+        //
+        // 把已经完全处理完毕的部分写入临时文件
+        //
+        for line in processed_part {
+            writeln!(file, "{}", line)?;
+        }
+        line_number += processed_part.len();
+        buffer_lines.clear();
+        //
+        // 未完全处理的部分并入下一次的循环
+        //
+        let last_lines = unprocessed_part.iter().map(|s| s.to_string());
+        //
+        // 读取接下来 n 行
+        // 如果为空，说明没有后续内容，则把剩余部分写入文件，结束循环
+        // 如果不为空，则继续循环
+        //
+        let next = line_reader.read_lines(*max_line_number)?;
+        if next.is_empty() {
+            for line in last_lines {
+                writeln!(file, "{}", line)?;
+            }
+            break;
+        } else {
+            buffer_lines.extend(last_lines);
+            buffer_lines.extend(next);
+        }
+    }
+    file.flush()?;
+
+    //
+    // Persist the temp file
+    //
+    let _ = temp_file.persist(&temp_file_path)?;
+
+    Ok(temp_file_path)
+}
+
+///
+/// 直接替换整个文件
+///
+fn replace_in_file_whole_file(
+    target_file: &PathBuf,
+    re: &Regex,
+    replacement: &Template,
+    selector: &mut Selector,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    //
+    // 创建临时文件
+    //
+    let temp_file = install::create_temp_file(target_file)?;
+    let temp_file_path = temp_file.path().to_path_buf();
+    let file = OpenOptions::new()
+        .append(true)
+        .open(temp_file_path.clone())?;
+    let mut file = BufWriter::new(file);
+
+    //
+    // 读取整个文件
+    //
+    let contents = fs::read_to_string(target_file)?;
+    //
+    // 替换内容
+    //
+    let replaced_contents = replace_text(&contents, 1, re, replacement, selector);
+    write!(file, "{}", replaced_contents)?;
+
+    file.flush()?;
+    let _ = temp_file.persist(&temp_file_path)?;
+
+    Ok(temp_file_path)
+}
+
+///
+/// 一个文件的替换结果
+///
+pub struct Replaced {
+    pub temp_file: PathBuf,
+    ///
+    /// 所有规则一共替换的次数
+    ///
+    pub replacements: usize,
+}
+
+///
+/// 替换文件内容
+/// 多条规则按顺序依次应用，上一条规则的输出作为下一条规则的输入
+///
+pub fn replace_in_file(
+    target_file: &Path,
+    plan: &ReplacePlan,
+) -> Result<Replaced, Box<dyn std::error::Error>> {
+    script::set_file(target_file);
+    replace_in_file_as(target_file, plan.config.encoding_for(target_file), plan)
+}
+
+///
+/// 按指定的编码替换文件内容，用于内容的副本不在原路径上、编码要按原路径确定的情况
+/// 按 UTF-8 处理的文件开头有 BOM 时按 BOM 判断编码
+///
+pub fn replace_in_file_as(
+    target_file: &Path,
+    encoding: Encoding,
+    plan: &ReplacePlan,
+) -> Result<Replaced, Box<dyn std::error::Error>> {
+    let mut selector = plan.selection.selector();
+    let start = selector.checkpoint();
+
+    if let Some(bytes) = &plan.bytes {
+        return match bytes::replace_in_file(target_file, &plan.rules, bytes, &mut selector) {
+            Ok(temp_file) => Ok(Replaced {
+                temp_file,
+                replacements: selector.replaced(),
+            }),
+            Err(err) => {
+                selector.rollback(start);
+                Err(err)
+            }
+        };
+    }
+
+    //
+    // 有 BOM 或者需要改动 BOM 的文件都要先解码，否则 BOM 会被当成内容，^ 匹配不到第一行
+    //
+    let bom = encoding::sniff(target_file)?;
+    let encoding = match (encoding, bom) {
+        (Encoding::Utf8, Some(sniffed)) => sniffed,
+        _ => encoding,
+    };
+    let result = match encoding {
+        Encoding::Utf8 if plan.normalize.is_none() && bom.is_none() && plan.bom != BomMode::Add => {
+            match replace_in_file_with_rules(target_file, plan, &mut selector) {
+                //
+                // 有损解码时不是有效 UTF-8 的文件先解码再替换
+                //
+                Err(err)
+                    if plan.invalid_utf8 == InvalidUtf8::Lossy && is_invalid_data(err.as_ref()) =>
+                {
+                    selector.rollback(start);
+                    replace_in_encoded_file(target_file, encoding, plan, &mut selector)
+                }
+                result => result,
+            }
+        }
+        encoding => replace_in_encoded_file(target_file, encoding, plan, &mut selector),
+    };
+    match result {
+        Ok(temp_file) => Ok(Replaced {
+            temp_file,
+            replacements: selector.replaced(),
+        }),
+        //
+        // 文件处理失败时不会写回，把占用的替换次数还回去
+        //
+        Err(err) => {
+            selector.rollback(start);
+            Err(err)
+        }
+    }
+}
+
+///
+/// 替换非 UTF-8 编码、带 BOM 或者需要规范化的文件
+/// 先解码成不带 BOM 的 UTF-8 临时文件，按正常流程替换，最后再编码回原来的编码，按 BomMode 加上 BOM
+/// 规范化在解码之后和编码之前各做一次，替换字符串引入的字符也会被规范化
+///
+fn replace_in_encoded_file(
+    target_file: &Path,
+    encoding: Encoding,
+    plan: &ReplacePlan,
+    selector: &mut Selector,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let bytes = fs::read(target_file)?;
+    let decoded = match (encoding, plan.invalid_utf8) {
+        (Encoding::Utf8, InvalidUtf8::Lossy) => String::from_utf8_lossy(&bytes).into_owned(),
+        _ => encoding.decode(&bytes)?,
+    };
+    let has_bom = decoded.starts_with('\u{FEFF}');
+    let mut contents = encoding::strip_bom(&decoded).to_string();
+    if let Some(form) = plan.normalize {
+        contents = form.apply(&contents);
+    }
+    let decoded = install::create_temp_file(target_file)?;
+    fs::write(decoded.path(), contents)?;
+
+    let temp_file_path = replace_in_file_with_rules(decoded.path(), plan, selector)?;
+    let mut replaced = fs::read_to_string(&temp_file_path)?;
+    if let Some(form) = plan.normalize {
+        replaced = form.apply(&replaced);
+    }
+    let mut bytes = match plan.bom {
+        BomMode::Preserve if has_bom => encoding.bom().to_vec(),
+        BomMode::Add => encoding.bom().to_vec(),
+        _ => Vec::new(),
+    };
+    bytes.extend(encoding.encode(&replaced)?);
+    fs::write(&temp_file_path, bytes)?;
+
+    Ok(temp_file_path)
+}
+
+///
+/// 整个文件替换，超大的文件在正则允许时改用流式替换，不需要把整个文件读入内存
+///
+fn replace_in_file_whole(
+    source: &PathBuf,
+    rule: &Rule,
+    selector: &mut Selector,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let large = fs::metadata(source)?.len() >= stream::STREAM_THRESHOLD;
+    match rule.stream_window {
+        Some(window) if large && selector.supports_streaming() => {
+            stream::replace_in_file_streaming(source, &rule.re, &rule.replacement, window, selector)
+        }
+        _ => replace_in_file_whole_file(source, &rule.re, &rule.replacement, selector),
+    }
+}
+
+fn replace_in_file_with_rules(
+    target_file: &Path,
+    plan: &ReplacePlan,
+    selector: &mut Selector,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    if let Some(edit) = plan.line_edit {
+        return lines::replace_in_file(target_file, plan, edit, selector);
+    }
+    if let Some(separator) = &plan.record_separator {
+        return records::replace_in_file(target_file, &plan.rules, separator, selector);
+    }
+    if let Some(csv) = &plan.csv {
+        return csv::replace_in_file(target_file, &plan.rules, csv, selector);
+    }
+    if let Some(path) = &plan.json_path {
+        return jsonpath::replace_in_file(target_file, &plan.rules, path, selector);
+    }
+    if let Some(path) = &plan.key_path {
+        return keypath::replace_in_file(target_file, &plan.rules, path, selector);
+    }
+
+    let mut source = target_file.to_path_buf();
+    let mut last_temp_file: Option<PathBuf> = None;
+
+    for rule in &plan.rules {
+        let line_by_line = match rule.max_line_number {
+            Some(max_line_number) if !selector.needs_whole_file(max_line_number) => {
+                Some(max_line_number)
+            }
+            _ => None,
+        };
+        let temp_file_path = match (plan.strategy, line_by_line) {
+            (Strategy::Whole, _) | (Strategy::Auto, None) => {
+                replace_in_file_whole(&source, rule, selector)?
+            }
+            (Strategy::Line, None) => {
+                return Err(format!(
+                    "正则 {:?} 的匹配可能跨越任意多行，或者选择条件需要整个文件，不能逐行替换",
+                    rule.re.as_str()
+                )
+                .into())
+            }
+            (_, Some(1)) if fs::metadata(&source)?.len() >= patch::PATCH_THRESHOLD => {
+                patch::replace_in_file(&source, &rule.re, &rule.replacement, selector)?
+            }
+            (strategy, Some(max_line_number)) => {
+                let checkpoint = selector.checkpoint();
+                match replace_in_file_line_by_line(
+                    &source,
+                    &rule.re,
+                    &rule.replacement,
+                    &max_line_number,
+                    selector,
+                ) {
+                    Ok(temp_file_path) => temp_file_path,
+                    //
+                    // 读取失败（例如内容不是有效的 UTF-8）不是跨行的匹配，整个文件替换同样会失败
+                    //
+                    Err(err) if err.is::<io::Error>() => return Err(err),
+                    Err(_) if strategy == Strategy::Line => {
+                        return Err(format!(
+                            "正则 {:?} 在替换结果中出现了跨行的匹配，不能逐行替换",
+                            rule.re.as_str()
+                        )
+                        .into())
+                    }
+                    Err(_) => {
+                        selector.rollback(checkpoint);
+                        replace_in_file_whole(&source, rule, selector)?
+                    }
+                }
+            }
+        };
+
+        //
+        // 上一条规则产生的临时文件已经没用了
+        //
+        if let Some(previous) = last_temp_file.replace(temp_file_path.clone()) {
+            fs::remove_file(previous)?;
+        }
+        source = temp_file_path;
+    }
+
+    match last_temp_file {
+        Some(temp_file_path) => Ok(temp_file_path),
+        None => Err("没有可应用的规则".into()),
+    }
+}
+
+///
+/// 文件中是否有超过 max_line_length 字节的行（不含换行符），按块读取，不需要把整行读入内存
+///
+fn has_long_line(file: &Path, max_line_length: usize) -> io::Result<bool> {
+    let mut reader = BufReader::new(File::open(file)?);
+    let mut length = 0;
+    loop {
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            return Ok(false);
+        }
+        let mut start = 0;
+        for end in memchr::memchr_iter(b'\n', buffer).chain(iter::once(buffer.len())) {
+            length += end - start;
+            if length > max_line_length {
+                return Ok(true);
+            }
+            if end < buffer.len() {
+                length = 0;
+            }
+            start = end + 1;
+        }
+        let consumed = buffer.len();
+        reader.consume(consumed);
+    }
+}
+
+///
+/// 错误是否是读取的内容不是有效的 UTF-8（或者按编码解码失败）
+///
+fn is_invalid_data(err: &(dyn std::error::Error + 'static)) -> bool {
+    err.downcast_ref::<io::Error>()
+        .is_some_and(|err| err.kind() == io::ErrorKind::InvalidData)
+}
+
+///
+/// 检查字符串是否包含有效的转义序列
+/// 对于单个反斜杠，默认情况下会被 rust 忽略处理
+/// 但是这里选择直接报错，必须确保输入的正则是完全正确的
+///
+fn check_string(_s: &str) -> Result<(), String> {
+    // 有点问题，先注释
+    // let mut chars = s.chars().peekable();
+    // while let Some(ch) = chars.next() {
+    //     if ch == '\\' {
+    //         match chars.peek() {
+    //             Some('n') | Some('r') | Some('t') | Some('\'') | Some('"') => {
+    //                 let _ = chars.next();
+    //             }
+    //             _ => return Err(format!("存在多余的单斜杠: {}", s)),
+    //         }
+    //     }
+    // }
+    Ok(())
+}
+
+///
+/// 遍历目录的选项
+/// 遍历时总是会读取 .gitignore、.ignore 和 .rgignore
+///
+#[derive(Debug, Clone, Default)]
+pub struct WalkOptions {
+    ///
+    /// 额外使用的忽略文件，格式和 .gitignore 相同
+    ///
+    pub ignore_files: Vec<PathBuf>,
+}
+
+impl WalkOptions {
+    ///
+    /// 遍历目录，返回其中所有没有被忽略的文件，按路径排序
+    ///
+    pub fn walk(&self, dir: &Path) -> Result<Vec<PathBuf>, String> {
+        let files = Mutex::new(Vec::new());
+        self.walk_parallel(dir, |file| files.lock().unwrap().push(file))?;
+        let mut files = files.into_inner().unwrap();
+        files.sort();
+        Ok(files)
+    }
+
+    ///
+    /// 多线程遍历目录，每找到一个文件就交给 visit，visit 会在多个线程中同时被调用
+    /// 遇到错误时继续遍历，结束后返回第一个错误
+    ///
+    pub fn walk_parallel<F>(&self, dir: &Path, visit: F) -> Result<(), String>
+    where
+        F: Fn(PathBuf) + Sync,
+    {
+        self.walk_until(dir, |file| {
+            visit(file);
+            true
+        })
+    }
+
+    ///
+    /// 数出目录下的文件数，超过 limit 时马上停止遍历，返回值大于 limit 时只说明文件数超过了 limit
+    /// 遍历的错误留给之后真正的遍历报告
+    ///
+    pub fn count(&self, dir: &Path, limit: usize) -> usize {
+        let count = AtomicUsize::new(0);
+        let _ = self.walk_until(dir, |_| count.fetch_add(1, Ordering::Relaxed) < limit);
+        count.into_inner()
+    }
+
+    ///
+    /// 同 walk_parallel，visit 返回 false 时停止遍历
+    ///
+    fn walk_until<F>(&self, dir: &Path, visit: F) -> Result<(), String>
+    where
+        F: Fn(PathBuf) -> bool + Sync,
+    {
+        let mut builder = WalkBuilder::new(dir);
+        //
+        // .ignore 默认就会读取，不在 git 仓库中时 .gitignore 不生效，可以用它们排除目录
+        //
+        builder
+            .git_ignore(true)
+            .ignore(true)
+            .add_custom_ignore_filename(".rgignore");
+        for ignore_file in &self.ignore_files {
+            if let Some(err) = builder.add_ignore(ignore_file) {
+                return Err(format!(
+                    "读取忽略文件 {:?} 失败: {}",
+                    paths::to_host(ignore_file),
+                    err
+                ));
+            }
+        }
+
+        let error = Mutex::new(None);
+        builder.build_parallel().run(|| {
+            Box::new(|result| {
+                match result {
+                    Ok(entry) => {
+                        if (entry.file_type().is_some_and(|kind| kind.is_file())
+                            || entry.path().is_file())
+                            && !visit(entry.into_path())
+                        {
+                            return WalkState::Quit;
+                        }
+                    }
+                    Err(err) => {
+                        error.lock().unwrap().get_or_insert(err.to_string());
+                    }
+                }
+                WalkState::Continue
+            })
+        });
+        match error.into_inner().unwrap() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
+
+///
+/// 运行过程中和运行结束后的输出
+///
+pub struct Reporting {
+    ///
+    /// 最后输出 git diff --stat 风格的统计
+    ///
+    pub stat: bool,
+    ///
+    /// 最后列出每个文件改动的行
+    ///
+    pub report: bool,
+    pub progress: Option<Progress>,
+    ///
+    /// 处理出错时立即把错误输出到标准错误，不等运行结束
+    ///
+    pub errors: bool,
+    ///
+    /// 已经开始处理的文件数，收到停止请求后用来报告进度
+    ///
+    started: AtomicUsize,
+    metrics: Metrics,
+    ///
+    /// 最后输出资源使用情况
+    ///
+    pub resource_usage: bool,
+    ///
+    /// 因为超过 --max-filesize 而跳过的文件，最后列出
+    ///
+    skipped: Mutex<Vec<(PathBuf, FileError)>>,
+    ///
+    /// 最后列出耗时最长的这么多个文件和各个阶段的合计
+    ///
+    pub timings: Option<usize>,
+    ///
+    /// 耗时以 JSON 格式输出到标准输出
+    ///
+    pub timings_json: bool,
+    file_timings: Timings,
+    ///
+    /// 把所有写回的改动写成这个统一 diff 格式的补丁文件
+    ///
+    pub patch: Option<PathBuf>,
+}
+
+impl Reporting {
+    ///
+    /// 只在 progress 不为空时输出进度，其他输出需要再打开对应的开关
+    ///
+    pub fn new(progress: Option<Progress>) -> Self {
+        Self {
+            stat: false,
+            report: false,
+            progress,
+            errors: false,
+            started: AtomicUsize::new(0),
+            metrics: Metrics::new(),
+            resource_usage: false,
+            skipped: Mutex::new(Vec::new()),
+            timings: None,
+            timings_json: false,
+            file_timings: Timings::new(),
+            patch: None,
+        }
+    }
+}
+
+impl Default for Reporting {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+///
+/// 写回队列的长度，替换完、等待写回的文件最多这么多个
+///
+fn pipeline_depth() -> usize {
+    rayon::current_num_threads() * 2
+}
+
+///
+/// 按替换计划替换文件并写回
+///
+pub struct Replacer<'a> {
+    plan: &'a ReplacePlan,
+    options: &'a WriteOptions,
+    reporting: Reporting,
+    ///
+    /// 内容写回之后执行的重命名
+    ///
+    renames: &'a [Rename],
+    ///
+    /// 记录没有匹配的文件，跳过上次运行之后没有变化的文件
+    ///
+    cache: Option<&'a Cache>,
+    ///
+    /// 处理出错、没有通过 --validate 检查或者写回失败的文件和错误信息
+    ///
+    failed: Mutex<Vec<(PathBuf, FileError)>>,
+    ///
+    /// --fail-fast 时已经遇到错误，或者要修改的文件、匹配超过了上限，不再开始新的文件
+    ///
+    aborted: AtomicBool,
+    ///
+    /// 替换完、等待写回的文件数和其中的替换次数
+    ///
+    changed_files: AtomicUsize,
+    changed_matches: AtomicUsize,
+    ///
+    /// 写临时文件之前检查空间，--no-space-check 时为 None
+    ///
+    space: Option<space::Budget>,
+}
+
+///
+/// 替换完成、等待写回的文件
+///
+struct Prepared {
+    file: PathBuf,
+    temp_file: PathBuf,
+    ///
+    /// 读取之前记录的文件状态，读不到时为 None
+    ///
+    fingerprint: Option<Fingerprint>,
+    stat: Option<stat::FileStat>,
+    report: Option<report::FileReport>,
+    diff: Option<diff::FileDiff>,
+}
+
+impl<'a> Replacer<'a> {
+    ///
+    /// 不输出统计和进度的替换器
+    ///
+    pub fn new(plan: &'a ReplacePlan, options: &'a WriteOptions) -> Self {
+        Self {
+            plan,
+            options,
+            reporting: Reporting::default(),
+            renames: &[],
+            cache: None,
+            failed: Mutex::new(Vec::new()),
+            aborted: AtomicBool::new(false),
+            changed_files: AtomicUsize::new(0),
+            changed_matches: AtomicUsize::new(0),
+            space: options.check_space.then(space::Budget::new),
+        }
+    }
+
+    ///
+    /// 设置运行中和运行结束后的输出
+    ///
+    pub fn reporting(mut self, reporting: Reporting) -> Self {
+        self.reporting = reporting;
+        self
+    }
+
+    ///
+    /// 设置所有文件写回之后要执行的重命名，--atomic-run 时和写回一起回滚
+    ///
+    pub fn renames(mut self, renames: &'a [Rename]) -> Self {
+        self.renames = renames;
+        self
+    }
+
+    ///
+    /// 设置 --cache 的缓存，运行结束时写回缓存文件
+    ///
+    pub fn cache(mut self, cache: Option<&'a Cache>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    ///
+    /// 并行替换所有文件，然后把结果写回，返回的结果需要调用方输出
+    ///
+    pub fn replace_files(&self, files: &[PathBuf]) -> Summary {
+        self.run(files.iter().cloned(), Some(files.len()))
+    }
+
+    ///
+    /// 边接收文件边替换，不需要事先知道完整的文件列表，例如配合 WalkOptions::walk_parallel
+    ///
+    pub fn replace_stream<I>(&self, files: I) -> Summary
+    where
+        I: Iterator<Item = PathBuf> + Send,
+    {
+        self.run(files, None)
+    }
+
+    ///
+    /// total 是文件总数，事先不知道时为 None
+    ///
+    fn run<I>(&self, files: I, total: Option<usize>) -> Summary
+    where
+        I: Iterator<Item = PathBuf> + Send,
+    {
+        let mut stats = Vec::new();
+        let mut reports = Vec::new();
+        let mut diffs = Vec::new();
+        //
+        // par_bridge 按列表顺序把文件分给各个线程，排在前面的文件先开始处理
+        // 限制了要修改的文件数或匹配数时，所有文件都替换完、确认没有超过上限之后才开始写回
+        //
+        let limited =
+            self.options.abort_changed_files.is_some() || self.options.abort_matches.is_some();
+        let installed = if self.options.atomic || limited {
+            let prepared: Vec<Prepared> = files
+                .par_bridge()
+                .filter_map(|file| self.prepare_file(&file))
+                .collect();
+            match self.over_limit() {
+                Some(err) => {
+                    remove_temp_files(prepared);
+                    Err(err)
+                }
+                None if self.options.atomic => {
+                    //
+                    // 写回阶段收到中断信号不会立即退出，每个文件写回之前检查一次
+                    //
+                    let _writing = interrupt::writing();
+                    self.install_atomic(
+                        prepared,
+                        stop::requested(),
+                        &mut stats,
+                        &mut reports,
+                        &mut diffs,
+                    )
+                }
+                None => {
+                    self.install_each(prepared.into_iter(), &mut stats, &mut reports, &mut diffs)
+                }
+            }
+        } else {
+            //
+            // 替换完的文件经过有界的通道交给单独的写回线程，边替换边写回
+            // 通道满了时替换线程等待写回，等待写回的临时文件数量有上限
+            //
+            let (sender, receiver) = mpsc::sync_channel(pipeline_depth());
+            thread::scope(|scope| {
+                let writer = scope.spawn(|| {
+                    self.install_each(receiver.into_iter(), &mut stats, &mut reports, &mut diffs)
+                });
+                files
+                    .par_bridge()
+                    .filter_map(|file| self.prepare_file(&file))
+                    .for_each(|prepared| {
+                        let _ = sender.send(prepared);
+                    });
+                drop(sender);
+                writer.join().unwrap()
+            })
+        };
+        let stopped = stop::requested();
+        let cache_error = self.cache.and_then(|cache| cache.save().err());
+
+        let usage = self.reporting.metrics.usage();
+        let mut failed = mem::take(&mut *self.failed.lock().unwrap());
+        failed.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut skipped = mem::take(&mut *self.reporting.skipped.lock().unwrap());
+        skipped.sort_by(|a, b| a.0.cmp(&b.0));
+        if let Some(progress) = &self.reporting.progress {
+            progress.finish(&usage, &failed, &skipped);
+        }
+        let mut patch_error = None;
+        if installed.is_ok() {
+            stats.sort_by(|a, b| a.path.cmp(&b.path));
+            reports.sort_by(|a, b| a.path.cmp(&b.path));
+            if let Some(patch) = &self.reporting.patch {
+                patch_error = diff::write(patch, &mut diffs).err();
+            }
+        }
+        Summary {
+            stopped: stopped.then(|| (self.reporting.started.load(Ordering::SeqCst), total)),
+            stats,
+            reports,
+            skipped,
+            failed,
+            usage,
+            timings: self.reporting.file_timings.take(),
+            cache_error,
+            patch_error,
+            error: installed.err(),
+            options: SummaryOptions {
+                stat: self.reporting.stat,
+                report: self.reporting.report,
+                resource_usage: self.reporting.resource_usage,
+                timings: self.reporting.timings,
+                timings_json: self.reporting.timings_json,
+                max_filesize: self.plan.max_filesize,
+                max_line_length: self.plan.max_line_length,
+            },
+        }
+    }
+
+    ///
+    /// 指定了 --timings 时记录写回一个文件的耗时
+    ///
+    fn written(&self, file: &Path, started: Instant) {
+        if self.reporting.timings.is_some() {
+            let elapsed = started.elapsed();
+            self.reporting
+                .file_timings
+                .record(file, |timing| timing.write = elapsed);
+        }
+    }
+
+    ///
+    /// 记下一个要写回的文件，要修改的文件或匹配超过上限时不再开始新的文件
+    ///
+    fn count_change(&self, replacements: usize) {
+        let files = self.changed_files.fetch_add(1, Ordering::SeqCst) + 1;
+        let matches = self
+            .changed_matches
+            .fetch_add(replacements, Ordering::SeqCst)
+            + replacements;
+        if self
+            .options
+            .abort_changed_files
+            .is_some_and(|limit| files > limit)
+            || self
+                .options
+                .abort_matches
+                .is_some_and(|limit| matches > limit)
+        {
+            self.aborted.store(true, Ordering::SeqCst);
+        }
+    }
+
+    ///
+    /// 要修改的文件或匹配超过了 --abort-if-changed-files-exceed 或 --abort-if-matches-exceed
+    ///
+    fn over_limit(&self) -> Option<String> {
+        let files = self.changed_files.load(Ordering::SeqCst);
+        let matches = self.changed_matches.load(Ordering::SeqCst);
+        if let Some(limit) = self
+            .options
+            .abort_changed_files
+            .filter(|&limit| files > limit)
+        {
+            return Some(format!(
+                "错误: 要修改的文件超过了 {} 个（--abort-if-changed-files-exceed），没有修改任何文件",
+                limit
+            ));
+        }
+        if let Some(limit) = self.options.abort_matches.filter(|&limit| matches > limit) {
+            return Some(format!(
+                "错误: 要替换的匹配超过了 {} 处（--abort-if-matches-exceed），没有修改任何文件",
+                limit
+            ));
+        }
+        None
+    }
+
+    ///
+    /// 记录处理失败的文件并输出错误，--fail-fast 时不再开始新的文件
+    ///
+    fn fail(&self, file: &Path, err: FileError) {
+        if self.reporting.errors {
+            eprintln!("{}", describe(file, &err));
+        }
+        if self.options.fail_fast {
+            self.aborted.store(true, Ordering::SeqCst);
+        }
+        self.failed.lock().unwrap().push((file.to_path_buf(), err));
+    }
+
+    ///
+    /// 按替换完成的顺序逐个写回，写回失败的文件记下来之后继续写回其他文件
+    /// --fail-fast 时遇到第一个错误就停止，已经写回的文件保持修改
+    ///
+    fn install_each(
+        &self,
+        mut pending: impl Iterator<Item = Prepared>,
+        stats: &mut Vec<stat::FileStat>,
+        reports: &mut Vec<report::FileReport>,
+        diffs: &mut Vec<diff::FileDiff>,
+    ) -> Result<(), String> {
+        let mut done = 0;
+        while let Some(prepared) = pending.next() {
+            let stop = if interrupt::requested() {
+                Some(format!(
+                    "收到中断信号，已写回 {} 个文件，其余文件没有修改",
+                    done
+                ))
+            } else if self.aborted.load(Ordering::SeqCst) {
+                Some(format!("遇到错误，已停止运行，已写回 {} 个文件", done))
+            } else {
+                None
+            };
+            if let Some(stop) = stop {
+                //
+                // 让替换线程不再开始新的文件，正在替换的文件完成之后删除它们的临时文件
+                //
+                self.aborted.store(true, Ordering::SeqCst);
+                remove_temp_files(iter::once(prepared).chain(pending));
+                return Err(stop);
+            }
+            //
+            // 写回一个文件的过程中收到中断信号不会立即退出，写回完再停下
+            //
+            let writing = interrupt::writing();
+            let started = Instant::now();
+            let installed = self.check_unchanged(&prepared).and_then(|()| {
+                install::install(&prepared.file, &prepared.temp_file, self.options)
+                    .map_err(FileError::Write)
+            });
+            self.written(&prepared.file, started);
+            drop(writing);
+            if let Err(err) = installed {
+                let _ = fs::remove_file(&prepared.temp_file);
+                if self.options.fail_fast {
+                    self.aborted.store(true, Ordering::SeqCst);
+                    remove_temp_files(pending);
+                    return Err(format!(
+                        "{}\n遇到错误，已停止运行，已写回 {} 个文件",
+                        describe(&prepared.file, &err),
+                        done
+                    ));
+                }
+                self.fail(&prepared.file, err);
+                continue;
+            }
+            done += 1;
+            stats.extend(prepared.stat);
+            reports.extend(prepared.report);
+            diffs.extend(prepared.diff);
+        }
+        //
+        // 最后一个出错的文件之后可能没有等待写回的文件了
+        //
+        if self.aborted.load(Ordering::SeqCst) {
+            return Err(format!("遇到错误，已停止运行，已写回 {} 个文件", done));
+        }
+        let _writing = interrupt::writing();
+        rename::apply(self.renames, false).map_err(|err| format!("错误: {}", err))
+    }
+
+    ///
+    /// --atomic-run：所有文件都替换成功之后才开始写回，写回或者重命名失败时恢复所有已经写回的文件
+    ///
+    fn install_atomic(
+        &self,
+        prepared: Vec<Prepared>,
+        stopped: bool,
+        stats: &mut Vec<stat::FileStat>,
+        reports: &mut Vec<report::FileReport>,
+        diffs: &mut Vec<diff::FileDiff>,
+    ) -> Result<(), String> {
+        let failed = self.failed.lock().unwrap().len();
+        if failed > 0 || stopped {
+            remove_temp_files(prepared);
+            return Err(if stopped {
+                "收到停止请求，没有修改任何文件".to_string()
+            } else {
+                format!("{} 个文件处理失败，没有修改任何文件", failed)
+            });
+        }
+
+        let mut transaction = Transaction::new();
+        let mut pending = prepared.into_iter();
+        let mut installed = Ok(());
+        for prepared in pending.by_ref() {
+            installed = if interrupt::requested() {
+                Err("收到中断信号".to_string())
+            } else {
+                self.check_unchanged(&prepared)
+                    .map_err(|err| describe(&prepared.file, &err))
+                    .and_then(|()| {
+                        let started = Instant::now();
+                        let installed =
+                            transaction.install(&prepared.file, &prepared.temp_file, self.options);
+                        self.written(&prepared.file, started);
+                        installed
+                    })
+            };
+            if installed.is_err() {
+                let _ = fs::remove_file(prepared.temp_file);
+                break;
+            }
+            stats.extend(prepared.stat);
+            reports.extend(prepared.report);
+            diffs.extend(prepared.diff);
+        }
+        if let Err(err) = installed.and_then(|()| rename::apply(self.renames, true)) {
+            remove_temp_files(pending);
+            return Err(match transaction.rollback() {
+                Ok(restored) => format!("{}\n已恢复写回的 {} 个文件", err, restored),
+                Err(rollback_err) => format!("{}\n{}", err, rollback_err),
+            });
+        }
+        transaction.commit();
+        Ok(())
+    }
+
+    ///
+    /// 读取和写回之间隔着替换和等待写回的时间，写回之前确认文件没有被其他程序修改
+    /// 指定了 --force 时不检查
+    ///
+    fn check_unchanged(&self, prepared: &Prepared) -> Result<(), FileError> {
+        if self.options.force {
+            return Ok(());
+        }
+        match (&prepared.fingerprint, Fingerprint::of(&prepared.file)) {
+            (Some(before), Ok(now)) if *before == now => Ok(()),
+            _ => Err(FileError::Modified),
+        }
+    }
+
+    ///
+    /// 记下一个跳过的文件，最后和处理失败的文件分开列出
+    ///
+    fn skip(&self, file: &Path, reason: FileError) {
+        self.reporting
+            .skipped
+            .lock()
+            .unwrap()
+            .push((file.to_path_buf(), reason));
+        if let Some(progress) = &self.reporting.progress {
+            progress.file_done(0, 0);
+        }
+    }
+
+    ///
+    /// 替换单个文件到临时文件，并完成写回之前的检查和统计
+    /// 出错或者不需要写回时返回 None
+    ///
+    fn prepare_file(&self, file: &Path) -> Option<Prepared> {
+        //
+        // 收到停止请求、中断信号或者 --fail-fast 遇到错误后不再开始新的文件
+        //
+        if stop::requested() || interrupt::requested() || self.aborted.load(Ordering::SeqCst) {
+            return None;
+        }
+        self.reporting.started.fetch_add(1, Ordering::SeqCst);
+        let started = Instant::now();
+        let prepared = self.replace_and_check(file);
+        self.reporting.metrics.busy(started.elapsed());
+        prepared
+    }
+
+    fn replace_and_check(&self, file: &Path) -> Option<Prepared> {
+        if let Some(reason) = self.plan.skip_reason(file) {
+            self.skip(file, reason);
+            return None;
+        }
+        let fingerprint = Fingerprint::of(file).ok();
+        let started = Instant::now();
+        let has_match = match self.cache {
+            Some(cache) if cache.is_clean(file, self.plan) => Ok(false),
+            Some(cache) => {
+                let has_match = self.plan.has_match(file);
+                match has_match {
+                    Ok(false) => cache.mark_clean(file, self.plan),
+                    _ => cache.forget(file),
+                }
+                has_match
+            }
+            None => self.plan.has_match(file),
+        };
+        let checked = started.elapsed();
+        if let Ok(true) = has_match {
+            //
+            // skip_matched 要再读一遍文件，只检查有匹配的文件，没有匹配的文件也不用在最后列出
+            //
+            if let Some(reason) = self.plan.skip_matched(file) {
+                self.skip(file, reason);
+                return None;
+            }
+            //
+            // 空间不足时不再开始新的文件，--atomic-run 时不会写回任何文件
+            //
+            if let Some(Err(err)) = self
+                .space
+                .as_ref()
+                .map(|space| space.reserve(file, self.options.atomic))
+            {
+                self.aborted.store(true, Ordering::SeqCst);
+                self.fail(file, FileError::NoSpace(err));
+                if let Some(progress) = &self.reporting.progress {
+                    progress.file_done(0, 0);
+                }
+                return None;
+            }
+        }
+        let replaced = match has_match {
+            Ok(true) => replace_in_file(file, self.plan).map(Some),
+            Ok(false) => Ok(None),
+            Err(err) => Err(err.into()),
+        };
+        if self.reporting.timings.is_some() {
+            let replacing = started.elapsed() - checked;
+            self.reporting.file_timings.record(file, |timing| {
+                timing.check = checked;
+                timing.replace = replacing;
+            });
+        }
+        let bytes = fs::metadata(file)
+            .map(|metadata| metadata.len())
+            .unwrap_or(0);
+        let matches = match &replaced {
+            Ok(Some(replaced)) => replaced.replacements,
+            _ => 0,
+        };
+        if let Some(progress) = &self.reporting.progress {
+            progress.file_done(bytes, matches);
+        }
+        //
+        // 检查匹配读一遍文件，有匹配时替换再读一遍
+        //
+        let (read, written) = match &replaced {
+            Ok(Some(replaced)) => (
+                bytes * 2,
+                fs::metadata(&replaced.temp_file)
+                    .map(|metadata| metadata.len())
+                    .unwrap_or(0),
+            ),
+            _ => (bytes, 0),
+        };
+        self.reporting.metrics.file_done(read, written);
+        //
+        // 没有任何替换的文件保持原样，不会经过临时文件重写，修改时间也不会变
+        //
+        let (temp_file, replacements) = match replaced {
+            Ok(Some(replaced)) if replaced.replacements > 0 => {
+                (replaced.temp_file, replaced.replacements)
+            }
+            Ok(Some(replaced)) => {
+                let _ = fs::remove_file(replaced.temp_file);
+                return None;
+            }
+            Ok(None) => return None,
+            Err(err) => {
+                match FileError::from_replace(err.as_ref()) {
+                    error @ FileError::NotUtf8(_)
+                        if self.plan.invalid_utf8 == InvalidUtf8::Skip =>
+                    {
+                        self.reporting
+                            .skipped
+                            .lock()
+                            .unwrap()
+                            .push((file.to_path_buf(), error))
+                    }
+                    error => self.fail(file, error),
+                }
+                return None;
+            }
+        };
+
+        if let Err(err) = validate_replaced(file, &temp_file, self.plan, self.options) {
+            self.fail(file, err);
+            let _ = fs::remove_file(temp_file);
+            return None;
+        }
+
+        let (stat, report) = if self.reporting.stat || self.reporting.report {
+            match read_replaced(file, &temp_file, self.plan) {
+                Ok((original, replaced)) => {
+                    let path = paths::to_host(file);
+                    (
+                        self.reporting
+                            .stat
+                            .then(|| stat::FileStat::new(path.clone(), &original, &replaced)),
+                        self.reporting
+                            .report
+                            .then(|| report::FileReport::new(path, &original, &replaced)),
+                    )
+                }
+                Err(err) => {
+                    if self.reporting.errors {
+                        eprintln!("统计文件错误 {:?}: {}", paths::to_host(file), err);
+                    }
+                    (None, None)
+                }
+            }
+        } else {
+            (None, None)
+        };
+
+        let diff = self
+            .reporting
+            .patch
+            .as_ref()
+            .and_then(|_| self.diff_file(file, &temp_file));
+
+        self.count_change(replacements);
+        Some(Prepared {
+            file: file.to_path_buf(),
+            temp_file,
+            fingerprint,
+            stat,
+            report,
+            diff,
+        })
+    }
+
+    ///
+    /// --output-patch 的补丁，按原始字节比较；不是 UTF-8 的文件不能写进补丁
+    ///
+    fn diff_file(&self, file: &Path, temp_file: &Path) -> Option<diff::FileDiff> {
+        let path = paths::to_host(file);
+        let contents = fs::read(file).and_then(|original| Ok((original, fs::read(temp_file)?)));
+        let diff = match contents {
+            Ok((original, replaced)) => diff::FileDiff::new(path.clone(), &original, &replaced),
+            Err(err) => {
+                if self.reporting.errors {
+                    eprintln!("生成补丁错误 {:?}: {}", path, err);
+                }
+                return None;
+            }
+        };
+        if diff.is_none() && self.reporting.errors {
+            eprintln!("文件 {:?} 不是 UTF-8 编码，没有写进补丁", path);
+        }
+        diff
+    }
+}
+
+///
+/// 错误信息前面加上文件的路径
+///
+fn describe(file: &Path, err: &FileError) -> String {
+    format!("{:?}: {}", paths::to_host(file), err)
+}
+
+///
+/// 删除不再写回的临时文件
+///
+fn remove_temp_files(prepared: impl IntoIterator<Item = Prepared>) {
+    for prepared in prepared {
+        let _ = fs::remove_file(prepared.temp_file);
+    }
+}
+
+///
+/// 读取原文件和替换结果，用来统计和报告改动
+/// --bytes 时不是 UTF-8 的内容只用来显示，按有损的方式转换
+///
+fn read_replaced(
+    file: &Path,
+    temp_file: &Path,
+    plan: &ReplacePlan,
+) -> Result<(String, String), Box<dyn std::error::Error>> {
+    if plan.bytes.is_some() {
+        let lossy = |bytes: Vec<u8>| String::from_utf8_lossy(&bytes).into_owned();
+        return Ok((lossy(fs::read(file)?), lossy(fs::read(temp_file)?)));
+    }
+    let original = plan.read_text(file)?;
+    let replaced = plan.encoding_for(file).decode(&fs::read(temp_file)?)?;
+    Ok((original, encoding::strip_bom(&replaced).to_string()))
+}
+
+///
+/// 按 --validate 检查替换后的文件，需要跳过写回时返回错误
+///
+pub fn validate_replaced(
+    file: &Path,
+    temp_file: &Path,
+    plan: &ReplacePlan,
+    options: &WriteOptions,
+) -> Result<(), FileError> {
+    let Some(mode) = options.validate else {
+        return Ok(());
+    };
+    let encoding = plan.encoding_for(file);
+    match validate::check_replaced(file, temp_file, encoding) {
+        Ok(None) => Ok(()),
+        Ok(Some(problem)) if mode == ValidateMode::Warn => {
+            eprintln!(
+                "警告: 替换改坏了文件 {:?}: {}",
+                paths::to_host(file),
+                problem
+            );
+            Ok(())
+        }
+        Ok(Some(problem)) => Err(FileError::Invalid(problem)),
+        Err(err) => Err(FileError::from_replace(&err)),
+    }
+}
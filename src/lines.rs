@@ -0,0 +1,154 @@
+//!
+//! 按行编辑：删除包含匹配的行（--delete-lines），加上 --invert 时只保留包含匹配的行，
+//! 或者在包含匹配的行前后插入一行（--insert-before、--insert-after）
+//! 和 sed 的 /正则/d 一样逐行判断，正则不会跨行匹配
+//! 指定了 --null-data 时以 NUL 分隔的记录代替行
+//!
+
+use std::error::Error;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use regex::Captures;
+
+use crate::select::Selector;
+use crate::{install, ReplacePlan, Rule};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEdit {
+    ///
+    /// 删除包含匹配的行
+    ///
+    Delete,
+    ///
+    /// 只保留包含匹配的行，没有匹配的文件会被清空
+    ///
+    Keep,
+    ///
+    /// 在包含匹配的行之前插入一行，内容是规则的替换模板
+    ///
+    InsertBefore,
+    ///
+    /// 在包含匹配的行之后插入一行，内容是规则的替换模板
+    ///
+    InsertAfter,
+}
+
+///
+/// 一行中第一个被选中的匹配，多条规则的匹配按位置排序后再交给选择条件
+///
+fn first_selected<'t, 'r>(
+    content: &'t str,
+    offset: usize,
+    number: usize,
+    rules: &'r [Rule],
+    selector: &mut Selector,
+) -> Option<(&'r Rule, Captures<'t>)> {
+    let mut matches: Vec<_> = rules
+        .iter()
+        .flat_map(|rule| rule.re.captures_iter(content).map(move |caps| (rule, caps)))
+        .collect();
+    matches.sort_by_key(|(_, caps)| {
+        let m = caps.get(0).unwrap();
+        (m.start(), m.end())
+    });
+    matches.into_iter().find(|(_, caps)| {
+        let m = caps.get(0).unwrap();
+        selector.allows(offset + m.start()..offset + m.end(), number)
+    })
+}
+
+///
+/// 按行编辑文本，每删除或插入一行计一次替换
+/// Keep 时选择条件作用在要删除的整行上，其他情况作用在匹配上
+/// null_data 为 true 时按 NUL 分隔的记录处理
+///
+pub fn edit_lines(
+    text: &str,
+    rules: &[Rule],
+    edit: LineEdit,
+    null_data: bool,
+    selector: &mut Selector,
+) -> String {
+    selector.set_text(text);
+    let mut output = String::with_capacity(text.len());
+    let mut offset = 0;
+    let separator = if null_data { '\0' } else { '\n' };
+
+    for (index, line) in text.split_inclusive(separator).enumerate() {
+        let number = index + 1;
+        let content = line.strip_suffix(separator).unwrap_or(line);
+        //
+        // 插入的行使用和当前行相同的换行符
+        //
+        let (content, newline) = match content.strip_suffix('\r') {
+            Some(content) if !null_data && line.ends_with('\n') => (content, "\r\n"),
+            _ if null_data => (content, "\0"),
+            _ => (content, "\n"),
+        };
+
+        match edit {
+            LineEdit::Delete => {
+                if first_selected(content, offset, number, rules, selector).is_none() {
+                    output.push_str(line);
+                }
+            }
+            LineEdit::Keep => {
+                let matched = rules.iter().any(|rule| rule.re.is_match(content));
+                if matched || !selector.allows(offset..offset + content.len(), number) {
+                    output.push_str(line);
+                }
+            }
+            LineEdit::InsertBefore => {
+                if let Some((rule, caps)) = first_selected(content, offset, number, rules, selector)
+                {
+                    rule.replacement.append(&caps, number, &mut output);
+                    output.push_str(newline);
+                }
+                output.push_str(line);
+            }
+            LineEdit::InsertAfter => {
+                output.push_str(line);
+                if let Some((rule, caps)) = first_selected(content, offset, number, rules, selector)
+                {
+                    //
+                    // 最后一行没有换行符时，插入的行同样不以换行结尾
+                    //
+                    if !line.ends_with(separator) {
+                        output.push_str(newline);
+                    }
+                    rule.replacement.append(&caps, number, &mut output);
+                    if line.ends_with(separator) {
+                        output.push_str(newline);
+                    }
+                }
+            }
+        }
+        offset += line.len();
+    }
+    output
+}
+
+///
+/// 按行编辑文件到临时文件
+///
+pub fn replace_in_file(
+    target_file: &Path,
+    plan: &ReplacePlan,
+    edit: LineEdit,
+    selector: &mut Selector,
+) -> Result<PathBuf, Box<dyn Error>> {
+    let text = fs::read_to_string(target_file)?;
+    let edited = edit_lines(&text, &plan.rules, edit, plan.null_data, selector);
+
+    let temp_file = install::create_temp_file(target_file)?;
+    let temp_file_path = temp_file.path().to_path_buf();
+    let mut file = OpenOptions::new()
+        .append(true)
+        .open(temp_file_path.clone())?;
+    file.write_all(edited.as_bytes())?;
+    let _ = temp_file.persist(&temp_file_path)?;
+
+    Ok(temp_file_path)
+}
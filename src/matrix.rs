@@ -0,0 +1,138 @@
+use rayon::prelude::*;
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+
+use crate::paths;
+use crate::{selected_match_lines, ReplacePlan};
+
+///
+/// 规则列中正则最多显示的字符数
+///
+const MAX_PATTERN_WIDTH: usize = 40;
+
+///
+/// 只读扫描，输出规则 × 顶层目录的匹配数矩阵，不修改文件
+/// 顶层目录相对于 root 计算，直接位于 root 下的文件归到 "."
+/// 每条规则都按原始内容统计，不考虑前面的规则替换之后的变化
+///
+pub fn matrix(files: &[PathBuf], plan: &ReplacePlan, root: &Path) {
+    let counts: Vec<(String, Vec<usize>)> = files
+        .par_iter()
+        .filter_map(|file| {
+            let contents = match plan.read_text(file) {
+                Ok(contents) => contents,
+                Err(err) => {
+                    eprintln!("读取文件错误 {:?}: {}", paths::to_host(file), err);
+                    return None;
+                }
+            };
+            let matches = plan
+                .rules
+                .iter()
+                .map(|rule| selected_match_lines(&contents, &rule.re, &plan.selection).len())
+                .collect();
+            Some((top_level_dir(file, root), matches))
+        })
+        .collect();
+
+    let mut grid: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for (dir, matches) in counts {
+        let row = grid.entry(dir).or_insert_with(|| vec![0; plan.rules.len()]);
+        for (total, count) in row.iter_mut().zip(matches) {
+            *total += count;
+        }
+    }
+
+    let labels: Vec<String> = plan
+        .rules
+        .iter()
+        .enumerate()
+        .map(|(index, rule)| format!("#{} {}", index + 1, truncate(rule.re.as_str())))
+        .collect();
+    let mut header = vec!["规则".to_string()];
+    header.extend(grid.keys().cloned());
+    header.push("合计".to_string());
+
+    let mut rows = vec![header];
+    for (index, label) in labels.into_iter().enumerate() {
+        let mut row = vec![label];
+        row.extend(grid.values().map(|counts| counts[index].to_string()));
+        row.push(
+            grid.values()
+                .map(|counts| counts[index])
+                .sum::<usize>()
+                .to_string(),
+        );
+        rows.push(row);
+    }
+
+    print_table(&rows);
+}
+
+///
+/// 文件相对于 root 的第一级目录
+///
+fn top_level_dir(file: &Path, root: &Path) -> String {
+    let relative = file.strip_prefix(root).unwrap_or(file);
+    let mut components = relative
+        .components()
+        .filter(|component| !matches!(component, Component::CurDir));
+    match (components.next(), components.next()) {
+        (Some(first), Some(_)) => first.as_os_str().to_string_lossy().into_owned(),
+        _ => ".".to_string(),
+    }
+}
+
+fn truncate(pattern: &str) -> String {
+    if pattern.chars().count() <= MAX_PATTERN_WIDTH {
+        pattern.to_string()
+    } else {
+        let mut truncated: String = pattern.chars().take(MAX_PATTERN_WIDTH - 1).collect();
+        truncated.push('…');
+        truncated
+    }
+}
+
+///
+/// 按列对齐输出，第一列左对齐，其余列右对齐
+///
+fn print_table(rows: &[Vec<String>]) {
+    let columns = rows.first().map_or(0, Vec::len);
+    let widths: Vec<usize> = (0..columns)
+        .map(|column| {
+            rows.iter()
+                .map(|row| display_width(&row[column]))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    for row in rows {
+        let cells: Vec<String> = row
+            .iter()
+            .enumerate()
+            .map(|(column, cell)| {
+                let padding = " ".repeat(widths[column] - display_width(cell));
+                if column == 0 {
+                    format!("{}{}", cell, padding)
+                } else {
+                    format!("{}{}", padding, cell)
+                }
+            })
+            .collect();
+        println!("{}", cells.join("  "));
+    }
+}
+
+///
+/// 终端显示宽度的近似值，中日韩等全角字符占两列
+///
+fn display_width(text: &str) -> usize {
+    text.chars()
+        .map(|ch| match ch as u32 {
+            0x1100..=0x115f | 0x2e80..=0xa4cf | 0xac00..=0xd7a3 | 0xf900..=0xfaff => 2,
+            0xfe30..=0xfe4f | 0xff00..=0xff60 | 0xffe0..=0xffe6 => 2,
+            _ => 1,
+        })
+        .sum()
+}
@@ -0,0 +1,368 @@
+//!
+//! 运行期间的资源使用统计，用于评估定时大批量运行需要的机器资源
+//!
+
+use std::collections::HashMap;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::{json, paths};
+
+pub struct Metrics {
+    started: Instant,
+    files: AtomicUsize,
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+    ///
+    /// 所有线程处理文件花费的时间之和（纳秒）
+    ///
+    busy_nanos: AtomicU64,
+}
+
+///
+/// 某一时刻的资源使用情况
+///
+pub struct Usage {
+    pub elapsed: Duration,
+    ///
+    /// 进程的峰值常驻内存（字节），平台不支持时为 None
+    ///
+    pub peak_rss: Option<u64>,
+    pub files: usize,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    pub threads: usize,
+    busy: Duration,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            files: AtomicUsize::new(0),
+            bytes_read: AtomicU64::new(0),
+            bytes_written: AtomicU64::new(0),
+            busy_nanos: AtomicU64::new(0),
+        }
+    }
+
+    ///
+    /// 一个文件处理完毕
+    /// read 是读取文件内容的总字节数，同一个文件读了几遍就算几遍，written 是写入临时文件的字节数
+    ///
+    pub fn file_done(&self, read: u64, written: u64) {
+        self.files.fetch_add(1, Ordering::SeqCst);
+        self.bytes_read.fetch_add(read, Ordering::SeqCst);
+        self.bytes_written.fetch_add(written, Ordering::SeqCst);
+    }
+
+    ///
+    /// 记录一个线程处理文件花费的时间
+    ///
+    pub fn busy(&self, busy: Duration) {
+        self.busy_nanos
+            .fetch_add(busy.as_nanos() as u64, Ordering::SeqCst);
+    }
+
+    pub fn usage(&self) -> Usage {
+        Usage {
+            elapsed: self.started.elapsed(),
+            peak_rss: imp::peak_rss(),
+            files: self.files.load(Ordering::SeqCst),
+            bytes_read: self.bytes_read.load(Ordering::SeqCst),
+            bytes_written: self.bytes_written.load(Ordering::SeqCst),
+            threads: rayon::current_num_threads(),
+            busy: Duration::from_nanos(self.busy_nanos.load(Ordering::SeqCst)),
+        }
+    }
+}
+
+impl Usage {
+    pub fn files_per_second(&self) -> f64 {
+        self.files as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    ///
+    /// 线程利用率：处理文件的时间占 线程数 × 总耗时 的比例
+    ///
+    pub fn thread_utilization(&self) -> f64 {
+        let capacity = self.elapsed.as_secs_f64() * self.threads as f64;
+        (self.busy.as_secs_f64() / capacity.max(f64::EPSILON)).min(1.0)
+    }
+
+    ///
+    /// 输出到标准错误，不影响标准输出上的其他结果
+    ///
+    pub fn print(&self) {
+        let peak_rss = match self.peak_rss {
+            Some(peak_rss) => format_bytes(peak_rss),
+            None => "未知".to_string(),
+        };
+        eprintln!(
+            "资源使用: 耗时 {:.2}s，峰值内存 {}，读取 {}，写入 {}，每秒 {:.1} 个文件，{} 个线程的利用率 {:.0}%",
+            self.elapsed.as_secs_f64(),
+            peak_rss,
+            format_bytes(self.bytes_read),
+            format_bytes(self.bytes_written),
+            self.files_per_second(),
+            self.threads,
+            self.thread_utilization() * 100.0
+        );
+    }
+
+    ///
+    /// JSON 对象，嵌入到机器可读的输出中
+    ///
+    pub fn to_json(&self) -> String {
+        let peak_rss = match self.peak_rss {
+            Some(peak_rss) => peak_rss.to_string(),
+            None => "null".to_string(),
+        };
+        format!(
+            "{{\"elapsed_ms\":{},\"peak_rss_bytes\":{},\"files\":{},\"bytes_read\":{},\"bytes_written\":{},\"files_per_second\":{:.1},\"threads\":{},\"thread_utilization\":{:.3}}}",
+            self.elapsed.as_millis(),
+            peak_rss,
+            self.files,
+            self.bytes_read,
+            self.bytes_written,
+            self.files_per_second(),
+            self.threads,
+            self.thread_utilization()
+        )
+    }
+}
+
+///
+/// 单个文件在各个阶段花费的时间
+///
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileTiming {
+    ///
+    /// 读取文件并检查有没有匹配
+    ///
+    pub check: Duration,
+    ///
+    /// 替换到临时文件
+    ///
+    pub replace: Duration,
+    ///
+    /// 写回目标文件
+    ///
+    pub write: Duration,
+}
+
+impl FileTiming {
+    pub fn total(&self) -> Duration {
+        self.check + self.replace + self.write
+    }
+
+    fn add(&mut self, other: &FileTiming) {
+        self.check += other.check;
+        self.replace += other.replace;
+        self.write += other.write;
+    }
+
+    fn to_json(self) -> String {
+        format!(
+            "\"check_ms\":{:.3},\"replace_ms\":{:.3},\"write_ms\":{:.3},\"total_ms\":{:.3}",
+            millis(self.check),
+            millis(self.replace),
+            millis(self.write),
+            millis(self.total())
+        )
+    }
+}
+
+///
+/// --timings：记录每个文件各个阶段的耗时，最后列出最慢的文件和各阶段的合计
+/// 用来调整线程数，找出写得不好、在某些文件上特别慢的正则
+///
+#[derive(Default)]
+pub struct Timings {
+    files: Mutex<HashMap<PathBuf, FileTiming>>,
+}
+
+impl Timings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///
+    /// 更新一个文件的耗时，同一个文件的各个阶段分别在替换和写回时记录
+    ///
+    pub fn record(&self, file: &Path, update: impl FnOnce(&mut FileTiming)) {
+        let mut files = self.files.lock().unwrap();
+        update(files.entry(file.to_path_buf()).or_default());
+    }
+
+    ///
+    /// 取出已经记录的耗时，留下空的记录
+    ///
+    pub fn take(&self) -> Self {
+        Self {
+            files: Mutex::new(mem::take(&mut *self.files.lock().unwrap())),
+        }
+    }
+
+    ///
+    /// 按总耗时从慢到快排序的前 top 个文件，以及所有文件的合计
+    ///
+    fn slowest(&self, top: usize) -> (Vec<(PathBuf, FileTiming)>, FileTiming, usize) {
+        let files = self.files.lock().unwrap();
+        let mut totals = FileTiming::default();
+        for timing in files.values() {
+            totals.add(timing);
+        }
+        let mut slowest: Vec<_> = files
+            .iter()
+            .map(|(file, timing)| (file.clone(), *timing))
+            .collect();
+        slowest.sort_by(|a, b| b.1.total().cmp(&a.1.total()).then_with(|| a.0.cmp(&b.0)));
+        slowest.truncate(top);
+        (slowest, totals, files.len())
+    }
+
+    ///
+    /// 输出到标准错误，elapsed 是整次运行的耗时
+    ///
+    pub fn print(&self, top: usize, elapsed: Duration) {
+        let (slowest, totals, files) = self.slowest(top);
+        if !slowest.is_empty() {
+            eprintln!("最慢的 {} 个文件:", slowest.len());
+        }
+        for (file, timing) in &slowest {
+            eprintln!(
+                "  {}（检查 {}，替换 {}，写回 {}） {}",
+                format_duration(timing.total()),
+                format_duration(timing.check),
+                format_duration(timing.replace),
+                format_duration(timing.write),
+                paths::to_host(file).display()
+            );
+        }
+        eprintln!(
+            "耗时合计: {} 个文件，检查 {}，替换 {}，写回 {}，运行 {}",
+            files,
+            format_duration(totals.check),
+            format_duration(totals.replace),
+            format_duration(totals.write),
+            format_duration(elapsed)
+        );
+    }
+
+    ///
+    /// JSON 对象，各阶段的合计是所有线程的时间之和，可以超过 elapsed_ms
+    ///
+    pub fn to_json(&self, top: usize, elapsed: Duration) -> String {
+        let (slowest, totals, files) = self.slowest(top);
+        let slowest: Vec<_> = slowest
+            .into_iter()
+            .map(|(file, timing)| {
+                format!(
+                    "{{\"path\":{},{}}}",
+                    json::quote(&paths::to_host(&file).to_string_lossy()),
+                    timing.to_json()
+                )
+            })
+            .collect();
+        format!(
+            "{{\"files\":{},\"elapsed_ms\":{:.3},{},\"slowest\":[{}]}}",
+            files,
+            millis(elapsed),
+            totals.to_json(),
+            slowest.join(",")
+        )
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn format_duration(duration: Duration) -> String {
+    if duration < Duration::from_secs(1) {
+        format!("{:.1}ms", millis(duration))
+    } else {
+        format!("{:.2}s", duration.as_secs_f64())
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64;
+    let mut unit = "";
+    for next in UNITS {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = next;
+    }
+    format!("{:.1} {}", value, unit)
+}
+
+///
+/// 解析文件大小，例如 512、10K、10M、1.5G，单位按 1024 计算，可以写成 KB、KiB 的形式，不区分大小写
+///
+pub fn parse_size(size: &str) -> Result<u64, String> {
+    let size = size.trim();
+    let split = size
+        .find(|ch: char| !(ch.is_ascii_digit() || ch == '.'))
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("无效的文件大小: {}", size))?;
+    let unit = unit.trim().to_ascii_uppercase();
+    let unit = unit
+        .strip_suffix("IB")
+        .or_else(|| unit.strip_suffix('B'))
+        .unwrap_or(&unit);
+    let multiplier: u64 = match unit {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return Err(format!("无效的文件大小单位: {}", size)),
+    };
+    Ok((number * multiplier as f64) as u64)
+}
+
+#[cfg(unix)]
+mod imp {
+    pub fn peak_rss() -> Option<u64> {
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+        if unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) } != 0 {
+            return None;
+        }
+        let max_rss = u64::try_from(usage.ru_maxrss).ok()?;
+        //
+        // macOS 上 ru_maxrss 的单位是字节，其他系统是 KiB
+        //
+        if cfg!(target_os = "macos") {
+            Some(max_rss)
+        } else {
+            Some(max_rss * 1024)
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    pub fn peak_rss() -> Option<u64> {
+        None
+    }
+}
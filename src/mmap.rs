@@ -0,0 +1,123 @@
+//!
+//! 只读地映射整个文件，检查匹配时直接在映射的内存上运行正则，不需要先把文件读进缓冲区
+//!
+
+use std::fs::File;
+use std::io;
+use std::ops::Deref;
+use std::path::Path;
+
+///
+/// 文件的只读映射，离开作用域时解除映射
+///
+pub struct Mmap {
+    inner: imp::Inner,
+}
+
+impl Mmap {
+    pub fn open(file: &Path) -> io::Result<Self> {
+        let file = File::open(file)?;
+        let len = file.metadata()?.len();
+        let len = usize::try_from(len)
+            .map_err(|_| io::Error::new(io::ErrorKind::OutOfMemory, "文件太大，无法映射"))?;
+        Ok(Self {
+            inner: imp::Inner::map(&file, len)?,
+        })
+    }
+}
+
+impl Deref for Mmap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.inner.as_slice()
+    }
+}
+
+#[cfg(unix)]
+mod imp {
+    use std::fs::File;
+    use std::io;
+    use std::os::unix::io::AsRawFd;
+
+    pub struct Inner {
+        ptr: *mut libc::c_void,
+        len: usize,
+    }
+
+    //
+    // 映射是只读的，多个线程同时读取是安全的
+    //
+    unsafe impl Send for Inner {}
+    unsafe impl Sync for Inner {}
+
+    impl Inner {
+        pub fn map(file: &File, len: usize) -> io::Result<Self> {
+            //
+            // 长度为 0 的映射会失败，空文件不需要映射
+            //
+            if len == 0 {
+                return Ok(Self {
+                    ptr: std::ptr::null_mut(),
+                    len: 0,
+                });
+            }
+            let ptr = unsafe {
+                libc::mmap(
+                    std::ptr::null_mut(),
+                    len,
+                    libc::PROT_READ,
+                    libc::MAP_PRIVATE,
+                    file.as_raw_fd(),
+                    0,
+                )
+            };
+            if ptr == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self { ptr, len })
+        }
+
+        pub fn as_slice(&self) -> &[u8] {
+            if self.len == 0 {
+                return &[];
+            }
+            unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+        }
+    }
+
+    impl Drop for Inner {
+        fn drop(&mut self) {
+            if self.len > 0 {
+                unsafe {
+                    libc::munmap(self.ptr, self.len);
+                }
+            }
+        }
+    }
+}
+
+//
+// 不支持 mmap 的平台退回到一次性读取整个文件
+//
+#[cfg(not(unix))]
+mod imp {
+    use std::fs::File;
+    use std::io::{self, Read};
+
+    pub struct Inner {
+        data: Vec<u8>,
+    }
+
+    impl Inner {
+        pub fn map(mut file: &File, len: usize) -> io::Result<Self> {
+            let mut data = Vec::with_capacity(len);
+            file.read_to_end(&mut data)?;
+            Ok(Self { data })
+        }
+
+        pub fn as_slice(&self) -> &[u8] {
+            &self.data
+        }
+    }
+}
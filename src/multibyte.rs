@@ -0,0 +1,442 @@
+//!
+//! GBK、GB18030、Big5、Shift-JIS、EUC-JP、EUC-KR 这些多字节编码的解码和编码
+//! 码表见 encoding_tables.rs，第一次用到时展开；ASCII 在这些编码里都是单字节，不查码表
+//! GBK 按 GB18030 解码，编码时不使用四字节序列；EUC-KR 按兼容它的 CP949 处理
+//!
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use crate::encoding_tables as tables;
+
+///
+/// 一种多字节编码：按字符解码和编码的函数，ASCII 由 decode 和 encode 直接处理
+///
+pub struct Codec {
+    name: &'static str,
+    ///
+    /// 解码非 ASCII 字节开头的一个字符，返回字符和用掉的字节数，不是有效的序列时返回 None
+    ///
+    decode_char: fn(&[u8]) -> Option<(char, usize)>,
+    ///
+    /// 把一个非 ASCII 字符的编码追加到输出，没有对应的编码时返回 false
+    ///
+    encode_char: fn(char, &mut Vec<u8>) -> bool,
+}
+
+pub const GBK: Codec = Codec {
+    name: "gbk",
+    decode_char: decode_gb18030,
+    encode_char: encode_gbk,
+};
+
+pub const GB18030: Codec = Codec {
+    name: "gb18030",
+    decode_char: decode_gb18030,
+    encode_char: encode_gb18030,
+};
+
+pub const BIG5: Codec = Codec {
+    name: "big5",
+    decode_char: decode_big5,
+    encode_char: encode_big5,
+};
+
+pub const SHIFT_JIS: Codec = Codec {
+    name: "shift-jis",
+    decode_char: decode_shift_jis,
+    encode_char: encode_shift_jis,
+};
+
+pub const EUC_JP: Codec = Codec {
+    name: "euc-jp",
+    decode_char: decode_euc_jp,
+    encode_char: encode_euc_jp,
+};
+
+pub const EUC_KR: Codec = Codec {
+    name: "euc-kr",
+    decode_char: decode_euc_kr,
+    encode_char: encode_euc_kr,
+};
+
+impl Codec {
+    pub fn decode(&self, bytes: &[u8]) -> Result<String, String> {
+        let mut text = String::with_capacity(bytes.len());
+        let mut offset = 0;
+        while let Some(&byte) = bytes.get(offset) {
+            if byte.is_ascii() {
+                text.push(char::from(byte));
+                offset += 1;
+                continue;
+            }
+            match (self.decode_char)(&bytes[offset..]) {
+                Some((ch, len)) => {
+                    text.push(ch);
+                    offset += len;
+                }
+                None => {
+                    return Err(format!(
+                        "第 {} 个字节开始不是有效的 {} 编码",
+                        offset, self.name
+                    ))
+                }
+            }
+        }
+        Ok(text)
+    }
+
+    pub fn encode(&self, text: &str) -> Result<Vec<u8>, String> {
+        let mut bytes = Vec::with_capacity(text.len());
+        for ch in text.chars() {
+            if ch.is_ascii() {
+                bytes.push(ch as u8);
+            } else if !(self.encode_char)(ch, &mut bytes) {
+                return Err(format!("字符 {:?} 无法用 {} 编码", ch, self.name));
+            }
+        }
+        Ok(bytes)
+    }
+}
+
+///
+/// 展开之后的码表：按指针查字符，按字符查指针
+///
+struct Table {
+    chars: Vec<char>,
+    pointers: HashMap<char, u16>,
+}
+
+impl Table {
+    fn new(source: &str) -> Self {
+        let chars: Vec<char> = source.chars().collect();
+        //
+        // 同一个字符出现在多个指针上时编码成最后一个，和生成码表的编解码器一致
+        //
+        let pointers = chars
+            .iter()
+            .enumerate()
+            .filter(|(_, &ch)| ch != '\0')
+            .map(|(pointer, &ch)| (ch, pointer as u16))
+            .collect();
+        Self { chars, pointers }
+    }
+
+    fn char(&self, pointer: usize) -> Option<char> {
+        self.chars.get(pointer).copied().filter(|&ch| ch != '\0')
+    }
+
+    fn pointer(&self, ch: char) -> Option<usize> {
+        self.pointers.get(&ch).map(|&pointer| usize::from(pointer))
+    }
+}
+
+fn table(cell: &'static OnceLock<Table>, source: &'static str) -> &'static Table {
+    cell.get_or_init(|| Table::new(source))
+}
+
+static GB18030_TABLE: OnceLock<Table> = OnceLock::new();
+static BIG5_TABLE: OnceLock<Table> = OnceLock::new();
+static JIS0208_TABLE: OnceLock<Table> = OnceLock::new();
+static JIS0212_TABLE: OnceLock<Table> = OnceLock::new();
+static CP949_TABLE: OnceLock<Table> = OnceLock::new();
+
+///
+/// 码位 U+10000 的 GB18030 四字节序列 0x90308130 的序号，增补平面的字符从这里按码位顺序排列
+///
+const GB18030_SUPPLEMENTARY: u32 = 189_000;
+
+///
+/// 基本多文种平面内最后一个四字节序列 0x8431A439 的序号
+///
+const GB18030_BMP_LAST: u32 = 39_419;
+
+fn decode_gb18030(bytes: &[u8]) -> Option<(char, usize)> {
+    let lead = bytes[0];
+    if !(0x81..=0xFE).contains(&lead) {
+        return None;
+    }
+    let second = *bytes.get(1)?;
+    if (0x30..=0x39).contains(&second) {
+        let (third, fourth) = (*bytes.get(2)?, *bytes.get(3)?);
+        if !(0x81..=0xFE).contains(&third) || !(0x30..=0x39).contains(&fourth) {
+            return None;
+        }
+        let index = ((u32::from(lead - 0x81) * 10 + u32::from(second - 0x30)) * 126
+            + u32::from(third - 0x81))
+            * 10
+            + u32::from(fourth - 0x30);
+        return gb18030_four_byte(index).map(|ch| (ch, 4));
+    }
+    let offset = match second {
+        0x40..=0x7E => 0x40,
+        0x80..=0xFE => 0x41,
+        _ => return None,
+    };
+    let pointer = usize::from(lead - 0x81) * 190 + usize::from(second - offset);
+    table(&GB18030_TABLE, tables::GB18030)
+        .char(pointer)
+        .map(|ch| (ch, 2))
+}
+
+///
+/// 按序号查四字节序列对应的字符
+///
+fn gb18030_four_byte(index: u32) -> Option<char> {
+    if index >= GB18030_SUPPLEMENTARY {
+        return char::from_u32(index - GB18030_SUPPLEMENTARY + 0x10000);
+    }
+    if index > GB18030_BMP_LAST {
+        return None;
+    }
+    let range = tables::GB18030_RANGES.partition_point(|&(start, _)| start <= index) - 1;
+    let (start, code) = tables::GB18030_RANGES[range];
+    char::from_u32(code + index - start)
+}
+
+fn encode_gbk(ch: char, bytes: &mut Vec<u8>) -> bool {
+    let Some(pointer) = table(&GB18030_TABLE, tables::GB18030).pointer(ch) else {
+        return false;
+    };
+    let (lead, trail) = (pointer / 190, pointer % 190);
+    let offset = if trail < 0x3F { 0x40 } else { 0x41 };
+    bytes.extend([lead as u8 + 0x81, trail as u8 + offset]);
+    true
+}
+
+fn encode_gb18030(ch: char, bytes: &mut Vec<u8>) -> bool {
+    if encode_gbk(ch, bytes) {
+        return true;
+    }
+    //
+    // 不在双字节部分的字符都有四字节序列，区段按序号和码位都是递增的
+    //
+    let code = u32::from(ch);
+    let mut index = if code >= 0x10000 {
+        code - 0x10000 + GB18030_SUPPLEMENTARY
+    } else {
+        let range = tables::GB18030_RANGES.partition_point(|&(_, first)| first <= code) - 1;
+        let (start, first) = tables::GB18030_RANGES[range];
+        start + code - first
+    };
+    let fourth = index % 10;
+    index /= 10;
+    let third = index % 126;
+    index /= 126;
+    let second = index % 10;
+    let lead = index / 10;
+    bytes.extend([
+        lead as u8 + 0x81,
+        second as u8 + 0x30,
+        third as u8 + 0x81,
+        fourth as u8 + 0x30,
+    ]);
+    true
+}
+
+fn decode_big5(bytes: &[u8]) -> Option<(char, usize)> {
+    let lead = bytes[0];
+    if !(0x81..=0xFE).contains(&lead) {
+        return None;
+    }
+    let trail = *bytes.get(1)?;
+    let offset = match trail {
+        0x40..=0x7E => 0x40,
+        0xA1..=0xFE => 0x62,
+        _ => return None,
+    };
+    let pointer = usize::from(lead - 0x81) * 157 + usize::from(trail - offset);
+    table(&BIG5_TABLE, tables::BIG5)
+        .char(pointer)
+        .map(|ch| (ch, 2))
+}
+
+fn encode_big5(ch: char, bytes: &mut Vec<u8>) -> bool {
+    let Some(pointer) = table(&BIG5_TABLE, tables::BIG5).pointer(ch) else {
+        return false;
+    };
+    let (lead, trail) = (pointer / 157, pointer % 157);
+    let offset = if trail < 0x3F { 0x40 } else { 0x62 };
+    bytes.extend([lead as u8 + 0x81, trail as u8 + offset]);
+    true
+}
+
+///
+/// 半角片假名 U+FF61..=U+FF9F，Shift-JIS 中是单字节 0xA1..=0xDF，EUC-JP 中以 0x8E 开头
+///
+fn halfwidth_katakana(byte: u8) -> Option<char> {
+    (0xA1..=0xDF)
+        .contains(&byte)
+        .then(|| char::from_u32(0xFF61 + u32::from(byte - 0xA1)))
+        .flatten()
+}
+
+fn halfwidth_katakana_byte(ch: char) -> Option<u8> {
+    let code = u32::from(ch);
+    (0xFF61..=0xFF9F)
+        .contains(&code)
+        .then(|| (code - 0xFF61) as u8 + 0xA1)
+}
+
+///
+/// JIS X 0201 罗马字中和 ASCII 不同的两个字符，解码时按 ASCII 处理，编码时仍然可以写出
+///
+fn jis_roman_byte(ch: char) -> Option<u8> {
+    match ch {
+        '\u{A5}' => Some(0x5C),
+        '\u{203E}' => Some(0x7E),
+        _ => None,
+    }
+}
+
+fn decode_shift_jis(bytes: &[u8]) -> Option<(char, usize)> {
+    let lead = bytes[0];
+    if let Some(ch) = halfwidth_katakana(lead) {
+        return Some((ch, 1));
+    }
+    let lead_offset = match lead {
+        0x81..=0x9F => 0x81,
+        0xE0..=0xFC => 0xC1,
+        _ => return None,
+    };
+    let trail = *bytes.get(1)?;
+    let trail_offset = match trail {
+        0x40..=0x7E => 0x40,
+        0x80..=0xFC => 0x41,
+        _ => return None,
+    };
+    //
+    // 每个前导字节对应 JIS X 0208 的两个区，指针和 EUC-JP 的相同
+    //
+    let pointer = usize::from(lead - lead_offset) * 188 + usize::from(trail - trail_offset);
+    table(&JIS0208_TABLE, tables::JIS0208)
+        .char(pointer)
+        .map(|ch| (ch, 2))
+}
+
+fn encode_shift_jis(ch: char, bytes: &mut Vec<u8>) -> bool {
+    if let Some(byte) = jis_roman_byte(ch).or_else(|| halfwidth_katakana_byte(ch)) {
+        bytes.push(byte);
+        return true;
+    }
+    let Some(pointer) = table(&JIS0208_TABLE, tables::JIS0208).pointer(ch) else {
+        return false;
+    };
+    let (lead, trail) = (pointer / 188, pointer % 188);
+    let lead_offset = if lead < 0x1F { 0x81 } else { 0xC1 };
+    let trail_offset = if trail < 0x3F { 0x40 } else { 0x41 };
+    bytes.extend([lead as u8 + lead_offset, trail as u8 + trail_offset]);
+    true
+}
+
+///
+/// EUC 编码的双字节：区和点都是 0xA1..=0xFE，按 94 区 94 点算出指针
+///
+fn euc_pointer(lead: u8, trail: u8) -> Option<usize> {
+    ((0xA1..=0xFE).contains(&lead) && (0xA1..=0xFE).contains(&trail))
+        .then(|| usize::from(lead - 0xA1) * 94 + usize::from(trail - 0xA1))
+}
+
+fn euc_bytes(pointer: usize) -> [u8; 2] {
+    [(pointer / 94) as u8 + 0xA1, (pointer % 94) as u8 + 0xA1]
+}
+
+fn decode_euc_jp(bytes: &[u8]) -> Option<(char, usize)> {
+    match bytes[0] {
+        0x8E => halfwidth_katakana(*bytes.get(1)?).map(|ch| (ch, 2)),
+        0x8F => {
+            let pointer = euc_pointer(*bytes.get(1)?, *bytes.get(2)?)?;
+            table(&JIS0212_TABLE, tables::JIS0212)
+                .char(pointer)
+                .map(|ch| (ch, 3))
+        }
+        lead => {
+            let pointer = euc_pointer(lead, *bytes.get(1)?)?;
+            table(&JIS0208_TABLE, tables::JIS0208)
+                .char(pointer)
+                .map(|ch| (ch, 2))
+        }
+    }
+}
+
+fn encode_euc_jp(ch: char, bytes: &mut Vec<u8>) -> bool {
+    if let Some(byte) = jis_roman_byte(ch) {
+        bytes.push(byte);
+    } else if let Some(byte) = halfwidth_katakana_byte(ch) {
+        bytes.extend([0x8E, byte]);
+    } else if let Some(pointer) = table(&JIS0208_TABLE, tables::JIS0208).pointer(ch) {
+        bytes.extend(euc_bytes(pointer));
+    } else if let Some(pointer) = table(&JIS0212_TABLE, tables::JIS0212).pointer(ch) {
+        bytes.push(0x8F);
+        bytes.extend(euc_bytes(pointer));
+    } else {
+        return false;
+    }
+    true
+}
+
+fn decode_euc_kr(bytes: &[u8]) -> Option<(char, usize)> {
+    let lead = bytes[0];
+    let trail = *bytes.get(1)?;
+    if !(0x81..=0xFE).contains(&lead) || !(0x41..=0xFE).contains(&trail) {
+        return None;
+    }
+    let pointer = usize::from(lead - 0x81) * 190 + usize::from(trail - 0x41);
+    table(&CP949_TABLE, tables::CP949)
+        .char(pointer)
+        .map(|ch| (ch, 2))
+}
+
+fn encode_euc_kr(ch: char, bytes: &mut Vec<u8>) -> bool {
+    let Some(pointer) = table(&CP949_TABLE, tables::CP949).pointer(ch) else {
+        return false;
+    };
+    bytes.extend([(pointer / 190) as u8 + 0x81, (pointer % 190) as u8 + 0x41]);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(codec: &Codec, text: &str, bytes: &[u8]) {
+        assert_eq!(codec.encode(text).unwrap(), bytes);
+        assert_eq!(codec.decode(bytes).unwrap(), text);
+    }
+
+    #[test]
+    fn gbk_and_gb18030() {
+        round_trip(&GBK, "a中文", b"a\xD6\xD0\xCE\xC4");
+        round_trip(&GB18030, "中€", b"\xD6\xD0\xA2\xE3");
+        round_trip(&GB18030, "ß😀", b"\x81\x30\x89\x38\x94\x39\xFC\x36");
+        assert!(GBK.encode("😀").is_err());
+    }
+
+    #[test]
+    fn big5() {
+        round_trip(&BIG5, "中文", b"\xA4\xA4\xA4\xE5");
+    }
+
+    #[test]
+    fn japanese() {
+        round_trip(&SHIFT_JIS, "日本ｱ", b"\x93\xFA\x96\x7B\xB1");
+        round_trip(&EUC_JP, "日本ｱ丂", b"\xC6\xFC\xCB\xDC\x8E\xB1\x8F\xB0\xA1");
+        assert_eq!(SHIFT_JIS.encode("¥").unwrap(), b"\\");
+    }
+
+    #[test]
+    fn korean() {
+        round_trip(&EUC_KR, "한국갂", b"\xC7\xD1\xB1\xB9\x81\x41");
+    }
+
+    #[test]
+    fn invalid_sequences() {
+        assert!(GBK.decode(b"\xD6").is_err());
+        assert!(GB18030.decode(b"\x80").is_err());
+        assert!(SHIFT_JIS.decode(b"\x81\x20").is_err());
+        assert_eq!(
+            EUC_KR.decode(b"ok\xFF").unwrap_err(),
+            "第 2 个字节开始不是有效的 euc-kr 编码"
+        );
+    }
+}
@@ -0,0 +1,222 @@
+//!
+//! --normalize：匹配之前把文件内容和正则统一成 NFC 或 NFD，写回时使用同样的形式
+//! 分解、组合和组合类的数据见 unicode_tables.rs，韩文音节按算法分解和组合
+//!
+
+use clap::ValueEnum;
+
+use crate::unicode_tables::{COMBINING_CLASSES, COMPOSITIONS, DECOMPOSITIONS};
+
+///
+/// Unicode 规范化形式
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Normalization {
+    ///
+    /// 规范组合，例如 é 写成一个字符
+    ///
+    Nfc,
+    ///
+    /// 规范分解，例如 é 写成 e 加上组合用的尖音符
+    ///
+    Nfd,
+}
+
+impl Normalization {
+    pub fn apply(&self, text: &str) -> String {
+        //
+        // ASCII 文本在两种形式下都不会改变
+        //
+        if text.is_ascii() {
+            return text.to_string();
+        }
+        match self {
+            Self::Nfc => compose(decompose(text)).into_iter().collect(),
+            Self::Nfd => decompose(text).into_iter().collect(),
+        }
+    }
+}
+
+const S_BASE: u32 = 0xAC00;
+const L_BASE: u32 = 0x1100;
+const V_BASE: u32 = 0x1161;
+const T_BASE: u32 = 0x11A7;
+const L_COUNT: u32 = 19;
+const V_COUNT: u32 = 21;
+const T_COUNT: u32 = 28;
+const N_COUNT: u32 = V_COUNT * T_COUNT;
+const S_COUNT: u32 = L_COUNT * N_COUNT;
+
+fn from_u32(code: u32) -> char {
+    char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+}
+
+fn combining_class(ch: char) -> u8 {
+    let index = COMBINING_CLASSES.partition_point(|&(start, _, _)| start <= ch);
+    match index.checked_sub(1).map(|index| COMBINING_CLASSES[index]) {
+        Some((_, end, class)) if ch <= end => class,
+        _ => 0,
+    }
+}
+
+fn decompose_char(ch: char, chars: &mut Vec<char>) {
+    let code = ch as u32;
+    if (S_BASE..S_BASE + S_COUNT).contains(&code) {
+        let index = code - S_BASE;
+        chars.push(from_u32(L_BASE + index / N_COUNT));
+        chars.push(from_u32(V_BASE + index % N_COUNT / T_COUNT));
+        if !index.is_multiple_of(T_COUNT) {
+            chars.push(from_u32(T_BASE + index % T_COUNT));
+        }
+        return;
+    }
+    match DECOMPOSITIONS.binary_search_by_key(&ch, |&(decomposed, _)| decomposed) {
+        Ok(index) => chars.extend_from_slice(DECOMPOSITIONS[index].1),
+        Err(_) => chars.push(ch),
+    }
+}
+
+///
+/// 完整分解后按组合类做规范排序，得到 NFD
+///
+fn decompose(text: &str) -> Vec<char> {
+    let mut chars = Vec::with_capacity(text.len());
+    for ch in text.chars() {
+        decompose_char(ch, &mut chars);
+    }
+
+    //
+    // 连续的非起始字符按组合类稳定排序
+    //
+    let mut start = 0;
+    while start < chars.len() {
+        if combining_class(chars[start]) == 0 {
+            start += 1;
+            continue;
+        }
+        let mut end = start;
+        while end < chars.len() && combining_class(chars[end]) != 0 {
+            end += 1;
+        }
+        chars[start..end].sort_by_key(|&ch| combining_class(ch));
+        start = end;
+    }
+    chars
+}
+
+fn compose_pair(first: char, second: char) -> Option<char> {
+    let (a, b) = (first as u32, second as u32);
+    if (L_BASE..L_BASE + L_COUNT).contains(&a) && (V_BASE..V_BASE + V_COUNT).contains(&b) {
+        return Some(from_u32(
+            S_BASE + ((a - L_BASE) * V_COUNT + (b - V_BASE)) * T_COUNT,
+        ));
+    }
+    if (S_BASE..S_BASE + S_COUNT).contains(&a)
+        && (a - S_BASE).is_multiple_of(T_COUNT)
+        && (T_BASE + 1..T_BASE + T_COUNT).contains(&b)
+    {
+        return Some(from_u32(a + (b - T_BASE)));
+    }
+    COMPOSITIONS
+        .binary_search_by_key(&(first, second), |&(a, b, _)| (a, b))
+        .ok()
+        .map(|index| COMPOSITIONS[index].2)
+}
+
+///
+/// 对 NFD 做规范组合，得到 NFC
+/// 字符和前面最近的起始字符之间没有组合类相同或更高的字符阻隔时，尝试和起始字符组合
+///
+fn compose(chars: Vec<char>) -> Vec<char> {
+    let mut composed: Vec<char> = Vec::with_capacity(chars.len());
+    let mut starter: Option<usize> = None;
+    for ch in chars {
+        let class = combining_class(ch);
+        if let Some(index) = starter {
+            let blocked = index + 1 < composed.len() && {
+                let last = combining_class(composed[composed.len() - 1]);
+                last == 0 || last >= class
+            };
+            if !blocked {
+                if let Some(pair) = compose_pair(composed[index], ch) {
+                    composed[index] = pair;
+                    continue;
+                }
+            }
+        }
+        if class == 0 {
+            starter = Some(composed.len());
+        }
+        composed.push(ch);
+    }
+    composed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nfd(text: &str) -> String {
+        Normalization::Nfd.apply(text)
+    }
+
+    fn nfc(text: &str) -> String {
+        Normalization::Nfc.apply(text)
+    }
+
+    #[test]
+    fn tables_are_sorted() {
+        assert!(DECOMPOSITIONS.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert!(COMPOSITIONS
+            .windows(2)
+            .all(|pair| (pair[0].0, pair[0].1) < (pair[1].0, pair[1].1)));
+        assert!(COMBINING_CLASSES
+            .windows(2)
+            .all(|pair| pair[0].0 <= pair[0].1 && pair[0].1 < pair[1].0));
+    }
+
+    #[test]
+    fn decompositions_are_complete() {
+        for (ch, decomposed) in DECOMPOSITIONS {
+            assert!(
+                decomposed.iter().all(|part| DECOMPOSITIONS
+                    .binary_search_by_key(part, |&(ch, _)| ch)
+                    .is_err()),
+                "{:?} 的分解还可以继续分解",
+                ch
+            );
+        }
+    }
+
+    #[test]
+    fn compositions_round_trip() {
+        for &(_, _, composed) in COMPOSITIONS {
+            let composed = composed.to_string();
+            assert_eq!(nfc(&nfd(&composed)), composed);
+        }
+    }
+
+    #[test]
+    fn canonical_order_and_blocking() {
+        assert_eq!(nfd("\u{1EC7}"), "e\u{323}\u{302}");
+        assert_eq!(nfc("e\u{302}\u{323}"), "\u{1EC7}");
+        assert_eq!(nfc("d\u{307}\u{323}"), "\u{1E0D}\u{307}");
+        assert_eq!(nfc("a\u{328}\u{323}\u{301}"), "\u{105}\u{323}\u{301}");
+    }
+
+    #[test]
+    fn singletons_and_exclusions() {
+        assert_eq!(nfc("\u{212B}"), "\u{C5}");
+        assert_eq!(nfc("\u{958}"), "\u{915}\u{93C}");
+    }
+
+    #[test]
+    fn hangul_syllables() {
+        assert_eq!(
+            nfd("한글"),
+            "\u{1112}\u{1161}\u{11AB}\u{1100}\u{1173}\u{11AF}"
+        );
+        assert_eq!(nfc("\u{1112}\u{1161}\u{11AB}"), "한");
+        assert_eq!(nfc("\u{1100}\u{1161}"), "가");
+    }
+}
@@ -0,0 +1,97 @@
+//!
+//! 超大文件的逐行替换：在文件的内存映射上逐行替换，只重写有改动的行
+//! 没有改动的区域用 io::copy 直接从原文件复制到临时文件，不经过按行拼接和切分字符串
+//! 输出和逐行替换完全相同：行尾的 \r\n 换成 \n，最后一行没有换行时补上
+//!
+
+use std::borrow::Cow;
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+
+use crate::select::Selector;
+use crate::template::Template;
+use crate::{install, mmap, replace_text};
+
+///
+/// 单行正则逐行替换时，不小于这个大小的文件只重写有改动的行
+///
+pub const PATCH_THRESHOLD: u64 = 64 * 1024 * 1024;
+
+///
+/// 把原文件中 start..end 的字节原样复制到输出
+///
+fn copy_range(
+    source: &mut File,
+    start: usize,
+    end: usize,
+    writer: &mut impl Write,
+) -> io::Result<()> {
+    if start == end {
+        return Ok(());
+    }
+    source.seek(SeekFrom::Start(start as u64))?;
+    io::copy(&mut source.take((end - start) as u64), writer)?;
+    Ok(())
+}
+
+///
+/// 用单行正则逐行替换文件到临时文件
+///
+pub fn replace_in_file(
+    target_file: &Path,
+    re: &Regex,
+    replacement: &Template,
+    selector: &mut Selector,
+) -> Result<PathBuf, Box<dyn Error>> {
+    let mapped = mmap::Mmap::open(target_file)?;
+    let text = std::str::from_utf8(&mapped).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "stream did not contain valid UTF-8",
+        )
+    })?;
+
+    let temp_file = install::create_temp_file(target_file)?;
+    let temp_file_path = temp_file.path().to_path_buf();
+    let file = OpenOptions::new()
+        .append(true)
+        .open(temp_file_path.clone())?;
+    let mut writer = BufWriter::new(file);
+    let mut source = File::open(target_file)?;
+
+    //
+    // 原文件中已经写到临时文件的位置，它和 offset 之间是还没有复制的、没有改动的行
+    //
+    let mut copied = 0;
+    let mut offset = 0;
+    for (index, line) in text.split_inclusive('\n').enumerate() {
+        //
+        // 和逐行替换使用的 lines() 一样去掉行尾的 \n 或 \r\n
+        //
+        let (content, rewrite) = match line.strip_suffix('\n') {
+            Some(content) => match content.strip_suffix('\r') {
+                Some(content) => (content, true),
+                None => (content, false),
+            },
+            None => (line, true),
+        };
+        let replaced = replace_text(content, index + 1, re, replacement, selector);
+        if rewrite || matches!(replaced, Cow::Owned(_)) {
+            copy_range(&mut source, copied, offset, &mut writer)?;
+            writer.write_all(replaced.as_bytes())?;
+            writer.write_all(b"\n")?;
+            copied = offset + line.len();
+        }
+        offset += line.len();
+    }
+    copy_range(&mut source, copied, offset, &mut writer)?;
+    writer.flush()?;
+    drop(writer);
+    let _ = temp_file.persist(&temp_file_path)?;
+
+    Ok(temp_file_path)
+}
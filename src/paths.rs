@@ -0,0 +1,482 @@
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+///
+/// 宿主机路径前缀到本地（容器内）路径前缀的映射
+///
+#[derive(Debug, Clone)]
+pub struct PrefixMap {
+    host: PathBuf,
+    local: PathBuf,
+}
+
+static PREFIX_MAPS: OnceLock<Vec<PrefixMap>> = OnceLock::new();
+
+///
+/// 解析 --path-prefix-map 的 /host=/mnt
+///
+pub fn parse_prefix_map(map: &str) -> Result<PrefixMap, String> {
+    match map.split_once('=') {
+        Some((host, local)) if !host.is_empty() && !local.is_empty() => Ok(PrefixMap {
+            host: PathBuf::from(host),
+            local: PathBuf::from(local),
+        }),
+        _ => Err(format!("路径映射的格式应为 宿主机前缀=本地前缀: {}", map)),
+    }
+}
+
+///
+/// 设置整次运行使用的路径映射，只能在启动时设置一次
+///
+pub fn set_prefix_maps(maps: Vec<PrefixMap>) {
+    let _ = PREFIX_MAPS.set(maps);
+}
+
+///
+/// 把宿主机路径换算成本地可以访问的路径
+/// Windows 上过长的路径加上 \\?\ 前缀，深层的 node_modules 和 UNC 共享目录下的文件也能处理
+///
+pub fn to_local(path: &Path) -> PathBuf {
+    extended(&remap(path, |map| (&map.host, &map.local)))
+}
+
+///
+/// 把本地路径换算回宿主机路径，输出和报告中的路径都使用宿主机路径
+/// Windows 上去掉 \\?\ 前缀，分隔符统一成 \
+///
+pub fn to_host(path: &Path) -> PathBuf {
+    display_form(&remap(path, |map| (&map.local, &map.host)))
+}
+
+///
+/// 写进补丁、SARIF 等文件中的路径：尽量相对于当前目录，使用 / 分隔
+///
+pub fn portable(path: &Path) -> String {
+    let relative = env::current_dir()
+        .ok()
+        .and_then(|dir| path.strip_prefix(dir).ok().map(Path::to_path_buf))
+        .unwrap_or_else(|| path.to_path_buf());
+    let name = relative.to_string_lossy().replace('\\', "/");
+    name.trim_start_matches("./").to_string()
+}
+
+///
+/// 把字节串转换成路径，unix 上文件名可以不是 UTF-8，按原样保留
+///
+pub fn from_bytes(bytes: &[u8]) -> PathBuf {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+    }
+    #[cfg(not(unix))]
+    {
+        PathBuf::from(String::from_utf8_lossy(bytes).as_ref())
+    }
+}
+
+///
+/// Windows 上不带 \\?\ 前缀的路径最长 260 个字符，目录还要给 8.3 短文件名留出位置
+///
+#[cfg(windows)]
+const MAX_PATH: usize = 248;
+
+///
+/// 超过 MAX_PATH 的路径转换成扩展长度的绝对路径：
+/// C:\dir 转换成 \\?\C:\dir，\\server\share\dir 转换成 \\?\UNC\server\share\dir
+/// 扩展长度的路径不会再做任何解析，所以先转换成绝对路径并去掉 . 和 ..
+///
+#[cfg(windows)]
+fn extended(path: &Path) -> PathBuf {
+    use std::ffi::OsString;
+    use std::path::{Component, Prefix};
+
+    if path.as_os_str().len() < MAX_PATH {
+        return path.to_path_buf();
+    }
+    let Ok(absolute) = std::path::absolute(path) else {
+        return path.to_path_buf();
+    };
+    let mut components = absolute.components();
+    let mut extended = match components.next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::Disk(letter) => OsString::from(format!(r"\\?\{}:", letter as char)),
+            Prefix::UNC(server, share) => {
+                let mut extended = OsString::from(r"\\?\UNC\");
+                extended.push(server);
+                extended.push(r"\");
+                extended.push(share);
+                extended
+            }
+            //
+            // 已经是扩展长度的路径或者设备路径
+            //
+            _ => return absolute,
+        },
+        _ => return absolute,
+    };
+    for component in components {
+        if let Component::Normal(name) = component {
+            extended.push(r"\");
+            extended.push(name);
+        }
+    }
+    PathBuf::from(extended)
+}
+
+#[cfg(not(windows))]
+fn extended(path: &Path) -> PathBuf {
+    path.to_path_buf()
+}
+
+///
+/// 输出用的路径形式：\\?\C:\dir 还原成 C:\dir，\\?\UNC\server\share 还原成 \\server\share，
+/// / 统一换成 \，不管路径来自参数、文件列表还是目录遍历，输出和 JSON 报告中的写法都一样
+///
+#[cfg(windows)]
+fn display_form(path: &Path) -> PathBuf {
+    use std::ffi::OsString;
+    use std::path::{Component, Prefix};
+
+    let mut host = OsString::new();
+    let mut separated = true;
+    for component in path.components() {
+        match component {
+            Component::Prefix(prefix) => {
+                match prefix.kind() {
+                    Prefix::VerbatimDisk(letter) | Prefix::Disk(letter) => {
+                        host.push(format!("{}:", letter as char))
+                    }
+                    Prefix::VerbatimUNC(server, share) | Prefix::UNC(server, share) => {
+                        host.push(r"\\");
+                        host.push(server);
+                        host.push(r"\");
+                        host.push(share);
+                    }
+                    _ => host.push(prefix.as_os_str()),
+                }
+                //
+                // C:dir 这样相对于盘符当前目录的路径，盘符后面不加分隔符
+                //
+                separated = true;
+            }
+            Component::RootDir => {
+                host.push(r"\");
+                separated = true;
+            }
+            _ => {
+                if !separated {
+                    host.push(r"\");
+                }
+                host.push(component.as_os_str());
+                separated = false;
+            }
+        }
+    }
+    PathBuf::from(host)
+}
+
+#[cfg(not(windows))]
+fn display_form(path: &Path) -> PathBuf {
+    path.to_path_buf()
+}
+
+///
+/// 按第一个匹配的前缀换算，前缀按路径组件比较，/host 不会匹配 /hostname
+///
+fn remap(path: &Path, direction: impl Fn(&PrefixMap) -> (&PathBuf, &PathBuf)) -> PathBuf {
+    for map in PREFIX_MAPS.get().into_iter().flatten() {
+        let (from, to) = direction(map);
+        if let Ok(rest) = path.strip_prefix(from) {
+            return to.join(rest);
+        }
+    }
+    path.to_path_buf()
+}
+
+///
+/// 去掉指向同一个文件的重复路径，保留第一次出现的顺序
+/// 同一个文件被并行处理两次会互相覆盖，所以必须按文件系统的语义判断是否相同：
+/// unix 上按设备号和 inode 判断，大小写不敏感的文件系统、硬链接都能识别
+/// 其他平台按规范化路径判断，文件系统大小写不敏感时忽略大小写
+///
+pub fn dedup(files: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut seen = HashSet::new();
+    files
+        .into_iter()
+        .filter(|file| seen.insert(identity(file)))
+        .collect()
+}
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum Identity {
+    #[cfg(unix)]
+    Inode(u64, u64),
+    Path(PathBuf),
+}
+
+#[cfg(unix)]
+fn identity(file: &Path) -> Identity {
+    use std::os::unix::fs::MetadataExt;
+    match std::fs::metadata(file) {
+        Ok(metadata) => Identity::Inode(metadata.dev(), metadata.ino()),
+        Err(_) => Identity::Path(file.to_path_buf()),
+    }
+}
+
+#[cfg(not(unix))]
+fn identity(file: &Path) -> Identity {
+    let path = std::fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf());
+    match path.parent() {
+        Some(dir) if is_case_insensitive(dir) => {
+            Identity::Path(PathBuf::from(path.to_string_lossy().to_lowercase()))
+        }
+        _ => Identity::Path(path),
+    }
+}
+
+///
+/// 探测目录所在的文件系统是否大小写不敏感：
+/// 找一个名字里有字母的已有条目，把名字的大小写反转之后看访问到的是不是同一个文件
+/// 不在目标目录里创建文件，只读的目录树也能探测；目录里没有这样的条目时，
+/// 和临时目录在同一个文件系统上就在临时目录里创建探测文件
+/// 结果按设备缓存，unix 上同样需要探测（macOS 默认的 APFS、Linux 上开启了 casefold 的目录）
+///
+pub fn is_case_insensitive(dir: &Path) -> bool {
+    static CACHE: OnceLock<Mutex<HashMap<u64, bool>>> = OnceLock::new();
+
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    let Ok(metadata) = fs::metadata(dir) else {
+        return false;
+    };
+    let dev = device(&metadata);
+    let cache = CACHE.get_or_init(Default::default);
+    if let Some(&insensitive) = cache.lock().unwrap().get(&dev) {
+        return insensitive;
+    }
+
+    let temp_dir = env::temp_dir();
+    let probed = probe_entries(dir).or_else(|| {
+        let same_device = fs::metadata(&temp_dir).is_ok_and(|temp| device(&temp) == dev);
+        same_device.then(|| probe_temp_file(&temp_dir)).flatten()
+    });
+    match probed {
+        Some(insensitive) => {
+            cache.lock().unwrap().insert(dev, insensitive);
+            insensitive
+        }
+        //
+        // 探测不出来时按大小写敏感处理，不缓存，下次换一个目录再探测
+        //
+        None => false,
+    }
+}
+
+///
+/// 用目录中已有的条目探测，没有名字里带字母的条目时返回 None
+///
+fn probe_entries(dir: &Path) -> Option<bool> {
+    for entry in fs::read_dir(dir).ok()?.flatten() {
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else {
+            continue;
+        };
+        let swapped = swap_case(name);
+        if swapped == name {
+            continue;
+        }
+        let Ok(original) = entry.metadata() else {
+            continue;
+        };
+        return Some(match fs::symlink_metadata(dir.join(swapped)) {
+            Ok(found) => same_file(&original, &found),
+            Err(_) => false,
+        });
+    }
+    None
+}
+
+///
+/// 在临时目录里创建一个小写名字的探测文件，看大写的名字能不能访问到它，结束时删除
+///
+fn probe_temp_file(temp_dir: &Path) -> Option<bool> {
+    let probe = tempfile::Builder::new()
+        .prefix(".regex-replace-case-probe-")
+        .tempfile_in(temp_dir)
+        .ok()?;
+    let name = probe.path().file_name()?.to_string_lossy().to_uppercase();
+    Some(temp_dir.join(name).exists())
+}
+
+fn swap_case(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_lowercase() {
+                c.to_uppercase().to_string()
+            } else {
+                c.to_lowercase().to_string()
+            }
+        })
+        .collect()
+}
+
+#[cfg(unix)]
+fn device(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.dev()
+}
+
+#[cfg(not(unix))]
+fn device(_metadata: &fs::Metadata) -> u64 {
+    0
+}
+
+#[cfg(unix)]
+fn same_file(a: &fs::Metadata, b: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    (a.dev(), a.ino()) == (b.dev(), b.ino())
+}
+
+//
+// 其他平台拿不到 inode，反转大小写之后能访问到的文件就认为是同一个
+//
+#[cfg(not(unix))]
+fn same_file(_a: &fs::Metadata, _b: &fs::Metadata) -> bool {
+    true
+}
+
+///
+/// 比较路径是否冲突时使用的键：所在目录的文件系统大小写不敏感时忽略文件名的大小写
+///
+pub fn case_key(path: &Path) -> PathBuf {
+    match (path.parent(), path.file_name()) {
+        (Some(dir), Some(name)) if is_case_insensitive(dir) => {
+            dir.join(name.to_string_lossy().to_lowercase())
+        }
+        _ => path.to_path_buf(),
+    }
+}
+
+///
+/// 重命名文件，新旧名字只有大小写不同时分两步：先改成一个临时的名字，再改成新名字
+/// 大小写不敏感的文件系统上新名字指向的就是自己，直接重命名可能什么都不做
+///
+pub fn rename(from: &Path, to: &Path) -> io::Result<()> {
+    let case_only = from.parent() == to.parent()
+        && match (from.file_name(), to.file_name()) {
+            (Some(old), Some(new)) => {
+                old != new
+                    && old.to_string_lossy().to_lowercase() == new.to_string_lossy().to_lowercase()
+            }
+            _ => false,
+        };
+    if !case_only {
+        return fs::rename(from, to);
+    }
+    //
+    // 中间的名字不使用临时文件的前缀，中断之后 cleanup 不会把它当成遗留的临时文件删除
+    //
+    let name = from.file_name().unwrap_or_default().to_string_lossy();
+    let step = from.with_file_name(format!(
+        ".regex-replace-rename.{}.{}",
+        std::process::id(),
+        name
+    ));
+    fs::rename(from, &step)?;
+    fs::rename(&step, to).inspect_err(|_| {
+        let _ = fs::rename(&step, from);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swap_case_of_mixed_names() {
+        assert_eq!(swap_case("Readme.md"), "rEADME.MD");
+        assert_eq!(swap_case("123_-"), "123_-");
+    }
+
+    #[test]
+    fn probing_leaves_the_directory_untouched() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("Cargo.toml"), "").unwrap();
+        let insensitive = is_case_insensitive(dir.path());
+        let entries: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(entries, ["Cargo.toml"]);
+        assert_eq!(probe_entries(dir.path()), Some(insensitive));
+    }
+
+    fn file_names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn case_key_follows_the_filesystem() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("Readme.md"), "").unwrap();
+        let path = dir.path().join("Readme.md");
+        let expected = if is_case_insensitive(dir.path()) {
+            dir.path().join("readme.md")
+        } else {
+            path.clone()
+        };
+        assert_eq!(case_key(&path), expected);
+    }
+
+    #[test]
+    fn rename_only_changing_case() {
+        let dir = tempfile::tempdir().unwrap();
+        let from = dir.path().join("readme.md");
+        fs::write(&from, "contents").unwrap();
+        rename(&from, &dir.path().join("README.md")).unwrap();
+        assert_eq!(file_names(dir.path()), ["README.md"]);
+        assert_eq!(
+            fs::read_to_string(dir.path().join("README.md")).unwrap(),
+            "contents"
+        );
+    }
+
+    #[test]
+    fn rename_to_another_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let from = dir.path().join("old.txt");
+        fs::write(&from, "contents").unwrap();
+        rename(&from, &dir.path().join("new.txt")).unwrap();
+        assert_eq!(file_names(dir.path()), ["new.txt"]);
+    }
+
+    #[test]
+    fn failed_case_only_rename_keeps_the_original() {
+        let dir = tempfile::tempdir().unwrap();
+        let from = dir.path().join("name");
+        fs::write(&from, "").unwrap();
+        //
+        // 目标是非空目录时第二步重命名失败，文件要改回原来的名字
+        //
+        fs::create_dir(dir.path().join("NAME")).unwrap();
+        fs::write(dir.path().join("NAME").join("inside"), "").unwrap();
+        if is_case_insensitive(dir.path()) {
+            return;
+        }
+        assert!(rename(&from, &dir.path().join("NAME")).is_err());
+        assert_eq!(file_names(dir.path()), ["NAME", "name"]);
+    }
+}
@@ -0,0 +1,239 @@
+//!
+//! 在执行正则之前跳过不可能匹配的文件和行
+//! 多条规则时用 RegexSet 一次检查所有正则，规则文件生成的上百条规则不需要对每一行逐条执行
+//! 规则都有必须包含的字面量时，先用 memchr 或 Aho-Corasick 查找字面量，找不到就不需要执行正则
+//!
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::OnceLock;
+
+use aho_corasick::AhoCorasick;
+use memchr::memmem;
+use regex::RegexSet;
+
+use crate::{analysis, RegexLimits, Rule};
+
+///
+/// 按块查找字面量时每次读入的字节数
+///
+const CHUNK_SIZE: usize = 64 * 1024;
+
+///
+/// 所有规则的 RegexSet 和字面量，第一次检查时构造
+///
+#[derive(Debug, Clone, Default)]
+pub struct Prefilter {
+    set: OnceLock<Option<RegexSet>>,
+    literals: OnceLock<Option<Literals>>,
+}
+
+///
+/// 所有规则必须包含的字面量的并集，找到其中任何一个才可能有匹配
+///
+#[derive(Debug, Clone)]
+struct Literals {
+    searcher: Searcher,
+    ///
+    /// 最长的字面量的字节数，按块查找时相邻的块要重叠这么多减一
+    ///
+    max_len: usize,
+}
+
+#[derive(Debug, Clone)]
+enum Searcher {
+    One(Box<memmem::Finder<'static>>),
+    Many(AhoCorasick),
+}
+
+impl Literals {
+    ///
+    /// 任何一条规则没有必须包含的字面量时返回 None
+    ///
+    fn new(rules: &[Rule]) -> Option<Self> {
+        let mut literals = Vec::new();
+        for rule in rules {
+            literals.extend(analysis::required_literals(rule.re.as_str())?);
+        }
+        literals.sort();
+        literals.dedup();
+        let max_len = literals.iter().map(Vec::len).max().unwrap_or(0);
+        let searcher = match literals.as_slice() {
+            [literal] => Searcher::One(Box::new(memmem::Finder::new(literal).into_owned())),
+            _ => Searcher::Many(AhoCorasick::new(&literals).ok()?),
+        };
+        Some(Self { searcher, max_len })
+    }
+
+    fn is_match(&self, haystack: &[u8]) -> bool {
+        match &self.searcher {
+            Searcher::One(finder) => finder.find(haystack).is_some(),
+            Searcher::Many(searcher) => searcher.is_match(haystack),
+        }
+    }
+}
+
+impl Prefilter {
+    ///
+    /// 只有一条规则时直接用规则的正则，编译失败（例如超过大小限制）时退回逐条检查
+    ///
+    fn set(&self, rules: &[Rule], null_data: bool, limits: RegexLimits) -> Option<&RegexSet> {
+        self.set
+            .get_or_init(|| {
+                if rules.len() < 2 {
+                    return None;
+                }
+                let mut builder = limits.set_builder(rules.iter().map(|rule| rule.re.as_str()));
+                //
+                // 和 Rule::null_data 使用相同的选项，否则 ^ 和 $ 的含义不同
+                //
+                if null_data {
+                    builder.multi_line(true).line_terminator(b'\0');
+                }
+                builder.build().ok()
+            })
+            .as_ref()
+    }
+
+    fn literals(&self, rules: &[Rule]) -> Option<&Literals> {
+        self.literals.get_or_init(|| Literals::new(rules)).as_ref()
+    }
+
+    ///
+    /// text 中是否有任何规则的匹配
+    ///
+    pub fn is_match(
+        &self,
+        rules: &[Rule],
+        null_data: bool,
+        limits: RegexLimits,
+        text: &str,
+    ) -> bool {
+        match self.set(rules, null_data, limits) {
+            Some(set) => set.is_match(text),
+            None => rules.iter().any(|rule| rule.re.is_match(text)),
+        }
+    }
+
+    ///
+    /// text 中是否可能有匹配：包含某个必须的字面量，或者规则没有必须的字面量
+    ///
+    pub fn may_match(&self, rules: &[Rule], text: &str) -> bool {
+        self.may_match_bytes(rules, text.as_bytes())
+    }
+
+    ///
+    /// 同 may_match，直接查找原始的字节，不要求是有效的 UTF-8
+    ///
+    pub fn may_match_bytes(&self, rules: &[Rule], bytes: &[u8]) -> bool {
+        match self.literals(rules) {
+            Some(literals) => literals.is_match(bytes),
+            None => true,
+        }
+    }
+
+    ///
+    /// 按块读取文件查找字面量，不需要把整个文件读入内存
+    /// 直接查找原始的字节，去掉 BOM 和行尾之前的内容只会更多，找不到时同样不可能有匹配
+    ///
+    pub fn may_match_file(&self, rules: &[Rule], file: &Path) -> io::Result<bool> {
+        let Some(literals) = self.literals(rules) else {
+            return Ok(true);
+        };
+        let overlap = literals.max_len.saturating_sub(1);
+        let mut reader = File::open(file)?;
+        let mut buffer = Vec::with_capacity(CHUNK_SIZE + overlap);
+        loop {
+            //
+            // 保留上一块末尾的 overlap 个字节，跨越两块的字面量也能找到
+            //
+            let keep = buffer.len().min(overlap);
+            buffer.drain(..buffer.len() - keep);
+            let start = buffer.len();
+            buffer.resize(start + CHUNK_SIZE, 0);
+            let read = reader.read(&mut buffer[start..])?;
+            buffer.truncate(start + read);
+            if read == 0 {
+                return Ok(false);
+            }
+            if literals.is_match(&buffer) {
+                return Ok(true);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::{TokenScope, Tokens};
+    use std::fs;
+
+    fn rules(patterns: &[&str]) -> Vec<Rule> {
+        let tokens = Tokens::new(TokenScope::Run);
+        patterns
+            .iter()
+            .map(|pattern| Rule::new(pattern, "", &tokens).unwrap())
+            .collect()
+    }
+
+    ///
+    /// 字面量从 offset 开始的文件，前后用不含字面量的字节填充
+    ///
+    fn file_with(dir: &Path, literal: &str, offset: usize) -> std::path::PathBuf {
+        let mut contents = vec![b'.'; offset];
+        contents.extend(literal.as_bytes());
+        contents.resize(offset + literal.len() + CHUNK_SIZE, b'.');
+        let file = dir.join(format!("{}-{}", literal, offset));
+        fs::write(&file, contents).unwrap();
+        file
+    }
+
+    #[test]
+    fn literal_across_chunks() {
+        let dir = tempfile::tempdir().unwrap();
+        let prefilter = Prefilter::default();
+        let rules = rules(&[r"needle\d"]);
+        for boundary in [CHUNK_SIZE, 2 * CHUNK_SIZE] {
+            for offset in boundary - "needle".len()..=boundary {
+                let file = file_with(dir.path(), "needle", offset);
+                assert!(
+                    prefilter.may_match_file(&rules, &file).unwrap(),
+                    "{}",
+                    offset
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn several_literals_across_chunks() {
+        let dir = tempfile::tempdir().unwrap();
+        let prefilter = Prefilter::default();
+        let rules = rules(&["zz", "longer literal"]);
+        let file = file_with(dir.path(), "longer literal", CHUNK_SIZE - 3);
+        assert!(prefilter.may_match_file(&rules, &file).unwrap());
+        let file = file_with(dir.path(), "longer literaX", CHUNK_SIZE - 3);
+        assert!(!prefilter.may_match_file(&rules, &file).unwrap());
+    }
+
+    #[test]
+    fn missing_literal() {
+        let dir = tempfile::tempdir().unwrap();
+        let prefilter = Prefilter::default();
+        let file = file_with(dir.path(), "needl", CHUNK_SIZE - 2);
+        assert!(!prefilter
+            .may_match_file(&rules(&["needle"]), &file)
+            .unwrap());
+    }
+
+    #[test]
+    fn rules_without_literals_may_always_match() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = file_with(dir.path(), "", 0);
+        assert!(Prefilter::default()
+            .may_match_file(&rules(&["needle", r"\d+"]), &file)
+            .unwrap());
+    }
+}
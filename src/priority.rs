@@ -0,0 +1,30 @@
+use rayon::prelude::*;
+use std::path::PathBuf;
+
+use crate::paths;
+use crate::{selected_match_lines, ReplacePlan};
+
+///
+/// 先做一遍只读扫描统计每个文件的匹配数，按匹配数从多到少排序
+/// 匹配数相同的文件保持原来的顺序，读取失败的文件排在最后，留给替换阶段报告错误
+///
+pub fn prioritize(files: Vec<PathBuf>, plan: &ReplacePlan) -> Vec<PathBuf> {
+    let counts: Vec<usize> = files
+        .par_iter()
+        .map(|file| match plan.read_text(file) {
+            Ok(contents) => plan
+                .rules
+                .iter()
+                .map(|rule| selected_match_lines(&contents, &rule.re, &plan.selection).len())
+                .sum(),
+            Err(err) => {
+                eprintln!("读取文件错误 {:?}: {}", paths::to_host(file), err);
+                0
+            }
+        })
+        .collect();
+
+    let mut ranked: Vec<(usize, PathBuf)> = counts.into_iter().zip(files).collect();
+    ranked.sort_by_key(|(count, _)| std::cmp::Reverse(*count));
+    ranked.into_iter().map(|(_, file)| file).collect()
+}
@@ -0,0 +1,161 @@
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::error::FileError;
+use crate::metrics::Usage;
+use crate::{json, paths};
+
+///
+/// 两条进度记录之间的最短间隔
+///
+const INTERVAL: Duration = Duration::from_millis(200);
+
+///
+/// 把进度以 JSON Lines 的格式写到单独的文件描述符上，供图形界面或者外层脚本显示进度
+///
+/// {"files_done":3,"files_total":10,"matches":42,"bytes":10240,"elapsed_ms":120,"bytes_per_second":85333,"done":false}
+///
+/// 最后一条记录的 done 为 true，并带有 failed、skipped 和 usage 字段
+/// failed 列出处理失败的文件，例如 [{"path":"a.json","kind":"invalid","error":"..."}]，
+/// kind 见 error::FileError::kind，
+/// skipped 列出因为 --max-filesize、--max-line-length、--invalid-utf8 skip
+/// 而跳过的文件以及跳过的生成文件，格式相同
+/// usage 的内容见 metrics::Usage::to_json
+///
+pub struct Progress {
+    output: Mutex<File>,
+    started: Instant,
+    last_emitted: Mutex<Instant>,
+    files_total: usize,
+    files_done: AtomicUsize,
+    matches: AtomicUsize,
+    bytes: AtomicU64,
+}
+
+impl Progress {
+    ///
+    /// 使用 --progress-fd 指定的文件描述符，它需要由调用方打开
+    ///
+    pub fn open(fd: i32, files_total: usize) -> io::Result<Self> {
+        let output = imp::open_fd(fd)?;
+        let now = Instant::now();
+        Ok(Self {
+            output: Mutex::new(output),
+            started: now,
+            last_emitted: Mutex::new(now),
+            files_total,
+            files_done: AtomicUsize::new(0),
+            matches: AtomicUsize::new(0),
+            bytes: AtomicU64::new(0),
+        })
+    }
+
+    ///
+    /// 一个文件处理完毕，距离上一条记录足够久时输出一条
+    ///
+    pub fn file_done(&self, bytes: u64, matches: usize) {
+        self.files_done.fetch_add(1, Ordering::SeqCst);
+        self.matches.fetch_add(matches, Ordering::SeqCst);
+        self.bytes.fetch_add(bytes, Ordering::SeqCst);
+
+        let mut last_emitted = self.last_emitted.lock().unwrap();
+        if last_emitted.elapsed() >= INTERVAL {
+            *last_emitted = Instant::now();
+            drop(last_emitted);
+            self.emit(false, "");
+        }
+    }
+
+    ///
+    /// 输出最后一条记录，附带处理失败、跳过的文件和整次运行的资源使用情况
+    ///
+    pub fn finish(
+        &self,
+        usage: &Usage,
+        failed: &[(PathBuf, FileError)],
+        skipped: &[(PathBuf, FileError)],
+    ) {
+        let summary = format!(
+            ",\"failed\":{},\"skipped\":{},\"usage\":{}",
+            file_errors(failed),
+            file_errors(skipped),
+            usage.to_json()
+        );
+        self.emit(true, &summary);
+    }
+
+    ///
+    /// summary 是追加在最后一条记录末尾的字段
+    ///
+    fn emit(&self, done: bool, summary: &str) {
+        let elapsed = self.started.elapsed();
+        let bytes = self.bytes.load(Ordering::SeqCst);
+        let bytes_per_second = (bytes as f64 / elapsed.as_secs_f64().max(f64::EPSILON)) as u64;
+        let record = format!(
+            "{{\"files_done\":{},\"files_total\":{},\"matches\":{},\"bytes\":{},\"elapsed_ms\":{},\"bytes_per_second\":{},\"done\":{}{}}}\n",
+            self.files_done.load(Ordering::SeqCst),
+            self.files_total,
+            self.matches.load(Ordering::SeqCst),
+            bytes,
+            elapsed.as_millis(),
+            bytes_per_second,
+            done,
+            summary
+        );
+        //
+        // 读取进度的一方关闭了管道时不影响替换本身
+        //
+        let mut output = self.output.lock().unwrap();
+        let _ = output.write_all(record.as_bytes());
+        let _ = output.flush();
+    }
+}
+
+fn file_errors(errors: &[(PathBuf, FileError)]) -> String {
+    let errors: Vec<_> = errors
+        .iter()
+        .map(|(file, err)| {
+            format!(
+                "{{\"path\":{},\"kind\":\"{}\",\"error\":{}}}",
+                json::quote(&paths::to_host(file).to_string_lossy()),
+                err.kind(),
+                json::quote(&err.to_string())
+            )
+        })
+        .collect();
+    format!("[{}]", errors.join(","))
+}
+
+#[cfg(unix)]
+mod imp {
+    use std::fs::File;
+    use std::io;
+    use std::os::unix::io::FromRawFd;
+
+    pub fn open_fd(fd: i32) -> io::Result<File> {
+        //
+        // 先确认文件描述符是打开的，否则 from_raw_fd 会接管一个无效的描述符
+        //
+        if unsafe { libc::fcntl(fd, libc::F_GETFD) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(unsafe { File::from_raw_fd(fd) })
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use std::fs::File;
+    use std::io;
+
+    pub fn open_fd(_fd: i32) -> io::Result<File> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "当前平台不支持 --progress-fd",
+        ))
+    }
+}
@@ -0,0 +1,222 @@
+use rayon::prelude::*;
+use std::fs::{self, Metadata};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::paths;
+use crate::template::Tokens;
+use crate::{selected_match_lines, RegexLimits, ReplacePlan, Rule};
+
+const HEADER: &str = "# regex-replace queue v1";
+
+///
+/// 队列中的一个文件
+/// 记录扫描时的大小和修改时间，apply 时用来判断文件是否被改动过
+///
+struct QueuedFile {
+    path: PathBuf,
+    size: u64,
+    modified: u128,
+    matches: usize,
+    lines: Vec<usize>,
+}
+
+///
+/// 扫描所有文件，把规则和有匹配的文件写入队列文件
+///
+/// 队列文件是按行的文本格式，路径使用宿主机路径，字段用制表符分隔：
+/// rule    正则    替换字符串
+/// file    大小    修改时间(纳秒)    匹配数    匹配所在行号(逗号分隔)    路径
+///
+pub fn write_queue(queue: &Path, files: &[PathBuf], plan: &ReplacePlan) -> Result<(), String> {
+    let mut queued: Vec<QueuedFile> = files
+        .par_iter()
+        .filter_map(|file| match scan_file(file, plan) {
+            Ok(queued) => queued,
+            Err(err) => {
+                eprintln!("读取文件错误 {:?}: {}", paths::to_host(file), err);
+                None
+            }
+        })
+        .collect();
+    queued.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let output =
+        fs::File::create(queue).map_err(|err| format!("创建队列文件 {:?} 失败: {}", queue, err))?;
+    let mut output = BufWriter::new(output);
+    let write_error = |err: std::io::Error| format!("写入队列文件 {:?} 失败: {}", queue, err);
+
+    writeln!(output, "{}", HEADER).map_err(write_error)?;
+    for rule in &plan.rules {
+        writeln!(
+            output,
+            "rule\t{}\t{}",
+            escape(rule.re.as_str()),
+            escape(&rule.raw_replacement)
+        )
+        .map_err(write_error)?;
+    }
+    for item in &queued {
+        let lines = item
+            .lines
+            .iter()
+            .map(|line| line.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        writeln!(
+            output,
+            "file\t{}\t{}\t{}\t{}\t{}",
+            item.size,
+            item.modified,
+            item.matches,
+            lines,
+            escape(&paths::to_host(&item.path).to_string_lossy())
+        )
+        .map_err(write_error)?;
+    }
+    output.flush().map_err(write_error)?;
+
+    let total_matches: usize = queued.iter().map(|item| item.matches).sum();
+    println!(
+        "已写入队列 {:?}: {} 个文件，{} 处匹配",
+        queue,
+        queued.len(),
+        total_matches
+    );
+
+    Ok(())
+}
+
+///
+/// 读取队列文件，返回其中的规则和仍然可以处理的文件
+/// 扫描之后大小或修改时间发生变化的文件会被跳过
+///
+pub fn load_queue(
+    queue: &Path,
+    tokens: &Tokens,
+    limits: RegexLimits,
+) -> Result<(Vec<Rule>, Vec<PathBuf>), String> {
+    let contents = fs::read_to_string(queue)
+        .map_err(|err| format!("读取队列文件 {:?} 失败: {}", queue, err))?;
+
+    let mut lines = contents.lines().enumerate();
+    match lines.next() {
+        Some((_, header)) if header == HEADER => {}
+        _ => return Err(format!("{:?} 不是有效的队列文件", queue)),
+    }
+
+    let mut rules = Vec::new();
+    let mut files = Vec::new();
+    for (index, line) in lines {
+        let line_error = |err: String| format!("队列文件 {:?} 第 {} 行: {}", queue, index + 1, err);
+        let fields: Vec<&str> = line.split('\t').collect();
+        match fields.as_slice() {
+            ["rule", pattern, replacement] => {
+                let rule =
+                    Rule::with_limits(&unescape(pattern), &unescape(replacement), tokens, limits)
+                        .map_err(line_error)?;
+                rules.push(rule);
+            }
+            ["file", size, modified, _matches, _lines, path] => {
+                let host_path = PathBuf::from(unescape(path));
+                let path = paths::to_local(&host_path);
+                let size = size
+                    .parse::<u64>()
+                    .map_err(|_| line_error(format!("无效的文件大小 {}", size)))?;
+                let modified = modified
+                    .parse::<u128>()
+                    .map_err(|_| line_error(format!("无效的修改时间 {}", modified)))?;
+
+                match fs::metadata(&path) {
+                    Ok(metadata)
+                        if metadata.len() == size && modified_nanos(&metadata) == modified =>
+                    {
+                        files.push(path);
+                    }
+                    Ok(_) => eprintln!("文件 {:?} 在扫描之后被修改过，跳过", host_path),
+                    Err(err) => eprintln!("获取元信息错误 {:?}: {}，跳过", host_path, err),
+                }
+            }
+            _ => return Err(line_error(format!("无法识别的内容: {}", line))),
+        }
+    }
+
+    if rules.is_empty() {
+        return Err(format!("队列文件 {:?} 中没有任何规则", queue));
+    }
+
+    Ok((rules, files))
+}
+
+///
+/// 扫描单个文件，没有匹配时返回 None
+///
+fn scan_file(file: &Path, plan: &ReplacePlan) -> std::io::Result<Option<QueuedFile>> {
+    let metadata = fs::metadata(file)?;
+    let contents = plan.read_text(file)?;
+
+    let mut lines = Vec::new();
+    for rule in &plan.rules {
+        lines.extend(selected_match_lines(&contents, &rule.re, &plan.selection));
+    }
+    let matches = lines.len();
+    if matches == 0 {
+        return Ok(None);
+    }
+    lines.sort_unstable();
+    lines.dedup();
+
+    Ok(Some(QueuedFile {
+        path: file.to_path_buf(),
+        size: metadata.len(),
+        modified: modified_nanos(&metadata),
+        matches,
+        lines,
+    }))
+}
+
+fn modified_nanos(metadata: &Metadata) -> u128 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_nanos())
+        .unwrap_or(0)
+}
+
+///
+/// 转义制表符、换行和反斜杠，保证每条记录占一行
+///
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            ch => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+pub fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            unescaped.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
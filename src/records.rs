@@ -0,0 +1,119 @@
+//!
+//! --record-separator：按自定义的分隔符把文件切成记录，每条记录单独替换
+//! 例如 SQL 导出文件按 ;\n 切分，段落模式按 \n\n 切分
+//! 文件逐行读入，凑齐一条记录就替换并写出，不需要把整个文件读入内存
+//! 匹配不会跨越分隔符，^ 和 $ 匹配记录的开头和结尾
+//!
+
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use regex::{Regex, RegexBuilder};
+
+use crate::select::Selector;
+use crate::{count_newlines, install, replace_text, stream, Rule};
+
+///
+/// 解析 --record-separator，分隔符按正则处理，不允许匹配空字符串
+/// 解析参数时还没有 --regex-size-limit，按 regex 默认的大小限制编译
+///
+pub fn parse_separator(separator: &str) -> Result<Regex, String> {
+    let re = RegexBuilder::new(separator)
+        .build()
+        .map_err(|err| format!("无效的记录分隔符: {}", err))?;
+    if re.is_match("") {
+        return Err(format!("记录分隔符不能匹配空字符串: {}", separator));
+    }
+    Ok(re)
+}
+
+///
+/// 依次应用所有规则替换一条记录
+/// first_line 是记录第一行在文件中的行号
+///
+fn replace_record(
+    record: &str,
+    first_line: usize,
+    rules: &[Rule],
+    selector: &mut Selector,
+) -> String {
+    let mut replaced = record.to_string();
+    for rule in rules {
+        replaced =
+            replace_text(&replaced, first_line, &rule.re, &rule.replacement, selector).into_owned();
+    }
+    replaced
+}
+
+///
+/// 按记录替换文件到临时文件
+///
+pub fn replace_in_file(
+    target_file: &Path,
+    rules: &[Rule],
+    separator: &Regex,
+    selector: &mut Selector,
+) -> Result<PathBuf, Box<dyn Error>> {
+    let temp_file = install::create_temp_file(target_file)?;
+    let temp_file_path = temp_file.path().to_path_buf();
+    let file = OpenOptions::new()
+        .append(true)
+        .open(temp_file_path.clone())?;
+    let mut writer = BufWriter::new(file);
+    let mut reader = BufReader::new(File::open(target_file)?);
+
+    //
+    // 分隔符的最大长度，新读入的内容只需要从这么远之前开始查找分隔符
+    // 长度没有上限时每次从头查找
+    //
+    let window = stream::window(separator.as_str());
+    let mut buffer = String::new();
+    let mut line = 1;
+    let mut searched: usize = 0;
+
+    loop {
+        let read = reader.read_line(&mut buffer)?;
+        let eof = read == 0;
+
+        loop {
+            let mut start = match window {
+                Some(window) => searched.saturating_sub(window),
+                None => 0,
+            };
+            while !buffer.is_char_boundary(start) {
+                start -= 1;
+            }
+            //
+            // 分隔符一直匹配到缓冲区末尾时，后面的内容可能让它匹配得更长，读完之后再确定
+            //
+            let Some(m) = separator
+                .find_at(&buffer, start)
+                .filter(|m| eof || m.end() < buffer.len())
+            else {
+                break;
+            };
+            let record = &buffer[..m.start()];
+            writer.write_all(replace_record(record, line, rules, selector).as_bytes())?;
+            writer.write_all(m.as_str().as_bytes())?;
+            line += count_newlines(&buffer[..m.end()]);
+            buffer.drain(..m.end());
+            searched = 0;
+        }
+        searched = buffer.len();
+
+        if eof {
+            break;
+        }
+    }
+
+    if !buffer.is_empty() {
+        writer.write_all(replace_record(&buffer, line, rules, selector).as_bytes())?;
+    }
+    writer.flush()?;
+    drop(writer);
+    let _ = temp_file.persist(&temp_file_path)?;
+
+    Ok(temp_file_path)
+}
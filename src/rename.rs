@@ -0,0 +1,293 @@
+//!
+//! --rename：把同样的规则应用到文件名和目录名上
+//! 先检查所有的重命名有没有冲突，没有冲突才开始重命名，从最深的路径开始，目录最后改名
+//!
+
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::normalize::Normalization;
+use crate::select::Selection;
+use crate::{interrupt, paths, replace_text, Rule};
+
+///
+/// 一次重命名，from 和 to 在同一个目录中
+///
+pub struct Rename {
+    from: PathBuf,
+    to: PathBuf,
+}
+
+///
+/// 按规则替换一个文件名，没有变化时返回 None
+/// 行号和区域之类的选择条件只针对文件内容，文件名中的匹配全部替换
+/// 指定了规范化形式时，文件名规范化之后再匹配，新的文件名同样规范化
+///
+fn rename_name(name: &str, rules: &[Rule], normalize: Option<Normalization>) -> Option<String> {
+    let selection = Selection::default();
+    let mut renamed = match normalize {
+        Some(form) => Cow::Owned(form.apply(name)),
+        None => Cow::Borrowed(name),
+    };
+    let mut changed = false;
+    for rule in rules {
+        let mut selector = selection.selector();
+        if let Cow::Owned(replaced) =
+            replace_text(&renamed, 1, &rule.re, &rule.replacement, &mut selector)
+        {
+            renamed = Cow::Owned(replaced);
+            changed = true;
+        }
+    }
+    let renamed = match normalize {
+        Some(form) => form.apply(&renamed),
+        None => renamed.into_owned(),
+    };
+    (changed && renamed != name).then_some(renamed)
+}
+
+///
+/// 计算所有需要的重命名，按从深到浅的顺序排列
+/// root 是遍历的目录，它下面包含这些文件的目录也会被重命名，root 本身不会
+/// 有冲突时返回所有冲突的说明
+///
+pub fn plan(
+    files: &[PathBuf],
+    root: Option<&Path>,
+    rules: &[Rule],
+    normalize: Option<Normalization>,
+) -> Result<Vec<Rename>, Vec<String>> {
+    let mut paths: BTreeSet<&Path> = files.iter().map(PathBuf::as_path).collect();
+    if let Some(root) = root {
+        for file in files {
+            paths.extend(
+                file.ancestors()
+                    .skip(1)
+                    .take_while(|dir| *dir != root && dir.starts_with(root)),
+            );
+        }
+    }
+
+    let mut renames = Vec::new();
+    let mut errors = Vec::new();
+    for path in paths {
+        let (Some(parent), Some(name)) = (
+            path.parent(),
+            path.file_name().and_then(|name| name.to_str()),
+        ) else {
+            continue;
+        };
+        let Some(renamed) = rename_name(name, rules, normalize) else {
+            continue;
+        };
+        if renamed.is_empty() || renamed == "." || renamed == ".." || renamed.contains(['/', '\\'])
+        {
+            errors.push(format!(
+                "{:?} 会被重命名为无效的名字 {:?}",
+                paths::to_host(path),
+                renamed
+            ));
+            continue;
+        }
+        renames.push(Rename {
+            from: path.to_path_buf(),
+            to: parent.join(renamed),
+        });
+    }
+
+    //
+    // 两个路径改成同一个名字，或者新名字已经被其他文件占用，都算冲突
+    // 不区分大小写的文件系统上只有大小写不同的新名字也是同一个名字
+    // 只改大小写时在不区分大小写的文件系统上新名字就是自己，不算冲突
+    //
+    let mut targets: HashMap<PathBuf, &Path> = HashMap::new();
+    for rename in &renames {
+        if let Some(other) = targets.insert(paths::case_key(&rename.to), &rename.from) {
+            errors.push(format!(
+                "{:?} 和 {:?} 都会被重命名为 {:?}",
+                paths::to_host(other),
+                paths::to_host(&rename.from),
+                paths::to_host(&rename.to)
+            ));
+        } else if fs::symlink_metadata(&rename.to).is_ok()
+            && !same_file::is_same_file(&rename.from, &rename.to).unwrap_or(false)
+        {
+            errors.push(format!(
+                "无法把 {:?} 重命名为 {:?}: 目标已经存在",
+                paths::to_host(&rename.from),
+                paths::to_host(&rename.to)
+            ));
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    //
+    // 先改深处的路径，目录里的内容改完之后再改目录本身，之前计算的路径才一直有效
+    //
+    renames.sort_by(|a, b| {
+        b.from
+            .components()
+            .count()
+            .cmp(&a.from.components().count())
+            .then_with(|| a.from.cmp(&b.from))
+    });
+    Ok(renames)
+}
+
+///
+/// 依次执行重命名，遇到错误时停止
+/// atomic 为 true 时（--atomic-run）按相反的顺序撤销已经完成的重命名
+///
+pub fn apply(renames: &[Rename], atomic: bool) -> Result<(), String> {
+    for (done, rename) in renames.iter().enumerate() {
+        //
+        // 重命名在写回阶段执行，收到中断信号时在两次重命名之间停下
+        //
+        let result = if interrupt::requested() {
+            Err("收到中断信号".to_string())
+        } else {
+            paths::rename(&rename.from, &rename.to).map_err(|err| {
+                format!(
+                    "重命名 {:?} 为 {:?} 失败: {}",
+                    paths::to_host(&rename.from),
+                    paths::to_host(&rename.to),
+                    err
+                )
+            })
+        };
+        if let Err(err) = result {
+            if !atomic {
+                return Err(format!("{}，已完成 {} 个重命名", err, done));
+            }
+            return match undo(&renames[..done]) {
+                Ok(()) => Err(format!("{}，已撤销 {} 个重命名", err, done)),
+                Err(undo_err) => Err(format!("{}\n{}", err, undo_err)),
+            };
+        }
+        println!(
+            "{:?} -> {:?}",
+            paths::to_host(&rename.from),
+            paths::to_host(&rename.to)
+        );
+    }
+    Ok(())
+}
+
+///
+/// 按相反的顺序撤销重命名，目录里的内容在目录本身撤销之后才撤销，路径一直有效
+///
+fn undo(renames: &[Rename]) -> Result<(), String> {
+    let mut errors = Vec::new();
+    for rename in renames.iter().rev() {
+        match paths::rename(&rename.to, &rename.from) {
+            Ok(()) => println!(
+                "{:?} -> {:?}",
+                paths::to_host(&rename.to),
+                paths::to_host(&rename.from)
+            ),
+            Err(err) => errors.push(format!(
+                "撤销重命名 {:?} 为 {:?} 失败: {}",
+                paths::to_host(&rename.to),
+                paths::to_host(&rename.from),
+                err
+            )),
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::{TokenScope, Tokens};
+
+    fn rule(pattern: &str, replacement: &str) -> Vec<Rule> {
+        vec![Rule::new(pattern, replacement, &Tokens::new(TokenScope::Run)).unwrap()]
+    }
+
+    fn touch(path: &Path) -> PathBuf {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, "").unwrap();
+        path.to_path_buf()
+    }
+
+    #[test]
+    fn deepest_paths_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let files = [
+            touch(&root.join("old/old.txt")),
+            touch(&root.join("old/sub/old.rs")),
+        ];
+        let renames = plan(&files, Some(root), &rule("old", "new"), None).unwrap();
+        let names: Vec<_> = renames
+            .iter()
+            .map(|rename| {
+                (
+                    rename.from.strip_prefix(root).unwrap().to_path_buf(),
+                    rename.to.strip_prefix(root).unwrap().to_path_buf(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("old/sub/old.rs".into(), "old/sub/new.rs".into()),
+                ("old/old.txt".into(), "old/new.txt".into()),
+                ("old".into(), "new".into()),
+            ] as [(PathBuf, PathBuf); 3]
+        );
+        apply(&renames, false).unwrap();
+        assert!(root.join("new/sub/new.rs").is_file());
+        assert!(root.join("new/new.txt").is_file());
+    }
+
+    #[test]
+    fn conflicting_targets() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = [
+            touch(&dir.path().join("a1.txt")),
+            touch(&dir.path().join("a2.txt")),
+        ];
+        let errors = plan(&files, None, &rule(r"\d", ""), None).err().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("都会被重命名为"));
+    }
+
+    #[test]
+    fn existing_target() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = [touch(&dir.path().join("old.txt"))];
+        touch(&dir.path().join("new.txt"));
+        let errors = plan(&files, None, &rule("old", "new"), None).err().unwrap();
+        assert!(errors[0].contains("目标已经存在"));
+    }
+
+    #[test]
+    fn case_only_rename_is_not_a_conflict() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = [touch(&dir.path().join("readme.md"))];
+        let renames = plan(&files, None, &rule("readme", "README"), None).unwrap();
+        apply(&renames, false).unwrap();
+        assert!(dir.path().join("README.md").is_file());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn invalid_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = [touch(&dir.path().join("a.txt"))];
+        let errors = plan(&files, None, &rule(r"^a\.txt$", "x/y"), None)
+            .err()
+            .unwrap();
+        assert!(errors[0].contains("无效的名字"));
+    }
+}
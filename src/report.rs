@@ -0,0 +1,228 @@
+//!
+//! --report：列出每个被修改的文件中改动的行号，以及改动前后的简短摘录
+//!
+
+use std::ops::Range;
+use std::path::PathBuf;
+
+///
+/// 摘录最多显示的字符数
+///
+const EXCERPT_WIDTH: usize = 60;
+
+///
+/// 超过这个编辑距离时不再细分，整个改动区域作为一处改动
+///
+const MAX_EDIT_DISTANCE: usize = 2000;
+
+///
+/// 一处改动：原文件中的行和替换后对应的行，行号从 0 开始
+///
+pub struct Hunk {
+    pub original: Range<usize>,
+    pub replaced: Range<usize>,
+}
+
+///
+/// 一个文件的改动报告
+///
+pub struct FileReport {
+    pub path: PathBuf,
+    lines: Vec<String>,
+}
+
+impl FileReport {
+    pub fn new(path: PathBuf, original: &str, replaced: &str) -> Self {
+        let original: Vec<&str> = original.lines().collect();
+        let replaced: Vec<&str> = replaced.lines().collect();
+        let lines = hunks(&original, &replaced)
+            .iter()
+            .map(|hunk| {
+                let before = original[hunk.original.clone()].join("\n");
+                let after = replaced[hunk.replaced.clone()].join("\n");
+                let (before, after) = excerpts(&before, &after);
+                format!("  {}: {} → {}", line_numbers(&hunk.original), before, after)
+            })
+            .collect();
+        Self { path, lines }
+    }
+}
+
+///
+/// 原文件中的行号，从 1 开始，多行时显示范围
+/// 只增加了行时显示插入位置之前的那一行
+///
+fn line_numbers(lines: &Range<usize>) -> String {
+    match lines.len() {
+        0 => format!("{}+", lines.start),
+        1 => (lines.start + 1).to_string(),
+        _ => format!("{}-{}", lines.start + 1, lines.end),
+    }
+}
+
+///
+/// 改动前后的摘录，跳过相同的开头，让改动的部分出现在摘录里
+/// 换行显示为 \n
+///
+fn excerpts(before: &str, after: &str) -> (String, String) {
+    let common = before
+        .chars()
+        .zip(after.chars())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let skip = common.saturating_sub(EXCERPT_WIDTH / 4);
+    let excerpt = |text: &str| {
+        let mut excerpt: String = text
+            .chars()
+            .skip(skip)
+            .take(EXCERPT_WIDTH)
+            .collect::<String>()
+            .replace('\n', "\\n");
+        if skip > 0 {
+            excerpt.insert(0, '…');
+        }
+        if text.chars().count() > skip + EXCERPT_WIDTH {
+            excerpt.push('…');
+        }
+        excerpt
+    };
+    (excerpt(before), excerpt(after))
+}
+
+///
+/// 按行比较，找出所有改动的区域
+///
+pub fn hunks(original: &[&str], replaced: &[&str]) -> Vec<Hunk> {
+    let prefix = original
+        .iter()
+        .zip(replaced)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = original[prefix..]
+        .iter()
+        .rev()
+        .zip(replaced[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let a = &original[prefix..original.len() - suffix];
+    let b = &replaced[prefix..replaced.len() - suffix];
+    if a.is_empty() && b.is_empty() {
+        return Vec::new();
+    }
+
+    let Some((deleted, inserted)) = diff(a, b) else {
+        return vec![Hunk {
+            original: prefix..prefix + a.len(),
+            replaced: prefix..prefix + b.len(),
+        }];
+    };
+
+    //
+    // 连续的删除和增加合成一处改动，相同的行把改动分开
+    //
+    let mut hunks = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if deleted.get(i) != Some(&true) && inserted.get(j) != Some(&true) {
+            i += 1;
+            j += 1;
+            continue;
+        }
+        let (start_i, start_j) = (i, j);
+        loop {
+            let before = (i, j);
+            while deleted.get(i) == Some(&true) {
+                i += 1;
+            }
+            while inserted.get(j) == Some(&true) {
+                j += 1;
+            }
+            if (i, j) == before {
+                break;
+            }
+        }
+        hunks.push(Hunk {
+            original: prefix + start_i..prefix + i,
+            replaced: prefix + start_j..prefix + j,
+        });
+    }
+    hunks
+}
+
+///
+/// Myers 算法求最短编辑脚本，返回被删除的原始行和新增的行
+/// 编辑距离超过 MAX_EDIT_DISTANCE 时返回 None
+///
+fn diff(a: &[&str], b: &[&str]) -> Option<(Vec<bool>, Vec<bool>)> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = (n + m) as usize;
+    let offset = max as isize;
+    let mut v = vec![0isize; 2 * max + 2];
+    //
+    // 每一步结束时对角线 -d..=d 上的 v，用来回溯
+    //
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+    let mut found = false;
+
+    'search: for d in 0..=max.min(MAX_EDIT_DISTANCE) as isize {
+        let mut k = -d;
+        while k <= d {
+            let index = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[index - 1] < v[index + 1]) {
+                v[index + 1]
+            } else {
+                v[index - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[index] = x;
+            if x >= n && y >= m {
+                trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+                found = true;
+                break 'search;
+            }
+            k += 2;
+        }
+        trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+    }
+
+    if !found {
+        return None;
+    }
+
+    let mut deleted = vec![false; a.len()];
+    let mut inserted = vec![false; b.len()];
+    let (mut x, mut y) = (n, m);
+    for d in (1..trace.len() as isize).rev() {
+        let previous = &trace[d as usize - 1];
+        let at = |k: isize| previous[(k + d - 1) as usize];
+        let k = x - y;
+        let down = k == -d || (k != d && at(k - 1) < at(k + 1));
+        let previous_k = if down { k + 1 } else { k - 1 };
+        let previous_x = at(previous_k);
+        let previous_y = previous_x - previous_k;
+        if down {
+            inserted[previous_y as usize] = true;
+        } else {
+            deleted[previous_x as usize] = true;
+        }
+        x = previous_x;
+        y = previous_y;
+    }
+    Some((deleted, inserted))
+}
+
+///
+/// 依次输出每个文件和它的改动
+///
+pub fn print(reports: &[FileReport]) {
+    for report in reports.iter().filter(|report| !report.lines.is_empty()) {
+        println!("{}", report.path.display());
+        for line in &report.lines {
+            println!("{}", line);
+        }
+    }
+}
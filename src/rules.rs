@@ -0,0 +1,297 @@
+use std::fs;
+use std::path::Path;
+
+use crate::template::Tokens;
+use crate::toml::{self, Spanned, Value};
+use crate::{RegexLimits, Rule};
+
+///
+/// 读取规则文件
+/// 每行一条规则，格式与 sed 的替换命令相同：s/正则/替换/
+/// 分隔符可以是 s 后面的任意字符，例如 s|a/b|c|
+/// 规则中出现分隔符本身时用反斜杠转义
+/// 以 # 开头的行是注释，空行会被忽略
+/// 扩展名为 .toml 的文件按规则清单读取，见 load_manifest
+///
+pub fn load_rules_file(
+    path: &Path,
+    tokens: &Tokens,
+    limits: RegexLimits,
+) -> Result<Vec<Rule>, String> {
+    let contents =
+        fs::read_to_string(path).map_err(|err| format!("读取规则文件 {:?} 失败: {}", path, err))?;
+    if path
+        .extension()
+        .is_some_and(|extension| extension == "toml")
+    {
+        let rules = load_manifest(path, &contents, tokens, limits)?;
+        return Ok(rules.into_iter().map(|(rule, _)| rule).collect());
+    }
+
+    let mut rules = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (pattern, replacement) = parse_rule_line(line)
+            .map_err(|err| format!("规则文件 {:?} 第 {} 行: {}", path, index + 1, err))?;
+        let rule = Rule::with_limits(&pattern, &replacement, tokens, limits)
+            .map_err(|err| format!("规则文件 {:?} 第 {} 行: {}", path, index + 1, err))?;
+        rules.push(rule);
+    }
+
+    if rules.is_empty() {
+        return Err(format!("规则文件 {:?} 中没有任何规则", path));
+    }
+
+    Ok(rules)
+}
+
+///
+/// 解析一行 s/正则/替换/
+///
+fn parse_rule_line(line: &str) -> Result<(String, String), String> {
+    let mut chars = line.chars();
+    if chars.next() != Some('s') {
+        return Err(format!("规则必须以 s 开头: {}", line));
+    }
+    let delimiter = match chars.next() {
+        Some(ch) if !ch.is_alphanumeric() && ch != '\\' && !ch.is_whitespace() => ch,
+        _ => return Err(format!("缺少有效的分隔符: {}", line)),
+    };
+
+    let mut parts = vec![String::new()];
+    while let Some(ch) = chars.next() {
+        if ch == '\\' {
+            //
+            // 转义的分隔符还原为分隔符本身，其他转义原样保留，交给正则或替换字符串处理
+            //
+            match chars.next() {
+                Some(next) if next == delimiter => parts.last_mut().unwrap().push(next),
+                Some(next) => {
+                    let part = parts.last_mut().unwrap();
+                    part.push('\\');
+                    part.push(next);
+                }
+                None => parts.last_mut().unwrap().push('\\'),
+            }
+        } else if ch == delimiter {
+            parts.push(String::new());
+        } else {
+            parts.last_mut().unwrap().push(ch);
+        }
+    }
+
+    //
+    // s/a/b/ 切分后是 ["a", "b", ""]
+    //
+    if parts.len() != 3 {
+        return Err(format!(
+            "规则格式应为 s{0}正则{0}替换{0}: {1}",
+            delimiter, line
+        ));
+    }
+    if !parts[2].trim().is_empty() {
+        return Err(format!("不支持的规则标记 {:?}: {}", parts[2], line));
+    }
+    if parts[0].is_empty() {
+        return Err(format!("正则不能为空: {}", line));
+    }
+
+    let replacement = parts.remove(1);
+    let pattern = parts.remove(0);
+    Ok((pattern, replacement))
+}
+
+///
+/// 规则清单中随规则一起写的测试用例
+///
+pub struct RuleTest {
+    pub input: String,
+    pub output: String,
+    ///
+    /// 测试用例在清单中的行号
+    ///
+    pub line: usize,
+}
+
+///
+/// 读取规则清单中的规则和它们的测试用例
+///
+pub fn load_rule_tests(
+    path: &Path,
+    tokens: &Tokens,
+    limits: RegexLimits,
+) -> Result<Vec<(Rule, Vec<RuleTest>)>, String> {
+    let contents =
+        fs::read_to_string(path).map_err(|err| format!("读取规则清单 {:?} 失败: {}", path, err))?;
+    load_manifest(path, &contents, tokens, limits)
+}
+
+///
+/// 读取 TOML 格式的规则清单，并按下面的结构检查
+/// 未知的键、类型不对、缺少 pattern 或 replacement 都会报告具体的行号
+/// tests 是可选的，列出规则的输入和期望的输出，由 test-rules 子命令运行
+///
+/// ```toml
+/// [[rule]]
+/// pattern = "foo(\\d+)"
+/// replacement = "bar$1"
+/// tests = [{ input = "foo1\n", output = "bar1\n" }]
+/// ```
+///
+fn load_manifest(
+    path: &Path,
+    contents: &str,
+    tokens: &Tokens,
+    limits: RegexLimits,
+) -> Result<Vec<(Rule, Vec<RuleTest>)>, String> {
+    let document = toml::parse(contents).map_err(|err| format!("规则清单 {:?} {}", path, err))?;
+    let error =
+        |line: usize, message: String| format!("规则清单 {:?} 第 {} 行: {}", path, line, message);
+
+    let mut rules = Vec::new();
+    for (key, spanned) in &document.entries {
+        match (key.as_str(), &spanned.value) {
+            ("rule", Value::Array(items)) => {
+                for item in items {
+                    let rule = manifest_rule(item, tokens, limits)
+                        .map_err(|(line, message)| error(line, message))?;
+                    rules.push(rule);
+                }
+            }
+            ("rule", value) => {
+                return Err(error(
+                    spanned.line,
+                    format!("rule 应该写成表数组 [[rule]]，而不是{}", value.type_name()),
+                ))
+            }
+            (key, _) => {
+                return Err(error(
+                    spanned.line,
+                    format!("未知的键 {}，顶层只允许 [[rule]]", key),
+                ))
+            }
+        }
+    }
+
+    if rules.is_empty() {
+        return Err(format!("规则清单 {:?} 中没有任何规则", path));
+    }
+
+    Ok(rules)
+}
+
+///
+/// 检查并构造清单中的一条规则，出错时返回行号和错误信息
+///
+fn manifest_rule(
+    item: &Spanned,
+    tokens: &Tokens,
+    limits: RegexLimits,
+) -> Result<(Rule, Vec<RuleTest>), (usize, String)> {
+    let Value::Table(table) = &item.value else {
+        return Err((
+            item.line,
+            format!("规则应该是表，而不是{}", item.value.type_name()),
+        ));
+    };
+
+    let mut pattern = None;
+    let mut replacement = None;
+    let mut tests = Vec::new();
+    for (key, spanned) in &table.entries {
+        let slot = match key.as_str() {
+            "pattern" => &mut pattern,
+            "replacement" => &mut replacement,
+            "tests" => {
+                tests = manifest_tests(spanned)?;
+                continue;
+            }
+            _ => {
+                return Err((
+                    spanned.line,
+                    format!("未知的键 {}，规则只允许 pattern、replacement 和 tests", key),
+                ))
+            }
+        };
+        match &spanned.value {
+            Value::String(text) => *slot = Some((text.as_str(), spanned.line)),
+            value => {
+                return Err((
+                    spanned.line,
+                    format!("{} 应该是字符串，而不是{}", key, value.type_name()),
+                ))
+            }
+        }
+    }
+
+    match (pattern, replacement) {
+        (Some(("", line)), _) => Err((line, "pattern 不能为空".to_string())),
+        (Some((pattern, line)), Some((replacement, _))) => {
+            Rule::with_limits(pattern, replacement, tokens, limits)
+                .map(|rule| (rule, tests))
+                .map_err(|err| (line, err))
+        }
+        (Some(_), None) => Err((item.line, "规则缺少 replacement".to_string())),
+        (None, Some(_)) => Err((item.line, "规则缺少 pattern".to_string())),
+        (None, None) => Err((item.line, "规则缺少 pattern 和 replacement".to_string())),
+    }
+}
+
+///
+/// 检查并读取规则的 tests：由 { input = "...", output = "..." } 组成的数组
+///
+fn manifest_tests(spanned: &Spanned) -> Result<Vec<RuleTest>, (usize, String)> {
+    let Value::Array(items) = &spanned.value else {
+        return Err((
+            spanned.line,
+            format!("tests 应该是数组，而不是{}", spanned.value.type_name()),
+        ));
+    };
+
+    let mut tests = Vec::new();
+    for item in items {
+        let Value::Table(table) = &item.value else {
+            return Err((
+                item.line,
+                format!("测试用例应该是表，而不是{}", item.value.type_name()),
+            ));
+        };
+        let mut input = None;
+        let mut output = None;
+        for (key, spanned) in &table.entries {
+            let slot = match key.as_str() {
+                "input" => &mut input,
+                "output" => &mut output,
+                _ => {
+                    return Err((
+                        spanned.line,
+                        format!("未知的键 {}，测试用例只允许 input 和 output", key),
+                    ))
+                }
+            };
+            match &spanned.value {
+                Value::String(text) => *slot = Some(text.clone()),
+                value => {
+                    return Err((
+                        spanned.line,
+                        format!("{} 应该是字符串，而不是{}", key, value.type_name()),
+                    ))
+                }
+            }
+        }
+        match (input, output) {
+            (Some(input), Some(output)) => tests.push(RuleTest {
+                input,
+                output,
+                line: item.line,
+            }),
+            (Some(_), None) => return Err((item.line, "测试用例缺少 output".to_string())),
+            (None, Some(_)) => return Err((item.line, "测试用例缺少 input".to_string())),
+            (None, None) => return Err((item.line, "测试用例缺少 input 和 output".to_string())),
+        }
+    }
+    Ok(tests)
+}
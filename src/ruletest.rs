@@ -0,0 +1,100 @@
+//!
+//! test-rules 子命令：运行规则清单中的测试用例
+//! 每个用例的输入写入临时文件，按正常的替换流程处理，再和期望的输出比较
+//!
+
+use std::error::Error;
+use std::fs;
+use std::io::Write;
+use std::slice;
+
+use crate::bytes::ByteRules;
+use crate::prefilter::Prefilter;
+use crate::rules::RuleTest;
+use crate::{replace_in_file, ReplacePlan, Rule};
+
+///
+/// 运行所有测试用例，返回是否全部通过
+/// plan 提供选择条件、策略等运行参数，规则换成被测试的那一条
+///
+pub fn run(rules: Vec<(Rule, Vec<RuleTest>)>, plan: &ReplacePlan) -> bool {
+    let mut total = 0;
+    let mut failed = 0;
+    for (index, (rule, tests)) in rules.into_iter().enumerate() {
+        let pattern = rule.re.as_str().to_string();
+        //
+        // 换了规则，不能沿用原计划编译好的 RegexSet 和按字节匹配的正则
+        //
+        let bytes = match &plan.bytes {
+            Some(_) => {
+                match ByteRules::new(slice::from_ref(&rule), plan.null_data, plan.regex_limits) {
+                    Ok(bytes) => Some(bytes),
+                    Err(err) => {
+                        total += tests.len();
+                        failed += tests.len();
+                        println!("规则 #{} {:?} 编译出错: {}", index + 1, pattern, err);
+                        continue;
+                    }
+                }
+            }
+            None => None,
+        };
+        let plan = ReplacePlan {
+            rules: vec![rule],
+            prefilter: Prefilter::default(),
+            bytes,
+            ..plan.clone()
+        };
+        for test in tests {
+            total += 1;
+            match run_test(&test.input, &plan) {
+                Ok(output) if output == test.output => {}
+                Ok(output) => {
+                    failed += 1;
+                    println!(
+                        "规则 #{} {:?} 第 {} 行的测试失败",
+                        index + 1,
+                        pattern,
+                        test.line
+                    );
+                    println!("  输入: {:?}", test.input);
+                    println!("  期望: {:?}", test.output);
+                    println!("  实际: {:?}", output);
+                }
+                Err(err) => {
+                    failed += 1;
+                    println!(
+                        "规则 #{} {:?} 第 {} 行的测试出错: {}",
+                        index + 1,
+                        pattern,
+                        test.line,
+                        err
+                    );
+                }
+            }
+        }
+    }
+
+    println!("共 {} 个测试，{} 个失败", total, failed);
+    failed == 0
+}
+
+///
+/// 替换一段输入，和正常运行一样，没有任何替换时内容保持原样
+///
+fn run_test(input: &str, plan: &ReplacePlan) -> Result<String, Box<dyn Error>> {
+    let mut file = tempfile::Builder::new()
+        .prefix(".regex-replace-test")
+        .tempfile()?;
+    file.write_all(input.as_bytes())?;
+    file.flush()?;
+
+    let replaced = replace_in_file(file.path(), plan)?;
+    let output = if replaced.replacements > 0 {
+        fs::read_to_string(&replaced.temp_file)?
+    } else {
+        input.to_string()
+    };
+    fs::remove_file(&replaced.temp_file)?;
+    Ok(output)
+}
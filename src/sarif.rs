@@ -0,0 +1,105 @@
+//!
+//! --format sarif：按 SARIF 2.1.0 输出 search 和 --check 找到的匹配
+//! 每条规则对应一个 SARIF 规则，列号按字符计算（columnKind 为 unicodeCodePoints）
+//!
+
+use std::path::{Path, PathBuf};
+
+use crate::json::quote;
+use crate::search::Found;
+use crate::{paths, ReplacePlan};
+
+const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+///
+/// 规则的 id，按规则在列表中的顺序编号，从 1 开始
+///
+fn rule_id(index: usize) -> String {
+    format!("regex-replace/rule-{}", index + 1)
+}
+
+///
+/// 整个 SARIF 日志，只有一次运行，以换行结尾
+///
+pub fn log(results: &[(PathBuf, Vec<Found>)], plan: &ReplacePlan) -> String {
+    let rules: Vec<String> = plan
+        .rules
+        .iter()
+        .enumerate()
+        .map(|(index, rule)| {
+            let description = match rule.raw_replacement.as_str() {
+                "" => format!("匹配 {}", rule.re.as_str()),
+                replacement => format!("匹配 {}，可以替换为 {}", rule.re.as_str(), replacement),
+            };
+            format!(
+                r#"{{"id":{},"shortDescription":{{"text":{}}}}}"#,
+                quote(&rule_id(index)),
+                quote(&description)
+            )
+        })
+        .collect();
+
+    let mut entries = Vec::new();
+    for (file, found) in results {
+        let uri = uri(&paths::portable(file));
+        for found in found {
+            entries.push(format!(
+                r#"{{"ruleId":{},"ruleIndex":{},"level":"warning","message":{{"text":{}}},"locations":[{{"physicalLocation":{{"artifactLocation":{{"uri":{}}},"region":{}}}}}]}}"#,
+                quote(&rule_id(found.rule)),
+                found.rule,
+                quote(&format!("匹配 {}", found.text.replace('\n', "\\n"))),
+                quote(&uri),
+                region(found)
+            ));
+        }
+    }
+
+    format!(
+        r#"{{"$schema":{},"version":"2.1.0","runs":[{{"tool":{{"driver":{{"name":"regex-replace","version":{},"rules":[{}]}}}},"columnKind":"unicodeCodePoints","results":[{}]}}]}}"#,
+        quote(SCHEMA),
+        quote(env!("CARGO_PKG_VERSION")),
+        rules.join(","),
+        entries.join(",")
+    ) + "\n"
+}
+
+///
+/// 匹配所在的区域，结束的列是匹配之后的第一列
+///
+fn region(found: &Found) -> String {
+    let lines = found.text.matches('\n').count();
+    let end_column = match found.text.rfind('\n') {
+        Some(index) => found.text[index + 1..].chars().count() + 1,
+        None => found.column + found.text.chars().count(),
+    };
+    format!(
+        r#"{{"startLine":{},"startColumn":{},"endLine":{},"endColumn":{}}}"#,
+        found.line,
+        found.column,
+        found.line + lines,
+        end_column
+    )
+}
+
+///
+/// 路径转换成 URI 引用，除了不需要转义的字符和 / 之外都按 UTF-8 字节转义
+/// 当前目录之外的绝对路径使用 file: URI，其中 Windows 盘符后的 : 保留
+///
+fn uri(path: &str) -> String {
+    let absolute = Path::new(path).is_absolute();
+    let mut uri = match (absolute, path.starts_with('/')) {
+        (false, _) => String::new(),
+        (true, true) => "file://".to_string(),
+        (true, false) => "file:///".to_string(),
+    };
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(byte as char)
+            }
+            b':' if absolute => uri.push(':'),
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
@@ -0,0 +1,550 @@
+//!
+//! --script：用一个小的表达式语言计算替换结果
+//!
+//! 表达式由字符串（"..." 或 '...'）、整数、变量 m、函数调用和运算符组成：
+//! 运算符 + 在任一边是字符串时拼接，两边都是整数时相加；- * / % 只用于整数
+//! f(a, b) 也可以写成 a.f(b)，例如 m.group(1).upper()
+//!
+//! m 表示当前的匹配：
+//! m.text 匹配的文本，m.group(n) / m.group("name") 捕获组，没有参与匹配时是空字符串
+//! m.line 匹配所在的行号，m.file 文件路径，m.file_name 文件名，
+//! m.file_stem 不含扩展名的文件名，m.extension 扩展名
+//!
+//! 函数：模板函数 upper、lower、trim、snake_case、camel_case、pascal_case、kebab_case、
+//! screaming_snake_case、base64、hex、dec，以及 len(s)、str(x)、int(s)、replace(s, from, to)、
+//! pad(s, width, fill) 在左边填充到指定宽度、if(cond, a, b) 条件不是空字符串也不是 0 时取 a
+//!
+
+use regex::{Captures, Regex};
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+
+use crate::template::{resolve_group, Filter};
+
+thread_local! {
+    ///
+    /// 当前线程正在替换的文件，m.file 等属性从这里取
+    ///
+    static CURRENT_FILE: RefCell<PathBuf> = const { RefCell::new(PathBuf::new()) };
+}
+
+///
+/// 设置当前线程正在替换的文件，每个文件只在一个线程中替换
+///
+pub fn set_file(file: &Path) {
+    CURRENT_FILE.with(|current| *current.borrow_mut() = file.to_path_buf());
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Text,
+    Line,
+    File,
+    FileName,
+    FileStem,
+    Extension,
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(Self::Text),
+            "line" => Some(Self::Line),
+            "file" => Some(Self::File),
+            "file_name" => Some(Self::FileName),
+            "file_stem" => Some(Self::FileStem),
+            "extension" => Some(Self::Extension),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Function {
+    Filter(Filter),
+    Len,
+    Str,
+    Int,
+    Replace,
+    Pad,
+    If,
+}
+
+impl Function {
+    fn parse(name: &str) -> Option<Self> {
+        let function = match name {
+            "len" => Self::Len,
+            "str" => Self::Str,
+            "int" => Self::Int,
+            "replace" => Self::Replace,
+            "pad" => Self::Pad,
+            "if" => Self::If,
+            name => return Filter::parse(name).ok().map(Self::Filter),
+        };
+        Some(function)
+    }
+
+    fn arity(&self) -> usize {
+        match self {
+            Self::Filter(_) | Self::Len | Self::Str | Self::Int => 1,
+            Self::Replace | Self::Pad | Self::If => 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Str(String),
+    Int(i64),
+    Field(Field),
+    ///
+    /// 解析时已经换算成下标的捕获组
+    ///
+    Group(usize),
+    ///
+    /// 运行时才知道的捕获组，按序号或组名查找
+    ///
+    DynamicGroup(Box<Expr>),
+    Call(Function, Vec<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+    Neg(Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    Str(String),
+    Int(i64),
+}
+
+impl Value {
+    fn into_string(self) -> String {
+        match self {
+            Self::Str(text) => text,
+            Self::Int(number) => number.to_string(),
+        }
+    }
+
+    ///
+    /// 捕获组都是字符串，内容是整数的字符串也可以参与运算
+    ///
+    fn as_int(&self) -> Result<i64, String> {
+        match self {
+            Self::Int(number) => Ok(*number),
+            Self::Str(text) => text
+                .trim()
+                .parse()
+                .map_err(|_| format!("需要整数，得到的是 {:?}", text)),
+        }
+    }
+
+    fn is_truthy(&self) -> bool {
+        match self {
+            Self::Int(number) => *number != 0,
+            Self::Str(text) => !text.is_empty(),
+        }
+    }
+}
+
+///
+/// 词法单元
+///
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Str(String),
+    Int(i64),
+    Ident(String),
+    Punct(char),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Str(text) => write!(f, "{:?}", text),
+            Self::Int(number) => write!(f, "{}", number),
+            Self::Ident(name) => write!(f, "{}", name),
+            Self::Punct(ch) => write!(f, "{}", ch),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    while let Some(&ch) = chars.peek() {
+        if ch.is_whitespace() {
+            chars.next();
+        } else if ch == '"' || ch == '\'' {
+            chars.next();
+            let mut text = String::new();
+            loop {
+                match chars.next() {
+                    Some(c) if c == ch => break,
+                    Some('\\') => match chars.next() {
+                        Some('n') => text.push('\n'),
+                        Some('t') => text.push('\t'),
+                        Some(c) => text.push(c),
+                        None => return Err("字符串没有闭合".to_string()),
+                    },
+                    Some(c) => text.push(c),
+                    None => return Err("字符串没有闭合".to_string()),
+                }
+            }
+            tokens.push(Token::Str(text));
+        } else if ch.is_ascii_digit() {
+            let mut digits = String::new();
+            while let Some(&c) = chars.peek().filter(|c| c.is_ascii_digit()) {
+                digits.push(c);
+                chars.next();
+            }
+            let number = digits
+                .parse()
+                .map_err(|_| format!("整数超出范围: {}", digits))?;
+            tokens.push(Token::Int(number));
+        } else if ch.is_alphabetic() || ch == '_' {
+            let mut ident = String::new();
+            while let Some(&c) = chars.peek().filter(|c| c.is_alphanumeric() || **c == '_') {
+                ident.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Ident(ident));
+        } else if "+-*/%().,".contains(ch) {
+            tokens.push(Token::Punct(ch));
+            chars.next();
+        } else {
+            return Err(format!("无法识别的字符 {:?}", ch));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    position: usize,
+    re: &'a Regex,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn eat(&mut self, ch: char) -> bool {
+        if self.peek() == Some(&Token::Punct(ch)) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, ch: char) -> Result<(), String> {
+        if self.eat(ch) {
+            Ok(())
+        } else {
+            Err(format!("缺少 {:?}", ch))
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        let mut left = self.term()?;
+        loop {
+            let op = if self.eat('+') {
+                Op::Add
+            } else if self.eat('-') {
+                Op::Sub
+            } else {
+                return Ok(left);
+            };
+            left = Expr::Binary(op, Box::new(left), Box::new(self.term()?));
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr, String> {
+        let mut left = self.unary()?;
+        loop {
+            let op = if self.eat('*') {
+                Op::Mul
+            } else if self.eat('/') {
+                Op::Div
+            } else if self.eat('%') {
+                Op::Rem
+            } else {
+                return Ok(left);
+            };
+            left = Expr::Binary(op, Box::new(left), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat('-') {
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        self.postfix()
+    }
+
+    fn postfix(&mut self) -> Result<Expr, String> {
+        //
+        // m 只能出现在 . 的左边，单独的 m 没有值
+        //
+        let mut expr = if self.peek() == Some(&Token::Ident("m".to_string())) {
+            self.position += 1;
+            self.expect('.')?;
+            let name = self.ident()?;
+            if name == "group" {
+                self.expect('(')?;
+                let group = self.expr()?;
+                self.expect(')')?;
+                self.group(group)?
+            } else {
+                Expr::Field(Field::parse(&name).ok_or_else(|| format!("m 没有属性 {}", name))?)
+            }
+        } else {
+            self.primary()?
+        };
+
+        while self.eat('.') {
+            let name = self.ident()?;
+            self.expect('(')?;
+            let mut args = vec![expr];
+            args.extend(self.args()?);
+            expr = self.call(&name, args)?;
+        }
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        match self.tokens.get(self.position).cloned() {
+            Some(Token::Str(text)) => {
+                self.position += 1;
+                Ok(Expr::Str(text))
+            }
+            Some(Token::Int(number)) => {
+                self.position += 1;
+                Ok(Expr::Int(number))
+            }
+            Some(Token::Ident(name)) => {
+                self.position += 1;
+                self.expect('(')?;
+                let args = self.args()?;
+                self.call(&name, args)
+            }
+            Some(Token::Punct('(')) => {
+                self.position += 1;
+                let expr = self.expr()?;
+                self.expect(')')?;
+                Ok(expr)
+            }
+            Some(token) => Err(format!("意外的 {}", token)),
+            None => Err("表达式不完整".to_string()),
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, String> {
+        match self.tokens.get(self.position).cloned() {
+            Some(Token::Ident(name)) => {
+                self.position += 1;
+                Ok(name)
+            }
+            _ => Err(". 后面需要名字".to_string()),
+        }
+    }
+
+    ///
+    /// 解析 ( 之后的参数列表，包括结尾的 )
+    ///
+    fn args(&mut self) -> Result<Vec<Expr>, String> {
+        let mut args = Vec::new();
+        if self.eat(')') {
+            return Ok(args);
+        }
+        loop {
+            args.push(self.expr()?);
+            if self.eat(')') {
+                return Ok(args);
+            }
+            self.expect(',')?;
+        }
+    }
+
+    fn call(&self, name: &str, args: Vec<Expr>) -> Result<Expr, String> {
+        let function = Function::parse(name).ok_or_else(|| format!("未知的函数 {}", name))?;
+        if args.len() != function.arity() {
+            return Err(format!(
+                "函数 {} 需要 {} 个参数，得到 {} 个",
+                name,
+                function.arity(),
+                args.len()
+            ));
+        }
+        Ok(Expr::Call(function, args))
+    }
+
+    ///
+    /// 字面量的捕获组在解析时就检查是否存在
+    ///
+    fn group(&self, group: Expr) -> Result<Expr, String> {
+        match group {
+            Expr::Int(index) => Ok(Expr::Group(resolve_group(&index.to_string(), self.re)?)),
+            Expr::Str(name) => Ok(Expr::Group(resolve_group(&name, self.re)?)),
+            group => Ok(Expr::DynamicGroup(Box::new(group))),
+        }
+    }
+}
+
+///
+/// 解析好的替换脚本
+///
+#[derive(Debug, Clone)]
+pub struct Script {
+    expr: Expr,
+    re: Regex,
+}
+
+impl Script {
+    pub fn parse(source: &str, re: &Regex) -> Result<Self, String> {
+        let mut parser = Parser {
+            tokens: tokenize(source).map_err(|err| format!("无效的脚本: {}", err))?,
+            position: 0,
+            re,
+        };
+        let expr = parser
+            .expr()
+            .map_err(|err| format!("无效的脚本: {}", err))?;
+        if let Some(token) = parser.peek() {
+            return Err(format!("无效的脚本: 意外的 {}", token));
+        }
+        Ok(Self {
+            expr,
+            re: re.clone(),
+        })
+    }
+
+    ///
+    /// 对一个匹配求值，line 是匹配所在的行号
+    ///
+    pub fn run(&self, caps: &Captures<'_>, line: usize) -> Result<String, String> {
+        let context = Context {
+            caps,
+            line,
+            re: &self.re,
+        };
+        context.eval(&self.expr).map(Value::into_string)
+    }
+}
+
+struct Context<'a, 'h> {
+    caps: &'a Captures<'h>,
+    line: usize,
+    re: &'a Regex,
+}
+
+impl Context<'_, '_> {
+    fn group(&self, index: usize) -> Value {
+        Value::Str(self.caps.get(index).map_or("", |m| m.as_str()).to_string())
+    }
+
+    fn field(&self, field: Field) -> Value {
+        if field == Field::Text {
+            return self.group(0);
+        }
+        if field == Field::Line {
+            return Value::Int(self.line as i64);
+        }
+        CURRENT_FILE.with(|file| {
+            let file = file.borrow();
+            let text = match field {
+                Field::FileName => file.file_name(),
+                Field::FileStem => file.file_stem(),
+                Field::Extension => file.extension(),
+                _ => Some(file.as_os_str()),
+            };
+            Value::Str(
+                text.map(|text| text.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+            )
+        })
+    }
+
+    fn eval(&self, expr: &Expr) -> Result<Value, String> {
+        match expr {
+            Expr::Str(text) => Ok(Value::Str(text.clone())),
+            Expr::Int(number) => Ok(Value::Int(*number)),
+            Expr::Field(field) => Ok(self.field(*field)),
+            Expr::Group(index) => Ok(self.group(*index)),
+            Expr::DynamicGroup(group) => {
+                let name = self.eval(group)?.into_string();
+                Ok(self.group(resolve_group(&name, self.re)?))
+            }
+            Expr::Neg(expr) => Ok(Value::Int(-self.eval(expr)?.as_int()?)),
+            Expr::Binary(op, left, right) => {
+                let (left, right) = (self.eval(left)?, self.eval(right)?);
+                if *op == Op::Add {
+                    if let (Value::Int(a), Value::Int(b)) = (&left, &right) {
+                        return Ok(Value::Int(a.wrapping_add(*b)));
+                    }
+                    return Ok(Value::Str(left.into_string() + &right.into_string()));
+                }
+                let (a, b) = (left.as_int()?, right.as_int()?);
+                let value = match op {
+                    Op::Sub => a.wrapping_sub(b),
+                    Op::Mul => a.wrapping_mul(b),
+                    Op::Div | Op::Rem if b == 0 => return Err("除数为 0".to_string()),
+                    Op::Div => a.wrapping_div(b),
+                    _ => a.wrapping_rem(b),
+                };
+                Ok(Value::Int(value))
+            }
+            Expr::Call(function, args) => {
+                let mut values = args
+                    .iter()
+                    .map(|arg| self.eval(arg))
+                    .collect::<Result<Vec<_>, _>>()?
+                    .into_iter();
+                let mut next = || values.next().unwrap_or(Value::Int(0));
+                let value = match function {
+                    Function::Filter(filter) => Value::Str(filter.apply(&next().into_string())),
+                    Function::Len => Value::Int(next().into_string().chars().count() as i64),
+                    Function::Str => Value::Str(next().into_string()),
+                    Function::Int => Value::Int(next().as_int()?),
+                    Function::Replace => {
+                        let text = next().into_string();
+                        let from = next().into_string();
+                        let to = next().into_string();
+                        Value::Str(text.replace(&from, &to))
+                    }
+                    Function::Pad => {
+                        let text = next().into_string();
+                        let width = next().as_int()?.max(0) as usize;
+                        let fill = next().into_string();
+                        let count = text.chars().count();
+                        let padding: String = fill
+                            .chars()
+                            .cycle()
+                            .take(width.saturating_sub(count))
+                            .collect();
+                        Value::Str(padding + &text)
+                    }
+                    Function::If => {
+                        let condition = next();
+                        let (then, otherwise) = (next(), next());
+                        if condition.is_truthy() {
+                            then
+                        } else {
+                            otherwise
+                        }
+                    }
+                };
+                Ok(value)
+            }
+        }
+    }
+}
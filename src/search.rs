@@ -0,0 +1,186 @@
+//!
+//! search 子命令：只查找不替换，像 grep 一样输出每一处匹配
+//!
+
+use clap::ValueEnum;
+use rayon::prelude::*;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{count_newlines, InvalidUtf8, ReplacePlan};
+use crate::{paths, sarif};
+
+///
+/// search 和 --check 输出匹配的格式
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    ///
+    /// 每处匹配一行：path:line:column: 匹配的文本
+    ///
+    Text,
+    ///
+    /// 和 rg --vimgrep 相同，每处匹配一行：path:line:column:匹配所在的整行，可以直接载入 Vim 的 quickfix 列表
+    ///
+    Vimgrep,
+    ///
+    /// 和 grep -nH 相同，每个有匹配的行输出一次：path:line:整行
+    ///
+    Grep,
+    ///
+    /// SARIF 2.1.0，可以上传到 GitHub 代码扫描等工具
+    ///
+    Sarif,
+}
+
+///
+/// 一处匹配的位置和内容
+///
+pub struct Found {
+    ///
+    /// 匹配到的规则在规则列表中的序号
+    ///
+    pub rule: usize,
+    pub start: usize,
+    pub line: usize,
+    pub column: usize,
+    pub text: String,
+    ///
+    /// 匹配开始的那一整行，不包括换行
+    ///
+    pub line_text: String,
+}
+
+///
+/// 找出文件中所有被选中的匹配，多条规则的匹配按位置排序
+/// 行号和区域之类的选择条件和替换时一样生效，列号从 1 开始按字符计算
+///
+pub fn search_text(text: &str, plan: &ReplacePlan) -> Vec<Found> {
+    let mut found = Vec::new();
+    for (index, rule) in plan.rules.iter().enumerate() {
+        let mut selector = plan.selection.selector();
+        selector.set_text(text);
+        let mut line = 1;
+        let mut counted = 0;
+        for m in rule.re.find_iter(text) {
+            line += count_newlines(&text[counted..m.start()]);
+            counted = m.start();
+            if !selector.allows(m.range(), line) {
+                continue;
+            }
+            let line_start = text[..m.start()].rfind('\n').map_or(0, |index| index + 1);
+            let line_end = text[m.start()..]
+                .find('\n')
+                .map_or(text.len(), |index| m.start() + index);
+            let line_text = &text[line_start..line_end];
+            found.push(Found {
+                rule: index,
+                start: m.start(),
+                line,
+                column: text[line_start..m.start()].chars().count() + 1,
+                text: m.as_str().to_string(),
+                line_text: line_text
+                    .strip_suffix('\r')
+                    .unwrap_or(line_text)
+                    .to_string(),
+            });
+        }
+    }
+    found.sort_by_key(|found| found.start);
+    found
+}
+
+///
+/// 并行查找所有文件中的匹配，读取失败的文件输出错误后跳过
+/// 返回每个文件的匹配和是否有读取失败的文件，--invalid-utf8 skip 跳过的文件不算读取失败
+///
+fn find_all(files: &[PathBuf], plan: &ReplacePlan) -> (Vec<(PathBuf, Vec<Found>)>, bool) {
+    let failed = AtomicBool::new(false);
+    let mut results: Vec<_> = files
+        .par_iter()
+        .filter_map(|file| match plan.read_text(file) {
+            Ok(text) => Some((paths::to_host(file), search_text(&text, plan))),
+            Err(err)
+                if err.kind() == io::ErrorKind::InvalidData
+                    && plan.invalid_utf8 == InvalidUtf8::Skip =>
+            {
+                eprintln!("跳过不是有效 UTF-8 的文件 {:?}", paths::to_host(file));
+                None
+            }
+            Err(err) => {
+                eprintln!("读取文件错误 {:?}: {}", paths::to_host(file), err);
+                failed.store(true, Ordering::Relaxed);
+                None
+            }
+        })
+        .collect();
+    results.retain(|(_, found)| !found.is_empty());
+    (results, failed.into_inner())
+}
+
+///
+/// 按 format 输出匹配，text 格式中跨行的匹配中的换行显示为 \n
+/// 返回匹配数，grep 格式同一行的多处匹配只输出一次，但是都计入匹配数
+///
+fn print_found(results: &[(PathBuf, Vec<Found>)], plan: &ReplacePlan, format: Format) -> usize {
+    let count = results.iter().map(|(_, found)| found.len()).sum();
+    if format == Format::Sarif {
+        print!("{}", sarif::log(results, plan));
+        return count;
+    }
+    for (file, found) in results {
+        let file = file.display();
+        let mut printed_line = None;
+        for found in found {
+            match format {
+                Format::Vimgrep => println!(
+                    "{}:{}:{}:{}",
+                    file, found.line, found.column, found.line_text
+                ),
+                //
+                // 匹配按位置排序，同一行的匹配是相邻的
+                //
+                Format::Grep if printed_line != Some(found.line) => {
+                    printed_line = Some(found.line);
+                    println!("{}:{}:{}", file, found.line, found.line_text);
+                }
+                Format::Grep => {}
+                _ => println!(
+                    "{}:{}:{}: {}",
+                    file,
+                    found.line,
+                    found.column,
+                    found.text.replace('\n', "\\n")
+                ),
+            }
+        }
+    }
+    count
+}
+
+///
+/// 输出所有匹配，有任何匹配时返回 true
+///
+pub fn search(files: &[PathBuf], plan: &ReplacePlan, format: Format) -> bool {
+    let (results, _) = find_all(files, plan);
+    print_found(&results, plan, format) > 0
+}
+
+///
+/// --check：不修改文件，按 search 的格式输出所有匹配，最后在标准错误输出匹配的总数
+/// 返回退出状态：没有匹配时为 0，有匹配时为 1，没有匹配但有文件读取失败时为 2
+///
+pub fn check(files: &[PathBuf], plan: &ReplacePlan, format: Format) -> i32 {
+    let (results, failed) = find_all(files, plan);
+    let count = print_found(&results, plan, format);
+    if count > 0 {
+        eprintln!("检查失败: {} 个文件中有 {} 处匹配", results.len(), count);
+        1
+    } else if failed {
+        eprintln!("检查失败: 有文件读取失败");
+        2
+    } else {
+        0
+    }
+}
@@ -0,0 +1,362 @@
+use regex::Regex;
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+///
+/// 从 1 开始的序号范围，两端都包含，用于行号和匹配序号
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NumberRange {
+    start: usize,
+    end: usize,
+}
+
+impl NumberRange {
+    fn contains(&self, number: usize) -> bool {
+        self.start <= number && number <= self.end
+    }
+}
+
+///
+/// 解析 --lines 的行号范围
+/// 支持 120..180、120..（到文件末尾）、..180（从文件开头）、42（单行）
+/// 行号从 1 开始，两端都包含
+///
+pub fn parse_line_range(range: &str) -> Result<NumberRange, String> {
+    parse_range(range, "行号")
+}
+
+///
+/// 解析 --occurrence 的匹配序号范围，格式与 --lines 相同
+///
+pub fn parse_occurrence(range: &str) -> Result<NumberRange, String> {
+    parse_range(range, "匹配序号")
+}
+
+fn parse_range(range: &str, name: &str) -> Result<NumberRange, String> {
+    let parse = |value: &str, default: usize| -> Result<usize, String> {
+        if value.is_empty() {
+            return Ok(default);
+        }
+        match value.trim().parse::<usize>() {
+            Ok(number) if number > 0 => Ok(number),
+            _ => Err(format!("无效的{}: {}", name, value)),
+        }
+    };
+
+    let (start, end) = match range.split_once("..") {
+        Some((start, end)) => (parse(start, 1)?, parse(end, usize::MAX)?),
+        None => {
+            let number = parse(range, 0)?;
+            (number, number)
+        }
+    };
+    if start > end {
+        return Err(format!("{}范围的起点大于终点: {}", name, range));
+    }
+
+    Ok(NumberRange { start, end })
+}
+
+///
+/// 按 Markdown 文件开头的 front matter 限制替换范围
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrontMatter {
+    ///
+    /// 只替换 front matter 中的内容，没有 front matter 的文件不替换
+    ///
+    Only,
+    ///
+    /// 只替换 front matter 之后的正文，没有 front matter 的文件整个替换
+    ///
+    BodyOnly,
+}
+
+///
+/// 决定哪些匹配需要被替换
+///
+#[derive(Debug, Clone, Default)]
+pub struct Selection {
+    ///
+    /// 只替换落在这些行号范围内的匹配，为空时不限制
+    ///
+    pub lines: Vec<NumberRange>,
+    ///
+    /// 只替换位于起始标记和结束标记之间的匹配，标记本身不会被替换
+    ///
+    pub within: Option<(Regex, Regex)>,
+    ///
+    /// 只替换 front matter 或者只替换正文
+    ///
+    pub front_matter: Option<FrontMatter>,
+    ///
+    /// 只替换每个文件中第几处匹配，序号在行号和区域过滤之后计算，为空时不限制
+    ///
+    pub occurrences: Vec<NumberRange>,
+    ///
+    /// 每个文件中保留不替换的前几处匹配，与匹配序号一样在行号和区域过滤之后计算
+    ///
+    pub skip: usize,
+    ///
+    /// 每个文件最多替换的次数
+    ///
+    pub max_count: Option<usize>,
+    ///
+    /// 整次运行最多替换的次数
+    ///
+    pub global_max: Option<usize>,
+    ///
+    /// 整次运行已经替换的次数，所有线程共享
+    ///
+    pub global_replaced: Arc<AtomicUsize>,
+}
+
+impl Selection {
+    ///
+    /// 是否必须整个文件替换
+    /// 多行正则的逐行替换会把替换过的行带入下一轮，行号只是近似值，
+    /// 而且只替换部分匹配时，剩下的匹配会让逐行替换的跨行检查失败
+    /// 区域的起止标记可能相隔任意多行，逐行处理无法跟踪，front matter 也一样
+    ///
+    pub fn needs_whole_file(&self, max_line_number: usize) -> bool {
+        let partial = !self.lines.is_empty()
+            || !self.occurrences.is_empty()
+            || self.skip > 0
+            || self.max_count.is_some()
+            || self.global_max.is_some();
+        (partial && max_line_number > 1) || self.within.is_some() || self.front_matter.is_some()
+    }
+
+    ///
+    /// 复制一份计数清零的选择条件，用于只读的扫描，避免占用真正替换时的全局配额
+    ///
+    pub fn fresh(&self) -> Self {
+        Self {
+            global_replaced: Arc::new(AtomicUsize::new(0)),
+            ..self.clone()
+        }
+    }
+
+    ///
+    /// 为一个文件的替换创建选择器
+    ///
+    pub fn selector(&self) -> Selector<'_> {
+        Selector {
+            selection: self,
+            regions: None,
+            next_region: 0,
+            seen: 0,
+            replaced: 0,
+        }
+    }
+}
+
+///
+/// 选择器状态的快照，替换失败换用其他方式重试时用来撤销计数
+///
+#[derive(Debug, Clone, Copy)]
+pub struct Checkpoint {
+    seen: usize,
+    replaced: usize,
+}
+
+///
+/// 一次替换过程中的选择状态
+///
+pub struct Selector<'a> {
+    selection: &'a Selection,
+    ///
+    /// 当前文本中允许替换的区域，按位置排序
+    ///
+    regions: Option<Vec<Range<usize>>>,
+    next_region: usize,
+    ///
+    /// 当前文件中通过了行号和区域过滤的匹配数
+    ///
+    seen: usize,
+    ///
+    /// 当前文件已经替换的次数
+    ///
+    replaced: usize,
+}
+
+impl Selector<'_> {
+    pub fn needs_whole_file(&self, max_line_number: usize) -> bool {
+        self.selection.needs_whole_file(max_line_number)
+    }
+
+    ///
+    /// 能否流式替换：区域的起止标记需要看到整个文件才能确定
+    ///
+    pub fn supports_streaming(&self) -> bool {
+        self.selection.within.is_none() && self.selection.front_matter.is_none()
+    }
+
+    ///
+    /// 开始处理一段新的文本
+    ///
+    pub fn set_text(&mut self, text: &str) {
+        self.regions = match (&self.selection.within, self.selection.front_matter) {
+            (Some((start, end)), _) => Some(find_regions(text, start, end)),
+            (None, Some(mode)) => Some(front_matter_regions(text, mode)),
+            (None, None) => None,
+        };
+        self.next_region = 0;
+    }
+
+    ///
+    /// 当前文件已经替换的次数
+    ///
+    pub fn replaced(&self) -> usize {
+        self.replaced
+    }
+
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            seen: self.seen,
+            replaced: self.replaced,
+        }
+    }
+
+    ///
+    /// 撤销快照之后的替换计数，包括占用的全局配额
+    ///
+    pub fn rollback(&mut self, checkpoint: Checkpoint) {
+        let undone = self.replaced - checkpoint.replaced;
+        if self.selection.global_max.is_some() && undone > 0 {
+            self.selection
+                .global_replaced
+                .fetch_sub(undone, Ordering::SeqCst);
+        }
+        self.seen = checkpoint.seen;
+        self.replaced = checkpoint.replaced;
+    }
+
+    ///
+    /// 位于 range、起始于第 line 行的匹配是否需要被替换
+    /// 需要按位置顺序调用，返回 true 时计入替换次数
+    ///
+    pub fn allows(&mut self, range: Range<usize>, line: usize) -> bool {
+        if !self.selection.lines.is_empty()
+            && !self
+                .selection
+                .lines
+                .iter()
+                .any(|lines| lines.contains(line))
+        {
+            return false;
+        }
+
+        if let Some(regions) = &self.regions {
+            while self.next_region < regions.len() && regions[self.next_region].end < range.end {
+                self.next_region += 1;
+            }
+            match regions.get(self.next_region) {
+                Some(region) if region.start <= range.start => {}
+                _ => return false,
+            }
+        }
+
+        self.seen += 1;
+        if self.seen <= self.selection.skip {
+            return false;
+        }
+        if !self.selection.occurrences.is_empty()
+            && !self
+                .selection
+                .occurrences
+                .iter()
+                .any(|occurrences| occurrences.contains(self.seen))
+        {
+            return false;
+        }
+
+        if let Some(max_count) = self.selection.max_count {
+            if self.replaced >= max_count {
+                return false;
+            }
+        }
+
+        if let Some(global_max) = self.selection.global_max {
+            let reserved = self.selection.global_replaced.fetch_update(
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+                |replaced| (replaced < global_max).then_some(replaced + 1),
+            );
+            if reserved.is_err() {
+                return false;
+            }
+        }
+
+        self.replaced += 1;
+        true
+    }
+}
+
+///
+/// 区域状态机：在区域外寻找起始标记，进入区域后寻找结束标记
+/// 没有结束标记的区域一直延伸到文本末尾
+///
+fn find_regions(text: &str, start: &Regex, end: &Regex) -> Vec<Range<usize>> {
+    let mut regions = Vec::new();
+    let mut position = 0;
+    while position <= text.len() {
+        let Some(start_marker) = start.find_at(text, position) else {
+            break;
+        };
+        match end.find_at(text, start_marker.end()) {
+            Some(end_marker) => {
+                regions.push(start_marker.end()..end_marker.start());
+                //
+                // 防止空匹配导致死循环
+                //
+                position = end_marker.end().max(start_marker.end() + 1);
+            }
+            None => {
+                regions.push(start_marker.end()..text.len());
+                break;
+            }
+        }
+    }
+    regions
+}
+
+///
+/// front matter 是文件开头 --- 和 --- （或 ...）之间的 YAML，Hugo 的 +++ 和 +++ 之间的 TOML 也算
+/// 返回 front matter 内容的范围和正文的起始位置，分隔行本身两边都不包括
+///
+fn find_front_matter(text: &str) -> Option<(Range<usize>, usize)> {
+    let offset = if text.starts_with('\u{feff}') { 3 } else { 0 };
+    let mut lines = text[offset..].split_inclusive('\n');
+    let opening = lines.next()?;
+    let closings: &[&str] = match opening.trim_end() {
+        "---" => &["---", "..."],
+        "+++" => &["+++"],
+        _ => return None,
+    };
+    if !opening.ends_with('\n') {
+        return None;
+    }
+
+    let start = offset + opening.len();
+    let mut position = start;
+    for line in lines {
+        if closings.contains(&line.trim_end()) {
+            return Some((start..position, position + line.len()));
+        }
+        position += line.len();
+    }
+    None
+}
+
+fn front_matter_regions(text: &str, mode: FrontMatter) -> Vec<Range<usize>> {
+    let region = match (find_front_matter(text), mode) {
+        (Some((front_matter, _)), FrontMatter::Only) => Some(front_matter),
+        (None, FrontMatter::Only) => None,
+        (Some((_, body)), FrontMatter::BodyOnly) => Some(body..text.len()),
+        (None, FrontMatter::BodyOnly) => Some(0..text.len()),
+    };
+    region.into_iter().collect()
+}
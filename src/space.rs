@@ -0,0 +1,109 @@
+//!
+//! 写临时文件之前检查它所在的文件系统有没有足够的空间
+//! 空间不够时不再开始新的文件，而不是写到一半遇到 ENOSPC；--atomic-run 时不会修改任何文件
+//!
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::{install, metrics, paths};
+
+///
+/// 按文件检查临时空间的预算，只检查有匹配、真正要写临时文件的文件
+/// 列表和边遍历边替换时一样生效，不需要事先知道所有的文件
+///
+#[derive(Debug, Default)]
+pub struct Budget {
+    ///
+    /// 每个文件系统上已经答应使用、但还没有真正占用的空间
+    /// --atomic-run 的备份在写回时才创建，替换时要先给它留出空间
+    ///
+    reserved: Mutex<HashMap<u64, u64>>,
+}
+
+impl Budget {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///
+    /// 写临时文件之前确认它所在的文件系统有足够的空间，按替换结果和原文件一样大估计
+    /// --atomic-run 时还要放得下写回时的备份
+    /// 可用的空间随已经写出的临时文件实时变化，拿不到设备或者可用空间的文件系统不检查
+    ///
+    pub fn reserve(&self, file: &Path, atomic: bool) -> Result<(), String> {
+        let Ok(metadata) = fs::metadata(file) else {
+            return Ok(());
+        };
+        let dir = install::temp_dir(file);
+        let (Some(device), Some(available)) = (imp::device(&dir), imp::available(&dir)) else {
+            return Ok(());
+        };
+        let size = metadata.len();
+        let mut reserved = self.reserved.lock().unwrap();
+        let reserved = reserved.entry(device).or_default();
+        let backup = if atomic { size } else { 0 };
+        let required = size.saturating_add(backup).saturating_add(*reserved);
+        if required > available {
+            return Err(format!(
+                "临时文件所在的 {:?} 空间不足，需要大约 {}，只剩 {}\n\
+                 可以用 --tmpdir 把临时文件放到其他文件系统，或者用 --no-space-check 跳过检查",
+                paths::to_host(&dir),
+                metrics::format_bytes(required),
+                metrics::format_bytes(available)
+            ));
+        }
+        *reserved += backup;
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+mod imp {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::MetadataExt;
+    use std::path::Path;
+    use std::{fs, mem};
+
+    pub fn device(dir: &Path) -> Option<u64> {
+        fs::metadata(dir).ok().map(|metadata| metadata.dev())
+    }
+
+    ///
+    /// 普通用户可以使用的空间，不包括为 root 保留的部分
+    ///
+    pub fn available(dir: &Path) -> Option<u64> {
+        let path = CString::new(dir.as_os_str().as_bytes()).ok()?;
+        let mut stat: libc::statvfs = unsafe { mem::zeroed() };
+        //
+        // ramfs 等不限制大小的文件系统报告的总块数是 0，不做检查
+        //
+        if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 || stat.f_blocks == 0 {
+            return None;
+        }
+        //
+        // 字段的类型随平台变化，macOS 上是 u32
+        //
+        #[allow(clippy::useless_conversion)]
+        Some(u64::from(stat.f_bavail).saturating_mul(u64::from(stat.f_frsize)))
+    }
+}
+
+//
+// 其他平台拿不到可用空间，不做检查
+//
+#[cfg(not(unix))]
+mod imp {
+    use std::path::Path;
+
+    pub fn device(_dir: &Path) -> Option<u64> {
+        None
+    }
+
+    pub fn available(_dir: &Path) -> Option<u64> {
+        None
+    }
+}
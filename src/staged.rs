@@ -0,0 +1,108 @@
+//!
+//! --staged：替换暂存区中的文件内容并写回暂存区，不修改工作区，可以作为 pre-commit 钩子使用
+//!
+
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::FileError;
+use crate::vcs::Vcs;
+use crate::{encoding, paths, replace_in_file_as, script, InvalidUtf8, ReplacePlan};
+
+///
+/// 替换一个已暂存的文件，返回是否写回了暂存区
+/// 暂存区中的内容先写到临时目录里同名的文件中，按扩展名判断格式的功能照常生效
+/// 编码仍然按工作区中的路径匹配配置
+/// 和普通的替换一样跳过过大、有过长的行、生成的文件，以及 --invalid-utf8 skip 时无效的文件
+///
+fn replace_staged_file(
+    vcs: &dyn Vcs,
+    file: &Path,
+    path: &Path,
+    temp_dir: &Path,
+    plan: &ReplacePlan,
+) -> Result<bool, String> {
+    let contents = vcs.read_staged(file)?;
+    let Some(name) = file.file_name() else {
+        return Ok(false);
+    };
+    fs::create_dir(temp_dir).map_err(|err| err.to_string())?;
+    let copy = temp_dir.join(name);
+    fs::write(&copy, &contents).map_err(|err| err.to_string())?;
+
+    let skip = |reason: FileError| {
+        eprintln!("{:?}: {}", paths::to_host(path), reason);
+        Ok(false)
+    };
+    //
+    // 只有 --invalid-utf8 skip 时无效的文件不算失败
+    //
+    let failed = |err: &(dyn Error + 'static)| match FileError::from_replace(err) {
+        FileError::NotUtf8(_) if plan.invalid_utf8 == InvalidUtf8::Skip => {
+            eprintln!("跳过不是有效 UTF-8 的文件 {:?}", paths::to_host(path));
+            Ok(false)
+        }
+        _ => Err(err.to_string()),
+    };
+    if let Some(reason) = plan.skip_reason(&copy) {
+        return skip(reason);
+    }
+    //
+    // 临时副本的路径匹配不到配置中按路径指定的编码，按原路径确定编码
+    //
+    let encoding = encoding::detect(&copy, plan.config.encoding_for(path));
+    match plan.has_match_as(&copy, encoding) {
+        Ok(true) => {}
+        Ok(false) => return Ok(false),
+        Err(err) => return failed(&err),
+    }
+    if let Some(reason) = plan.skip_matched(&copy) {
+        return skip(reason);
+    }
+
+    script::set_file(path);
+    let replaced = match replace_in_file_as(&copy, encoding, plan) {
+        Ok(replaced) => replaced,
+        Err(err) => return failed(err.as_ref()),
+    };
+    let result = fs::read(&replaced.temp_file);
+    let _ = fs::remove_file(&replaced.temp_file);
+    let result = result.map_err(|err| err.to_string())?;
+    if replaced.replacements == 0 || result == contents {
+        return Ok(false);
+    }
+    vcs.write_staged(file, &result)?;
+    Ok(true)
+}
+
+///
+/// 依次替换已暂存的文件，files 中是版本库中的路径和对应的 -d 目录下的路径
+/// 输出写回了暂存区的文件，全部成功时返回 true
+///
+pub fn replace_staged(vcs: &dyn Vcs, files: &[(PathBuf, PathBuf)], plan: &ReplacePlan) -> bool {
+    let temp_dir = match tempfile::tempdir() {
+        Ok(temp_dir) => temp_dir,
+        Err(err) => {
+            eprintln!("错误: 创建临时目录失败: {}", err);
+            return false;
+        }
+    };
+
+    let mut ok = true;
+    for (index, (file, path)) in files.iter().enumerate() {
+        //
+        // 每个文件放在单独的子目录里，同名的文件不会互相覆盖
+        //
+        let dir = temp_dir.path().join(index.to_string());
+        match replace_staged_file(vcs, file, path, &dir, plan) {
+            Ok(true) => println!("{:?}", paths::to_host(path)),
+            Ok(false) => {}
+            Err(err) => {
+                eprintln!("替换暂存区中的 {:?} 失败: {}", paths::to_host(path), err);
+                ok = false;
+            }
+        }
+    }
+    ok
+}
@@ -0,0 +1,152 @@
+use std::path::PathBuf;
+
+///
+/// 直方图最多占用的列数
+///
+const MAX_BAR_WIDTH: usize = 50;
+
+///
+/// 一个文件增加和删除的行数
+///
+pub struct FileStat {
+    pub path: PathBuf,
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+impl FileStat {
+    ///
+    /// 按行比较替换前后的内容，统计增加和删除的行数
+    ///
+    pub fn new(path: PathBuf, original: &str, replaced: &str) -> Self {
+        let original: Vec<&str> = original.split_inclusive('\n').collect();
+        let replaced: Vec<&str> = replaced.split_inclusive('\n').collect();
+
+        //
+        // 先去掉相同的开头和结尾，替换通常只改动少数几行
+        //
+        let prefix = original
+            .iter()
+            .zip(&replaced)
+            .take_while(|(a, b)| a == b)
+            .count();
+        let suffix = original[prefix..]
+            .iter()
+            .rev()
+            .zip(replaced[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let original = &original[prefix..original.len() - suffix];
+        let replaced = &replaced[prefix..replaced.len() - suffix];
+
+        //
+        // 编辑距离 = 增加 + 删除，而 增加 - 删除 = 行数之差
+        //
+        let distance = edit_distance(original, replaced);
+        let insertions = (distance + replaced.len() - original.len()) / 2;
+        let deletions = distance - insertions;
+
+        Self {
+            path,
+            insertions,
+            deletions,
+        }
+    }
+
+    fn changes(&self) -> usize {
+        self.insertions + self.deletions
+    }
+}
+
+///
+/// Myers 算法计算只允许插入和删除时的编辑距离，时间 O((N + M) D)，空间 O(N + M)
+///
+fn edit_distance(a: &[&str], b: &[&str]) -> usize {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = (n + m) as usize;
+    if max == 0 {
+        return 0;
+    }
+    let offset = max as isize;
+    //
+    // v[k] 是对角线 k 上能到达的最远的 x
+    //
+    let mut v = vec![0isize; 2 * max + 2];
+    for d in 0..=max as isize {
+        let mut k = -d;
+        while k <= d {
+            let index = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[index - 1] < v[index + 1]) {
+                v[index + 1]
+            } else {
+                v[index - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[index] = x;
+            if x >= n && y >= m {
+                return d as usize;
+            }
+            k += 2;
+        }
+    }
+    max
+}
+
+///
+/// 按 git diff --stat 的格式输出
+///
+pub fn print(stats: &[FileStat]) {
+    let stats: Vec<&FileStat> = stats.iter().filter(|stat| stat.changes() > 0).collect();
+    let names: Vec<String> = stats
+        .iter()
+        .map(|stat| stat.path.to_string_lossy().into_owned())
+        .collect();
+    let name_width = names
+        .iter()
+        .map(|name| name.chars().count())
+        .max()
+        .unwrap_or(0);
+    let max_changes = stats.iter().map(|stat| stat.changes()).max().unwrap_or(0);
+    let count_width = max_changes.to_string().len();
+
+    for (stat, name) in stats.iter().zip(&names) {
+        let (plus, minus) = bar(stat, max_changes);
+        println!(
+            " {:<name_width$} | {:>count_width$} {}{}",
+            name,
+            stat.changes(),
+            "+".repeat(plus),
+            "-".repeat(minus),
+        );
+    }
+
+    let insertions: usize = stats.iter().map(|stat| stat.insertions).sum();
+    let deletions: usize = stats.iter().map(|stat| stat.deletions).sum();
+    println!(
+        " {} 个文件被修改，{} 行增加(+)，{} 行删除(-)",
+        stats.len(),
+        insertions,
+        deletions
+    );
+}
+
+///
+/// 直方图中 + 和 - 的个数，改动最多的文件占满最大宽度，有改动的一侧至少显示一个
+///
+fn bar(stat: &FileStat, max_changes: usize) -> (usize, usize) {
+    if max_changes <= MAX_BAR_WIDTH {
+        return (stat.insertions, stat.deletions);
+    }
+    let scale = |count: usize| {
+        if count == 0 {
+            0
+        } else {
+            (count * MAX_BAR_WIDTH / max_changes).max(1)
+        }
+    };
+    (scale(stat.insertions), scale(stat.deletions))
+}
@@ -0,0 +1,68 @@
+//!
+//! 温和地停止一次运行：正在处理的文件照常完成并写回，尚未开始的文件不再处理
+//! 与 Ctrl-C 不同，停止之后仍然会输出已完成部分的统计
+//! 可以通过 SIGUSR1 信号（仅 unix）或者创建 --stop-file 指定的控制文件来请求停止
+//!
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+static STOP_FILE: OnceLock<PathBuf> = OnceLock::new();
+
+///
+/// 注册 SIGUSR1 处理函数，并记录控制文件的路径
+/// 控制文件在启动时就存在多半是上一次运行留下的，直接报错，以免什么都没处理就停止
+///
+pub fn install(stop_file: Option<PathBuf>) -> Result<(), String> {
+    if let Some(stop_file) = stop_file {
+        if stop_file.exists() {
+            return Err(format!("停止控制文件 {:?} 已经存在，请先删除", stop_file));
+        }
+        let _ = STOP_FILE.set(stop_file);
+    }
+    imp::install_signal_handler();
+    Ok(())
+}
+
+///
+/// 是否已经请求停止
+///
+pub fn requested() -> bool {
+    if REQUESTED.load(Ordering::SeqCst) {
+        return true;
+    }
+    if STOP_FILE.get().is_some_and(|stop_file| stop_file.exists()) {
+        REQUESTED.store(true, Ordering::SeqCst);
+        return true;
+    }
+    false
+}
+
+#[cfg(unix)]
+mod imp {
+    use super::REQUESTED;
+    use std::sync::atomic::Ordering;
+
+    //
+    // 信号处理函数里只修改原子变量，是异步信号安全的
+    //
+    extern "C" fn handle_signal(_signal: libc::c_int) {
+        REQUESTED.store(true, Ordering::SeqCst);
+    }
+
+    pub fn install_signal_handler() {
+        unsafe {
+            libc::signal(
+                libc::SIGUSR1,
+                handle_signal as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            );
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    pub fn install_signal_handler() {}
+}
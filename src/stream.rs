@@ -0,0 +1,184 @@
+//!
+//! 流式替换：按块读取文件，只在内存中保留一个重叠窗口，用于无法逐行替换的超大文件
+//!
+//! 重叠窗口由正则的最大匹配长度决定：距离缓冲区末尾超过窗口大小的位置上，
+//! 所有可能的匹配都已经完整地落在缓冲区里，之后读入的内容不会改变它们
+//!
+
+use regex::Regex;
+use regex_syntax::hir::Look;
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::select::Selector;
+use crate::template::Template;
+use crate::{count_newlines, install};
+
+///
+/// 需要整个文件替换时，不小于这个大小的文件改用流式替换
+///
+pub const STREAM_THRESHOLD: u64 = 64 * 1024 * 1024;
+
+///
+/// 每次读取的字节数
+///
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+///
+/// 搜索起点之前保留的字节数，\b、(?m)^ 需要看到前一个字符
+///
+const CONTEXT: usize = 4;
+
+///
+/// 流式替换需要的重叠窗口大小（字节）
+/// 只有匹配长度有上限、并且不使用 \A、\z 这类整段文本锚点的正则才能流式替换
+///
+pub fn window(pattern: &str) -> Option<usize> {
+    let hir = regex_syntax::parse(pattern).ok()?;
+    let properties = hir.properties();
+    let looks = properties.look_set();
+    if looks.contains(Look::Start) || looks.contains(Look::End) {
+        return None;
+    }
+    properties.maximum_len().map(|len| len + CONTEXT)
+}
+
+///
+/// 用流式的方法替换文件
+/// 内存占用只和块大小、重叠窗口有关，与文件大小无关
+///
+pub fn replace_in_file_streaming(
+    target_file: &Path,
+    re: &Regex,
+    replacement: &Template,
+    window: usize,
+    selector: &mut Selector,
+) -> Result<PathBuf, Box<dyn Error>> {
+    let temp_file = install::create_temp_file(target_file)?;
+    let temp_file_path = temp_file.path().to_path_buf();
+    let file = OpenOptions::new()
+        .append(true)
+        .open(temp_file_path.clone())?;
+    let mut file = BufWriter::new(file);
+    let mut input = File::open(target_file)?;
+
+    let mut replaced = String::new();
+    let mut chunk = vec![0u8; CHUNK_SIZE];
+    //
+    // 块的末尾可能截断了一个多字节字符，剩下的字节留到下一块
+    //
+    let mut pending: Vec<u8> = Vec::new();
+    let mut buffer = String::new();
+    //
+    // 以下位置都是 buffer 中的下标：
+    // position 是下一次搜索的起点，written 之前的内容已经写入临时文件，
+    // counted 之前的换行已经计入 line
+    //
+    let mut position = 0;
+    let mut written = 0;
+    let mut counted = 0;
+    let mut line = 1;
+    let mut last_match_end: Option<usize> = None;
+
+    loop {
+        let read = input.read(&mut chunk)?;
+        let eof = read == 0;
+        pending.extend_from_slice(&chunk[..read]);
+        let valid = match std::str::from_utf8(&pending) {
+            Ok(text) => text.len(),
+            Err(err) if err.error_len().is_none() && !eof => err.valid_up_to(),
+            Err(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "stream did not contain valid UTF-8",
+                )
+                .into())
+            }
+        };
+        buffer.push_str(std::str::from_utf8(&pending[..valid])?);
+        pending.drain(..valid);
+
+        //
+        // 起点在 limit 之前的匹配已经确定，之后的要等读入更多内容再判断
+        //
+        let limit = if eof {
+            buffer.len()
+        } else {
+            floor_char_boundary(&buffer, buffer.len().saturating_sub(window))
+        };
+
+        while position <= buffer.len() {
+            let Some(caps) = re.captures_at(&buffer, position) else {
+                break;
+            };
+            let m = caps.get(0).unwrap();
+            if !eof && m.start() >= limit {
+                break;
+            }
+            //
+            // 紧跟在上一个匹配之后的空匹配不算，和 captures_iter 的行为一致
+            //
+            if m.is_empty() && last_match_end == Some(m.start()) {
+                position = next_char_boundary(&buffer, m.start());
+                continue;
+            }
+
+            line += count_newlines(&buffer[counted..m.start()]);
+            counted = m.start();
+            if selector.allows(m.range(), line) {
+                file.write_all(&buffer.as_bytes()[written..m.start()])?;
+                replaced.clear();
+                replacement.append(&caps, line, &mut replaced);
+                file.write_all(replaced.as_bytes())?;
+                written = m.end();
+            }
+            last_match_end = Some(m.end());
+            position = m.end();
+        }
+
+        if eof {
+            break;
+        }
+
+        //
+        // limit 之前的内容不会再变化，写出去，只保留搜索起点之前的少量上下文
+        //
+        position = position.max(limit);
+        file.write_all(&buffer.as_bytes()[written..position])?;
+        written = position;
+        let drain = floor_char_boundary(&buffer, position.saturating_sub(CONTEXT));
+        if counted < drain {
+            line += count_newlines(&buffer[counted..drain]);
+            counted = drain;
+        }
+        buffer.drain(..drain);
+        position -= drain;
+        written -= drain;
+        counted -= drain;
+        last_match_end = last_match_end.and_then(|end| end.checked_sub(drain));
+    }
+
+    if written < buffer.len() {
+        file.write_all(&buffer.as_bytes()[written..])?;
+    }
+    file.flush()?;
+    let _ = temp_file.persist(&temp_file_path)?;
+
+    Ok(temp_file_path)
+}
+
+fn floor_char_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+fn next_char_boundary(text: &str, index: usize) -> usize {
+    match text[index..].chars().next() {
+        Some(ch) => index + ch.len_utf8(),
+        None => index + 1,
+    }
+}
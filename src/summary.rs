@@ -0,0 +1,160 @@
+//!
+//! 一次替换运行的结果：统计、改动报告、跳过和失败的文件、资源使用和耗时
+//! Replacer 只收集这些结果，由调用方决定是否输出
+//!
+
+use std::path::PathBuf;
+
+use crate::error::FileError;
+use crate::metrics::{self, Timings, Usage};
+use crate::{paths, report, stat};
+
+pub struct Summary {
+    ///
+    /// 收到停止请求时已经开始处理的文件数和文件总数，事先不知道总数时为 None
+    ///
+    pub stopped: Option<(usize, Option<usize>)>,
+    ///
+    /// 打开 stat 时每个写回文件增加和删除的行数，按路径排序
+    ///
+    pub stats: Vec<stat::FileStat>,
+    ///
+    /// 打开 report 时每个写回文件的改动，按路径排序
+    ///
+    pub reports: Vec<report::FileReport>,
+    ///
+    /// 跳过和处理失败的文件，按路径排序
+    ///
+    pub skipped: Vec<(PathBuf, FileError)>,
+    pub failed: Vec<(PathBuf, FileError)>,
+    pub usage: Usage,
+    pub timings: Timings,
+    ///
+    /// 缓存文件保存失败，不影响替换结果
+    ///
+    pub cache_error: Option<String>,
+    ///
+    /// 补丁文件写入失败，不影响替换结果
+    ///
+    pub patch_error: Option<String>,
+    ///
+    /// 整次运行失败的原因，例如 --atomic-run 回滚或者超过了修改上限
+    /// 这时没有文件被修改，不输出统计和改动报告
+    ///
+    pub error: Option<String>,
+    ///
+    /// 输出哪些内容
+    ///
+    pub options: SummaryOptions,
+}
+
+///
+/// 输出时需要的 Reporting 和替换计划中的选项
+///
+#[derive(Default)]
+pub struct SummaryOptions {
+    pub stat: bool,
+    pub report: bool,
+    pub resource_usage: bool,
+    pub timings: Option<usize>,
+    pub timings_json: bool,
+    pub max_filesize: Option<u64>,
+    pub max_line_length: Option<usize>,
+}
+
+impl Summary {
+    ///
+    /// 整次运行失败或者有文件处理失败时返回错误
+    ///
+    pub fn result(&self) -> Result<(), String> {
+        if let Some(err) = &self.error {
+            return Err(err.clone());
+        }
+        match self.failed.len() {
+            0 => Ok(()),
+            count => Err(format!("错误: {} 个文件处理失败", count)),
+        }
+    }
+
+    ///
+    /// 按 Reporting 打开的开关输出，统计、改动报告和 JSON 格式的耗时输出到标准输出，其他输出到标准错误
+    /// 不输出 result 返回的错误
+    ///
+    pub fn print(&self) {
+        if let Some(err) = &self.cache_error {
+            eprintln!("警告: {}", err);
+        }
+        if self.error.is_none() {
+            self.print_changes();
+            if let Some(err) = &self.patch_error {
+                eprintln!("错误: {}", err);
+            }
+        }
+        if !self.failed.is_empty() {
+            eprintln!("处理失败的文件:");
+            for (file, err) in &self.failed {
+                eprintln!("  {}: {}", paths::to_host(file).display(), err.label());
+            }
+        }
+        if self.options.resource_usage {
+            self.usage.print();
+        }
+        if let Some(top) = self.options.timings {
+            if self.options.timings_json {
+                println!("{}", self.timings.to_json(top, self.usage.elapsed));
+            } else {
+                self.timings.print(top, self.usage.elapsed);
+            }
+        }
+    }
+
+    ///
+    /// 输出停止请求、改动报告、统计和跳过的文件
+    ///
+    fn print_changes(&self) {
+        if let Some((done, total)) = self.stopped {
+            match total {
+                Some(total) => eprintln!(
+                    "收到停止请求，已处理 {} 个文件，还有 {} 个文件没有处理",
+                    done,
+                    total - done
+                ),
+                None => eprintln!("收到停止请求，已处理 {} 个文件", done),
+            }
+        }
+        if self.options.report {
+            report::print(&self.reports);
+        }
+        if self.options.stat {
+            stat::print(&self.stats);
+        }
+        let print_skipped = |reason: String, kind: &str| {
+            let files: Vec<_> = self
+                .skipped
+                .iter()
+                .filter(|(_, error)| error.kind() == kind)
+                .collect();
+            if files.is_empty() {
+                return;
+            }
+            eprintln!("跳过了 {} 个{}的文件:", files.len(), reason);
+            for (file, _) in files {
+                eprintln!("  {}", paths::to_host(file).display());
+            }
+        };
+        if let Some(max_filesize) = self.options.max_filesize {
+            print_skipped(
+                format!("超过 {} ", metrics::format_bytes(max_filesize)),
+                "too_large",
+            );
+        }
+        if let Some(max_line_length) = self.options.max_line_length {
+            print_skipped(
+                format!("有超过 {} 字节的行", max_line_length),
+                "line_too_long",
+            );
+        }
+        print_skipped("不是有效 UTF-8 ".to_string(), "not_utf8");
+        print_skipped("生成".to_string(), "generated");
+    }
+}
@@ -0,0 +1,792 @@
+use clap::ValueEnum;
+use heck::{ToKebabCase, ToLowerCamelCase, ToShoutySnakeCase, ToSnakeCase, ToUpperCamelCase};
+use regex::{bytes, Captures, Regex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::exec::ExecCommand;
+use crate::script::Script;
+
+///
+/// {{now}}、{{uuid}} 这类生成型占位符的取值范围
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TokenScope {
+    ///
+    /// 整次运行使用同一个值
+    ///
+    Run,
+    ///
+    /// 每次匹配重新生成
+    ///
+    Match,
+}
+
+///
+/// 整次运行共享的占位符取值
+///
+#[derive(Debug, Clone)]
+pub struct Tokens {
+    scope: TokenScope,
+    now: SystemTime,
+    uuid: String,
+    ///
+    /// 是否允许 ${env:VAR} 读取环境变量
+    ///
+    env: bool,
+}
+
+impl Tokens {
+    pub fn new(scope: TokenScope) -> Self {
+        Self {
+            scope,
+            now: SystemTime::now(),
+            uuid: uuid_v4(),
+            env: false,
+        }
+    }
+
+    ///
+    /// 允许替换字符串通过 ${env:VAR} 读取环境变量
+    ///
+    pub fn env(mut self, env: bool) -> Self {
+        self.env = env;
+        self
+    }
+}
+
+///
+/// 替换模板的组成部分
+///
+#[derive(Debug, Clone)]
+enum Part {
+    Literal(String),
+    ///
+    /// 捕获组引用，解析时已经把组名换算成下标
+    /// 可以带若干个依次应用的函数，例如 ${1|trim|upper}
+    ///
+    Group(usize, Vec<Filter>),
+    ///
+    /// {{now:格式}}，按匹配生成当前时间
+    ///
+    Now(String),
+    ///
+    /// {{uuid}}，按匹配生成 uuid
+    ///
+    Uuid,
+    ///
+    /// \U、\L、\E，切换后续内容的大小写
+    ///
+    Case(CaseMode),
+    ///
+    /// --exec-replace，外部命令的输出
+    ///
+    Exec(ExecCommand),
+    ///
+    /// --script，表达式的值
+    ///
+    Script(Script),
+}
+
+///
+/// 模板函数
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    Upper,
+    Lower,
+    Trim,
+    SnakeCase,
+    CamelCase,
+    PascalCase,
+    KebabCase,
+    ScreamingSnakeCase,
+    Base64,
+    ///
+    /// 十进制转十六进制
+    ///
+    Hex,
+    ///
+    /// 十六进制（可以带 0x 前缀）转十进制
+    ///
+    Dec,
+    ///
+    /// ${1:+1} 这类算术运算，运算符和右侧的整数
+    ///
+    Arithmetic(char, i64),
+}
+
+impl Filter {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name.trim() {
+            "upper" => Ok(Self::Upper),
+            "lower" => Ok(Self::Lower),
+            "trim" => Ok(Self::Trim),
+            "snake_case" => Ok(Self::SnakeCase),
+            "camel_case" => Ok(Self::CamelCase),
+            "pascal_case" => Ok(Self::PascalCase),
+            "kebab_case" => Ok(Self::KebabCase),
+            "screaming_snake_case" => Ok(Self::ScreamingSnakeCase),
+            "base64" => Ok(Self::Base64),
+            "hex" => Ok(Self::Hex),
+            "dec" => Ok(Self::Dec),
+            other => Err(format!("未知的模板函数: {}", other)),
+        }
+    }
+
+    ///
+    /// 解析 ${1:+1} 中冒号之后的算术运算：+、-、*、/、% 加一个整数
+    ///
+    pub fn parse_arithmetic(expr: &str) -> Result<Self, String> {
+        let expr = expr.trim();
+        let mut chars = expr.chars();
+        let op = chars.next().unwrap_or_default();
+        if !matches!(op, '+' | '-' | '*' | '/' | '%') {
+            return Err(format!("无效的算术运算: {}", expr));
+        }
+        let operand = chars
+            .as_str()
+            .trim()
+            .parse::<i64>()
+            .map_err(|_| format!("无效的算术运算: {}", expr))?;
+        if operand == 0 && matches!(op, '/' | '%') {
+            return Err(format!("算术运算中除数为 0: {}", expr));
+        }
+        Ok(Self::Arithmetic(op, operand))
+    }
+
+    pub fn apply(&self, text: &str) -> String {
+        match self {
+            Self::Upper => text.to_uppercase(),
+            Self::Lower => text.to_lowercase(),
+            Self::Trim => text.trim().to_string(),
+            Self::SnakeCase => text.to_snake_case(),
+            Self::CamelCase => text.to_lower_camel_case(),
+            Self::PascalCase => text.to_upper_camel_case(),
+            Self::KebabCase => text.to_kebab_case(),
+            Self::ScreamingSnakeCase => text.to_shouty_snake_case(),
+            Self::Base64 => base64_encode(text.as_bytes()),
+            Self::Hex => map_number(text, 10, |number| Some(format!("{:x}", number))),
+            Self::Dec => {
+                let digits = text.trim();
+                let digits = digits
+                    .strip_prefix("0x")
+                    .or_else(|| digits.strip_prefix("0X"))
+                    .unwrap_or(digits);
+                map_number(digits, 16, |number| Some(number.to_string()))
+            }
+            Self::Arithmetic(op, operand) => map_number(text, 10, |number| {
+                let result = match op {
+                    '+' => number.checked_add(*operand),
+                    '-' => number.checked_sub(*operand),
+                    '*' => number.checked_mul(*operand),
+                    '/' => number.checked_div(*operand),
+                    _ => number.checked_rem(*operand),
+                }?;
+                //
+                // 带前导零的数字保持原来的宽度，例如 007 加 1 得到 008
+                //
+                let digits = text.trim().trim_start_matches('-');
+                let width = if digits.len() > 1 && digits.starts_with('0') {
+                    digits.len()
+                } else {
+                    0
+                };
+                Some(if result < 0 {
+                    format!("-{:0width$}", result.unsigned_abs())
+                } else {
+                    format!("{:0width$}", result)
+                })
+            }),
+        }
+    }
+}
+
+///
+/// 把文本按 radix 进制解析成整数后转换，不是整数或者转换失败时保持原文
+///
+fn map_number(text: &str, radix: u32, convert: impl Fn(i64) -> Option<String>) -> String {
+    i64::from_str_radix(text.trim(), radix)
+        .ok()
+        .and_then(convert)
+        .unwrap_or_else(|| text.to_string())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CaseMode {
+    Keep,
+    Upper,
+    Lower,
+}
+
+///
+/// 替换模板
+/// 语法与 regex 的替换字符串一致：$1、$name、${name}，$$ 表示 $ 本身
+/// 区别在于解析时会校验所有引用的捕获组都存在于正则中，
+/// 而不是在替换时悄悄替换成空字符串
+///
+/// 另外支持两个生成型占位符：
+/// {{now}} / {{now:%Y-%m-%d}}：当前 UTC 时间，默认格式为 %Y-%m-%dT%H:%M:%SZ
+/// {{uuid}}：随机 uuid (v4)
+/// 其他 {{...}} 按字面量处理
+///
+/// 开启 --env 时，${env:VAR} 替换成环境变量 VAR 的值，同样可以带模板函数，例如 ${env:VERSION|trim}
+/// 环境变量在解析时读取，不存在时报错
+///
+/// 捕获组可以带模板函数，用 | 串联，例如 ${1|upper}、${name|trim|snake_case}
+/// 可用的函数：upper、lower、trim、snake_case、camel_case、pascal_case、
+/// kebab_case、screaming_snake_case、base64、hex（十进制转十六进制）、dec（十六进制转十进制）
+/// 不是整数的捕获组在 hex、dec 和算术运算中保持原文
+///
+/// 捕获组后面用冒号接算术运算，例如 ${1:+1}、${major:*2}，支持 +、-、*、/、%，
+/// 运算在模板函数之前进行，例如 ${1:+1|hex}
+///
+/// 以及 sed/perl 风格的大小写切换：
+/// \U 之后的内容转成大写，\L 之后的内容转成小写，\E 结束转换
+/// 例如 \U$1\E_suffix
+///
+/// 开启 --preserve-case 时，整个替换结果再按匹配文本的大小写形式调整，见 match_case
+///
+#[derive(Debug, Clone)]
+pub struct Template {
+    parts: Vec<Part>,
+    preserve_case: bool,
+}
+
+impl Template {
+    pub fn parse(replacement: &str, re: &Regex, tokens: &Tokens) -> Result<Self, String> {
+        let mut parts = Vec::new();
+
+        //
+        // 先按 \U、\L、\E 切开，其余的转义序列交给 unescape 处理
+        //
+        let mut segment = String::new();
+        let mut chars = replacement.chars();
+        while let Some(ch) = chars.next() {
+            if ch != '\\' {
+                segment.push(ch);
+                continue;
+            }
+            let mode = match chars.next() {
+                Some('U') => CaseMode::Upper,
+                Some('L') => CaseMode::Lower,
+                Some('E') => CaseMode::Keep,
+                Some(next) => {
+                    segment.push('\\');
+                    segment.push(next);
+                    continue;
+                }
+                None => {
+                    segment.push('\\');
+                    continue;
+                }
+            };
+            parse_segment(&std::mem::take(&mut segment), re, tokens, &mut parts)?;
+            parts.push(Part::Case(mode));
+        }
+        parse_segment(&segment, re, tokens, &mut parts)?;
+
+        Ok(Self {
+            parts,
+            preserve_case: false,
+        })
+    }
+
+    ///
+    /// 由外部命令生成替换结果的模板
+    ///
+    pub fn exec(command: &str, re: &Regex) -> Self {
+        Self {
+            parts: vec![Part::Exec(ExecCommand::new(command, re))],
+            preserve_case: false,
+        }
+    }
+
+    ///
+    /// 由 --script 的表达式计算替换结果的模板
+    ///
+    pub fn script(source: &str, re: &Regex) -> Result<Self, String> {
+        Ok(Self {
+            parts: vec![Part::Script(Script::parse(source, re)?)],
+            preserve_case: false,
+        })
+    }
+
+    pub fn set_preserve_case(&mut self, preserve_case: bool) {
+        self.preserve_case = preserve_case;
+    }
+}
+
+///
+/// 按匹配文本的大小写形式调整替换结果
+/// 全大写（至少两个字母）-> 全大写，首字母大写其余小写 -> 首字母大写，全小写 -> 全小写，
+/// 其他形式（例如 camelCase）或者没有字母时保持替换结果不变
+///
+fn match_case(text: &str, matched: &str) -> Option<String> {
+    let letters: Vec<char> = matched.chars().filter(|ch| ch.is_alphabetic()).collect();
+    let (first, rest) = letters.split_first()?;
+    if rest.iter().all(|ch| ch.is_lowercase()) {
+        if first.is_lowercase() {
+            return Some(text.to_lowercase());
+        }
+        if !rest.is_empty() || first.is_uppercase() {
+            let mut chars = text.chars();
+            return chars
+                .next()
+                .map(|head| head.to_uppercase().chain(chars).collect());
+        }
+    }
+    if letters.len() > 1 && letters.iter().all(|ch| ch.is_uppercase()) {
+        return Some(text.to_uppercase());
+    }
+    None
+}
+
+///
+/// 解析不含大小写切换的一段替换字符串
+///
+fn parse_segment(
+    segment: &str,
+    re: &Regex,
+    tokens: &Tokens,
+    parts: &mut Vec<Part>,
+) -> Result<(), String> {
+    let segment = unescape::unescape(segment).ok_or_else(|| "目标字符串转义失败".to_string())?;
+    let mut literal = String::new();
+    let mut rest = segment.as_str();
+
+    while let Some(index) = rest.find(['$', '{']) {
+        literal.push_str(&rest[..index]);
+        rest = &rest[index..];
+
+        if rest.starts_with('{') {
+            match parse_token(rest, tokens)? {
+                Some((part, after)) => {
+                    match part {
+                        Part::Literal(text) => literal.push_str(&text),
+                        part => {
+                            if !literal.is_empty() {
+                                parts.push(Part::Literal(std::mem::take(&mut literal)));
+                            }
+                            parts.push(part);
+                        }
+                    }
+                    rest = after;
+                }
+                None => {
+                    literal.push('{');
+                    rest = &rest[1..];
+                }
+            }
+            continue;
+        }
+
+        rest = &rest[1..];
+
+        if let Some(after) = rest.strip_prefix('$') {
+            literal.push('$');
+            rest = after;
+            continue;
+        }
+
+        let (name, after) = if let Some(braced) = rest.strip_prefix('{') {
+            match braced.find('}') {
+                Some(end) => (&braced[..end], &braced[end + 1..]),
+                None => return Err(format!("替换字符串中的 ${{ 没有闭合: {}", segment)),
+            }
+        } else {
+            let end = rest
+                .find(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '_'))
+                .unwrap_or(rest.len());
+            (&rest[..end], &rest[end..])
+        };
+
+        //
+        // 单独的 $ 后面没有组名，按字面量处理，与 regex 的行为保持一致
+        //
+        if name.is_empty() {
+            literal.push('$');
+            continue;
+        }
+
+        let mut pipeline = name.split('|');
+        let name = pipeline.next().unwrap_or_default().trim();
+        let filters = pipeline.map(Filter::parse).collect::<Result<Vec<_>, _>>()?;
+
+        if let Some(var) = name.strip_prefix("env:") {
+            let value = env_var(var, tokens)?;
+            literal.push_str(
+                &filters
+                    .iter()
+                    .fold(value, |text, filter| filter.apply(&text)),
+            );
+            rest = after;
+            continue;
+        }
+
+        let (name, filters) = match name.split_once(':') {
+            Some((name, expr)) => {
+                let mut all = vec![Filter::parse_arithmetic(expr)?];
+                all.extend(filters);
+                (name.trim(), all)
+            }
+            None => (name, filters),
+        };
+
+        if !literal.is_empty() {
+            parts.push(Part::Literal(std::mem::take(&mut literal)));
+        }
+        parts.push(Part::Group(resolve_group(name, re)?, filters));
+        rest = after;
+    }
+    literal.push_str(rest);
+    if !literal.is_empty() {
+        parts.push(Part::Literal(literal));
+    }
+
+    Ok(())
+}
+
+///
+/// 读取 ${env:VAR} 引用的环境变量
+///
+fn env_var(var: &str, tokens: &Tokens) -> Result<String, String> {
+    if !tokens.env {
+        return Err(format!(
+            "替换字符串中的 ${{env:{}}} 需要指定 --env 才能读取环境变量",
+            var
+        ));
+    }
+    match std::env::var(var) {
+        Ok(value) => Ok(value),
+        Err(std::env::VarError::NotPresent) => Err(format!("环境变量 {} 不存在", var)),
+        Err(std::env::VarError::NotUnicode(_)) => {
+            Err(format!("环境变量 {} 的值不是有效的 UTF-8", var))
+        }
+    }
+}
+
+///
+/// 解析 {{now}}、{{now:格式}}、{{uuid}}
+/// 不是已知占位符时返回 None，调用方按字面量处理
+/// 运行级别的占位符直接求值成字面量
+///
+fn parse_token<'a>(rest: &'a str, tokens: &Tokens) -> Result<Option<(Part, &'a str)>, String> {
+    let Some(inner) = rest.strip_prefix("{{") else {
+        return Ok(None);
+    };
+    let Some(end) = inner.find("}}") else {
+        return Ok(None);
+    };
+    let (token, after) = (&inner[..end], &inner[end + 2..]);
+
+    let part = if token == "uuid" {
+        match tokens.scope {
+            TokenScope::Run => Part::Literal(tokens.uuid.clone()),
+            TokenScope::Match => Part::Uuid,
+        }
+    } else if token == "now" || token.starts_with("now:") {
+        let format = token
+            .strip_prefix("now:")
+            .unwrap_or("%Y-%m-%dT%H:%M:%SZ")
+            .to_string();
+        //
+        // 先格式化一次，提前发现不支持的格式
+        //
+        let formatted = format_time(tokens.now, &format)?;
+        match tokens.scope {
+            TokenScope::Run => Part::Literal(formatted),
+            TokenScope::Match => Part::Now(format),
+        }
+    } else {
+        return Ok(None);
+    };
+
+    Ok(Some((part, after)))
+}
+
+///
+/// 按 strftime 的常用子集格式化 UTC 时间
+/// 支持 %Y %y %m %d %H %M %S %j %s %F %T %%
+///
+fn format_time(time: SystemTime, format: &str) -> Result<String, String> {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    let days = (secs / 86400) as i64;
+    let seconds_of_day = secs % 86400;
+    let (year, month, day) = civil_from_days(days);
+    let (hour, minute, second) = (
+        seconds_of_day / 3600,
+        seconds_of_day % 3600 / 60,
+        seconds_of_day % 60,
+    );
+    let day_of_year = days - days_from_civil(year, 1, 1) + 1;
+
+    let mut output = String::new();
+    let mut chars = format.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '%' {
+            output.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => output.push_str(&format!("{:04}", year)),
+            Some('y') => output.push_str(&format!("{:02}", year % 100)),
+            Some('m') => output.push_str(&format!("{:02}", month)),
+            Some('d') => output.push_str(&format!("{:02}", day)),
+            Some('H') => output.push_str(&format!("{:02}", hour)),
+            Some('M') => output.push_str(&format!("{:02}", minute)),
+            Some('S') => output.push_str(&format!("{:02}", second)),
+            Some('j') => output.push_str(&format!("{:03}", day_of_year)),
+            Some('s') => output.push_str(&secs.to_string()),
+            Some('F') => output.push_str(&format!("{:04}-{:02}-{:02}", year, month, day)),
+            Some('T') => output.push_str(&format!("{:02}:{:02}:{:02}", hour, minute, second)),
+            Some('%') => output.push('%'),
+            Some(other) => return Err(format!("{{{{now}}}} 不支持的时间格式 %{}", other)),
+            None => return Err("{{now}} 的时间格式不能以单独的 % 结尾".to_string()),
+        }
+    }
+    Ok(output)
+}
+
+///
+/// 从 1970-01-01 起的天数换算成年月日
+/// 算法来自 http://howardhinnant.github.io/date_algorithms.html
+///
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let month = month as i64;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+///
+/// 标准 base64 编码（带填充）
+///
+fn base64_encode(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut output = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        output.push(ALPHABET[(n >> 18) as usize & 63] as char);
+        output.push(ALPHABET[(n >> 12) as usize & 63] as char);
+        output.push(if chunk.len() > 1 {
+            ALPHABET[(n >> 6) as usize & 63] as char
+        } else {
+            '='
+        });
+        output.push(if chunk.len() > 2 {
+            ALPHABET[n as usize & 63] as char
+        } else {
+            '='
+        });
+    }
+    output
+}
+
+///
+/// 生成随机 uuid (v4)
+///
+fn uuid_v4() -> String {
+    let mut bytes = [0u8; 16];
+    for byte in bytes.iter_mut() {
+        *byte = fastrand::u8(..);
+    }
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+///
+/// 把组名或者组序号换算成捕获组下标
+///
+pub fn resolve_group(name: &str, re: &Regex) -> Result<usize, String> {
+    if let Ok(index) = name.parse::<usize>() {
+        if index < re.captures_len() {
+            return Ok(index);
+        }
+        return Err(format!(
+            "替换字符串引用了不存在的捕获组 ${}，正则只有 {} 个捕获组",
+            name,
+            re.captures_len() - 1
+        ));
+    }
+
+    re.capture_names()
+        .position(|group| group == Some(name))
+        .ok_or_else(|| format!("替换字符串引用了不存在的命名捕获组 ${{{}}}", name))
+}
+
+impl Template {
+    ///
+    /// 把一个匹配的替换结果追加到 dst，line 是匹配所在的行号
+    ///
+    pub fn append(&self, caps: &Captures<'_>, line: usize, dst: &mut String) {
+        let replacement_start = dst.len();
+        let mut mode = CaseMode::Keep;
+        for part in &self.parts {
+            let start = dst.len();
+            match part {
+                Part::Literal(text) => dst.push_str(text),
+                Part::Group(index, filters) => {
+                    if let Some(m) = caps.get(*index) {
+                        match filters.as_slice() {
+                            [] => dst.push_str(m.as_str()),
+                            filters => {
+                                let value = filters
+                                    .iter()
+                                    .fold(m.as_str().to_string(), |value, filter| {
+                                        filter.apply(&value)
+                                    });
+                                dst.push_str(&value);
+                            }
+                        }
+                    }
+                }
+                Part::Now(format) => {
+                    //
+                    // 格式在解析时已经校验过
+                    //
+                    if let Ok(formatted) = format_time(SystemTime::now(), format) {
+                        dst.push_str(&formatted);
+                    }
+                }
+                Part::Uuid => dst.push_str(&uuid_v4()),
+                Part::Case(next) => mode = *next,
+                //
+                // 命令失败时保留匹配的原文，不让一次失败把文本替换成空
+                //
+                Part::Exec(command) => match command.run(caps) {
+                    Ok(output) => dst.push_str(&output),
+                    Err(err) => {
+                        eprintln!("错误: {}", err);
+                        dst.push_str(&caps[0]);
+                    }
+                },
+                Part::Script(script) => match script.run(caps, line) {
+                    Ok(output) => dst.push_str(&output),
+                    Err(err) => {
+                        eprintln!("错误: 脚本执行失败: {}", err);
+                        dst.push_str(&caps[0]);
+                    }
+                },
+            }
+
+            let converted = match mode {
+                CaseMode::Keep => continue,
+                CaseMode::Upper => dst[start..].to_uppercase(),
+                CaseMode::Lower => dst[start..].to_lowercase(),
+            };
+            dst.truncate(start);
+            dst.push_str(&converted);
+        }
+
+        if self.preserve_case {
+            if let Some(converted) = match_case(&dst[replacement_start..], &caps[0]) {
+                dst.truncate(replacement_start);
+                dst.push_str(&converted);
+            }
+        }
+    }
+
+    ///
+    /// --bytes 时把一个匹配的替换结果追加到 dst
+    /// 捕获组按原样复制字节；模板函数、大小写切换和 --preserve-case 只转换有效的 UTF-8，
+    /// 大小写切换遇到无效的 UTF-8 时只转换 ASCII 字母
+    ///
+    pub fn append_bytes(&self, caps: &bytes::Captures<'_>, dst: &mut Vec<u8>) {
+        let replacement_start = dst.len();
+        let mut mode = CaseMode::Keep;
+        for part in &self.parts {
+            let start = dst.len();
+            match part {
+                Part::Literal(text) => dst.extend_from_slice(text.as_bytes()),
+                Part::Group(index, filters) => {
+                    if let Some(m) = caps.get(*index) {
+                        match std::str::from_utf8(m.as_bytes()) {
+                            Ok(value) if !filters.is_empty() => {
+                                let value = filters
+                                    .iter()
+                                    .fold(value.to_string(), |value, filter| filter.apply(&value));
+                                dst.extend_from_slice(value.as_bytes());
+                            }
+                            _ => dst.extend_from_slice(m.as_bytes()),
+                        }
+                    }
+                }
+                Part::Now(format) => {
+                    if let Ok(formatted) = format_time(SystemTime::now(), format) {
+                        dst.extend_from_slice(formatted.as_bytes());
+                    }
+                }
+                Part::Uuid => dst.extend_from_slice(uuid_v4().as_bytes()),
+                Part::Case(next) => mode = *next,
+                //
+                // --bytes 不能和 --exec-replace、--script 一起使用，保留匹配的原文
+                //
+                Part::Exec(_) | Part::Script(_) => dst.extend_from_slice(&caps[0]),
+            }
+
+            let converted = match (mode, std::str::from_utf8(&dst[start..])) {
+                (CaseMode::Keep, _) => continue,
+                (CaseMode::Upper, Ok(text)) => text.to_uppercase(),
+                (CaseMode::Lower, Ok(text)) => text.to_lowercase(),
+                (CaseMode::Upper, Err(_)) => {
+                    dst[start..].make_ascii_uppercase();
+                    continue;
+                }
+                (CaseMode::Lower, Err(_)) => {
+                    dst[start..].make_ascii_lowercase();
+                    continue;
+                }
+            };
+            dst.truncate(start);
+            dst.extend_from_slice(converted.as_bytes());
+        }
+
+        if self.preserve_case {
+            let text = std::str::from_utf8(&dst[replacement_start..]);
+            let matched = std::str::from_utf8(&caps[0]);
+            if let Some(converted) = text
+                .ok()
+                .zip(matched.ok())
+                .and_then(|(text, matched)| match_case(text, matched))
+            {
+                dst.truncate(replacement_start);
+                dst.extend_from_slice(converted.as_bytes());
+            }
+        }
+    }
+}
@@ -0,0 +1,678 @@
+//!
+//! 配置文件和规则清单使用的 TOML 子集解析器
+//! 支持：注释、[表]、[[表数组]]、键值对、字符串（基本、字面量、多行）、整数、布尔值、
+//! 数组（可以跨行）、内联表
+//! 每个值都记录所在的行号，方便给出准确的错误信息
+//!
+
+use std::fmt;
+use std::ops::Range;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<Spanned>),
+    Table(Table),
+}
+
+impl Value {
+    ///
+    /// 类型名，用于错误信息
+    ///
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "字符串",
+            Value::Integer(_) => "整数",
+            Value::Boolean(_) => "布尔值",
+            Value::Array(_) => "数组",
+            Value::Table(_) => "表",
+        }
+    }
+}
+
+///
+/// 带行号的值
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spanned {
+    pub value: Value,
+    pub line: usize,
+    ///
+    /// 值在原文中的字节范围，字符串包含引号；隐式创建的表为空范围
+    ///
+    pub span: Range<usize>,
+}
+
+///
+/// 保持书写顺序的表
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Table {
+    pub entries: Vec<(String, Spanned)>,
+}
+
+impl Table {
+    pub fn get(&self, key: &str) -> Option<&Spanned> {
+        self.entries
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value)
+    }
+
+    fn get_mut(&mut self, key: &str) -> Option<&mut Spanned> {
+        self.entries
+            .iter_mut()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "第 {} 行: {}", self.line, self.message)
+    }
+}
+
+pub fn parse(source: &str) -> Result<Table, Error> {
+    Parser {
+        chars: source.chars().collect(),
+        offsets: source
+            .char_indices()
+            .map(|(offset, _)| offset)
+            .chain([source.len()])
+            .collect(),
+        position: 0,
+        line: 1,
+    }
+    .parse_document()
+}
+
+struct Parser {
+    chars: Vec<char>,
+    ///
+    /// 每个字符的字节偏移，最后多一项是文本长度
+    ///
+    offsets: Vec<usize>,
+    position: usize,
+    line: usize,
+}
+
+impl Parser {
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, Error> {
+        Err(Error {
+            line: self.line,
+            message: message.into(),
+        })
+    }
+
+    ///
+    /// 从 start 个字符到当前位置的字节范围
+    ///
+    fn span_from(&self, start: usize) -> Range<usize> {
+        self.offsets[start]..self.offsets[self.position]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.position + offset).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let ch = self.peek()?;
+        self.position += 1;
+        if ch == '\n' {
+            self.line += 1;
+        }
+        Some(ch)
+    }
+
+    fn starts_with(&self, text: &str) -> bool {
+        text.chars()
+            .enumerate()
+            .all(|(offset, ch)| self.peek_at(offset) == Some(ch))
+    }
+
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.next();
+        }
+    }
+
+    ///
+    /// 跳过空白、换行和注释，用于数组内部
+    ///
+    fn skip_whitespace_and_comments(&mut self) {
+        loop {
+            match self.peek() {
+                Some(' ' | '\t' | '\r' | '\n') => {
+                    self.next();
+                }
+                Some('#') => self.skip_comment(),
+                _ => break,
+            }
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        while !matches!(self.peek(), None | Some('\n')) {
+            self.next();
+        }
+    }
+
+    ///
+    /// 一行的剩余部分只能是空白和注释
+    ///
+    fn expect_line_end(&mut self) -> Result<(), Error> {
+        self.skip_spaces();
+        if self.peek() == Some('#') {
+            self.skip_comment();
+        }
+        match self.peek() {
+            None => Ok(()),
+            Some('\n') => {
+                self.next();
+                Ok(())
+            }
+            Some('\r') if self.peek_at(1) == Some('\n') => {
+                self.next();
+                self.next();
+                Ok(())
+            }
+            Some(ch) => self.error(format!("行尾有多余的内容: {:?}", ch)),
+        }
+    }
+
+    fn parse_document(&mut self) -> Result<Table, Error> {
+        let mut root = Table::default();
+        //
+        // 当前所在的表，用从根开始的键路径表示
+        // 表数组的元素总是取最后一个
+        //
+        let mut current: Vec<String> = Vec::new();
+
+        loop {
+            self.skip_whitespace_and_comments();
+            let Some(ch) = self.peek() else {
+                break;
+            };
+
+            if ch == '[' {
+                let line = self.line;
+                let start = self.position;
+                let is_array = self.peek_at(1) == Some('[');
+                self.next();
+                if is_array {
+                    self.next();
+                }
+                self.skip_spaces();
+                let path = self.parse_key_path()?;
+                self.skip_spaces();
+                let closing = if is_array { "]]" } else { "]" };
+                if !self.starts_with(closing) {
+                    return self.error(format!("表头缺少 {}", closing));
+                }
+                for _ in 0..closing.len() {
+                    self.next();
+                }
+                let span = self.span_from(start);
+                self.expect_line_end()?;
+
+                let (last, parents) = path.split_last().unwrap();
+                let parent = self.table_at(&mut root, parents, line, span.start)?;
+                if is_array {
+                    let entry = Spanned {
+                        value: Value::Table(Table::default()),
+                        line,
+                        span: span.clone(),
+                    };
+                    match parent.get_mut(last) {
+                        Some(Spanned {
+                            value: Value::Array(items),
+                            ..
+                        }) => items.push(entry),
+                        Some(_) => return self.error(format!("{} 已经定义为其他类型", last)),
+                        None => parent.entries.push((
+                            last.clone(),
+                            Spanned {
+                                value: Value::Array(vec![entry]),
+                                line,
+                                span,
+                            },
+                        )),
+                    }
+                } else {
+                    match parent.get(last) {
+                        Some(Spanned {
+                            value: Value::Table(_),
+                            ..
+                        }) => {}
+                        Some(_) => return self.error(format!("{} 已经定义为其他类型", last)),
+                        None => parent.entries.push((
+                            last.clone(),
+                            Spanned {
+                                value: Value::Table(Table::default()),
+                                line,
+                                span,
+                            },
+                        )),
+                    }
+                }
+                current = path;
+                continue;
+            }
+
+            let line = self.line;
+            let offset = self.offsets[self.position];
+            let key = self.parse_key()?;
+            self.skip_spaces();
+            if self.next() != Some('=') {
+                return self.error(format!("键 {} 后面缺少 =", key));
+            }
+            self.skip_spaces();
+            let value = self.parse_value()?;
+            self.expect_line_end()?;
+
+            let table = self.table_at(&mut root, &current, line, offset)?;
+            if table.get(&key).is_some() {
+                return Err(Error {
+                    line,
+                    message: format!("重复的键 {}", key),
+                });
+            }
+            table.entries.push((key, value));
+        }
+
+        Ok(root)
+    }
+
+    ///
+    /// 按键路径找到表，表数组取最后一个元素
+    /// 不存在的表在 offset 处隐式创建
+    ///
+    fn table_at<'t>(
+        &self,
+        root: &'t mut Table,
+        path: &[String],
+        line: usize,
+        offset: usize,
+    ) -> Result<&'t mut Table, Error> {
+        let mut table = root;
+        for key in path {
+            if table.get(key).is_none() {
+                table.entries.push((
+                    key.clone(),
+                    Spanned {
+                        value: Value::Table(Table::default()),
+                        line,
+                        span: offset..offset,
+                    },
+                ));
+            }
+            table = match table.get_mut(key).map(|spanned| &mut spanned.value) {
+                Some(Value::Table(table)) => table,
+                Some(Value::Array(items)) => match items.last_mut().map(|item| &mut item.value) {
+                    Some(Value::Table(table)) => table,
+                    _ => {
+                        return Err(Error {
+                            line,
+                            message: format!("{} 不是表数组", key),
+                        })
+                    }
+                },
+                _ => {
+                    return Err(Error {
+                        line,
+                        message: format!("{} 不是表", key),
+                    })
+                }
+            };
+        }
+        Ok(table)
+    }
+
+    fn parse_key_path(&mut self) -> Result<Vec<String>, Error> {
+        let mut path = vec![self.parse_key()?];
+        loop {
+            self.skip_spaces();
+            if self.peek() != Some('.') {
+                break;
+            }
+            self.next();
+            self.skip_spaces();
+            path.push(self.parse_key()?);
+        }
+        Ok(path)
+    }
+
+    fn parse_key(&mut self) -> Result<String, Error> {
+        match self.peek() {
+            Some('"') => self.parse_basic_string(),
+            Some('\'') => self.parse_literal_string(),
+            _ => {
+                let mut key = String::new();
+                while let Some(ch) = self.peek() {
+                    if ch.is_ascii_alphanumeric() || ch == '_' || ch == '-' {
+                        key.push(ch);
+                        self.next();
+                    } else {
+                        break;
+                    }
+                }
+                if key.is_empty() {
+                    return self.error("缺少键名");
+                }
+                Ok(key)
+            }
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Spanned, Error> {
+        let line = self.line;
+        let start = self.position;
+        let value = match self.peek() {
+            Some('"') if self.starts_with("\"\"\"") => {
+                Value::String(self.parse_multiline_basic_string()?)
+            }
+            Some('"') => Value::String(self.parse_basic_string()?),
+            Some('\'') if self.starts_with("'''") => {
+                Value::String(self.parse_multiline_literal_string()?)
+            }
+            Some('\'') => Value::String(self.parse_literal_string()?),
+            Some('[') => Value::Array(self.parse_array()?),
+            Some('{') => Value::Table(self.parse_inline_table()?),
+            Some('t') if self.starts_with("true") => {
+                self.position += 4;
+                Value::Boolean(true)
+            }
+            Some('f') if self.starts_with("false") => {
+                self.position += 5;
+                Value::Boolean(false)
+            }
+            Some(ch) if ch.is_ascii_digit() || ch == '-' || ch == '+' => {
+                let mut number = String::new();
+                while let Some(ch) = self.peek() {
+                    if ch.is_ascii_digit() || ch == '-' || ch == '+' || ch == '_' {
+                        if ch != '_' {
+                            number.push(ch);
+                        }
+                        self.next();
+                    } else {
+                        break;
+                    }
+                }
+                match number.parse::<i64>() {
+                    Ok(number) => Value::Integer(number),
+                    Err(_) => return self.error(format!("无效的整数: {}", number)),
+                }
+            }
+            Some(ch) => return self.error(format!("无法识别的值: {:?}", ch)),
+            None => return self.error("缺少值"),
+        };
+        Ok(Spanned {
+            value,
+            line,
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_array(&mut self) -> Result<Vec<Spanned>, Error> {
+        self.next();
+        let mut items = Vec::new();
+        loop {
+            self.skip_whitespace_and_comments();
+            if self.peek() == Some(']') {
+                self.next();
+                return Ok(items);
+            }
+            items.push(self.parse_value()?);
+            self.skip_whitespace_and_comments();
+            match self.next() {
+                Some(',') => {}
+                Some(']') => return Ok(items),
+                _ => return self.error("数组元素之间缺少逗号"),
+            }
+        }
+    }
+
+    fn parse_inline_table(&mut self) -> Result<Table, Error> {
+        self.next();
+        let mut table = Table::default();
+        self.skip_spaces();
+        if self.peek() == Some('}') {
+            self.next();
+            return Ok(table);
+        }
+        loop {
+            self.skip_spaces();
+            let key = self.parse_key()?;
+            self.skip_spaces();
+            if self.next() != Some('=') {
+                return self.error(format!("键 {} 后面缺少 =", key));
+            }
+            self.skip_spaces();
+            let value = self.parse_value()?;
+            if table.get(&key).is_some() {
+                return self.error(format!("重复的键 {}", key));
+            }
+            table.entries.push((key, value));
+            self.skip_spaces();
+            match self.next() {
+                Some(',') => {}
+                Some('}') => return Ok(table),
+                _ => return self.error("内联表缺少 , 或 }"),
+            }
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<char, Error> {
+        match self.next() {
+            Some('n') => Ok('\n'),
+            Some('t') => Ok('\t'),
+            Some('r') => Ok('\r'),
+            Some('b') => Ok('\u{0008}'),
+            Some('f') => Ok('\u{000C}'),
+            Some('"') => Ok('"'),
+            Some('\\') => Ok('\\'),
+            Some(kind @ ('u' | 'U')) => {
+                let length = if kind == 'u' { 4 } else { 8 };
+                let mut hex = String::new();
+                for _ in 0..length {
+                    match self.next() {
+                        Some(ch) => hex.push(ch),
+                        None => return self.error("字符串没有闭合"),
+                    }
+                }
+                match u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                    Some(ch) => Ok(ch),
+                    None => self.error(format!("无效的 unicode 转义: \\{}{}", kind, hex)),
+                }
+            }
+            Some(ch) => self.error(format!("无效的转义: \\{}", ch)),
+            None => self.error("字符串没有闭合"),
+        }
+    }
+
+    fn parse_basic_string(&mut self) -> Result<String, Error> {
+        self.next();
+        let mut text = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(text),
+                Some('\\') => text.push(self.parse_escape()?),
+                Some('\n') | None => return self.error("字符串没有闭合"),
+                Some(ch) => text.push(ch),
+            }
+        }
+    }
+
+    fn parse_literal_string(&mut self) -> Result<String, Error> {
+        self.next();
+        let mut text = String::new();
+        loop {
+            match self.next() {
+                Some('\'') => return Ok(text),
+                Some('\n') | None => return self.error("字符串没有闭合"),
+                Some(ch) => text.push(ch),
+            }
+        }
+    }
+
+    fn parse_multiline_basic_string(&mut self) -> Result<String, Error> {
+        self.position += 3;
+        self.skip_leading_newline();
+        let mut text = String::new();
+        loop {
+            if self.starts_with("\"\"\"") {
+                self.position += 3;
+                return Ok(text);
+            }
+            match self.next() {
+                Some('\\') => {
+                    //
+                    // 行尾的反斜杠会吃掉换行和下一行开头的空白
+                    //
+                    if matches!(self.peek(), Some(' ' | '\t' | '\r' | '\n')) {
+                        while matches!(self.peek(), Some(' ' | '\t' | '\r' | '\n')) {
+                            self.next();
+                        }
+                    } else {
+                        text.push(self.parse_escape()?);
+                    }
+                }
+                Some(ch) => text.push(ch),
+                None => return self.error("多行字符串没有闭合"),
+            }
+        }
+    }
+
+    fn parse_multiline_literal_string(&mut self) -> Result<String, Error> {
+        self.position += 3;
+        self.skip_leading_newline();
+        let mut text = String::new();
+        loop {
+            if self.starts_with("'''") {
+                self.position += 3;
+                return Ok(text);
+            }
+            match self.next() {
+                Some(ch) => text.push(ch),
+                None => return self.error("多行字符串没有闭合"),
+            }
+        }
+    }
+
+    ///
+    /// 多行字符串紧跟在开头引号后面的换行不算内容
+    ///
+    fn skip_leading_newline(&mut self) {
+        if self.peek() == Some('\r') && self.peek_at(1) == Some('\n') {
+            self.next();
+        }
+        if self.peek() == Some('\n') {
+            self.next();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(table: &Table, key: &str) -> String {
+        match &table.get(key).unwrap().value {
+            Value::String(value) => value.clone(),
+            value => panic!("{} 是{}", key, value.type_name()),
+        }
+    }
+
+    fn table<'a>(table: &'a Table, key: &str) -> &'a Table {
+        match &table.get(key).unwrap().value {
+            Value::Table(table) => table,
+            value => panic!("{} 是{}", key, value.type_name()),
+        }
+    }
+
+    #[test]
+    fn tables_and_arrays_of_tables() {
+        let document = parse(
+            "# 规则\n\
+             name = \"demo\"\n\
+             \n\
+             [options]\n\
+             strict = true\n\
+             jobs = -4\n\
+             \n\
+             [[rule]]\n\
+             pattern = 'a'\n\
+             \n\
+             [[rule]]\n\
+             pattern = 'b' # 注释\n\
+             tags = [\"x\",\n  \"y\",\n]\n\
+             inline = { key = 1 }\n",
+        )
+        .unwrap();
+        assert_eq!(string(&document, "name"), "demo");
+        let options = table(&document, "options");
+        assert_eq!(options.get("strict").unwrap().value, Value::Boolean(true));
+        assert_eq!(options.get("jobs").unwrap().value, Value::Integer(-4));
+        let Value::Array(rules) = &document.get("rule").unwrap().value else {
+            panic!("rule 不是数组");
+        };
+        assert_eq!(rules.len(), 2);
+        let Value::Table(second) = &rules[1].value else {
+            panic!("rule 的元素不是表");
+        };
+        assert_eq!(string(second, "pattern"), "b");
+        assert_eq!(second.get("pattern").unwrap().line, 12);
+        let Value::Array(tags) = &second.get("tags").unwrap().value else {
+            panic!("tags 不是数组");
+        };
+        assert_eq!(tags[1].line, 14);
+        assert_eq!(
+            table(second, "inline").get("key").unwrap().value,
+            Value::Integer(1)
+        );
+    }
+
+    #[test]
+    fn strings() {
+        let source = "basic = \"a\\tb\\u00e9\\\"\"\n\
+                      literal = 'C:\\path'\n\
+                      multi = \"\"\"\nfirst \\\n   second\"\"\"\n\
+                      raw = '''\nline\\n'''\n";
+        let document = parse(source).unwrap();
+        assert_eq!(string(&document, "basic"), "a\tbé\"");
+        assert_eq!(string(&document, "literal"), "C:\\path");
+        assert_eq!(string(&document, "multi"), "first second");
+        assert_eq!(string(&document, "raw"), "line\\n");
+        let span = document.get("literal").unwrap().span.clone();
+        assert_eq!(&source[span], "'C:\\path'");
+    }
+
+    #[test]
+    fn errors_have_line_numbers() {
+        let error = |source: &str| parse(source).unwrap_err();
+        assert_eq!(
+            error("a = 1\na = 2\n"),
+            Error {
+                line: 2,
+                message: "重复的键 a".to_string()
+            }
+        );
+        assert_eq!(error("\n\nname \"x\"\n").line, 3);
+        assert_eq!(error("a = \"open\nb = 1\n").message, "字符串没有闭合");
+        assert_eq!(error("a = [1 2]\n").message, "数组元素之间缺少逗号");
+        assert_eq!(error("a = 1 b\n").message, "行尾有多余的内容: 'b'");
+    }
+}
@@ -0,0 +1,787 @@
+//!
+//! tui 子命令：全屏审阅每一处改动，逐段接受或拒绝，最后只写回接受的改动
+//! 左边是有改动的文件列表，右边是当前文件的改动，删除的行中高亮规则的匹配
+//! 文件列表可以从标准输入读取，按键从 /dev/tty 读取
+//!
+
+use rayon::prelude::*;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::PathBuf;
+
+use crate::error::FileError;
+use crate::install::{self, Fingerprint, Transaction, WriteOptions};
+use crate::report::{hunks, Hunk};
+use crate::{interrupt, paths, replace_in_file, search, stop, validate_replaced, ReplacePlan};
+
+///
+/// 每段改动前后显示的上下文行数
+///
+const CONTEXT: usize = 3;
+
+const RESET: &str = "\x1b[0m";
+const REVERSE: &str = "\x1b[7m";
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const MATCH: &str = "\x1b[1;37;41m";
+
+///
+/// 一个有改动的文件，每段改动默认接受
+///
+struct Change {
+    file: PathBuf,
+    fingerprint: Option<Fingerprint>,
+    original: String,
+    replaced: String,
+    ///
+    /// 原文件中被规则选中的匹配，用来高亮删除的行
+    ///
+    matches: Vec<Range<usize>>,
+    hunks: Vec<Hunk>,
+    accepted: Vec<bool>,
+}
+
+impl Change {
+    fn accepted(&self) -> usize {
+        self.accepted.iter().filter(|accepted| **accepted).count()
+    }
+
+    ///
+    /// 只应用接受的改动之后的内容，没有接受的改动保持原文
+    ///
+    fn contents(&self) -> String {
+        let original = lines(&self.original);
+        let replaced = lines(&self.replaced);
+        let mut contents = String::new();
+        let mut position = 0;
+        for (hunk, accepted) in self.hunks.iter().zip(&self.accepted) {
+            contents.push_str(&original[position..hunk.original.start].concat());
+            if *accepted {
+                contents.push_str(&replaced[hunk.replaced.clone()].concat());
+            } else {
+                contents.push_str(&original[hunk.original.clone()].concat());
+            }
+            position = hunk.original.end;
+        }
+        contents.push_str(&original[position..].concat());
+        contents
+    }
+}
+
+///
+/// 按行切分，保留行尾的换行，拼接起来和原文完全相同
+///
+fn lines(text: &str) -> Vec<&str> {
+    text.split_inclusive('\n').collect()
+}
+
+///
+/// 替换一个文件，只在内存中比较，不写回
+/// 没有改动或者不能审阅的文件返回 None
+///
+fn prepare(file: &PathBuf, plan: &ReplacePlan) -> Option<Change> {
+    let host_file = paths::to_host(file);
+    if let Some(reason) = plan.skip_reason(file) {
+        eprintln!("{:?}: {}", host_file, reason);
+        return None;
+    }
+    match plan.has_match(file) {
+        Ok(true) => {}
+        Ok(false) => return None,
+        Err(err) => {
+            eprintln!("读取文件错误 {:?}: {}", host_file, err);
+            return None;
+        }
+    }
+    if let Some(reason) = plan.skip_matched(file) {
+        eprintln!("{:?}: {}", host_file, reason);
+        return None;
+    }
+    let fingerprint = Fingerprint::of(file).ok();
+    let replaced = match replace_in_file(file, plan) {
+        Ok(replaced) => replaced,
+        Err(err) => {
+            eprintln!("处理文件错误 {:?}: {}", host_file, err);
+            return None;
+        }
+    };
+    let result = fs::read(&replaced.temp_file);
+    let _ = fs::remove_file(&replaced.temp_file);
+    let (original, replaced) = match fs::read(file).and_then(|original| Ok((original, result?))) {
+        Ok(contents) => contents,
+        Err(err) => {
+            eprintln!("读取文件错误 {:?}: {}", host_file, err);
+            return None;
+        }
+    };
+    let (Ok(original), Ok(replaced)) = (String::from_utf8(original), String::from_utf8(replaced))
+    else {
+        eprintln!(
+            "文件 {:?} 不是 UTF-8 编码，不能在 tui 中审阅，跳过",
+            host_file
+        );
+        return None;
+    };
+
+    let hunks = hunks(&lines(&original), &lines(&replaced));
+    if hunks.is_empty() {
+        return None;
+    }
+    let matches = search::search_text(&original, plan)
+        .into_iter()
+        .map(|found| found.start..found.start + found.text.len())
+        .collect();
+    Some(Change {
+        file: file.clone(),
+        fingerprint,
+        accepted: vec![true; hunks.len()],
+        original,
+        replaced,
+        matches,
+        hunks,
+    })
+}
+
+///
+/// 审阅 files 中的改动，确认之后写回接受的改动
+/// 所有接受的改动都写回成功，或者没有写回任何文件就退出时返回 true
+///
+pub fn review(files: &[PathBuf], plan: &ReplacePlan, options: &WriteOptions) -> bool {
+    let mut changes: Vec<Change> = files
+        .par_iter()
+        .filter_map(|file| prepare(file, plan))
+        .collect();
+    if changes.is_empty() {
+        println!("没有需要修改的文件");
+        return true;
+    }
+    changes.sort_by(|a, b| a.file.cmp(&b.file));
+
+    let mut review = Review {
+        changes,
+        file: 0,
+        hunk: 0,
+        scroll: 0,
+        confirming: false,
+    };
+    let apply = match imp::Terminal::open().and_then(|mut terminal| review.run(&mut terminal)) {
+        Ok(apply) => apply,
+        Err(err) => {
+            eprintln!("错误: 无法使用终端: {}", err);
+            return false;
+        }
+    };
+    if !apply {
+        println!("已退出，没有修改任何文件");
+        return true;
+    }
+    write_accepted(&review.changes, plan, options)
+}
+
+///
+/// 写回每个文件中接受的改动，和正常替换一样检查文件是否被修改并按 --validate 检查
+///
+fn write_accepted(changes: &[Change], plan: &ReplacePlan, options: &WriteOptions) -> bool {
+    if options.atomic {
+        return write_atomic(changes, plan, options);
+    }
+    let mut ok = true;
+    let mut written = 0;
+    let mut applied = 0;
+    for change in changes {
+        let accepted = change.accepted();
+        if accepted == 0 {
+            continue;
+        }
+        if interrupt::requested() {
+            eprintln!("收到中断信号，已写回 {} 个文件，其余文件没有修改", written);
+            return false;
+        }
+        match write_change(change, plan, options) {
+            Ok(()) => {
+                written += 1;
+                applied += accepted;
+            }
+            Err(err) => {
+                eprintln!("{:?}: {}", paths::to_host(&change.file), err);
+                ok = false;
+            }
+        }
+    }
+    println!("已写回 {} 个文件中的 {} 处改动", written, applied);
+    ok
+}
+
+fn write_change(
+    change: &Change,
+    plan: &ReplacePlan,
+    options: &WriteOptions,
+) -> Result<(), FileError> {
+    let temp_file_path = prepare_change(change, plan, options)?;
+    let _writing = interrupt::writing();
+    let installed =
+        install::install(&change.file, &temp_file_path, options).map_err(FileError::Write);
+    if installed.is_err() {
+        let _ = fs::remove_file(&temp_file_path);
+    }
+    installed
+}
+
+///
+/// 确认文件没有被修改，把接受的改动写到临时文件并按 --validate 检查，返回临时文件
+///
+fn prepare_change(
+    change: &Change,
+    plan: &ReplacePlan,
+    options: &WriteOptions,
+) -> Result<PathBuf, FileError> {
+    if !options.force && change.fingerprint != Fingerprint::of(&change.file).ok() {
+        return Err(FileError::Modified);
+    }
+    let temp_file =
+        install::create_temp_file(&change.file).map_err(|err| FileError::from_replace(&err))?;
+    let temp_file_path = temp_file.path().to_path_buf();
+    fs::write(&temp_file_path, change.contents())
+        .and_then(|()| temp_file.persist(&temp_file_path).map_err(|err| err.error))
+        .map_err(|err| FileError::from_replace(&err))?;
+    if let Err(err) = validate_replaced(&change.file, &temp_file_path, plan, options) {
+        let _ = fs::remove_file(&temp_file_path);
+        return Err(err);
+    }
+    Ok(temp_file_path)
+}
+
+///
+/// --atomic-run：所有接受的改动都通过检查之后才开始写回，写回失败时恢复所有已经写回的文件
+///
+fn write_atomic(changes: &[Change], plan: &ReplacePlan, options: &WriteOptions) -> bool {
+    let accepted: Vec<_> = changes
+        .iter()
+        .filter(|change| change.accepted() > 0)
+        .collect();
+    let mut prepared = Vec::new();
+    let mut ok = true;
+    for change in &accepted {
+        match prepare_change(change, plan, options) {
+            Ok(temp_file) => prepared.push((&change.file, temp_file)),
+            Err(err) => {
+                eprintln!("{:?}: {}", paths::to_host(&change.file), err);
+                ok = false;
+            }
+        }
+    }
+    if !ok {
+        for (_, temp_file) in prepared {
+            let _ = fs::remove_file(temp_file);
+        }
+        eprintln!("有文件没有通过检查，没有修改任何文件");
+        return false;
+    }
+
+    let _writing = interrupt::writing();
+    let mut transaction = Transaction::new();
+    let mut pending = prepared.into_iter();
+    let mut installed = Ok(());
+    for (file, temp_file) in pending.by_ref() {
+        installed = if interrupt::requested() {
+            Err("收到中断信号".to_string())
+        } else {
+            transaction.install(file, &temp_file, options)
+        };
+        if installed.is_err() {
+            let _ = fs::remove_file(temp_file);
+            break;
+        }
+    }
+    if let Err(err) = installed {
+        for (_, temp_file) in pending {
+            let _ = fs::remove_file(temp_file);
+        }
+        match transaction.rollback() {
+            Ok(restored) => eprintln!("{}\n已恢复写回的 {} 个文件", err, restored),
+            Err(rollback_err) => eprintln!("{}\n{}", err, rollback_err),
+        }
+        return false;
+    }
+    transaction.commit();
+    let applied: usize = accepted.iter().map(|change| change.accepted()).sum();
+    println!("已写回 {} 个文件中的 {} 处改动", accepted.len(), applied);
+    true
+}
+
+///
+/// 从终端读到的按键
+///
+enum Key {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Enter,
+    Escape,
+    Char(char),
+}
+
+impl Key {
+    ///
+    /// 一次读到的字节，方向键和翻页键是以 ESC 开头的转义序列
+    ///
+    fn parse(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            b"\x1b[A" | b"\x1bOA" => Some(Self::Up),
+            b"\x1b[B" | b"\x1bOB" => Some(Self::Down),
+            b"\x1b[5~" => Some(Self::PageUp),
+            b"\x1b[6~" => Some(Self::PageDown),
+            b"\x1b" => Some(Self::Escape),
+            [b'\r' | b'\n'] => Some(Self::Enter),
+            [byte, ..] if *byte != 0x1b => Some(Self::Char(*byte as char)),
+            _ => None,
+        }
+    }
+}
+
+///
+/// 右边改动区域中的一行，由不同样式的片段组成
+///
+type Line = Vec<(&'static str, String)>;
+
+///
+/// 审阅的状态：当前的文件和改动，改动区域滚动到的行
+///
+struct Review {
+    changes: Vec<Change>,
+    file: usize,
+    hunk: usize,
+    scroll: usize,
+    confirming: bool,
+}
+
+impl Review {
+    ///
+    /// 处理按键直到确认写回或者退出，确认写回时返回 true
+    ///
+    fn run(&mut self, terminal: &mut imp::Terminal) -> io::Result<bool> {
+        loop {
+            let (width, height) = terminal.size();
+            let screen = self.render(width, height);
+            terminal.draw(&screen)?;
+            //
+            // 请求停止时还没有确认写回，不修改任何文件
+            //
+            if stop::requested() {
+                return Ok(false);
+            }
+            let Some(key) = terminal.read_key()? else {
+                continue;
+            };
+            if self.confirming {
+                if let Key::Char('y') = key {
+                    return Ok(true);
+                }
+                self.confirming = false;
+                continue;
+            }
+            match key {
+                Key::Char('q' | '\x03') | Key::Escape => return Ok(false),
+                Key::Char('j') | Key::Down => self.next_hunk(),
+                Key::Char('k') | Key::Up => self.previous_hunk(),
+                Key::Char('J' | '\t') | Key::PageDown => self.select_file(self.file + 1),
+                Key::Char('K') | Key::PageUp => self.select_file(self.file.saturating_sub(1)),
+                Key::Char('y') => {
+                    self.changes[self.file].accepted[self.hunk] = true;
+                    self.next_hunk();
+                }
+                Key::Char('n') => {
+                    self.changes[self.file].accepted[self.hunk] = false;
+                    self.next_hunk();
+                }
+                Key::Char('a') => self.changes[self.file].accepted.fill(true),
+                Key::Char('r') => self.changes[self.file].accepted.fill(false),
+                Key::Char('w') | Key::Enter => self.confirming = true,
+                _ => {}
+            }
+        }
+    }
+
+    fn next_hunk(&mut self) {
+        if self.hunk + 1 < self.changes[self.file].hunks.len() {
+            self.hunk += 1;
+        } else if self.file + 1 < self.changes.len() {
+            self.select_file(self.file + 1);
+        }
+    }
+
+    fn previous_hunk(&mut self) {
+        if self.hunk > 0 {
+            self.hunk -= 1;
+        } else if self.file > 0 {
+            self.select_file(self.file - 1);
+            self.hunk = self.changes[self.file].hunks.len() - 1;
+        }
+    }
+
+    fn select_file(&mut self, file: usize) {
+        if file < self.changes.len() && file != self.file {
+            self.file = file;
+            self.hunk = 0;
+            self.scroll = 0;
+        }
+    }
+
+    ///
+    /// 生成整个屏幕：第一行是标题，最后一行是按键说明或者确认提示，中间左边是文件列表，右边是改动
+    ///
+    fn render(&mut self, width: usize, height: usize) -> Vec<String> {
+        let body = height.saturating_sub(2);
+        let list_width = (width / 3).clamp(1, 40);
+        let view_width = width.saturating_sub(list_width + 1);
+
+        let accepted: usize = self.changes.iter().map(Change::accepted).sum();
+        let total: usize = self.changes.iter().map(|change| change.hunks.len()).sum();
+        let title = format!(
+            " regex-replace 审阅：{} 个文件，已接受 {}/{} 处改动",
+            self.changes.len(),
+            accepted,
+            total
+        );
+        let mut screen = vec![styled(&[(BOLD, title)], width)];
+
+        let (view, current) = self.view(view_width);
+        //
+        // 当前的改动不在可见范围内时滚动到让它从第二行开始显示
+        //
+        if current < self.scroll || current >= self.scroll + body {
+            self.scroll = current.saturating_sub(1);
+        }
+        let list_scroll = (self.file + 1).saturating_sub(body);
+        for row in 0..body {
+            let entry = match self.changes.get(list_scroll + row) {
+                Some(change) => {
+                    let index = list_scroll + row;
+                    let label = format!(
+                        " {} {}/{}",
+                        paths::to_host(&change.file).display(),
+                        change.accepted(),
+                        change.hunks.len()
+                    );
+                    let style = if index == self.file { REVERSE } else { "" };
+                    styled(&[(style, label)], list_width)
+                }
+                None => styled(&[], list_width),
+            };
+            let line = view.get(self.scroll + row).map_or(&[][..], Vec::as_slice);
+            screen.push(format!("{}│{}", entry, styled(line, view_width)));
+        }
+
+        let status = if self.confirming {
+            let files = self
+                .changes
+                .iter()
+                .filter(|change| change.accepted() > 0)
+                .count();
+            format!(
+                " 写回 {} 个文件中接受的 {} 处改动？按 y 确认，其他键返回",
+                files, accepted
+            )
+        } else {
+            " j/k 下一处/上一处  y 接受  n 拒绝  a/r 接受/拒绝整个文件  J/K 切换文件  Enter 写回  q 退出"
+                .to_string()
+        };
+        screen.push(styled(&[(REVERSE, status)], width));
+        screen
+    }
+
+    ///
+    /// 当前文件的所有改动，每段改动前后带上下文，返回所有行和当前改动的标题所在的行
+    ///
+    fn view(&self, width: usize) -> (Vec<Line>, usize) {
+        let change = &self.changes[self.file];
+        let original = lines(&change.original);
+        let replaced = lines(&change.replaced);
+        let mut offsets = Vec::with_capacity(original.len());
+        let mut offset = 0;
+        for line in &original {
+            offsets.push(offset);
+            offset += line.len();
+        }
+
+        let mut view = Vec::new();
+        let mut current = 0;
+        for (index, hunk) in change.hunks.iter().enumerate() {
+            let accepted = change.accepted[index];
+            if index == self.hunk {
+                current = view.len();
+            }
+            let mut header = format!(
+                "@@ 第 {} 行 [{}] ",
+                hunk.original.start + 1,
+                if accepted { "接受" } else { "拒绝" }
+            );
+            while header.chars().count() < width {
+                header.push('─');
+            }
+            let style = if index == self.hunk { REVERSE } else { BOLD };
+            view.push(vec![(style, header)]);
+
+            let before = hunk.original.start.saturating_sub(CONTEXT);
+            for line in &original[before..hunk.original.start] {
+                view.push(vec![("", format!("  {}", trim_newline(line)))]);
+            }
+            let (removed, added) = if accepted { (RED, GREEN) } else { (DIM, DIM) };
+            for number in hunk.original.clone() {
+                let mut line = vec![(removed, "- ".to_string())];
+                line.extend(highlight(
+                    trim_newline(original[number]),
+                    offsets[number],
+                    &change.matches,
+                    removed,
+                ));
+                view.push(line);
+            }
+            for line in &replaced[hunk.replaced.clone()] {
+                view.push(vec![(added, format!("+ {}", trim_newline(line)))]);
+            }
+            let after = (hunk.original.end + CONTEXT).min(original.len());
+            for line in &original[hunk.original.end..after] {
+                view.push(vec![("", format!("  {}", trim_newline(line)))]);
+            }
+            view.push(Vec::new());
+        }
+        (view, current)
+    }
+}
+
+fn trim_newline(line: &str) -> &str {
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    line.strip_suffix('\r').unwrap_or(line)
+}
+
+///
+/// 把删除的一行按匹配切成片段，offset 是这一行在原文中的位置
+///
+fn highlight(line: &str, offset: usize, matches: &[Range<usize>], style: &'static str) -> Line {
+    let mut spans = Vec::new();
+    let mut position = 0;
+    for m in matches {
+        let start = m.start.saturating_sub(offset).max(position);
+        let end = m.end.saturating_sub(offset).min(line.len());
+        if m.end <= offset || start >= end {
+            continue;
+        }
+        if start > position {
+            spans.push((style, line[position..start].to_string()));
+        }
+        spans.push((MATCH, line[start..end].to_string()));
+        position = end;
+    }
+    spans.push((style, line[position..].to_string()));
+    spans
+}
+
+///
+/// 按显示宽度截断或者补齐一行，制表符显示为空格，其他控制字符显示为 ?
+///
+fn styled(spans: &[(&'static str, String)], width: usize) -> String {
+    let mut output = String::new();
+    let mut used = 0;
+    'spans: for (style, text) in spans {
+        output.push_str(style);
+        for c in text.chars() {
+            let (c, count) = match c {
+                '\t' => (' ', 4),
+                c if c.is_control() => ('?', 1),
+                c => (c, 1),
+            };
+            for _ in 0..count {
+                if used + char_width(c) > width {
+                    break 'spans;
+                }
+                used += char_width(c);
+                output.push(c);
+            }
+        }
+        output.push_str(RESET);
+    }
+    output.push_str(RESET);
+    let style = spans.first().map_or("", |(style, _)| *style);
+    if style == REVERSE {
+        output.push_str(REVERSE);
+    }
+    let _ = write!(output, "{:1$}", "", width - used);
+    output.push_str(RESET);
+    output
+}
+
+///
+/// 字符在终端中占的列数，中日韩文字和全角符号占两列
+///
+fn char_width(c: char) -> usize {
+    match c as u32 {
+        0x1100..=0x115F
+        | 0x2E80..=0x303E
+        | 0x3041..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x1F300..=0x1F64F
+        | 0x1F900..=0x1F9FF
+        | 0x20000..=0x3FFFD => 2,
+        _ => 1,
+    }
+}
+
+#[cfg(unix)]
+mod imp {
+    use std::fs::{File, OpenOptions};
+    use std::io::{self, Read, Write};
+    use std::mem;
+    use std::os::unix::io::{AsRawFd, RawFd};
+
+    use super::Key;
+    use crate::interrupt;
+
+    ///
+    /// 等待按键的最长时间，毫秒
+    ///
+    const POLL_INTERVAL: libc::c_int = 200;
+
+    ///
+    /// 切换到备用屏幕的原始模式终端，丢弃时恢复原来的设置和屏幕
+    ///
+    pub struct Terminal {
+        tty: File,
+        saved: libc::termios,
+    }
+
+    impl Terminal {
+        pub fn open() -> io::Result<Self> {
+            let tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+            let fd = tty.as_raw_fd();
+            let mut saved: libc::termios = unsafe { mem::zeroed() };
+            if unsafe { libc::tcgetattr(fd, &mut saved) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut raw = saved;
+            unsafe { libc::cfmakeraw(&mut raw) };
+            if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            //
+            // 收到 SIGINT 或 SIGTERM 时进程直接退出，不会执行 Drop，由中断处理恢复终端
+            //
+            interrupt::on_exit(Some(Box::new(move || restore(fd, &saved))));
+            let mut terminal = Self { tty, saved };
+            terminal.tty.write_all(b"\x1b[?1049h\x1b[?25l")?;
+            Ok(terminal)
+        }
+
+        ///
+        /// 终端的列数和行数，取不到时按 80x24 处理
+        ///
+        pub fn size(&self) -> (usize, usize) {
+            let mut size: libc::winsize = unsafe { mem::zeroed() };
+            let ok = unsafe { libc::ioctl(self.tty.as_raw_fd(), libc::TIOCGWINSZ, &mut size) } == 0;
+            if ok && size.ws_col > 0 && size.ws_row > 0 {
+                (size.ws_col as usize, size.ws_row as usize)
+            } else {
+                (80, 24)
+            }
+        }
+
+        pub fn draw(&mut self, screen: &[String]) -> io::Result<()> {
+            let mut output = String::from("\x1b[H");
+            for (row, line) in screen.iter().enumerate() {
+                output.push_str(&format!("\x1b[{};1H{}", row + 1, line));
+            }
+            self.tty.write_all(output.as_bytes())?;
+            self.tty.flush()
+        }
+
+        ///
+        /// 等待一个按键，超过 POLL_INTERVAL 没有输入时返回 None，让调用方检查停止请求
+        ///
+        pub fn read_key(&mut self) -> io::Result<Option<Key>> {
+            let mut fds = libc::pollfd {
+                fd: self.tty.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let ready = unsafe { libc::poll(&mut fds, 1, POLL_INTERVAL) };
+            if ready <= 0 {
+                let err = io::Error::last_os_error();
+                return match ready {
+                    0 => Ok(None),
+                    _ if err.kind() == io::ErrorKind::Interrupted => Ok(None),
+                    _ => Err(err),
+                };
+            }
+            let mut buffer = [0u8; 16];
+            let read = match self.tty.read(&mut buffer) {
+                Ok(read) => read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => 0,
+                Err(err) => return Err(err),
+            };
+            Ok(Key::parse(&buffer[..read]))
+        }
+    }
+
+    impl Drop for Terminal {
+        fn drop(&mut self) {
+            interrupt::on_exit(None);
+            restore(self.tty.as_raw_fd(), &self.saved);
+        }
+    }
+
+    ///
+    /// 离开备用屏幕，显示光标并恢复原来的终端设置
+    ///
+    fn restore(fd: RawFd, saved: &libc::termios) {
+        const RESET: &[u8] = b"\x1b[0m\x1b[?25h\x1b[?1049l";
+        unsafe {
+            libc::write(fd, RESET.as_ptr().cast(), RESET.len());
+            libc::tcsetattr(fd, libc::TCSANOW, saved);
+        }
+    }
+}
+
+//
+// 其他平台没有 termios，不支持全屏审阅
+//
+#[cfg(not(unix))]
+mod imp {
+    use std::io;
+
+    use super::Key;
+
+    pub struct Terminal;
+
+    impl Terminal {
+        pub fn open() -> io::Result<Self> {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "tui 只支持类 Unix 系统",
+            ))
+        }
+
+        pub fn size(&self) -> (usize, usize) {
+            (80, 24)
+        }
+
+        pub fn draw(&mut self, _screen: &[String]) -> io::Result<()> {
+            Ok(())
+        }
+
+        pub fn read_key(&mut self) -> io::Result<Option<Key>> {
+            Ok(None)
+        }
+    }
+}
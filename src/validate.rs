@@ -0,0 +1,194 @@
+use clap::ValueEnum;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::encoding::{strip_bom, Encoding};
+use crate::json;
+use crate::toml;
+
+///
+/// 替换后的内容没有通过检查时的处理方式
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ValidateMode {
+    ///
+    /// 只报告，照常写回
+    ///
+    Warn,
+    ///
+    /// 报告并且不写回这个文件
+    ///
+    Skip,
+}
+
+///
+/// 按扩展名识别的文件格式
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl Syntax {
+    pub fn detect(file: &Path) -> Option<Self> {
+        let extension = file.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "json" => Some(Self::Json),
+            "yaml" | "yml" => Some(Self::Yaml),
+            "toml" => Some(Self::Toml),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Json => "JSON",
+            Self::Yaml => "YAML",
+            Self::Toml => "TOML",
+        }
+    }
+
+    fn check(&self, text: &str) -> Result<(), String> {
+        match self {
+            Self::Json => json::parse(text).map(|_| ()),
+            Self::Yaml => check_yaml(text),
+            Self::Toml => toml::parse(text).map(|_| ()).map_err(|err| err.to_string()),
+        }
+    }
+}
+
+///
+/// 检查替换是否把文件改坏了，改坏时返回原因
+/// 只有原文件能通过检查、替换后的内容不能通过时才算改坏，原本就无法解析的文件不会被报告
+///
+pub fn check_replaced(
+    file: &Path,
+    temp_file: &Path,
+    encoding: Encoding,
+) -> io::Result<Option<String>> {
+    let original = encoding.decode(&fs::read(file)?);
+    let replaced = match encoding.decode(&fs::read(temp_file)?) {
+        Ok(replaced) => replaced,
+        Err(err) => {
+            return Ok(original
+                .is_ok()
+                .then(|| format!("替换后的内容不是有效的 {:?} 编码: {}", encoding, err)));
+        }
+    };
+    let (Ok(original), Some(syntax)) = (original, Syntax::detect(file)) else {
+        return Ok(None);
+    };
+    let (original, replaced) = (strip_bom(&original), strip_bom(&replaced));
+    if syntax.check(original).is_err() {
+        return Ok(None);
+    }
+
+    Ok(syntax
+        .check(replaced)
+        .err()
+        .map(|err| format!("替换后不是有效的 {}: {}", syntax.name(), err)))
+}
+
+///
+/// 轻量的 YAML 结构检查，不是完整的解析器
+/// 只检查缩进中的制表符、引号是否闭合以及流式集合的括号是否配对，跳过块标量的内容
+///
+fn check_yaml(text: &str) -> Result<(), String> {
+    let mut brackets: Vec<(char, usize)> = Vec::new();
+    let mut quote: Option<(char, usize)> = None;
+    let mut block_indent: Option<usize> = None;
+
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let content = line.trim_start_matches([' ', '\t']);
+        let indent = line.len() - content.len();
+
+        if let Some(parent_indent) = block_indent {
+            if content.is_empty() || indent > parent_indent {
+                continue;
+            }
+            block_indent = None;
+        }
+        if quote.is_none() && line[..indent].contains('\t') && !content.is_empty() {
+            return Err(format!("第 {} 行: 缩进中不能使用制表符", line_number));
+        }
+
+        let mut previous = None;
+        let mut chars = content.chars().peekable();
+        while let Some(ch) = chars.next() {
+            match quote {
+                Some(('\'', _)) if ch == '\'' => {
+                    if chars.peek() == Some(&'\'') {
+                        chars.next();
+                    } else {
+                        quote = None;
+                    }
+                }
+                Some(('"', _)) if ch == '\\' => {
+                    chars.next();
+                }
+                Some(('"', _)) if ch == '"' => quote = None,
+                Some(_) => {}
+                None => match ch {
+                    '#' if previous.is_none_or(char::is_whitespace) => break,
+                    '\'' | '"' if starts_scalar(previous) => quote = Some((ch, line_number)),
+                    '[' | '{' => brackets.push((ch, line_number)),
+                    ']' | '}' => {
+                        let open = if ch == ']' { '[' } else { '{' };
+                        match brackets.pop() {
+                            Some((found, _)) if found == open => {}
+                            _ => return Err(format!("第 {} 行: 多余的 {:?}", line_number, ch)),
+                        }
+                    }
+                    _ => {}
+                },
+            }
+            if !ch.is_whitespace() {
+                previous = Some(ch);
+            } else if quote.is_none() {
+                previous = Some(' ');
+            }
+        }
+
+        if quote.is_none() && brackets.is_empty() && is_block_scalar_header(content) {
+            block_indent = Some(indent);
+        }
+    }
+
+    if let Some((ch, line)) = quote {
+        return Err(format!("第 {} 行: 引号 {:?} 没有闭合", line, ch));
+    }
+    if let Some((ch, line)) = brackets.first() {
+        return Err(format!("第 {} 行: 括号 {:?} 没有闭合", line, ch));
+    }
+    Ok(())
+}
+
+///
+/// 引号只有出现在标量开头时才有引用的意义，例如 it's 中的单引号只是普通字符
+///
+fn starts_scalar(previous: Option<char>) -> bool {
+    matches!(
+        previous,
+        None | Some(' ' | ':' | '-' | '[' | '{' | ',' | '?')
+    )
+}
+
+///
+/// 行尾是 | 或 > 块标量标记（可以带 -、+ 和缩进数字）时，之后缩进更深的行都是块标量的内容
+///
+pub fn is_block_scalar_header(content: &str) -> bool {
+    let content = match content.find(" #") {
+        Some(comment) => &content[..comment],
+        None => content,
+    };
+    let Some(token) = content.trim_end().rsplit(' ').next() else {
+        return false;
+    };
+    let mut chars = token.chars();
+    matches!(chars.next(), Some('|' | '>'))
+        && chars.all(|ch| ch == '-' || ch == '+' || ch.is_ascii_digit())
+}
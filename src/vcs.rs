@@ -0,0 +1,239 @@
+//!
+//! 版本控制系统的抽象：按版本库状态选择文件等与版本控制相关的功能都通过 Vcs 实现，
+//! 不在 main 里直接调用 git 命令
+//!
+//! 目前只有 git 一个后端，Mercurial、Sapling 等只需要实现 Vcs 并加入 BACKENDS
+//!
+
+use clap::ValueEnum;
+use std::ffi::{OsStr, OsString};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::paths;
+
+///
+/// 按版本库状态选出的文件集合
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum FileSet {
+    ///
+    /// 版本库跟踪的所有文件
+    ///
+    Tracked,
+    ///
+    /// 工作区相对最近一次提交有改动的文件
+    ///
+    Modified,
+    ///
+    /// 已暂存的文件
+    ///
+    Staged,
+}
+
+pub trait Vcs {
+    ///
+    /// 后端的名字，用于错误信息
+    ///
+    fn name(&self) -> &'static str;
+
+    ///
+    /// 版本库的根目录
+    ///
+    fn root(&self) -> &Path;
+
+    ///
+    /// 列出集合中的文件，返回带根目录的路径，已删除的文件不包含在内
+    ///
+    fn files(&self, set: FileSet) -> Result<Vec<PathBuf>, String>;
+
+    ///
+    /// 读取文件在暂存区中的内容
+    ///
+    fn read_staged(&self, file: &Path) -> Result<Vec<u8>, String>;
+
+    ///
+    /// 把新的内容写入暂存区，保留原来的文件模式，不修改工作区
+    ///
+    fn write_staged(&self, file: &Path, contents: &[u8]) -> Result<(), String>;
+}
+
+///
+/// 已知的后端：版本库根目录下的标记目录和对应的构造函数
+///
+const BACKENDS: &[(&str, Backend)] = &[(".git", Git::boxed)];
+
+type Backend = fn(PathBuf) -> Box<dyn Vcs>;
+
+///
+/// 从 dir 开始向上查找版本库，返回最近的一个
+///
+pub fn detect(dir: &Path) -> Option<Box<dyn Vcs>> {
+    let dir = dir.canonicalize().ok()?;
+    for ancestor in dir.ancestors() {
+        for (marker, backend) in BACKENDS {
+            if ancestor.join(marker).exists() {
+                return Some(backend(ancestor.to_path_buf()));
+            }
+        }
+    }
+    None
+}
+
+struct Git {
+    root: PathBuf,
+}
+
+impl Git {
+    fn boxed(root: PathBuf) -> Box<dyn Vcs> {
+        Box::new(Self { root })
+    }
+
+    ///
+    /// 在版本库根目录运行 git，input 不为空时写入标准输入，返回标准输出
+    ///
+    fn run<I, S>(&self, args: I, input: Option<&[u8]>) -> Result<Vec<u8>, String>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let mut child = Command::new("git")
+            .arg("-C")
+            .arg(&self.root)
+            .args(args)
+            .stdin(if input.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|err| format!("运行 git 失败: {}", err))?;
+        if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+            stdin
+                .write_all(input)
+                .map_err(|err| format!("运行 git 失败: {}", err))?;
+        }
+        let output = child
+            .wait_with_output()
+            .map_err(|err| format!("运行 git 失败: {}", err))?;
+        if !output.status.success() {
+            return Err(format!(
+                "git 执行失败: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(output.stdout)
+    }
+
+    ///
+    /// 运行 git，返回以 NUL 分隔的路径列表
+    ///
+    fn list<I, S>(&self, args: I) -> Result<Vec<PathBuf>, String>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        Ok(self
+            .run(args, None)?
+            .split(|&b| b == 0)
+            .filter(|path| !path.is_empty())
+            .map(|path| self.root.join(paths::from_bytes(path)))
+            .collect())
+    }
+}
+
+impl Vcs for Git {
+    fn name(&self) -> &'static str {
+        "git"
+    }
+
+    fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn files(&self, set: FileSet) -> Result<Vec<PathBuf>, String> {
+        match set {
+            FileSet::Tracked => self.list(["ls-files", "-z"]),
+            FileSet::Modified if self.has_head() => {
+                self.list(["diff", "--name-only", "-z", "--diff-filter=d", "HEAD"])
+            }
+            //
+            // 还没有任何提交时没有 HEAD 可以比较，暂存区中的文件都算有改动
+            //
+            FileSet::Modified => Ok(self
+                .list(["ls-files", "-z"])?
+                .into_iter()
+                .filter(|path| path.symlink_metadata().is_ok())
+                .collect()),
+            FileSet::Staged => {
+                self.list(["diff", "--cached", "--name-only", "-z", "--diff-filter=d"])
+            }
+        }
+    }
+
+    fn read_staged(&self, file: &Path) -> Result<Vec<u8>, String> {
+        let mut object = OsString::from(":");
+        object.push(self.relative(file));
+        self.run([OsStr::new("cat-file"), OsStr::new("blob"), &object], None)
+    }
+
+    fn write_staged(&self, file: &Path, contents: &[u8]) -> Result<(), String> {
+        let relative = self.relative(file);
+        //
+        // ls-files -s 的输出：<模式> <对象> <阶段>\t<路径>
+        //
+        let entry = self.run(
+            [
+                OsStr::new("ls-files"),
+                OsStr::new("-s"),
+                OsStr::new("--"),
+                relative.as_os_str(),
+            ],
+            None,
+        )?;
+        let entry = String::from_utf8_lossy(&entry);
+        let Some(mode) = entry.split_whitespace().next() else {
+            return Err(format!("{:?} 不在暂存区中", file));
+        };
+
+        //
+        // 读取时拿到的是暂存区中的原始内容，写回时也不经过 clean 过滤器和换行转换
+        //
+        let object = self.run(
+            ["hash-object", "-w", "--no-filters", "--stdin"],
+            Some(contents),
+        )?;
+        let object = String::from_utf8_lossy(&object);
+        let mut cacheinfo = OsString::from(format!("{},{},", mode, object.trim()));
+        cacheinfo.push(&relative);
+        self.run(
+            [
+                OsStr::new("update-index"),
+                OsStr::new("--cacheinfo"),
+                &cacheinfo,
+            ],
+            None,
+        )?;
+        Ok(())
+    }
+}
+
+impl Git {
+    ///
+    /// 相对版本库根目录的路径，git 的路径参数都以此为准
+    ///
+    fn relative(&self, file: &Path) -> PathBuf {
+        file.strip_prefix(&self.root).unwrap_or(file).to_path_buf()
+    }
+
+    ///
+    /// 版本库是否已经有提交
+    ///
+    fn has_head(&self) -> bool {
+        self.run(["rev-parse", "--verify", "--quiet", "HEAD"], None)
+            .is_ok()
+    }
+}
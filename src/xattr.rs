@@ -0,0 +1,207 @@
+//!
+//! 读取和写入文件的扩展属性，例如 SELinux 标签、macOS 的隔离标记
+//! Linux 上的 POSIX ACL 也保存在扩展属性里，它由 acl 模块单独处理，这里会跳过
+//!
+
+use std::io;
+use std::path::Path;
+
+///
+/// 一个扩展属性
+///
+pub struct Xattr {
+    ///
+    /// 属性名，以 \0 结尾
+    ///
+    name: Vec<u8>,
+    value: Vec<u8>,
+}
+
+impl Xattr {
+    ///
+    /// 属性名，用于错误信息
+    ///
+    pub fn name(&self) -> String {
+        String::from_utf8_lossy(&self.name[..self.name.len() - 1]).into_owned()
+    }
+}
+
+///
+/// 读取文件的所有扩展属性，文件系统不支持时返回空列表
+///
+pub fn read_all(path: &Path) -> io::Result<Vec<Xattr>> {
+    imp::read_all(path)
+}
+
+///
+/// 把扩展属性写到文件上
+///
+pub fn write(path: &Path, xattr: &Xattr) -> io::Result<()> {
+    imp::write(path, xattr)
+}
+
+///
+/// 错误是否可以跳过：文件系统不支持扩展属性，或者没有权限设置这个属性（例如 trusted.*）
+///
+pub fn is_skippable(err: &io::Error) -> bool {
+    imp::is_unsupported(err) || err.kind() == io::ErrorKind::PermissionDenied
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod imp {
+    use super::Xattr;
+    use std::ffi::CString;
+    use std::io;
+    use std::os::raw::c_char;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    ///
+    /// 由 acl 模块负责的属性
+    ///
+    const SKIPPED_PREFIXES: &[&[u8]] = &[b"system.posix_acl_"];
+
+    pub fn read_all(path: &Path) -> io::Result<Vec<Xattr>> {
+        let path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+
+        let names =
+            match read_buffer(|buffer, size| unsafe { list(path.as_ptr(), buffer.cast(), size) }) {
+                Ok(names) => names,
+                Err(err) if is_unsupported(&err) => return Ok(Vec::new()),
+                Err(err) => return Err(err),
+            };
+
+        let mut xattrs = Vec::new();
+        for name in names.split_inclusive(|&byte| byte == 0) {
+            if name.len() <= 1
+                || SKIPPED_PREFIXES
+                    .iter()
+                    .any(|prefix| name.starts_with(prefix))
+            {
+                continue;
+            }
+            let value = match read_buffer(|buffer, size| unsafe {
+                get(path.as_ptr(), name.as_ptr().cast(), buffer, size)
+            }) {
+                Ok(value) => value,
+                //
+                // 列出之后属性被删掉了
+                //
+                Err(err) if is_missing(&err) => continue,
+                Err(err) => return Err(err),
+            };
+            xattrs.push(Xattr {
+                name: name.to_vec(),
+                value,
+            });
+        }
+        Ok(xattrs)
+    }
+
+    pub fn write(path: &Path, xattr: &Xattr) -> io::Result<()> {
+        let path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        let result = unsafe {
+            set(
+                path.as_ptr(),
+                xattr.name.as_ptr().cast(),
+                xattr.value.as_ptr(),
+                xattr.value.len(),
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub fn is_unsupported(err: &io::Error) -> bool {
+        err.raw_os_error() == Some(libc::ENOTSUP)
+    }
+
+    ///
+    /// 先用空缓冲区查询大小，再读取内容，两次调用之间内容变大时重试
+    ///
+    fn read_buffer(mut call: impl FnMut(*mut u8, usize) -> isize) -> io::Result<Vec<u8>> {
+        loop {
+            let size = call(std::ptr::null_mut(), 0);
+            if size < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut buffer = vec![0u8; size as usize];
+            let read = call(buffer.as_mut_ptr(), buffer.len());
+            if read < 0 {
+                let err = io::Error::last_os_error();
+                if err.raw_os_error() == Some(libc::ERANGE) {
+                    continue;
+                }
+                return Err(err);
+            }
+            buffer.truncate(read as usize);
+            return Ok(buffer);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn is_missing(err: &io::Error) -> bool {
+        err.raw_os_error() == Some(libc::ENODATA)
+    }
+
+    #[cfg(target_os = "macos")]
+    fn is_missing(err: &io::Error) -> bool {
+        err.raw_os_error() == Some(libc::ENOATTR)
+    }
+
+    #[cfg(target_os = "linux")]
+    unsafe fn list(path: *const c_char, buffer: *mut c_char, size: usize) -> isize {
+        libc::listxattr(path, buffer, size)
+    }
+
+    #[cfg(target_os = "linux")]
+    unsafe fn get(path: *const c_char, name: *const c_char, value: *mut u8, size: usize) -> isize {
+        libc::getxattr(path, name, value.cast(), size)
+    }
+
+    #[cfg(target_os = "linux")]
+    unsafe fn set(path: *const c_char, name: *const c_char, value: *const u8, size: usize) -> i32 {
+        libc::setxattr(path, name, value.cast(), size, 0)
+    }
+
+    #[cfg(target_os = "macos")]
+    unsafe fn list(path: *const c_char, buffer: *mut c_char, size: usize) -> isize {
+        libc::listxattr(path, buffer, size, 0)
+    }
+
+    #[cfg(target_os = "macos")]
+    unsafe fn get(path: *const c_char, name: *const c_char, value: *mut u8, size: usize) -> isize {
+        libc::getxattr(path, name, value.cast(), size, 0, 0)
+    }
+
+    #[cfg(target_os = "macos")]
+    unsafe fn set(path: *const c_char, name: *const c_char, value: *const u8, size: usize) -> i32 {
+        libc::setxattr(path, name, value.cast(), size, 0, 0)
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+mod imp {
+    use super::Xattr;
+    use std::io;
+    use std::path::Path;
+
+    ///
+    /// 其他平台不处理扩展属性
+    ///
+    pub fn read_all(_path: &Path) -> io::Result<Vec<Xattr>> {
+        Ok(Vec::new())
+    }
+
+    pub fn write(_path: &Path, _xattr: &Xattr) -> io::Result<()> {
+        Ok(())
+    }
+
+    pub fn is_unsupported(_err: &io::Error) -> bool {
+        false
+    }
+}
@@ -0,0 +1,346 @@
+//!
+//! --key-path 使用的 YAML 扫描器，不是完整的解析器
+//! 逐行跟踪缩进，找出块风格的映射和序列中每个标量值的键路径和位置
+//! 流式集合、别名和跨行的普通标量不会被选中
+//!
+
+use crate::json::{self, Kind};
+use crate::keypath::{Scalar, Style};
+use crate::validate::is_block_scalar_header;
+
+///
+/// 键路径中的一层：映射的键或者序列元素的下标
+///
+enum Segment {
+    Key(String),
+    Item(usize),
+}
+
+impl Segment {
+    fn name(&self) -> String {
+        match self {
+            Segment::Key(key) => key.clone(),
+            Segment::Item(index) => index.to_string(),
+        }
+    }
+}
+
+///
+/// 一行的内容，不包括行尾的 \n 或 \r\n
+///
+struct Line<'a> {
+    number: usize,
+    start: usize,
+    text: &'a str,
+}
+
+fn lines(text: &str) -> Vec<Line<'_>> {
+    let mut start = 0;
+    text.split_inclusive('\n')
+        .enumerate()
+        .map(|(index, raw)| {
+            let line = Line {
+                number: index + 1,
+                start,
+                text: raw
+                    .strip_suffix('\n')
+                    .map(|raw| raw.strip_suffix('\r').unwrap_or(raw))
+                    .unwrap_or(raw),
+            };
+            start += raw.len();
+            line
+        })
+        .collect()
+}
+
+///
+/// 找出所有标量值，按在文本中的位置排列
+///
+pub fn scalars(text: &str) -> Vec<Scalar> {
+    let lines = lines(text);
+    let mut scalars = Vec::new();
+    //
+    // 从根开始的每一层和它的缩进
+    //
+    let mut stack: Vec<(usize, Segment)> = Vec::new();
+    let mut index = 0;
+
+    while index < lines.len() {
+        let line = &lines[index];
+        index += 1;
+        let mut content = line.text.trim_start_matches(' ');
+        let mut indent = line.text.len() - content.len();
+        if content.is_empty() || content.starts_with('#') {
+            continue;
+        }
+        if indent == 0 && is_document_marker(content) {
+            stack.clear();
+            continue;
+        }
+
+        //
+        // "- " 开始一个序列元素，元素的内容从 "- " 之后开始，可以嵌套
+        //
+        let mut item_indent = None;
+        while let Some(rest) = content
+            .strip_prefix("- ")
+            .or((content == "-").then_some(""))
+        {
+            while stack.last().is_some_and(|(level, _)| *level > indent) {
+                stack.pop();
+            }
+            match stack.last_mut() {
+                Some((level, Segment::Item(count))) if *level == indent => *count += 1,
+                _ => stack.push((indent, Segment::Item(0))),
+            }
+            item_indent = Some(indent);
+            let trimmed = rest.trim_start_matches(' ');
+            indent += content.len() - trimmed.len();
+            content = trimmed;
+        }
+        if content.is_empty() || content.starts_with('#') {
+            continue;
+        }
+
+        let content_start = line.start + line.text.len() - content.len();
+        let (parent_indent, value_offset, is_key) = match split_key(content) {
+            Some((key, value_offset)) => {
+                while stack.last().is_some_and(|(level, _)| *level >= indent) {
+                    stack.pop();
+                }
+                stack.push((indent, Segment::Key(key)));
+                (indent, value_offset, true)
+            }
+            //
+            // 没有键的普通行只有作为序列元素时才是值，否则是上一个值的延续
+            //
+            None => match item_indent {
+                Some(item_indent) => (item_indent, 0, false),
+                None => continue,
+            },
+        };
+
+        let (value_offset, value) = skip_properties(content, value_offset);
+        if value.is_empty() || value.starts_with('#') {
+            //
+            // 值在之后缩进更深的行中，这个键是它们的父节点
+            //
+            continue;
+        }
+        let path: Vec<String> = stack.iter().map(|(_, segment)| segment.name()).collect();
+        if is_key {
+            stack.pop();
+        }
+
+        let value_start = content_start + value_offset;
+        match value.as_bytes()[0] {
+            b'|' | b'>' if is_block_scalar_header(value) => {
+                let (scalar, next) = block_scalar(&lines, index, parent_indent, path);
+                scalars.extend(scalar);
+                index = next;
+            }
+            quote @ (b'\'' | b'"') => match closing_quote(value, quote, 1) {
+                Some(end) => {
+                    scalars.extend(quoted_scalar(&value[..end], value_start, line.number, path))
+                }
+                None => index = skip_quoted(&lines, index, quote),
+            },
+            b'[' | b'{' => index = skip_flow(&lines, index, value),
+            b'*' => {}
+            _ => {
+                let end = value.find(" #").unwrap_or(value.len());
+                let plain = value[..end].trim_end();
+                scalars.push(Scalar {
+                    path,
+                    span: value_start..value_start + plain.len(),
+                    line: line.number,
+                    value: plain.to_string(),
+                    style: Style::Plain,
+                });
+            }
+        }
+    }
+
+    scalars
+}
+
+fn is_document_marker(content: &str) -> bool {
+    ["---", "..."].iter().any(|marker| {
+        content
+            .strip_prefix(marker)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(' '))
+    })
+}
+
+///
+/// 拆出一行开头的 key:，返回键和值在这一行中的偏移
+///
+fn split_key(content: &str) -> Option<(String, usize)> {
+    let (key, after) = match content.as_bytes()[0] {
+        quote @ (b'\'' | b'"') => {
+            let end = closing_quote(content, quote, 1)?;
+            let key = decode_quoted(&content[..end])?;
+            (
+                key,
+                content[end..].trim_start_matches(' ').strip_prefix(':')?,
+            )
+        }
+        b'[' | b'{' | b'#' | b'&' | b'*' | b'!' | b'|' | b'>' | b'?' | b'%' | b'@' | b'`' => {
+            return None
+        }
+        _ => {
+            let bytes = content.as_bytes();
+            let colon = (0..bytes.len()).find(|&position| {
+                bytes[position] == b':' && matches!(bytes.get(position + 1), None | Some(b' '))
+            })?;
+            if content[..colon].contains(" #") {
+                return None;
+            }
+            (
+                content[..colon].trim_end().to_string(),
+                &content[colon + 1..],
+            )
+        }
+    };
+    if !(after.is_empty() || after.starts_with(' ')) {
+        return None;
+    }
+    Some((key, content.len() - after.trim_start_matches(' ').len()))
+}
+
+///
+/// 跳过值前面的锚点（&name）和标签（!tag），返回值的偏移和内容
+///
+fn skip_properties(content: &str, mut offset: usize) -> (usize, &str) {
+    while content[offset..].starts_with(['&', '!']) {
+        let rest = &content[offset..];
+        let end = rest.find(' ').unwrap_or(rest.len());
+        offset += end + rest[end..].len() - rest[end..].trim_start_matches(' ').len();
+    }
+    (offset, &content[offset..])
+}
+
+///
+/// 从 from 开始在引号内扫描，返回结束引号之后的位置
+/// 单引号内 '' 表示一个单引号，双引号内可以用反斜杠转义
+///
+fn closing_quote(text: &str, quote: u8, from: usize) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut position = from;
+    while position < bytes.len() {
+        match bytes[position] {
+            b'\'' if quote == b'\'' && bytes.get(position + 1) == Some(&b'\'') => position += 2,
+            b'\\' if quote == b'"' => position += 2,
+            byte if byte == quote => return Some(position + 1),
+            _ => position += 1,
+        }
+    }
+    None
+}
+
+///
+/// 还原带引号的标量，双引号中 JSON 不支持的 YAML 转义无法处理，返回 None
+///
+fn decode_quoted(raw: &str) -> Option<String> {
+    if raw.starts_with('\'') {
+        return Some(raw[1..raw.len() - 1].replace("''", "'"));
+    }
+    match json::parse(raw).ok()?.kind {
+        Kind::String(value) => Some(value),
+        _ => None,
+    }
+}
+
+fn quoted_scalar(raw: &str, start: usize, line: usize, path: Vec<String>) -> Option<Scalar> {
+    Some(Scalar {
+        path,
+        span: start..start + raw.len(),
+        line,
+        value: decode_quoted(raw)?,
+        style: if raw.starts_with('\'') {
+            Style::SingleQuoted
+        } else {
+            Style::DoubleQuoted
+        },
+    })
+}
+
+///
+/// | 或 > 块标量：之后缩进比父节点深的行都是内容，空行也属于块标量
+/// 返回标量和下一个要处理的行
+///
+fn block_scalar(
+    lines: &[Line],
+    mut index: usize,
+    parent_indent: usize,
+    path: Vec<String>,
+) -> (Option<Scalar>, usize) {
+    let mut indent = None;
+    let mut first = None;
+    let mut last = 0;
+    while let Some(line) = lines.get(index) {
+        let content = line.text.trim_start_matches(' ');
+        if !content.is_empty() {
+            let line_indent = line.text.len() - content.len();
+            if line_indent <= parent_indent || line_indent < *indent.get_or_insert(line_indent) {
+                break;
+            }
+            first.get_or_insert(index);
+            last = index;
+        }
+        index += 1;
+    }
+
+    let (Some(indent), Some(first)) = (indent, first) else {
+        return (None, index);
+    };
+    let value = lines[first..=last]
+        .iter()
+        .map(|line| line.text.get(indent..).unwrap_or(""))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let (first, last) = (&lines[first], &lines[last]);
+    let scalar = Scalar {
+        path,
+        span: first.start + indent..last.start + last.text.len(),
+        line: first.number,
+        value,
+        style: Style::Block { indent },
+    };
+    (Some(scalar), index)
+}
+
+///
+/// 跳过没有在当前行结束的带引号标量
+///
+fn skip_quoted(lines: &[Line], mut index: usize, quote: u8) -> usize {
+    while let Some(line) = lines.get(index) {
+        index += 1;
+        if closing_quote(line.text, quote, 0).is_some() {
+            break;
+        }
+    }
+    index
+}
+
+///
+/// 跳过跨行的流式集合，只按括号配对，不考虑引号中的括号
+///
+fn skip_flow(lines: &[Line], mut index: usize, first: &str) -> usize {
+    let depth = |text: &str| {
+        text.bytes().fold(0isize, |depth, byte| match byte {
+            b'[' | b'{' => depth + 1,
+            b']' | b'}' => depth - 1,
+            _ => depth,
+        })
+    };
+    let mut open = depth(first);
+    while open > 0 {
+        let Some(line) = lines.get(index) else {
+            break;
+        };
+        open += depth(line.text);
+        index += 1;
+    }
+    index
+}
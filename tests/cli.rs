@@ -0,0 +1,185 @@
+//!
+//! 运行编译好的 regex-replace，检查命令行的完整流程
+//! 标准输入都接到 /dev/null，和 CI 中一样不是终端
+//!
+
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+fn regex_replace(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_regex-replace"))
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap()
+}
+
+///
+/// 文件列表从标准输入传入
+///
+fn regex_replace_listed(dir: &Path, args: &[&str], list: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_regex-replace"))
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(list.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn read(path: impl AsRef<Path>) -> String {
+    fs::read_to_string(path).unwrap()
+}
+
+fn write(path: impl AsRef<Path>, contents: &str) {
+    let path = path.as_ref();
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, contents).unwrap();
+}
+
+#[test]
+fn walks_the_directory_while_replacing() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    write(root.join("a.txt"), "foo 1\nbar\n");
+    write(root.join("nested/deeper/b.rs"), "let foo = 2;\n");
+    write(root.join("skipped/c.txt"), "foo 3\n");
+    write(root.join(".ignore"), "skipped/\n");
+
+    let output = regex_replace(root, &["-p", r"foo", "-r", "baz", "-d", "."]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(read(root.join("a.txt")), "baz 1\nbar\n");
+    assert_eq!(read(root.join("nested/deeper/b.rs")), "let baz = 2;\n");
+    assert_eq!(read(root.join("skipped/c.txt")), "foo 3\n");
+}
+
+#[test]
+fn reads_the_file_list_from_stdin() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    write(root.join("listed.txt"), "foo\n");
+    write(root.join("unlisted.txt"), "foo\n");
+
+    let output = regex_replace_listed(root, &["-p", "foo", "-r", "bar"], "listed.txt\n");
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(read(root.join("listed.txt")), "bar\n");
+    assert_eq!(read(root.join("unlisted.txt")), "foo\n");
+}
+
+#[test]
+fn failed_files_are_listed_and_others_still_replaced() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    write(root.join("a.txt"), "foo\n");
+
+    let output = regex_replace_listed(root, &["-p", "foo", "-r", "bar"], "a.txt\nmissing.txt\n");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(read(root.join("a.txt")), "bar\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("missing.txt: 文件不存在"), "{}", stderr);
+    assert!(stderr.contains("1 个文件处理失败"), "{}", stderr);
+}
+
+#[test]
+fn atomic_run_rolls_back_written_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    write(root.join("a.txt"), "foo\n");
+    write(root.join("foo.txt"), "foo\n");
+
+    //
+    // 内容都能写回，重命名 foo.txt 时新名字超过文件名的长度限制，已经写回的内容要全部恢复
+    //
+    let long = "x".repeat(300);
+    let output = regex_replace(
+        root,
+        &[
+            "-p",
+            "foo",
+            "-r",
+            &long,
+            "--rename",
+            "--atomic-run",
+            "-d",
+            ".",
+        ],
+    );
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("已恢复写回的 2 个文件"), "{}", stderr);
+    assert_eq!(read(root.join("a.txt")), "foo\n");
+    assert_eq!(read(root.join("foo.txt")), "foo\n");
+    let mut names: Vec<_> = fs::read_dir(root)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["a.txt", "foo.txt"]);
+}
+
+#[cfg(unix)]
+#[test]
+fn chmod_changes_only_replaced_files() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    write(root.join("script.sh"), "echo foo\n");
+    write(root.join("other.sh"), "echo other\n");
+    for name in ["script.sh", "other.sh"] {
+        fs::set_permissions(root.join(name), fs::Permissions::from_mode(0o644)).unwrap();
+    }
+
+    let output = regex_replace(
+        root,
+        &["-p", "foo", "-r", "bar", "--chmod", "755", "-d", "."],
+    );
+    assert!(output.status.success(), "{:?}", output);
+    let mode = |name: &str| fs::metadata(root.join(name)).unwrap().permissions().mode() & 0o7777;
+    assert_eq!(read(root.join("script.sh")), "echo bar\n");
+    assert_eq!(mode("script.sh"), 0o755);
+    assert_eq!(mode("other.sh"), 0o644);
+}
+
+fn git(dir: &Path, args: &[&str]) -> Option<Output> {
+    Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .env("HOME", dir)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+}
+
+#[test]
+fn staged_rewrites_the_index_only() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    //
+    // 没有 git 时跳过
+    //
+    if git(root, &["init", "-q"]).is_none() {
+        return;
+    }
+    write(root.join("a.txt"), "foo staged\n");
+    git(root, &["add", "a.txt"]).unwrap();
+    write(root.join("a.txt"), "foo worktree\n");
+
+    let output = regex_replace(root, &["-p", "foo", "-r", "bar", "--staged"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(read(root.join("a.txt")), "foo worktree\n");
+    let staged = git(root, &["show", ":a.txt"]).unwrap();
+    assert_eq!(String::from_utf8_lossy(&staged.stdout), "bar staged\n");
+}
@@ -0,0 +1,48 @@
+//!
+//! 把替换引擎当作库使用，和 README 中的例子一致
+//!
+
+use std::fs;
+use std::slice;
+
+use regex_replace::{
+    ReplacePlan, Replacer, Rule, Strategy, TokenScope, Tokens, WalkOptions, WriteOptions,
+};
+
+#[test]
+fn replace_a_walked_directory() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), "foo1 foo22\n").unwrap();
+    fs::write(dir.path().join("b.txt"), "nothing\n").unwrap();
+
+    let tokens = Tokens::new(TokenScope::Run);
+    let mut plan = ReplacePlan::new(vec![Rule::new(r"foo(\d+)", "bar$1", &tokens).unwrap()]);
+    plan.strategy = Strategy::Line;
+
+    let files = WalkOptions::default().walk(dir.path()).unwrap();
+    let summary = Replacer::new(&plan, &WriteOptions::default()).replace_files(&files);
+    summary.result().unwrap();
+    assert!(summary.failed.is_empty());
+    assert_eq!(
+        fs::read_to_string(dir.path().join("a.txt")).unwrap(),
+        "bar1 bar22\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.path().join("b.txt")).unwrap(),
+        "nothing\n"
+    );
+}
+
+#[test]
+fn failures_are_returned_instead_of_printed() {
+    let dir = tempfile::tempdir().unwrap();
+    let tokens = Tokens::new(TokenScope::Run);
+    let plan = ReplacePlan::new(vec![Rule::new("foo", "bar", &tokens).unwrap()]);
+
+    let missing = dir.path().join("missing.txt");
+    let summary =
+        Replacer::new(&plan, &WriteOptions::default()).replace_files(slice::from_ref(&missing));
+    assert_eq!(summary.failed.len(), 1);
+    assert_eq!(summary.failed[0].0, missing);
+    assert_eq!(summary.result(), Err("错误: 1 个文件处理失败".to_string()));
+}